@@ -0,0 +1,238 @@
+//! Request bodies, held in memory or read from a stream while the request is sent.
+
+use core::fmt;
+#[cfg(feature = "async")]
+use core::pin::Pin;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex};
+
+#[cfg(feature = "async")]
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::Error;
+
+/// The most bytes read from a stream before writing them, which is the size of the chunks of a
+/// body sent with `Transfer-Encoding: chunked`.
+const CHUNK_LENGTH: usize = 16 * 1024;
+
+/// The body of a [`Request`](crate::Request).
+#[derive(Clone, PartialEq, Eq)]
+pub(crate) enum Body {
+    Bytes(Vec<u8>),
+    Stream(BodyStream),
+}
+
+impl Body {
+    /// Returns whether the body can be sent again, after a redirect or on a new connection.
+    pub(crate) fn is_replayable(&self) -> bool { matches!(self, Body::Bytes(_)) }
+}
+
+impl fmt::Debug for Body {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Body::Bytes(bytes) => bytes.fmt(f),
+            Body::Stream(stream) => stream.fmt(f),
+        }
+    }
+}
+
+/// A body read from a stream, which can only be sent once.
+///
+/// The clones of a request share the stream, the first one sent takes it.
+#[derive(Clone)]
+pub(crate) struct BodyStream {
+    reader: Arc<Mutex<Option<Reader>>>,
+    /// The number of bytes to send, sent with `Transfer-Encoding: chunked` if unknown.
+    pub(crate) length: Option<u64>,
+}
+
+pub(crate) enum Reader {
+    Blocking(Box<dyn Read + Send>),
+    #[cfg(feature = "async")]
+    Async(Pin<Box<dyn AsyncRead + Send>>),
+}
+
+impl BodyStream {
+    pub(crate) fn new(reader: Reader, length: Option<u64>) -> BodyStream {
+        BodyStream { reader: Arc::new(Mutex::new(Some(reader))), length }
+    }
+
+    /// Takes the stream to send it from a blocking method.
+    pub(crate) fn take_blocking(&self) -> Result<Box<dyn Read + Send>, Error> {
+        let mut reader = self.reader.lock().unwrap();
+        match reader.take() {
+            Some(Reader::Blocking(reader)) => Ok(reader),
+            #[cfg(feature = "async")]
+            Some(async_reader @ Reader::Async(_)) => {
+                *reader = Some(async_reader);
+                Err(Error::AsyncBodyReader)
+            }
+            None => Err(Error::BodyNotReplayable),
+        }
+    }
+
+    /// Takes the stream to send it from an async method.
+    #[cfg(feature = "async")]
+    pub(crate) fn take(&self) -> Result<Reader, Error> {
+        self.reader.lock().unwrap().take().ok_or(Error::BodyNotReplayable)
+    }
+}
+
+impl PartialEq for BodyStream {
+    fn eq(&self, other: &BodyStream) -> bool { Arc::ptr_eq(&self.reader, &other.reader) }
+}
+
+impl Eq for BodyStream {}
+
+impl fmt::Debug for BodyStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BodyStream").field("length", &self.length).finish_non_exhaustive()
+    }
+}
+
+/// Frames the bytes read from a stream: as they are if the length of the body is known, or in
+/// chunks otherwise.
+struct Framing {
+    /// How many bytes are left to send, if known.
+    remaining: Option<u64>,
+    finished: bool,
+}
+
+impl Framing {
+    fn new(length: Option<u64>) -> Framing {
+        Framing { remaining: length, finished: length == Some(0) }
+    }
+
+    /// Returns how many bytes to read from the stream next.
+    fn read_length(&self) -> usize {
+        match self.remaining {
+            Some(remaining) => remaining.min(CHUNK_LENGTH as u64) as usize,
+            None => CHUNK_LENGTH,
+        }
+    }
+
+    /// Replaces `framed` with the bytes to write for `data`, read from the stream. Empty `data`
+    /// marks the end of the stream.
+    fn frame(&mut self, data: &[u8], framed: &mut Vec<u8>) -> io::Result<()> {
+        framed.clear();
+        match &mut self.remaining {
+            Some(remaining) => {
+                if data.is_empty() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "the request body ended before its announced length",
+                    ));
+                }
+                *remaining -= data.len() as u64;
+                self.finished = *remaining == 0;
+                framed.extend_from_slice(data);
+            }
+            None => {
+                // The last chunk is empty, and sent without trailers.
+                write!(framed, "{:x}\r\n", data.len())?;
+                framed.extend_from_slice(data);
+                framed.extend_from_slice(b"\r\n");
+                self.finished = data.is_empty();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes the body read from `reader` to `stream`.
+pub(crate) fn write_blocking<W: Write>(
+    mut reader: Box<dyn Read + Send>,
+    length: Option<u64>,
+    stream: &mut W,
+) -> io::Result<()> {
+    let mut framing = Framing::new(length);
+    let mut buffer = vec![0; CHUNK_LENGTH];
+    let mut framed = Vec::new();
+    while !framing.finished {
+        let read = match reader.read(&mut buffer[..framing.read_length()]) {
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        framing.frame(&buffer[..read], &mut framed)?;
+        stream.write_all(&framed)?;
+    }
+    Ok(())
+}
+
+/// Writes the body read from `reader` to `stream` asynchronously.
+#[cfg(feature = "async")]
+pub(crate) async fn write_async<W: AsyncWrite + Unpin>(
+    mut reader: Reader,
+    length: Option<u64>,
+    stream: &mut W,
+) -> io::Result<()> {
+    let mut framing = Framing::new(length);
+    let mut buffer = vec![0; CHUNK_LENGTH];
+    let mut framed = Vec::new();
+    while !framing.finished {
+        let read_length = framing.read_length();
+        let read = match read_async(&mut reader, &mut buffer, read_length).await {
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        framing.frame(&buffer[..read], &mut framed)?;
+        stream.write_all(&framed).await?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "async")]
+async fn read_async(reader: &mut Reader, buffer: &mut Vec<u8>, length: usize) -> io::Result<usize> {
+    match reader {
+        Reader::Async(reader) => reader.read(&mut buffer[..length]).await,
+        Reader::Blocking(reader) => {
+            // The read may block, so it is done on a thread where that is allowed.
+            let mut moved_reader = core::mem::replace(reader, Box::new(io::empty()));
+            let mut moved_buffer = core::mem::take(buffer);
+            let (moved_reader, moved_buffer, result) = tokio::task::spawn_blocking(move || {
+                let result = moved_reader.read(&mut moved_buffer[..length]);
+                (moved_reader, moved_buffer, result)
+            })
+            .await
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+            *reader = moved_reader;
+            *buffer = moved_buffer;
+            result
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(body: &[u8], length: Option<u64>) -> io::Result<Vec<u8>> {
+        let mut written = Vec::new();
+        write_blocking(Box::new(io::Cursor::new(body.to_vec())), length, &mut written)?;
+        Ok(written)
+    }
+
+    #[test]
+    fn known_length() {
+        assert_eq!(write(b"hello", Some(5)).unwrap(), b"hello");
+        // Only the announced length is sent.
+        assert_eq!(write(b"hello world", Some(5)).unwrap(), b"hello");
+        assert_eq!(write(b"", Some(0)).unwrap(), b"");
+        let err = write(b"hello", Some(6)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn chunked() {
+        assert_eq!(write(b"hello", None).unwrap(), b"5\r\nhello\r\n0\r\n\r\n");
+        assert_eq!(write(b"", None).unwrap(), b"0\r\n\r\n");
+
+        let body = vec![b'a'; CHUNK_LENGTH + 1];
+        let mut expected = format!("{:x}\r\n", CHUNK_LENGTH).into_bytes();
+        expected.extend_from_slice(&body[..CHUNK_LENGTH]);
+        expected.extend_from_slice(b"\r\n1\r\na\r\n0\r\n\r\n");
+        assert_eq!(write(&body, None).unwrap(), expected);
+    }
+}
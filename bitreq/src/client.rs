@@ -1,26 +1,54 @@
 //! Connection pooling client for HTTP requests.
 //!
 //! The `Client` caches connections to avoid repeated TCP handshakes and TLS negotiations.
-//!
-//! Due to std limitations, `Client` currently only supports async requests.
-
-#![cfg(feature = "async")]
 
-use std::collections::{hash_map, HashMap, VecDeque};
+use std::collections::VecDeque;
+#[cfg(feature = "async")]
+use std::collections::{hash_map, HashMap};
+use std::fmt;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
+#[cfg(feature = "async")]
 use crate::connection::AsyncConnection;
+use crate::connection::{Connection, KeptAlive, Sent};
+use crate::middleware::{Middleware, Middlewares};
 use crate::request::{OwnedConnectionParams as ConnectionKey, ParsedRequest};
+#[cfg(any(feature = "rustls", feature = "native-tls"))]
+use crate::TlsConfig;
 use crate::{Error, Request, Response};
 
+/// How long an unused connection is kept by default, below the 75 seconds nginx waits by default.
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
 /// A client that caches connections for reuse.
 ///
 /// The client maintains a pool of up to `capacity` connections, evicting
 /// the least recently used connection when the cache is full.
 ///
+/// Blocking requests each use a connection of their own, so requests sent
+/// from several threads at once open several connections to the same host.
+/// Once a response was read, its connection is kept for the next request
+/// unless the server closes it. Kept connections are closed after being
+/// unused for a while, see [`Client::with_idle_timeout`].
+///
 /// # Example
 ///
 /// ```no_run
+/// # fn request() -> Result<(), bitreq::Error> {
+/// use bitreq::{Client, RequestExt};
+///
+/// let client = Client::new(10); // Cache up to 10 connections
+/// let response = bitreq::get("http://example.com").send_with_client(&client)?;
+/// // Sent on the same connection, if the server keeps it open.
+/// let response = bitreq::get("http://example.com/other").send_with_client(&client)?;
+/// # Ok(()) }
+/// ```
+///
+/// With the `async` feature:
+///
+/// ```no_run
+/// # #[cfg(feature = "async")]
 /// # async fn request() {
 /// use bitreq::{Client, RequestExt};
 ///
@@ -32,9 +60,29 @@ use crate::{Error, Request, Response};
 /// ```
 #[derive(Clone)]
 pub struct Client {
+    blocking: Arc<Mutex<IdleConnections>>,
+    #[cfg(feature = "async")]
     r#async: Arc<Mutex<ClientImpl<AsyncConnection>>>,
+    #[cfg(any(feature = "rustls", feature = "native-tls"))]
+    tls: Option<TlsConfig>,
+    middlewares: Vec<Arc<dyn Middleware>>,
+}
+
+/// The connections of blocking requests waiting for the next one.
+struct IdleConnections {
+    /// The least recently used first.
+    connections: VecDeque<(ConnectionKey, IdleConnection)>,
+    capacity: usize,
+    timeout: Duration,
+}
+
+struct IdleConnection {
+    connection: Connection,
+    /// When to stop sending requests on the connection.
+    expires_at: Instant,
 }
 
+#[cfg(feature = "async")]
 struct ClientImpl<T> {
     connections: HashMap<ConnectionKey, Arc<T>>,
     lru_order: VecDeque<ConnectionKey>,
@@ -50,16 +98,135 @@ impl Client {
     ///   reached, the least recently used connection is evicted.
     pub fn new(capacity: usize) -> Self {
         Client {
+            blocking: Arc::new(Mutex::new(IdleConnections {
+                connections: VecDeque::new(),
+                capacity,
+                timeout: DEFAULT_IDLE_TIMEOUT,
+            })),
+            #[cfg(feature = "async")]
             r#async: Arc::new(Mutex::new(ClientImpl {
                 connections: HashMap::new(),
                 lru_order: VecDeque::new(),
                 capacity,
             })),
+            #[cfg(any(feature = "rustls", feature = "native-tls"))]
+            tls: None,
+            middlewares: Vec::new(),
+        }
+    }
+
+    /// Sets how long a connection of blocking requests is kept unused before
+    /// it is closed, 60 seconds by default.
+    ///
+    /// The server may close connections earlier, it is asked to keep them
+    /// open with the `Keep-Alive` header. Clones of this client share the
+    /// setting.
+    pub fn with_idle_timeout(self, timeout: Duration) -> Self {
+        self.blocking.lock().unwrap().timeout = timeout;
+        self
+    }
+
+    /// Sets the TLS settings of the HTTPS requests sent with this client,
+    /// unless they set their own with [`Request::with_tls_config`].
+    #[cfg(any(feature = "rustls", feature = "native-tls"))]
+    pub fn with_tls_config(mut self, tls: TlsConfig) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Adds a [`Middleware`] run around every request sent with this client,
+    /// before those of the request itself.
+    pub fn with_middleware(mut self, middleware: Arc<dyn Middleware>) -> Self {
+        self.middlewares.push(middleware);
+        self
+    }
+
+    /// Sends a request using a cached connection if available.
+    ///
+    /// The response is read completely, see [`Request::send`]. If the server
+    /// closes a cached connection before answering, the request is sent again
+    /// on a new connection when that is safe: if none of it was written, or if
+    /// its method is idempotent (`GET`, `HEAD`, `PUT`, `DELETE`, `OPTIONS` or
+    /// `TRACE`). Otherwise the error is returned, as the server may have acted
+    /// on the request.
+    pub fn send(&self, request: Request) -> Result<Response, Error> {
+        let (request, sending) = Middlewares::start(self.configure(request))?;
+        sending.finish(self.send_inner(request))
+    }
+
+    fn send_inner(&self, request: Request) -> Result<Response, Error> {
+        let mut request = ParsedRequest::new(request)?;
+        let key: ConnectionKey = request.connection_params().into();
+        loop {
+            let (connection, reused) = match self.take_idle(&key) {
+                Some(connection) => (connection, true),
+                None => (Connection::new(request.connection_params(), request.timeouts())?, false),
+            };
+            match connection.send_keep_alive(request)? {
+                Sent::Response(response, kept_alive) => {
+                    if let Some(kept_alive) = kept_alive {
+                        self.put_idle(key, kept_alive);
+                    }
+                    return Ok(response);
+                }
+                // The server closed an idle connection while we sent the request, the request is
+                // sent again on another connection.
+                Sent::Closed(unsent, _) if reused => request = *unsent,
+                Sent::Closed(_, e) => return Err(Error::IoError(e)),
+            }
+        }
+    }
+
+    /// Applies the settings of the client that `request` doesn't override.
+    fn configure(&self, mut request: Request) -> Request {
+        #[cfg(any(feature = "rustls", feature = "native-tls"))]
+        if request.tls.is_none() {
+            request.tls = self.tls.clone();
+        }
+        request.middlewares.0.splice(0..0, self.middlewares.iter().cloned());
+        request
+    }
+
+    /// Takes the most recently used connection to `key` that the server did not close.
+    fn take_idle(&self, key: &ConnectionKey) -> Option<Connection> {
+        loop {
+            let idle = {
+                let mut idle = self.blocking.lock().unwrap();
+                let now = Instant::now();
+                idle.connections.retain(|(_, connection)| connection.expires_at > now);
+                let position = idle.connections.iter().rposition(|(k, _)| k == key)?;
+                idle.connections.remove(position)?.1
+            };
+            if !idle.connection.is_stale() {
+                return Some(idle.connection);
+            }
+        }
+    }
+
+    /// Keeps `kept_alive` for the next request to `key`, evicting the least recently used
+    /// connection if the cache is full.
+    fn put_idle(&self, key: ConnectionKey, kept_alive: KeptAlive) {
+        let mut idle = self.blocking.lock().unwrap();
+        let mut expires_at = Instant::now() + idle.timeout;
+        if let Some(server_expires_at) = kept_alive.expires_at {
+            expires_at = expires_at.min(server_expires_at);
+        }
+        idle.connections
+            .push_back((key, IdleConnection { connection: kept_alive.connection, expires_at }));
+        if idle.connections.len() > idle.capacity {
+            idle.connections.pop_front();
         }
     }
 
     /// Sends a request asynchronously using a cached connection if available.
+    #[cfg(feature = "async")]
     pub async fn send_async(&self, request: Request) -> Result<Response, Error> {
+        let (request, sending) = Middlewares::start(self.configure(request))?;
+        sending.finish(self.send_async_inner(request).await)
+    }
+
+    #[cfg(feature = "async")]
+    async fn send_async_inner(&self, request: Request) -> Result<Response, Error> {
         let parsed_request = ParsedRequest::new(request)?;
         let key = parsed_request.connection_params();
         let owned_key = key.into();
@@ -77,7 +244,7 @@ impl Client {
         let conn = if let Some(conn) = conn_opt {
             conn
         } else {
-            let connection = AsyncConnection::new(key, parsed_request.timeout_at).await?;
+            let connection = AsyncConnection::new(key, parsed_request.timeouts()).await?;
             let connection = Arc::new(connection);
 
             let mut state = self.r#async.lock().unwrap();
@@ -98,9 +265,23 @@ impl Client {
     }
 }
 
+impl fmt::Debug for Client {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let idle = self.blocking.lock().unwrap();
+        f.debug_struct("Client")
+            .field("capacity", &idle.capacity)
+            .field("idle_timeout", &idle.timeout)
+            .finish_non_exhaustive()
+    }
+}
+
 /// Extension trait for `Request` to use with `Client`.
 pub trait RequestExt {
+    /// Sends this request using the provided client's connection pool.
+    fn send_with_client(self, client: &Client) -> Result<Response, Error>;
+
     /// Sends this request asynchronously using the provided client's connection pool.
+    #[cfg(feature = "async")]
     fn send_async_with_client(
         self,
         client: &Client,
@@ -108,6 +289,9 @@ pub trait RequestExt {
 }
 
 impl RequestExt for Request {
+    fn send_with_client(self, client: &Client) -> Result<Response, Error> { client.send(self) }
+
+    #[cfg(feature = "async")]
     fn send_async_with_client(
         self,
         client: &Client,
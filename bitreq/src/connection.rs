@@ -2,7 +2,9 @@ use core::time::Duration;
 #[cfg(feature = "async")]
 use std::future::Future;
 use std::io::{self, Read, Write};
-use std::net::{TcpStream, ToSocketAddrs};
+use std::net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
 #[cfg(feature = "async")]
 use std::pin::Pin;
 #[cfg(feature = "async")]
@@ -13,50 +15,211 @@ use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
 use std::time::Instant;
 
-#[cfg(all(feature = "async", feature = "proxy"))]
-use tokio::io::AsyncReadExt;
 #[cfg(feature = "async")]
-use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadHalf, WriteHalf};
+use tokio::io::{AsyncRead, AsyncWrite, ReadHalf, WriteHalf};
+#[cfg(all(feature = "async", feature = "proxy"))]
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 #[cfg(feature = "async")]
 use tokio::net::TcpStream as AsyncTcpStream;
+#[cfg(all(feature = "async", unix))]
+use tokio::net::UnixStream as AsyncUnixStream;
 #[cfg(feature = "async")]
 use tokio::sync::Mutex as AsyncMutex;
 
+use crate::body::Body;
+#[cfg(feature = "proxy")]
+use crate::proxy::ProxyKind;
 use crate::request::{ConnectionParams, OwnedConnectionParams, ParsedRequest};
-#[cfg(feature = "async")]
-use crate::Response;
-use crate::{Error, Method, ResponseLazy};
+use crate::{Error, HeaderMap, Method, Response, ResponseLazy};
 
 type UnsecuredStream = TcpStream;
 
-#[cfg(feature = "rustls")]
+#[cfg(any(feature = "rustls", feature = "native-tls"))]
 mod rustls_stream;
-#[cfg(feature = "rustls")]
+#[cfg(any(feature = "rustls", feature = "native-tls"))]
 type SecuredStream = rustls_stream::SecuredStream;
 
 pub(crate) enum HttpStream {
-    Unsecured(UnsecuredStream, Option<Instant>),
-    #[cfg(feature = "rustls")]
-    Secured(Box<SecuredStream>, Option<Instant>),
+    Unsecured(UnsecuredStream, Timeouts),
+    #[cfg(any(feature = "rustls", feature = "native-tls"))]
+    Secured(Box<SecuredStream>, Timeouts),
+    /// A Unix socket, with the byte [`HttpStream::wait_for_response`] read ahead of the response
+    /// until it is read.
+    #[cfg(unix)]
+    Unix(UnixStream, Timeouts, Option<u8>),
     #[cfg(feature = "async")]
     Buffer(std::io::Cursor<Vec<u8>>),
 }
 
 impl HttpStream {
-    fn create_unsecured(reader: UnsecuredStream, timeout_at: Option<Instant>) -> HttpStream {
-        HttpStream::Unsecured(reader, timeout_at)
+    fn create_unsecured(reader: UnsecuredStream, timeouts: Timeouts) -> HttpStream {
+        HttpStream::Unsecured(reader, timeouts)
     }
 
     #[cfg(feature = "async")]
     pub(crate) fn create_buffer(buffer: Vec<u8>) -> HttpStream {
         HttpStream::Buffer(std::io::Cursor::new(buffer))
     }
+
+    /// Returns the underlying socket and the timeouts of reading and writing it.
+    fn socket(&self) -> Option<(&TcpStream, Timeouts)> {
+        match self {
+            HttpStream::Unsecured(inner, timeouts) => Some((inner, *timeouts)),
+            #[cfg(any(feature = "rustls", feature = "native-tls"))]
+            HttpStream::Secured(inner, timeouts) => Some((inner.get_ref(), *timeouts)),
+            #[cfg(unix)]
+            HttpStream::Unix(..) => None,
+            #[cfg(feature = "async")]
+            HttpStream::Buffer(_) => None,
+        }
+    }
+
+    fn set_timeouts(&mut self, new_timeouts: Timeouts) {
+        match self {
+            HttpStream::Unsecured(_, timeouts) => *timeouts = new_timeouts,
+            #[cfg(any(feature = "rustls", feature = "native-tls"))]
+            HttpStream::Secured(_, timeouts) => *timeouts = new_timeouts,
+            #[cfg(unix)]
+            HttpStream::Unix(_, timeouts, _) => *timeouts = new_timeouts,
+            #[cfg(feature = "async")]
+            HttpStream::Buffer(_) => {}
+        }
+    }
+
+    /// Returns whether the server closed the connection or sent something nobody asked for,
+    /// either way no further request can be sent on it.
+    fn is_stale(&self) -> bool {
+        #[cfg(unix)]
+        if let HttpStream::Unix(unix, _, read_ahead) = self {
+            // `UnixStream::peek` isn't stable, but anything we could read makes the connection
+            // stale anyway, so we can just read instead.
+            if read_ahead.is_some() || unix.set_nonblocking(true).is_err() {
+                return true;
+            }
+            let idle = matches!((&mut &*unix).read(&mut [0]), Err(e) if e.kind() == io::ErrorKind::WouldBlock);
+            return unix.set_nonblocking(false).is_err() || !idle;
+        }
+        let (tcp, _) = match self.socket() {
+            Some(socket) => socket,
+            None => return true,
+        };
+        if tcp.set_nonblocking(true).is_err() {
+            return true;
+        }
+        let idle = matches!(tcp.peek(&mut [0]), Err(e) if e.kind() == io::ErrorKind::WouldBlock);
+        tcp.set_nonblocking(false).is_err() || !idle
+    }
+
+    /// Waits for the server to start answering, fails with [`io::ErrorKind::UnexpectedEof`] if
+    /// it closes the connection instead.
+    ///
+    /// Unix sockets can't be peeked at, so the first byte of the response is read ahead instead.
+    fn wait_for_response(&mut self) -> io::Result<()> {
+        #[cfg(unix)]
+        if let HttpStream::Unix(unix, timeouts, read_ahead) = self {
+            let (timeout, idle) = timeouts.next_read()?;
+            unix.set_read_timeout(timeout)?;
+            let mut byte = [0];
+            let result = unix.read(&mut byte);
+            if let Ok(1) = result {
+                *read_ahead = Some(byte[0]);
+            }
+            return response_started(result, idle);
+        }
+        let (tcp, timeouts) = match self.socket() {
+            Some(socket) => socket,
+            None => return Ok(()),
+        };
+        let (timeout, idle) = timeouts.next_read()?;
+        tcp.set_read_timeout(timeout)?;
+        response_started(tcp.peek(&mut [0]), idle)
+    }
+}
+
+/// Turns the result of reading ahead the first byte of a response into whether the server
+/// started answering, see [`HttpStream::wait_for_response`].
+fn response_started(result: io::Result<usize>, idle: bool) -> io::Result<()> {
+    match result {
+        Ok(0) => Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "the server closed the connection before responding",
+        )),
+        Ok(_) => Ok(()),
+        Err(e) if is_timeout(&e) => Err(read_timed_out(idle)),
+        Err(e) => Err(e),
+    }
+}
+
+/// The timeouts of a request, see
+/// [`Request::with_total_timeout`](crate::Request::with_total_timeout),
+/// [`Request::with_connect_timeout`](crate::Request::with_connect_timeout) and
+/// [`Request::with_read_timeout`](crate::Request::with_read_timeout).
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Timeouts {
+    /// When the whole request times out.
+    pub(crate) deadline: Option<Instant>,
+    /// How long connecting may take.
+    pub(crate) connect: Option<Duration>,
+    /// How long the server may send nothing while the response is read.
+    pub(crate) read: Option<Duration>,
+}
+
+impl Timeouts {
+    /// Returns when connecting, starting now, times out.
+    fn connect_deadline(&self) -> Option<Instant> {
+        let connect_at = self.connect.and_then(|connect| Instant::now().checked_add(connect));
+        match (connect_at, self.deadline) {
+            (Some(connect_at), Some(deadline)) => Some(connect_at.min(deadline)),
+            (connect_at, deadline) => connect_at.or(deadline),
+        }
+    }
+
+    /// Turns `error`, which happened while connecting until `connect_at`, into
+    /// [`Error::ConnectTimeout`] if it is because of the connect timeout, or into the error of the
+    /// whole request timing out.
+    fn connect_error(&self, error: Error, connect_at: Option<Instant>) -> Error {
+        match error {
+            Error::IoError(e) if is_timeout(&e) && connect_at.is_some() =>
+                if connect_at == self.deadline {
+                    Error::IoError(timeout_err())
+                } else {
+                    Error::ConnectTimeout
+                },
+            error => error,
+        }
+    }
+
+    /// Returns how long the next read may block, and whether that is the read timeout rather
+    /// than what remains of the whole request. Fails if the request has timed out already.
+    fn next_read(&self) -> io::Result<(Option<Duration>, bool)> {
+        Ok(match (timeout_at_to_duration(self.deadline)?, self.read) {
+            (Some(remaining), Some(read)) if read < remaining => (Some(read), true),
+            (Some(remaining), _) => (Some(remaining), false),
+            (None, read) => (read, read.is_some()),
+        })
+    }
 }
 
 fn timeout_err() -> io::Error {
     io::Error::new(io::ErrorKind::TimedOut, "the timeout of the request was reached")
 }
 
+/// Returns the error of a read that timed out, `idle` if because of the read timeout.
+fn read_timed_out(idle: bool) -> io::Error {
+    if idle {
+        // Unwrapped back into `Error::ReadTimeout` by `From<io::Error> for Error`
+        io::Error::new(io::ErrorKind::TimedOut, Error::ReadTimeout)
+    } else {
+        timeout_err()
+    }
+}
+
+/// Returns whether `error` is a socket timing out. Blocking sockets fail with
+/// [`io::ErrorKind::WouldBlock`] on Unix and [`io::ErrorKind::TimedOut`] on Windows.
+fn is_timeout(error: &io::Error) -> bool {
+    matches!(error.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut)
+}
+
 fn timeout_at_to_duration(timeout_at: Option<Instant>) -> Result<Option<Duration>, io::Error> {
     if let Some(timeout_at) = timeout_at {
         if let Some(duration) = timeout_at.checked_duration_since(Instant::now()) {
@@ -71,28 +234,40 @@ fn timeout_at_to_duration(timeout_at: Option<Instant>) -> Result<Option<Duration
 
 impl Read for HttpStream {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        let timeout = |tcp: &TcpStream, timeout_at: Option<Instant>| -> io::Result<()> {
-            let _ = tcp.set_read_timeout(timeout_at_to_duration(timeout_at)?);
-            Ok(())
+        let timeout = |tcp: &TcpStream, timeouts: Timeouts| -> io::Result<bool> {
+            let (timeout, idle) = timeouts.next_read()?;
+            tcp.set_read_timeout(timeout)?;
+            Ok(idle)
         };
 
-        let result = match self {
-            HttpStream::Unsecured(inner, timeout_at) => {
-                timeout(inner, *timeout_at)?;
-                inner.read(buf)
+        let (result, idle) = match self {
+            HttpStream::Unsecured(inner, timeouts) => {
+                let idle = timeout(inner, *timeouts)?;
+                (inner.read(buf), idle)
             }
-            #[cfg(feature = "rustls")]
-            HttpStream::Secured(inner, timeout_at) => {
-                timeout(inner.get_ref(), *timeout_at)?;
-                inner.read(buf)
+            #[cfg(any(feature = "rustls", feature = "native-tls"))]
+            HttpStream::Secured(inner, timeouts) => {
+                let idle = timeout(inner.get_ref(), *timeouts)?;
+                (inner.read(buf), idle)
+            }
+            #[cfg(unix)]
+            HttpStream::Unix(inner, timeouts, read_ahead) => {
+                if let (Some(byte), Some(first)) = (*read_ahead, buf.first_mut()) {
+                    *first = byte;
+                    *read_ahead = None;
+                    return Ok(1);
+                }
+                let (timeout, idle) = timeouts.next_read()?;
+                inner.set_read_timeout(timeout)?;
+                (inner.read(buf), idle)
             }
             #[cfg(feature = "async")]
-            HttpStream::Buffer(cursor) => std::io::Read::read(cursor, buf),
+            HttpStream::Buffer(cursor) => (std::io::Read::read(cursor, buf), false),
         };
         match result {
-            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+            Err(e) if is_timeout(&e) => {
                 // We're a blocking socket, so EWOULDBLOCK indicates a timeout
-                Err(timeout_err())
+                Err(read_timed_out(idle))
             }
             r => r,
         }
@@ -107,13 +282,18 @@ fn set_socket_write_timeout(tcp: &TcpStream, timeout_at: Option<Instant>) -> io:
 impl Write for HttpStream {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         let result = match self {
-            HttpStream::Unsecured(inner, timeout_at) => {
-                set_socket_write_timeout(inner, *timeout_at)?;
+            HttpStream::Unsecured(inner, timeouts) => {
+                set_socket_write_timeout(inner, timeouts.deadline)?;
+                inner.write(buf)
+            }
+            #[cfg(any(feature = "rustls", feature = "native-tls"))]
+            HttpStream::Secured(inner, timeouts) => {
+                set_socket_write_timeout(inner.get_ref(), timeouts.deadline)?;
                 inner.write(buf)
             }
-            #[cfg(feature = "rustls")]
-            HttpStream::Secured(inner, timeout_at) => {
-                set_socket_write_timeout(inner.get_ref(), *timeout_at)?;
+            #[cfg(unix)]
+            HttpStream::Unix(inner, timeouts, _) => {
+                inner.set_write_timeout(timeout_at_to_duration(timeouts.deadline)?)?;
                 inner.write(buf)
             }
             #[cfg(feature = "async")]
@@ -133,13 +313,18 @@ impl Write for HttpStream {
 
     fn flush(&mut self) -> io::Result<()> {
         let result = match self {
-            HttpStream::Unsecured(inner, timeout_at) => {
-                set_socket_write_timeout(inner, *timeout_at)?;
+            HttpStream::Unsecured(inner, timeouts) => {
+                set_socket_write_timeout(inner, timeouts.deadline)?;
+                inner.flush()
+            }
+            #[cfg(any(feature = "rustls", feature = "native-tls"))]
+            HttpStream::Secured(inner, timeouts) => {
+                set_socket_write_timeout(inner.get_ref(), timeouts.deadline)?;
                 inner.flush()
             }
-            #[cfg(feature = "rustls")]
-            HttpStream::Secured(inner, timeout_at) => {
-                set_socket_write_timeout(inner.get_ref(), *timeout_at)?;
+            #[cfg(unix)]
+            HttpStream::Unix(inner, timeouts, _) => {
+                inner.set_write_timeout(timeout_at_to_duration(timeouts.deadline)?)?;
                 inner.flush()
             }
             #[cfg(feature = "async")]
@@ -158,14 +343,19 @@ impl Write for HttpStream {
     }
 }
 
-#[cfg(feature = "tokio-rustls")]
+#[cfg(any(feature = "tokio-rustls", all(feature = "tokio-native-tls", not(feature = "rustls"))))]
 type AsyncSecuredStream = rustls_stream::AsyncSecuredStream;
 
 #[cfg(feature = "async")]
 pub(crate) enum AsyncHttpStream {
     Unsecured(AsyncTcpStream),
-    #[cfg(feature = "tokio-rustls")]
+    #[cfg(any(
+        feature = "tokio-rustls",
+        all(feature = "tokio-native-tls", not(feature = "rustls"))
+    ))]
     Secured(Box<AsyncSecuredStream>),
+    #[cfg(unix)]
+    Unix(AsyncUnixStream),
 }
 
 #[cfg(feature = "async")]
@@ -177,8 +367,13 @@ impl AsyncRead for AsyncHttpStream {
     ) -> Poll<io::Result<()>> {
         match &mut *self {
             AsyncHttpStream::Unsecured(inner) => Pin::new(inner).poll_read(cx, buf),
-            #[cfg(feature = "tokio-rustls")]
+            #[cfg(any(
+                feature = "tokio-rustls",
+                all(feature = "tokio-native-tls", not(feature = "rustls"))
+            ))]
             AsyncHttpStream::Secured(inner) => Pin::new(inner).poll_read(cx, buf),
+            #[cfg(unix)]
+            AsyncHttpStream::Unix(inner) => Pin::new(inner).poll_read(cx, buf),
         }
     }
 }
@@ -192,28 +387,108 @@ impl AsyncWrite for AsyncHttpStream {
     ) -> Poll<io::Result<usize>> {
         match &mut *self {
             AsyncHttpStream::Unsecured(inner) => Pin::new(inner).poll_write(cx, buf),
-            #[cfg(feature = "tokio-rustls")]
+            #[cfg(any(
+                feature = "tokio-rustls",
+                all(feature = "tokio-native-tls", not(feature = "rustls"))
+            ))]
             AsyncHttpStream::Secured(inner) => Pin::new(inner).poll_write(cx, buf),
+            #[cfg(unix)]
+            AsyncHttpStream::Unix(inner) => Pin::new(inner).poll_write(cx, buf),
         }
     }
 
     fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
         match &mut *self {
             AsyncHttpStream::Unsecured(inner) => Pin::new(inner).poll_flush(cx),
-            #[cfg(feature = "tokio-rustls")]
+            #[cfg(any(
+                feature = "tokio-rustls",
+                all(feature = "tokio-native-tls", not(feature = "rustls"))
+            ))]
             AsyncHttpStream::Secured(inner) => Pin::new(inner).poll_flush(cx),
+            #[cfg(unix)]
+            AsyncHttpStream::Unix(inner) => Pin::new(inner).poll_flush(cx),
         }
     }
 
     fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
         match &mut *self {
             AsyncHttpStream::Unsecured(inner) => Pin::new(inner).poll_shutdown(cx),
-            #[cfg(feature = "tokio-rustls")]
+            #[cfg(any(
+                feature = "tokio-rustls",
+                all(feature = "tokio-native-tls", not(feature = "rustls"))
+            ))]
             AsyncHttpStream::Secured(inner) => Pin::new(inner).poll_shutdown(cx),
+            #[cfg(unix)]
+            AsyncHttpStream::Unix(inner) => Pin::new(inner).poll_shutdown(cx),
         }
     }
 }
 
+/// Fails reads with [`Error::ReadTimeout`] once nothing was read for the read timeout.
+#[cfg(feature = "async")]
+struct IdleTimeout<R> {
+    inner: R,
+    timeout: Option<(Duration, Pin<Box<tokio::time::Sleep>>)>,
+}
+
+#[cfg(feature = "async")]
+impl<R> IdleTimeout<R> {
+    fn new(inner: R, timeout: Option<Duration>) -> IdleTimeout<R> {
+        let timeout = timeout.map(|timeout| (timeout, Box::pin(tokio::time::sleep(timeout))));
+        IdleTimeout { inner, timeout }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<R: AsyncRead + Unpin> AsyncRead for IdleTimeout<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        match Pin::new(&mut this.inner).poll_read(cx, buf) {
+            Poll::Ready(result) => {
+                if let Some((timeout, sleep)) = &mut this.timeout {
+                    sleep.as_mut().reset(tokio::time::Instant::now() + *timeout);
+                }
+                Poll::Ready(result)
+            }
+            Poll::Pending => match &mut this.timeout {
+                Some((_, sleep)) => sleep.as_mut().poll(cx).map(|()| Err(read_timed_out(true))),
+                None => Poll::Pending,
+            },
+        }
+    }
+}
+
+/// The parameters of a `Keep-Alive` response header.
+#[derive(Default)]
+struct KeepAlive {
+    /// How long the server keeps an idle connection open, less a second of leeway.
+    timeout: Option<Duration>,
+    /// How many more requests the server accepts on the connection.
+    max: Option<usize>,
+}
+
+impl KeepAlive {
+    /// Parses a `Keep-Alive` header, returns `None` if it has a parameter we don't understand.
+    fn parse(header: &str) -> Option<KeepAlive> {
+        let mut keep_alive = KeepAlive::default();
+        for param in header.split(',') {
+            let (k, v) = param.trim().split_once('=')?;
+            let v = v.parse::<usize>().ok()?;
+            match k.trim() {
+                "timeout" =>
+                    keep_alive.timeout = Some(Duration::from_secs((v as u64).saturating_sub(1))),
+                "max" => keep_alive.max = Some(v),
+                _ => return None,
+            }
+        }
+        Some(keep_alive)
+    }
+}
+
 #[cfg(feature = "async")]
 struct AsyncConnectionState {
     write: AsyncMutex<WriteHalf<AsyncHttpStream>>,
@@ -265,27 +540,43 @@ impl AsyncConnection {
     /// Creates a new `AsyncConnection`.
     pub(crate) async fn new(
         params: ConnectionParams<'_>,
-        timeout_at: Option<Instant>,
+        timeouts: Timeouts,
     ) -> Result<AsyncConnection, Error> {
         let future = async move {
+            #[cfg(unix)]
+            if let Some(path) = params.unix_socket {
+                if params.https {
+                    return Err(Error::HttpsOverUnixSocket);
+                }
+                return Ok(AsyncHttpStream::Unix(AsyncUnixStream::connect(path).await?));
+            }
+
             let socket = Self::connect(params).await?;
 
             if params.https {
-                #[cfg(not(feature = "tokio-rustls"))]
+                #[cfg(not(any(
+                    feature = "tokio-rustls",
+                    all(feature = "tokio-native-tls", not(feature = "rustls"))
+                )))]
                 return Err(Error::HttpsFeatureNotEnabled);
-                #[cfg(feature = "tokio-rustls")]
-                rustls_stream::wrap_async_stream(socket, params.host).await
+                #[cfg(any(
+                    feature = "tokio-rustls",
+                    all(feature = "tokio-native-tls", not(feature = "rustls"))
+                ))]
+                rustls_stream::wrap_async_stream(socket, params.host, params.tls).await
             } else {
                 Ok(AsyncHttpStream::Unsecured(socket))
             }
         };
-        let stream = if let Some(timeout_at) = timeout_at {
-            tokio::time::timeout_at(timeout_at.into(), future)
+        let connect_at = timeouts.connect_deadline();
+        let stream = if let Some(connect_at) = connect_at {
+            tokio::time::timeout_at(connect_at.into(), future)
                 .await
-                .unwrap_or(Err(Error::IoError(timeout_err())))?
+                .unwrap_or(Err(Error::IoError(timeout_err())))
         } else {
-            future.await?
+            future.await
         };
+        let stream = stream.map_err(|e| timeouts.connect_error(e, connect_at))?;
         let (read, write) = tokio::io::split(stream);
 
         Ok(AsyncConnection(Mutex::new(Arc::new(AsyncConnectionState {
@@ -340,6 +631,11 @@ impl AsyncConnection {
                 // do proxy things
                 let mut tcp = Self::tcp_connect(&proxy.server, proxy.port).await?;
 
+                if proxy.kind == ProxyKind::Socks5 {
+                    proxy.socks5_handshake_async(&mut tcp, params.host, params.port).await?;
+                    return Ok(tcp);
+                }
+
                 let proxy_request = proxy.connect(params.host, params.port);
                 tcp.write_all(proxy_request.as_bytes()).await?;
                 tcp.flush().await?;
@@ -447,7 +743,7 @@ impl AsyncConnection {
                 };
                 (_internal) => {
                     let new_connection =
-                        AsyncConnection::new(request.connection_params(), request.timeout_at)
+                        AsyncConnection::new(request.connection_params(), request.timeouts())
                             .await?;
                     *self.0.lock().unwrap() = Arc::clone(&*new_connection.0.lock().unwrap());
                     core::mem::drop(read);
@@ -484,18 +780,14 @@ impl AsyncConnection {
                 );
                 this_request.id = Some(request_id);
                 let write_res =
-                    Self::timeout(request.timeout_at, write.write_all(&request.as_bytes())).await;
+                    Self::timeout(request.timeout_at, request.write_to_async(&mut *write)).await;
                 match write_res {
-                    Err(e) => {
+                    Err(e) | Ok(Err(e)) => {
                         // If we failed to write the request, mark the socket as dead for future
                         // requests.
                         conn.next_request_id.store(usize::MAX, Ordering::Release);
                         return Err(e);
                     }
-                    Ok(Err(ioe)) => {
-                        conn.next_request_id.store(usize::MAX, Ordering::Release);
-                        return Err(Error::IoError(ioe));
-                    }
                     Ok(Ok(())) => {}
                 }
             }
@@ -548,7 +840,7 @@ impl AsyncConnection {
                 );
 
                 let response = Response::create_async(
-                    &mut *read,
+                    IdleTimeout::new(&mut *read, request.config.read_timeout),
                     request.config.method == Method::Head,
                     request.config.max_headers_size,
                     request.config.max_status_line_len,
@@ -570,41 +862,20 @@ impl AsyncConnection {
                 }
 
                 if let Some(header) = response.headers.get("keep-alive") {
-                    for param in header.split(',') {
-                        if let Some((k, v)) = param.trim().split_once('=') {
-                            if let Ok(v) = v.parse::<usize>() {
-                                match k.trim() {
-                                    "timeout" => {
-                                        let timeout_secs = (v as u64).saturating_sub(1);
-                                        *conn.socket_new_requests_timeout.lock().unwrap() =
-                                            Instant::now()
-                                                .checked_add(Duration::from_secs(timeout_secs))
-                                                .unwrap_or(Instant::now());
-                                    }
-                                    "max" => {
-                                        conn.next_request_id.fetch_max(
-                                            usize::MAX.saturating_sub(v),
-                                            Ordering::AcqRel,
-                                        );
-                                    }
-                                    _ => {
-                                        // If we can't parse the keep-alive header, don't send any
-                                        // new requests over this socket, but don't give up on
-                                        // reading pending responses.
-                                        conn.next_request_id.store(usize::MAX, Ordering::Release);
-                                    }
-                                }
-                            } else {
-                                // If we can't parse the keep-alive header, don't send any new
-                                // requests over this socket, but don't give up on reading pending
-                                // responses.
-                                conn.next_request_id.store(usize::MAX, Ordering::Release);
+                    match KeepAlive::parse(header) {
+                        Some(keep_alive) => {
+                            if let Some(timeout) = keep_alive.timeout {
+                                *conn.socket_new_requests_timeout.lock().unwrap() =
+                                    Instant::now().checked_add(timeout).unwrap_or(Instant::now());
+                            }
+                            if let Some(max) = keep_alive.max {
+                                conn.next_request_id
+                                    .fetch_max(usize::MAX.saturating_sub(max), Ordering::AcqRel);
                             }
-                        } else {
-                            // If we can't parse the keep-alive header, don't send any new requests
-                            // over this socket, but don't give up on reading pending responses.
-                            conn.next_request_id.store(usize::MAX, Ordering::Release);
                         }
+                        // If we can't parse the keep-alive header, don't send any new requests
+                        // over this socket, but don't give up on reading pending responses.
+                        None => conn.next_request_id.store(usize::MAX, Ordering::Release),
                     }
                 }
 
@@ -648,35 +919,81 @@ impl Connection {
     /// for specifics about *what* is being sent.
     pub(crate) fn new(
         params: ConnectionParams<'_>,
-        timeout_at: Option<Instant>,
+        timeouts: Timeouts,
     ) -> Result<Connection, Error> {
-        let socket = Self::connect(params, timeout_at)?;
+        let connect_at = timeouts.connect_deadline();
+        let stream = Self::connect_stream(params, timeouts, connect_at)
+            .map_err(|e| timeouts.connect_error(e, connect_at))?;
+        Ok(Connection { stream })
+    }
 
-        let stream = if params.https {
-            #[cfg(not(feature = "rustls"))]
+    fn connect_stream(
+        params: ConnectionParams<'_>,
+        timeouts: Timeouts,
+        connect_at: Option<Instant>,
+    ) -> Result<HttpStream, Error> {
+        #[cfg(unix)]
+        if let Some(path) = params.unix_socket {
+            if params.https {
+                return Err(Error::HttpsOverUnixSocket);
+            }
+            return Ok(HttpStream::Unix(UnixStream::connect(path)?, timeouts, None));
+        }
+
+        let socket = Self::connect(params, connect_at)?;
+
+        if params.https {
+            #[cfg(not(any(feature = "rustls", feature = "native-tls")))]
             return Err(Error::HttpsFeatureNotEnabled);
-            #[cfg(feature = "rustls")]
+            #[cfg(any(feature = "rustls", feature = "native-tls"))]
             {
-                let tls = rustls_stream::wrap_stream(socket, params.host)?;
-                HttpStream::Secured(Box::new(tls), timeout_at)
+                let tls = rustls_stream::wrap_stream(socket, params.host, params.tls)?;
+                Ok(HttpStream::Secured(Box::new(tls), timeouts))
             }
         } else {
-            HttpStream::create_unsecured(socket, timeout_at)
-        };
+            Ok(HttpStream::create_unsecured(socket, timeouts))
+        }
+    }
 
-        Ok(Connection { stream })
+    /// Resolves `host`, giving up at `timeout_at`.
+    ///
+    /// [ToSocketAddrs] can't time out, so with a timeout the lookup of a name is run on a new
+    /// thread, one per lookup, which keeps running after the timeout until the system resolver
+    /// gives up. IP addresses need no lookup and are returned directly.
+    fn lookup_host(
+        host: &str,
+        port: u16,
+        timeout_at: Option<Instant>,
+    ) -> Result<Vec<SocketAddr>, Error> {
+        let timeout = match timeout_at_to_duration(timeout_at)? {
+            Some(_) if host.parse::<IpAddr>().is_ok() =>
+                return Ok((host, port).to_socket_addrs()?.collect()),
+            Some(timeout) => timeout,
+            None => return Ok((host, port).to_socket_addrs()?.collect()),
+        };
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let host = host.to_string();
+        std::thread::spawn(move || {
+            let _ = sender.send((host.as_str(), port).to_socket_addrs().map(Iterator::collect));
+        });
+        match receiver.recv_timeout(timeout) {
+            Ok(addrs) => Ok(addrs?),
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => Err(Error::IoError(timeout_err())),
+            Err(std::sync::mpsc::RecvTimeoutError::Disconnected) =>
+                Err(Error::Other("host lookup paniced")),
+        }
     }
 
     fn tcp_connect(host: &str, port: u16, timeout_at: Option<Instant>) -> Result<TcpStream, Error> {
         #[cfg(feature = "log")]
         log::trace!("Looking up host {host}");
 
-        let addrs = (host, port).to_socket_addrs().map_err(Error::IoError)?;
+        let addrs = Self::lookup_host(host, port, timeout_at)?;
         let addrs_count = addrs.len();
 
         // Try all resolved addresses. Return the first one to which we could connect. If all
         // failed return the last error encountered.
-        for (i, addr) in addrs.enumerate() {
+        for (i, addr) in addrs.into_iter().enumerate() {
             #[cfg(feature = "log")]
             log::trace!("Attempting to connect to {addr} for {host}");
 
@@ -690,6 +1007,11 @@ impl Connection {
                 Ok(s) => {
                     #[cfg(feature = "log")]
                     log::trace!("Connected to {addr} for {host}");
+                    // Bounds the proxy and TLS handshakes, reads and writes of the request set
+                    // their own timeouts.
+                    let timeout = timeout_at_to_duration(timeout_at)?;
+                    s.set_read_timeout(timeout)?;
+                    s.set_write_timeout(timeout)?;
                     return Ok(s);
                 }
                 Err(e) =>
@@ -713,6 +1035,11 @@ impl Connection {
                 // do proxy things
                 let mut tcp = Self::tcp_connect(&proxy.server, proxy.port, timeout_at)?;
 
+                if proxy.kind == ProxyKind::Socks5 {
+                    proxy.socks5_handshake(&mut tcp, params.host, params.port)?;
+                    return Ok(tcp);
+                }
+
                 write!(tcp, "{}", proxy.connect(params.host, params.port))?;
                 tcp.flush()?;
 
@@ -755,13 +1082,14 @@ impl Connection {
             // Send request
             #[cfg(feature = "log")]
             log::trace!("Writing HTTP request.");
-            self.stream.write_all(&request.as_bytes())?;
+            request.write_to(&mut self.stream)?;
 
             // Receive response
             #[cfg(feature = "log")]
             log::trace!("Reading HTTP response.");
             let response = ResponseLazy::from_stream(
                 self.stream,
+                request.config.method == Method::Head,
                 request.config.max_headers_size,
                 request.config.max_status_line_len,
                 request.config.max_body_size,
@@ -769,6 +1097,135 @@ impl Connection {
             handle_redirects(request, response)
         })
     }
+
+    /// Returns whether the connection can't be used anymore, see [`HttpStream::is_stale`].
+    pub(crate) fn is_stale(&self) -> bool { self.stream.is_stale() }
+
+    /// Sends the [`Request`](struct.Request.html) and reads the whole response, returning the
+    /// connection too unless the server closes it.
+    ///
+    /// Redirects are followed on new connections, which are not kept.
+    pub(crate) fn send_keep_alive(mut self, request: ParsedRequest) -> Result<Sent, Error> {
+        enforce_timeout(request.timeout_at, move || {
+            self.stream.set_timeouts(request.timeouts());
+
+            #[cfg(feature = "log")]
+            log::trace!("Writing HTTP request.");
+            let mut writer = CountingWriter { inner: &mut self.stream, written: 0 };
+            let result = request.write_to(&mut writer);
+            let sent_nothing = writer.written == 0;
+            let written = match result {
+                Err(Error::IoError(e)) => Err(e),
+                Err(e) => return Err(e),
+                Ok(()) => self.stream.wait_for_response(),
+            };
+            if let Err(e) = written {
+                // The server may have acted on a request it received before closing the
+                // connection, so only those it can't have read or that are idempotent are sent
+                // again, see RFC 9112 section 9.3.1.
+                let replayable = request.config.body.as_ref().map_or(true, Body::is_replayable)
+                    && (sent_nothing || request.config.method.is_idempotent());
+                return match e.kind() {
+                    io::ErrorKind::BrokenPipe
+                    | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::UnexpectedEof
+                        if replayable =>
+                        Ok(Sent::Closed(Box::new(request), e)),
+                    _ => Err(e.into()),
+                };
+            }
+
+            #[cfg(feature = "log")]
+            log::trace!("Reading HTTP response.");
+            let is_head = request.config.method == Method::Head;
+            let response = ResponseLazy::from_stream(
+                self.stream,
+                is_head,
+                request.config.max_headers_size,
+                request.config.max_status_line_len,
+                request.config.max_body_size,
+            )?;
+            let (mut response, stream) =
+                Response::create_keeping_stream(response, is_head, request.config.max_body_size)?;
+            let kept_alive =
+                stream.and_then(|stream| KeptAlive::new(Connection { stream }, &response.headers));
+
+            let status_code = response.status_code;
+            let url = response.headers.get("location");
+            match get_redirect(request, status_code, url) {
+                NextHop::Redirect(request) => {
+                    let (request, _) = request?;
+                    let is_head = request.config.method == Method::Head;
+                    let max_body_size = request.config.max_body_size;
+                    let response =
+                        Connection::new(request.connection_params(), request.timeouts())?
+                            .send(request)?;
+                    Ok(Sent::Response(
+                        Response::create(response, is_head, max_body_size)?,
+                        kept_alive,
+                    ))
+                }
+                NextHop::Destination(request) => {
+                    let dst_url = request.url;
+                    dst_url.write_base_url_to(&mut response.url).unwrap();
+                    dst_url.write_resource_to(&mut response.url).unwrap();
+                    Ok(Sent::Response(response, kept_alive))
+                }
+            }
+        })
+    }
+}
+
+/// A connection the server keeps open after a response, to send further requests on.
+pub(crate) struct KeptAlive {
+    pub(crate) connection: Connection,
+    /// When the server may close the connection, as announced in its `Keep-Alive` header.
+    pub(crate) expires_at: Option<Instant>,
+}
+
+impl KeptAlive {
+    /// Returns `None` if the response headers don't allow further requests on the connection.
+    fn new(connection: Connection, headers: &HeaderMap) -> Option<KeptAlive> {
+        // HTTP/1.1 connections are persistent unless the server says otherwise.
+        if headers.get("connection").is_some_and(|value| value.eq_ignore_ascii_case("close")) {
+            return None;
+        }
+        let keep_alive = match headers.get("keep-alive") {
+            Some(header) => KeepAlive::parse(header)?,
+            None => KeepAlive::default(),
+        };
+        if keep_alive.max == Some(0) {
+            return None;
+        }
+        let expires_at = keep_alive.timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+        Some(KeptAlive { connection, expires_at })
+    }
+}
+
+/// The outcome of sending a request with [`Connection::send_keep_alive`].
+pub(crate) enum Sent {
+    /// The response, and the connection unless the server closes it.
+    Response(Response, Option<KeptAlive>),
+    /// The server closed the connection before answering, and the request can be sent again
+    /// because the server didn't receive any of it or its method is idempotent.
+    Closed(Box<ParsedRequest>, io::Error),
+}
+
+/// Counts the bytes written to the stream, to know whether any of a request was sent.
+struct CountingWriter<'a, W> {
+    inner: &'a mut W,
+    written: usize,
+}
+
+impl<W: Write> Write for CountingWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.written += written;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> { self.inner.flush() }
 }
 
 fn handle_redirects(
@@ -780,7 +1237,7 @@ fn handle_redirects(
     match get_redirect(request, status_code, url) {
         NextHop::Redirect(request) => {
             let (request, _) = request?;
-            Connection::new(request.connection_params(), request.timeout_at)?.send(request)
+            Connection::new(request.connection_params(), request.timeouts())?.send(request)
         }
         NextHop::Destination(request) => {
             let dst_url = request.url;
@@ -806,7 +1263,7 @@ async fn async_handle_redirects(
             let new_connection;
             if needs_new_connection {
                 new_connection =
-                    AsyncConnection::new(request.connection_params(), request.timeout_at).await?;
+                    AsyncConnection::new(request.connection_params(), request.timeouts()).await?;
                 connection = &new_connection;
             }
             connection.send(request).await
@@ -850,10 +1307,19 @@ macro_rules! redirect_utils {
                                 match request.config.method {
                                     Method::Post | Method::Put | Method::Delete => {
                                         request.config.method = Method::Get;
+                                        // A streamed body was sent already, the GET goes without.
+                                        if let Some(Body::Stream(_)) = request.config.body {
+                                            request.config.body = None;
+                                            request.config.headers.remove("Content-Length");
+                                            request.config.headers.remove("Transfer-Encoding");
+                                        }
                                     }
                                     _ => {}
                                 }
                             }
+                            if !request.config.body.as_ref().map_or(true, Body::is_replayable) {
+                                return $NextHop::Redirect(Err(Error::BodyNotReplayable));
+                            }
 
                             let needs_new_conn = previous_params != request.connection_params();
                             $NextHop::Redirect(Ok((request, needs_new_conn)))
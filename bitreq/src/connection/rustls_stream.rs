@@ -7,12 +7,21 @@ use alloc::sync::Arc;
 use core::convert::TryFrom;
 use std::io;
 use std::net::TcpStream;
+#[cfg(feature = "rustls")]
+use std::sync::Mutex;
 use std::sync::OnceLock;
+#[cfg(feature = "rustls")]
+use std::time::SystemTime;
 
 #[cfg(all(feature = "native-tls", not(feature = "rustls")))]
 use native_tls::{HandshakeError, TlsConnector, TlsStream};
 #[cfg(feature = "rustls")]
-use rustls::{self, ClientConfig, ClientConnection, RootCertStore, ServerName, StreamOwned};
+use rustls::client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+#[cfg(feature = "rustls")]
+use rustls::{
+    self, Certificate, ClientConfig, ClientConnection, PrivateKey, RootCertStore, ServerName,
+    StreamOwned,
+};
 #[cfg(all(feature = "native-tls", not(feature = "rustls"), feature = "tokio-native-tls"))]
 use tokio_native_tls::TlsConnector as AsyncTlsConnector;
 #[cfg(feature = "tokio-rustls")]
@@ -24,7 +33,7 @@ use webpki_roots::TLS_SERVER_ROOTS;
 use super::{AsyncHttpStream, AsyncTcpStream};
 #[cfg(all(feature = "native-tls", not(feature = "rustls"), feature = "tokio-native-tls"))]
 use super::{AsyncHttpStream, AsyncTcpStream};
-use crate::Error;
+use crate::{tls, Error, TlsConfig};
 
 #[cfg(feature = "rustls")]
 pub type SecuredStream = StreamOwned<ClientConnection, TcpStream>;
@@ -32,8 +41,25 @@ pub type SecuredStream = StreamOwned<ClientConnection, TcpStream>;
 #[cfg(feature = "rustls")]
 static CONFIG: OnceLock<Arc<ClientConfig>> = OnceLock::new();
 
+/// The client configs built for [`TlsConfig`]s, the most recently used last.
 #[cfg(feature = "rustls")]
-fn build_client_config() -> Arc<ClientConfig> {
+static CONFIGS: Mutex<Vec<(TlsConfig, Arc<ClientConfig>)>> = Mutex::new(Vec::new());
+
+/// How many client configs built for [`TlsConfig`]s are kept.
+#[cfg(feature = "rustls")]
+const MAX_CACHED_CONFIGS: usize = 16;
+
+#[cfg(feature = "rustls")]
+static BUILT_IN_ROOTS: OnceLock<RootCertStore> = OnceLock::new();
+
+/// Returns the built-in root certificates, loaded once as reading those of the OS is slow.
+#[cfg(feature = "rustls")]
+fn built_in_root_certificates() -> RootCertStore {
+    BUILT_IN_ROOTS.get_or_init(load_built_in_root_certificates).clone()
+}
+
+#[cfg(feature = "rustls")]
+fn load_built_in_root_certificates() -> RootCertStore {
     let mut root_certificates = RootCertStore::empty();
 
     // Try to load native certs
@@ -56,27 +82,131 @@ fn build_client_config() -> Arc<ClientConfig> {
         )
     }));
 
+    root_certificates
+}
+
+#[cfg(feature = "rustls")]
+fn build_client_config() -> Arc<ClientConfig> {
     let config = ClientConfig::builder()
         .with_safe_defaults()
-        .with_root_certificates(root_certificates)
+        .with_root_certificates(built_in_root_certificates())
         .with_no_client_auth();
     Arc::new(config)
 }
 
+/// Verifies server certificates as set by a [`TlsConfig`].
+#[cfg(feature = "rustls")]
+struct Verifier {
+    /// `None` to accept any certificate.
+    webpki: Option<WebPkiVerifier>,
+    pinned_public_keys: Vec<Vec<u8>>,
+}
+
 #[cfg(feature = "rustls")]
-pub(super) fn wrap_stream(tcp: TcpStream, host: &str) -> Result<SecuredStream, Error> {
+impl ServerCertVerifier for Verifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if let Some(ref webpki) = self.webpki {
+            webpki.verify_server_cert(
+                end_entity,
+                intermediates,
+                server_name,
+                scts,
+                ocsp_response,
+                now,
+            )?;
+        }
+        if !self.pinned_public_keys.is_empty()
+            && !tls::is_pinned(&end_entity.0, &self.pinned_public_keys)
+        {
+            return Err(rustls::Error::General(PUBLIC_KEY_NOT_PINNED.to_string()));
+        }
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+/// Returns the client config for `tls`, built once and shared by the connections using it.
+#[cfg(feature = "rustls")]
+fn client_config(tls: Option<&TlsConfig>) -> Result<Arc<ClientConfig>, Error> {
+    let tls = match tls {
+        Some(tls) => tls,
+        None => return Ok(CONFIG.get_or_init(build_client_config).clone()),
+    };
+
+    let mut configs = CONFIGS.lock().unwrap();
+    if let Some(position) = configs.iter().position(|(cached, _)| cached == tls) {
+        let entry = configs.remove(position);
+        let config = entry.1.clone();
+        configs.push(entry);
+        return Ok(config);
+    }
+    let config = build_tls_client_config(tls)?;
+    if configs.len() >= MAX_CACHED_CONFIGS {
+        configs.remove(0);
+    }
+    configs.push((tls.clone(), config.clone()));
+    Ok(config)
+}
+
+#[cfg(feature = "rustls")]
+fn build_tls_client_config(tls: &TlsConfig) -> Result<Arc<ClientConfig>, Error> {
+    let mut root_certificates =
+        if tls.built_in_roots { built_in_root_certificates() } else { RootCertStore::empty() };
+    for certificate in &tls.root_certificates {
+        root_certificates
+            .add(&Certificate(certificate.clone()))
+            .map_err(Error::RustlsCreateConnection)?;
+    }
+    let verifier = Verifier {
+        webpki: (!tls.accept_invalid_certs).then(|| WebPkiVerifier::new(root_certificates, None)),
+        pinned_public_keys: tls.pinned_public_keys.clone(),
+    };
+
+    let config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(verifier));
+    let config = match tls.identity {
+        Some(ref identity) => config
+            .with_client_auth_cert(
+                identity.certificates.iter().cloned().map(Certificate).collect(),
+                PrivateKey(identity.key.clone()),
+            )
+            .map_err(Error::RustlsCreateConnection)?,
+        None => config.with_no_client_auth(),
+    };
+    Ok(Arc::new(config))
+}
+
+#[cfg(feature = "rustls")]
+pub(super) fn wrap_stream(
+    tcp: TcpStream,
+    host: &str,
+    tls: Option<&TlsConfig>,
+) -> Result<SecuredStream, Error> {
     #[cfg(feature = "log")]
     log::trace!("Setting up TLS parameters for {host}.");
     let dns_name = match ServerName::try_from(host) {
         Ok(result) => result,
         Err(err) => return Err(Error::IoError(io::Error::new(io::ErrorKind::Other, err))),
     };
-    let sess = ClientConnection::new(CONFIG.get_or_init(build_client_config).clone(), dns_name)
+    let sess = ClientConnection::new(client_config(tls)?, dns_name)
         .map_err(Error::RustlsCreateConnection)?;
 
     #[cfg(feature = "log")]
     log::trace!("Establishing TLS session to {host}.");
-    Ok(StreamOwned::new(sess, tcp))
+    let mut stream = StreamOwned::new(sess, tcp);
+    // Handshake now rather than on the first write, while the connect timeout applies.
+    while stream.conn.is_handshaking() {
+        stream.conn.complete_io(&mut stream.sock)?;
+    }
+    Ok(stream)
 }
 
 // Async rustls TLS implementation
@@ -88,6 +218,7 @@ pub type AsyncSecuredStream = TlsStream<tokio::net::TcpStream>;
 pub(super) async fn wrap_async_stream(
     tcp: AsyncTcpStream,
     host: &str,
+    tls: Option<&TlsConfig>,
 ) -> Result<AsyncHttpStream, Error> {
     #[cfg(feature = "log")]
     log::trace!("Setting up TLS parameters for {host}.");
@@ -96,7 +227,7 @@ pub(super) async fn wrap_async_stream(
         Err(err) => return Err(Error::IoError(io::Error::new(io::ErrorKind::Other, err))),
     };
 
-    let connector = TlsConnector::from(CONFIG.get_or_init(build_client_config).clone());
+    let connector = TlsConnector::from(client_config(tls)?);
 
     #[cfg(feature = "log")]
     log::trace!("Establishing TLS session to {host}.");
@@ -110,12 +241,12 @@ pub(super) async fn wrap_async_stream(
 pub type SecuredStream = TlsStream<TcpStream>;
 
 #[cfg(all(feature = "native-tls", not(feature = "rustls")))]
-static CONNECTOR: OnceLock<Result<TlsConnector, Error>> = OnceLock::new();
+static CONNECTOR: OnceLock<Option<TlsConnector>> = OnceLock::new();
 
 #[cfg(all(feature = "native-tls", not(feature = "rustls")))]
 fn native_tls_err<S>(e: HandshakeError<S>) -> Error {
     match e {
-        HandshakeError::Failure(e) => Error::NativeTlsError(e),
+        HandshakeError::Failure(e) => Error::NativeTlsCreateConnection(e),
         HandshakeError::WouldBlock(_) => {
             debug_assert!(false, "We shouldn't hit a blocking error");
             Error::Other("Got a WouldBlock error from native-tls")
@@ -124,23 +255,85 @@ fn native_tls_err<S>(e: HandshakeError<S>) -> Error {
 }
 
 #[cfg(all(feature = "native-tls", not(feature = "rustls")))]
-fn build_tls_connector() -> Result<TlsConnector, Error> {
-    TlsConnector::builder().build().map_err(Error::NativeTlsError)
+fn build_tls_connector(tls: Option<&TlsConfig>) -> Result<TlsConnector, Error> {
+    let mut builder = TlsConnector::builder();
+    if let Some(tls) = tls {
+        builder.disable_built_in_roots(!tls.built_in_roots);
+        for certificate in &tls.root_certificates {
+            let certificate = native_tls::Certificate::from_der(certificate)
+                .map_err(Error::NativeTlsCreateConnection)?;
+            builder.add_root_certificate(certificate);
+        }
+        if let Some(ref identity) = tls.identity {
+            let certificates = identity
+                .certificates
+                .iter()
+                .map(|certificate| tls::pem_encode(certificate, "CERTIFICATE"))
+                .collect::<String>();
+            let key = tls::pem_encode(&identity.key, "PRIVATE KEY");
+            let identity =
+                native_tls::Identity::from_pkcs8(certificates.as_bytes(), key.as_bytes())
+                    .map_err(Error::NativeTlsCreateConnection)?;
+            builder.identity(identity);
+        }
+        builder.danger_accept_invalid_certs(tls.accept_invalid_certs);
+        builder.danger_accept_invalid_hostnames(tls.accept_invalid_certs);
+    }
+    builder.build().map_err(Error::NativeTlsCreateConnection)
+}
+
+/// Returns the connector for `tls`, the one for the default settings is shared.
+#[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+fn tls_connector(tls: Option<&TlsConfig>) -> Result<TlsConnector, Error> {
+    match tls {
+        // TODO: Use `get_or_try_init` once it is stable
+        // https://github.com/rust-lang/rust/issues/109737
+        None => match CONNECTOR.get_or_init(|| build_tls_connector(None).ok()) {
+            Some(connector) => Ok(connector.clone()),
+            // Building it again returns the error.
+            None => build_tls_connector(None),
+        },
+        Some(tls) => build_tls_connector(Some(tls)),
+    }
+}
+
+/// Checks that the server's certificate holds one of the pinned public keys, if any.
+#[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+fn verify_pinned<S: io::Read + io::Write>(
+    stream: &TlsStream<S>,
+    tls: Option<&TlsConfig>,
+) -> Result<(), Error> {
+    let pinned = match tls {
+        Some(tls) if !tls.pinned_public_keys.is_empty() => &tls.pinned_public_keys,
+        _ => return Ok(()),
+    };
+    let certificate = stream
+        .peer_certificate()
+        .and_then(|certificate| certificate.map(|c| c.to_der()).transpose())
+        .map_err(Error::NativeTlsCreateConnection)?;
+    match certificate {
+        Some(certificate) if tls::is_pinned(&certificate, pinned) => Ok(()),
+        _ => Err(Error::IoError(io::Error::new(io::ErrorKind::InvalidData, PUBLIC_KEY_NOT_PINNED))),
+    }
 }
 
 #[cfg(all(feature = "native-tls", not(feature = "rustls")))]
-pub(super) fn wrap_stream(tcp: TcpStream, host: &str) -> Result<SecuredStream, Error> {
+pub(super) fn wrap_stream(
+    tcp: TcpStream,
+    host: &str,
+    tls: Option<&TlsConfig>,
+) -> Result<SecuredStream, Error> {
     #[cfg(feature = "log")]
     log::trace!("Setting up TLS parameters for {host}.");
 
-    // TODO: Once we can `get_or_try_init`, so that instead
-    // https://github.com/rust-lang/rust/issues/109737
-    let connector = CONNECTOR.get_or_init(build_tls_connector)?;
+    let connector = tls_connector(tls)?;
 
     #[cfg(feature = "log")]
     log::trace!("Establishing TLS session to {host}.");
 
-    connector.connect(host, tcp).map_err(native_tls_err)
+    let stream = connector.connect(host, tcp).map_err(native_tls_err)?;
+    verify_pinned(&stream, tls)?;
+    Ok(stream)
 }
 
 #[cfg(all(feature = "native-tls", not(feature = "rustls"), feature = "tokio-native-tls"))]
@@ -150,18 +343,21 @@ pub type AsyncSecuredStream = tokio_native_tls::TlsStream<tokio::net::TcpStream>
 pub(super) async fn wrap_async_stream(
     tcp: AsyncTcpStream,
     host: &str,
+    tls: Option<&TlsConfig>,
 ) -> Result<AsyncHttpStream, Error> {
     #[cfg(feature = "log")]
     log::trace!("Setting up TLS parameters for {host}.");
 
-    // TODO: Once we can `get_or_try_init`, so that instead
-    // https://github.com/rust-lang/rust/issues/109737
-    let connector = AsyncTlsConnector::from(CONNECTOR.get_or_init(build_tls_connector)?.clone());
+    let connector = AsyncTlsConnector::from(tls_connector(tls)?);
 
     #[cfg(feature = "log")]
     log::trace!("Establishing TLS session to {host}.");
 
-    let tls = connector.connect(host, tcp).await.map_err(native_tls_err)?;
+    let stream = connector.connect(host, tcp).await.map_err(Error::NativeTlsCreateConnection)?;
+    verify_pinned(stream.get_ref(), tls)?;
 
-    Ok(AsyncHttpStream::Secured(Box::new(tls)))
+    Ok(AsyncHttpStream::Secured(Box::new(stream)))
 }
+
+/// The error when the server's public key is not one of those pinned.
+const PUBLIC_KEY_NOT_PINNED: &str = "the server's public key is not pinned";
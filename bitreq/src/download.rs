@@ -0,0 +1,365 @@
+//! Downloads of large files, resumed after interruptions and checked against a hash.
+
+use core::fmt;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::{Error, Request, ResponseLazy};
+
+mod sha256;
+
+use sha256::Sha256;
+
+/// The size of the buffer the body is copied to the file through.
+const COPY_BUFFER_LENGTH: usize = 64 * 1024;
+
+/// Streams the body of a request into a file.
+///
+/// The body is written to a `.part` file next to the destination, which is
+/// renamed to the destination once the whole body was received and its hash
+/// checked, so the destination only ever holds a complete file. When the
+/// connection drops, the download is resumed with a `Range` request for the
+/// missing bytes, and a `.part` file left by an earlier run is resumed the
+/// same way. So that only the version of the body it holds is resumed, the
+/// `ETag` or `Last-Modified` of that body is kept in a `.part.validator` file,
+/// without it the `.part` file is downloaded again from the start.
+///
+/// The request should be a `GET`. Its `Accept-Encoding`, `Range` and
+/// `If-Range` headers are overwritten, so that the bytes are received as they
+/// are stored on the server.
+///
+/// # Example
+///
+/// ```no_run
+/// # fn main() -> Result<(), bitreq::Error> {
+/// let expected = [0; 32]; // The SHA-256 hash published with the file.
+/// bitreq::Download::new(bitreq::get("http://example.com/big.tar.gz"), "big.tar.gz")
+///     .with_sha256(expected)
+///     .with_progress(|progress| {
+///         if let Some(total) = progress.total {
+///             println!("{}/{} bytes", progress.downloaded, total);
+///         }
+///     })
+///     .send()?;
+/// # Ok(()) }
+/// ```
+pub struct Download {
+    request: Request,
+    path: PathBuf,
+    sha256: Option<[u8; 32]>,
+    max_retries: u32,
+    progress: Option<Box<dyn FnMut(DownloadProgress)>>,
+}
+
+/// How far along a [`Download`] is, passed to its
+/// [`with_progress`](Download::with_progress) callback.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DownloadProgress {
+    /// How many bytes of the body are in the file.
+    pub downloaded: u64,
+    /// The size of the whole body, if the server told it.
+    pub total: Option<u64>,
+}
+
+/// The outcome of a request for the rest of the body.
+enum Attempt {
+    Complete,
+    /// The transfer stopped before the end of the body and can be resumed.
+    Interrupted(Error),
+}
+
+/// The file being written and what is known about its content.
+struct PartFile {
+    file: File,
+    hasher: Sha256,
+    downloaded: u64,
+    /// The `ETag` or `Last-Modified` of the body, to only resume the same version of it.
+    validator: Option<String>,
+    /// Where `validator` is kept for later runs.
+    validator_path: PathBuf,
+}
+
+impl Download {
+    /// Creates a download of the body of `request` into the file at `path`.
+    pub fn new<P: Into<PathBuf>>(request: Request, path: P) -> Download {
+        Download { request, path: path.into(), sha256: None, max_retries: 5, progress: None }
+    }
+
+    /// Checks the SHA-256 hash of the body once it was downloaded.
+    ///
+    /// If it doesn't match, [`send`](Download::send) fails with
+    /// [`Error::ChecksumMismatch`] and removes the `.part` file, so that the
+    /// next attempt starts over.
+    pub fn with_sha256(mut self, hash: [u8; 32]) -> Download {
+        self.sha256 = Some(hash);
+        self
+    }
+
+    /// Sets how many times in a row a transfer can fail before giving up.
+    ///
+    /// The count starts over each time some of the body was received. Defaults
+    /// to 5.
+    pub fn with_max_retries(mut self, max_retries: u32) -> Download {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Calls `progress` each time some of the body was written to the file,
+    /// and when a download starts or restarts.
+    pub fn with_progress<F: FnMut(DownloadProgress) + 'static>(mut self, progress: F) -> Download {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    /// Downloads the body, returning its length.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::UnexpectedStatusCode`] if the server doesn't answer
+    /// with the body, [`Error::ChecksumMismatch`] if its hash isn't the one
+    /// given to [`with_sha256`](Download::with_sha256), and the error of the
+    /// last transfer if it failed more times in a row than allowed with
+    /// [`with_max_retries`](Download::with_max_retries). Errors writing the
+    /// file are returned right away.
+    pub fn send(mut self) -> Result<u64, Error> {
+        let part_path = with_suffix(&self.path, ".part");
+        let mut part = PartFile::open(&part_path)?;
+
+        let mut failures = 0;
+        loop {
+            let downloaded = part.downloaded;
+            match self.attempt(&mut part)? {
+                Attempt::Complete => break,
+                Attempt::Interrupted(err) => {
+                    if part.downloaded > downloaded {
+                        failures = 0;
+                    }
+                    failures += 1;
+                    if failures > self.max_retries {
+                        return Err(err);
+                    }
+                    #[cfg(feature = "log")]
+                    log::debug!(
+                        "download of {} interrupted, retrying: {}",
+                        self.request.url(),
+                        err
+                    );
+                }
+            }
+        }
+
+        part.file.sync_all()?;
+        let PartFile { file, hasher, downloaded, validator_path, .. } = part;
+        drop(file);
+        if self.sha256.is_some_and(|expected| hasher.finish() != expected) {
+            fs::remove_file(&part_path)?;
+            remove_if_exists(&validator_path)?;
+            return Err(Error::ChecksumMismatch);
+        }
+        fs::rename(&part_path, &self.path)?;
+        remove_if_exists(&validator_path)?;
+        Ok(downloaded)
+    }
+
+    /// Requests the part of the body missing from the file and writes it.
+    fn attempt(&mut self, part: &mut PartFile) -> Result<Attempt, Error> {
+        let mut request = self.request.clone().with_header("Accept-Encoding", "identity");
+        if part.downloaded > 0 {
+            request = request.with_header("Range", format!("bytes={}-", part.downloaded));
+            if let Some(validator) = &part.validator {
+                request = request.with_header("If-Range", validator.clone());
+            }
+        }
+        let mut response = match request.send_lazy() {
+            Ok(response) => response,
+            Err(err) if is_transient(&err) => return Ok(Attempt::Interrupted(err)),
+            Err(err) => return Err(err),
+        };
+
+        let total = match response.status_code {
+            200 => {
+                // The server sends the whole body when it doesn't support ranges, or when the
+                // body changed since the `If-Range` validator.
+                part.restart()?;
+                header_u64(&response, "content-length")
+            }
+            206 if part.downloaded > 0 => match content_range(&response) {
+                Some((start, total)) if start == part.downloaded => total,
+                _ => {
+                    part.restart()?;
+                    return Ok(Attempt::Interrupted(invalid_data("unexpected Content-Range")));
+                }
+            },
+            416 if part.downloaded > 0 => {
+                // An earlier run may have stopped between receiving the last byte and the
+                // rename, otherwise the `.part` file is longer than the body.
+                if unsatisfied_range_length(&response) == Some(part.downloaded) {
+                    self.report(part.downloaded, Some(part.downloaded));
+                    return Ok(Attempt::Complete);
+                }
+                part.restart()?;
+                return Ok(Attempt::Interrupted(invalid_data("range not satisfiable")));
+            }
+            status_code => return Err(Error::UnexpectedStatusCode(status_code)),
+        };
+        part.set_validator(validator(&response))?;
+        self.report(part.downloaded, total);
+
+        let mut buffer = vec![0; COPY_BUFFER_LENGTH];
+        loop {
+            let length = match response.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(length) => length,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Ok(Attempt::Interrupted(err.into())),
+            };
+            part.write(&buffer[..length])?;
+            self.report(part.downloaded, total);
+        }
+
+        // The body of a response ends early when the connection is closed, which is only
+        // noticed by comparing with the length the server announced.
+        match total {
+            Some(total) if part.downloaded < total =>
+                Ok(Attempt::Interrupted(Error::IoError(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "connection closed before the end of the body",
+                )))),
+            _ => Ok(Attempt::Complete),
+        }
+    }
+
+    fn report(&mut self, downloaded: u64, total: Option<u64>) {
+        if let Some(progress) = &mut self.progress {
+            progress(DownloadProgress { downloaded, total });
+        }
+    }
+}
+
+impl fmt::Debug for Download {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Download")
+            .field("request", &self.request)
+            .field("path", &self.path)
+            .field("sha256", &self.sha256)
+            .field("max_retries", &self.max_retries)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PartFile {
+    /// Opens the `.part` file, hashing what an earlier run left in it.
+    ///
+    /// Without the validator of that content there is no telling whether the body changed
+    /// since, so it is emptied.
+    fn open(path: &Path) -> io::Result<PartFile> {
+        let validator_path = with_suffix(path, ".validator");
+        let validator = match fs::read_to_string(&validator_path) {
+            Ok(validator) => Some(validator),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err),
+        };
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(validator.is_none())
+            .open(path)?;
+        let mut hasher = Sha256::new();
+        let mut downloaded = 0;
+        let mut buffer = vec![0; COPY_BUFFER_LENGTH];
+        loop {
+            let length = match file.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(length) => length,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            hasher.update(&buffer[..length]);
+            downloaded += length as u64;
+        }
+        Ok(PartFile { file, hasher, downloaded, validator, validator_path })
+    }
+
+    /// Sets the validator of the body being written, keeping it for later runs.
+    fn set_validator(&mut self, validator: Option<String>) -> io::Result<()> {
+        if validator != self.validator {
+            match &validator {
+                Some(validator) => fs::write(&self.validator_path, validator)?,
+                None => remove_if_exists(&self.validator_path)?,
+            }
+            self.validator = validator;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.file.write_all(bytes)?;
+        self.hasher.update(bytes);
+        self.downloaded += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// Empties the file, to write the body from its start.
+    fn restart(&mut self) -> io::Result<()> {
+        if self.downloaded > 0 {
+            self.file.set_len(0)?;
+            self.file.seek(SeekFrom::Start(0))?;
+            self.hasher = Sha256::new();
+            self.downloaded = 0;
+        }
+        Ok(())
+    }
+}
+
+/// Returns `path` with `suffix` appended.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// Returns whether sending the request again might succeed.
+fn is_transient(err: &Error) -> bool {
+    matches!(err, Error::IoError(_) | Error::ConnectTimeout | Error::ReadTimeout)
+}
+
+fn invalid_data(message: &'static str) -> Error {
+    Error::IoError(io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+fn header_u64(response: &ResponseLazy, name: &str) -> Option<u64> {
+    response.headers.get(name)?.trim().parse().ok()
+}
+
+/// Parses the start and complete length of a `Content-Range: bytes <start>-<end>/<length>`
+/// header, see RFC 9110 section 14.4.
+fn content_range(response: &ResponseLazy) -> Option<(u64, Option<u64>)> {
+    let range = response.headers.get("content-range")?.trim().strip_prefix("bytes ")?;
+    let (range, length) = range.split_once('/')?;
+    let (start, _) = range.split_once('-')?;
+    let length = if length == "*" { None } else { Some(length.parse().ok()?) };
+    Some((start.parse().ok()?, length))
+}
+
+/// Parses the complete length of the `Content-Range: bytes */<length>` header of a `416`.
+fn unsatisfied_range_length(response: &ResponseLazy) -> Option<u64> {
+    response.headers.get("content-range")?.trim().strip_prefix("bytes */")?.parse().ok()
+}
+
+/// Returns the validator to send in `If-Range`, which must be a strong `ETag` or a date.
+fn validator(response: &ResponseLazy) -> Option<String> {
+    match response.headers.get("etag") {
+        Some(etag) if !etag.starts_with("W/") => Some(etag.clone()),
+        _ => response.headers.get("last-modified").cloned(),
+    }
+}
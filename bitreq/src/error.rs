@@ -26,8 +26,20 @@ pub enum Error {
     /// Ran into a native-tls error while creating the connection.
     NativeTlsCreateConnection(native_tls::Error),
     /// Ran into an IO problem while loading the response.
+    ///
+    /// Reaching the timeout of the whole request, set with
+    /// [Request::with_timeout](crate::request::Request::with_timeout), is an IO error of kind
+    /// [`TimedOut`](io::ErrorKind::TimedOut).
     #[cfg(feature = "std")]
     IoError(io::Error),
+    /// Connecting to the server took longer than
+    /// [Request::with_connect_timeout](crate::request::Request::with_connect_timeout).
+    #[cfg(feature = "std")]
+    ConnectTimeout,
+    /// The server sent nothing for longer than
+    /// [Request::with_read_timeout](crate::request::Request::with_read_timeout).
+    #[cfg(feature = "std")]
+    ReadTimeout,
     /// Couldn't parse the incoming chunk's length while receiving a
     /// response with the header `Transfer-Encoding: chunked`.
     MalformedChunkLength,
@@ -62,6 +74,12 @@ pub enum Error {
     /// `https://`), but the crate's `https` feature was not enabled,
     /// and as such, a connection cannot be made.
     HttpsFeatureNotEnabled,
+    /// Tried to send a secure request (ie. the url started with
+    /// `https://`) over a Unix domain socket set with
+    /// [Request::with_unix_socket](crate::request::Request::with_unix_socket),
+    /// which is not supported.
+    #[cfg(all(feature = "std", unix))]
+    HttpsOverUnixSocket,
     /// The provided proxy information was not properly formatted. See
     /// [Proxy](crate::Proxy) methods for the valid format.
     #[cfg(feature = "proxy")]
@@ -78,6 +96,29 @@ pub enum Error {
     /// The response body size surpasses
     /// [Request::with_max_body_size](crate::request::Request::with_max_body_size).
     BodyOverflow,
+    /// The PEM data given to [TlsConfig](crate::TlsConfig) is malformed
+    /// or holds no certificate or key.
+    #[cfg(all(feature = "std", any(feature = "rustls", feature = "native-tls")))]
+    MalformedPem,
+    /// The server answered a [Download](crate::Download) with a status
+    /// code other than `200 OK` or `206 Partial Content`.
+    #[cfg(feature = "std")]
+    UnexpectedStatusCode(i32),
+    /// The SHA-256 hash of a [Download](crate::Download) isn't the one
+    /// given to [Download::with_sha256](crate::Download::with_sha256).
+    #[cfg(feature = "std")]
+    ChecksumMismatch,
+    /// The request had to be sent again, to follow a redirect or on a new
+    /// connection, but its body was read from a stream set with
+    /// [Request::with_body_reader](crate::request::Request::with_body_reader),
+    /// which can only be sent once.
+    #[cfg(feature = "std")]
+    BodyNotReplayable,
+    /// The request's body was set with
+    /// [Request::with_body_async_reader](crate::request::Request::with_body_async_reader),
+    /// but it was sent with a blocking method.
+    #[cfg(feature = "async")]
+    AsyncBodyReader,
     // TODO: Uncomment these two for 3.0
     // /// The URL does not start with http:// or https://.
     // InvalidProtocol,
@@ -100,6 +141,10 @@ impl fmt::Display for Error {
             SerdeJsonError(err) => write!(f, "{}", err),
             #[cfg(feature = "std")]
             IoError(err) => write!(f, "{}", err),
+            #[cfg(feature = "std")]
+            ConnectTimeout => write!(f, "connecting to the server took longer than the connect timeout"),
+            #[cfg(feature = "std")]
+            ReadTimeout => write!(f, "the server sent nothing for longer than the read timeout"),
             InvalidUrl(err) => write!(f, "failed to parse given URL: {}", err),
             InvalidUtf8InBody(err) => write!(f, "{}", err),
             #[cfg(feature = "rustls")]
@@ -117,6 +162,8 @@ impl fmt::Display for Error {
             TooManyRedirections => write!(f, "too many redirections (over the max)"),
             InvalidUtf8InResponse => write!(f, "response contained invalid utf-8 where valid utf-8 was expected"),
             HttpsFeatureNotEnabled => write!(f, "request url contains https:// but the https feature is not enabled"),
+            #[cfg(all(feature = "std", unix))]
+            HttpsOverUnixSocket => write!(f, "request url contains https:// but https is not supported over unix sockets"),
             #[cfg(feature = "proxy")]
             BadProxy => write!(f, "the provided proxy information is malformed"),
             #[cfg(feature = "proxy")]
@@ -126,6 +173,16 @@ impl fmt::Display for Error {
             #[cfg(feature = "proxy")]
             InvalidProxyCreds => write!(f, "the provided proxy credentials are invalid"),
             BodyOverflow => write!(f, "the response body size surpassed max_body_size"),
+            #[cfg(all(feature = "std", any(feature = "rustls", feature = "native-tls")))]
+            MalformedPem => write!(f, "the provided PEM data is malformed or holds no certificate or key"),
+            #[cfg(feature = "std")]
+            UnexpectedStatusCode(status_code) => write!(f, "the server answered the download with status code {}", status_code),
+            #[cfg(feature = "std")]
+            ChecksumMismatch => write!(f, "the downloaded file's sha256 hash doesn't match the expected one"),
+            #[cfg(feature = "std")]
+            BodyNotReplayable => write!(f, "the request had to be sent again but its body was read from a stream"),
+            #[cfg(feature = "async")]
+            AsyncBodyReader => write!(f, "the request body is read from an async reader but the request was sent synchronously"),
             // TODO: Uncomment these two for 3.0
             // InvalidProtocol => write!(f, "the url does not start with http:// or https://"),
             // InvalidProtocolInRedirect => write!(f, "got redirected to an absolute url which does not start with http:// or https://"),
@@ -154,7 +211,14 @@ impl error::Error for Error {
 
 #[cfg(feature = "std")]
 impl From<io::Error> for Error {
-    fn from(other: io::Error) -> Error { Error::IoError(other) }
+    fn from(other: io::Error) -> Error {
+        // Timeouts are passed through `io::Read` as IO errors wrapping them.
+        match other.get_ref().and_then(|err| err.downcast_ref::<Error>()) {
+            Some(Error::ConnectTimeout) => Error::ConnectTimeout,
+            Some(Error::ReadTimeout) => Error::ReadTimeout,
+            _ => Error::IoError(other),
+        }
+    }
 }
 
 impl From<UrlParseError> for Error {
@@ -0,0 +1,191 @@
+//! HTTP header fields.
+
+use alloc::collections::BTreeMap;
+use core::{iter, slice};
+
+/// The header fields of a request or response, in the order they were added or received.
+///
+/// A field name may appear several times, as for `Set-Cookie` or `Via`. [`get`](HeaderMap::get)
+/// returns the last value of a repeated field, the one [`to_map`](HeaderMap::to_map) keeps, and
+/// [`get_all`](HeaderMap::get_all) returns all of them. Names are compared without regard to case,
+/// but are kept as given: the fields of a response have lowercase names.
+///
+/// # Example
+///
+/// ```no_run
+/// # fn main() -> Result<(), bitreq::Error> {
+/// let response = bitreq::get("http://example.com").send()?;
+/// if let Some(content_type) = response.headers.get("Content-Type") {
+///     println!("{}", content_type);
+/// }
+/// for cookie in response.headers.get_all("set-cookie") {
+///     println!("{}", cookie);
+/// }
+/// # Ok(()) }
+/// ```
+#[derive(Clone, Default, PartialEq, Eq, Hash, Debug)]
+pub struct HeaderMap {
+    fields: Vec<(String, String)>,
+}
+
+/// An iterator over the fields of a [`HeaderMap`], see [`HeaderMap::iter`].
+pub type Iter<'a> =
+    iter::Map<slice::Iter<'a, (String, String)>, fn(&(String, String)) -> (&String, &String)>;
+
+impl HeaderMap {
+    /// Creates an empty `HeaderMap`.
+    pub fn new() -> HeaderMap { HeaderMap::default() }
+
+    /// Returns the value of the last field named `name`.
+    pub fn get(&self, name: &str) -> Option<&String> {
+        self.fields.iter().rev().find(|(k, _)| k.eq_ignore_ascii_case(name)).map(|(_, v)| v)
+    }
+
+    /// Returns the values of all the fields named `name`, in order.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a String> + 'a {
+        self.fields.iter().filter(move |(k, _)| k.eq_ignore_ascii_case(name)).map(|(_, v)| v)
+    }
+
+    /// Returns whether there is a field named `name`.
+    pub fn contains_key(&self, name: &str) -> bool { self.get(name).is_some() }
+
+    /// Sets the value of the field named `name`, replacing all the fields of that name. Returns
+    /// the value of the last replaced field.
+    ///
+    /// The field keeps the position of the first replaced field, or is added last.
+    pub fn insert<K: Into<String>, V: Into<String>>(
+        &mut self,
+        name: K,
+        value: V,
+    ) -> Option<String> {
+        let name = name.into();
+        let first = match self.fields.iter().position(|(k, _)| k.eq_ignore_ascii_case(&name)) {
+            Some(first) => first,
+            None => {
+                self.fields.push((name, value.into()));
+                return None;
+            }
+        };
+        let mut old = core::mem::replace(&mut self.fields[first].1, value.into());
+        let mut i = first + 1;
+        while i < self.fields.len() {
+            if self.fields[i].0.eq_ignore_ascii_case(&name) {
+                old = self.fields.remove(i).1;
+            } else {
+                i += 1;
+            }
+        }
+        Some(old)
+    }
+
+    /// Adds a field after the others, keeping those of the same name.
+    pub fn append<K: Into<String>, V: Into<String>>(&mut self, name: K, value: V) {
+        self.fields.push((name.into(), value.into()));
+    }
+
+    /// Removes all the fields named `name`, returns the value of the last one.
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        let last = self.fields.iter().rposition(|(k, _)| k.eq_ignore_ascii_case(name))?;
+        let (_, value) = self.fields.remove(last);
+        self.fields.retain(|(k, _)| !k.eq_ignore_ascii_case(name));
+        Some(value)
+    }
+
+    /// Returns the fields as `(name, value)` pairs, in order.
+    pub fn iter(&self) -> Iter<'_> { self.fields.iter().map(|(k, v)| (k, v)) }
+
+    /// Returns the field names in order, repeated names included.
+    pub fn keys(&self) -> impl Iterator<Item = &String> { self.fields.iter().map(|(k, _)| k) }
+
+    /// Returns the number of fields, repeated names included.
+    pub fn len(&self) -> usize { self.fields.len() }
+
+    /// Returns whether there are no fields.
+    pub fn is_empty(&self) -> bool { self.fields.is_empty() }
+
+    /// Returns the fields as a map, like `headers` was before it became a `HeaderMap`.
+    ///
+    /// Of repeated fields only the last value is kept, as returned by [`get`](HeaderMap::get).
+    /// Names differing in case are different keys of the map.
+    pub fn to_map(&self) -> BTreeMap<String, String> { self.fields.iter().cloned().collect() }
+}
+
+impl<'a> IntoIterator for &'a HeaderMap {
+    type Item = (&'a String, &'a String);
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> { self.iter() }
+}
+
+impl IntoIterator for HeaderMap {
+    type Item = (String, String);
+    type IntoIter = alloc::vec::IntoIter<(String, String)>;
+
+    fn into_iter(self) -> Self::IntoIter { self.fields.into_iter() }
+}
+
+impl<K: Into<String>, V: Into<String>> Extend<(K, V)> for HeaderMap {
+    /// Appends the fields, keeping existing ones of the same names.
+    fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, fields: T) {
+        for (name, value) in fields {
+            self.append(name, value);
+        }
+    }
+}
+
+impl<K: Into<String>, V: Into<String>> FromIterator<(K, V)> for HeaderMap {
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(fields: T) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.extend(fields);
+        headers
+    }
+}
+
+impl From<BTreeMap<String, String>> for HeaderMap {
+    fn from(map: BTreeMap<String, String>) -> HeaderMap { map.into_iter().collect() }
+}
+
+impl From<HeaderMap> for BTreeMap<String, String> {
+    fn from(headers: HeaderMap) -> BTreeMap<String, String> { headers.fields.into_iter().collect() }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::collections::BTreeMap;
+
+    use super::HeaderMap;
+
+    #[test]
+    fn repeated_fields() {
+        let mut headers: HeaderMap =
+            [("set-cookie", "a=1"), ("Content-Type", "text/plain"), ("Set-Cookie", "b=2")]
+                .into_iter()
+                .collect();
+        assert_eq!(headers.get("SET-COOKIE").unwrap(), "b=2");
+        assert_eq!(headers.get_all("set-cookie").collect::<Vec<_>>(), ["a=1", "b=2"]);
+        assert_eq!(headers.len(), 3);
+
+        assert_eq!(headers.insert("set-cookie", "c=3").unwrap(), "b=2");
+        let fields: Vec<_> = headers.iter().collect();
+        assert_eq!(fields.len(), 2);
+        assert_eq!((fields[0].0.as_str(), fields[0].1.as_str()), ("set-cookie", "c=3"));
+        assert_eq!((fields[1].0.as_str(), fields[1].1.as_str()), ("Content-Type", "text/plain"));
+
+        headers.append("Set-Cookie", "d=4");
+        assert_eq!(headers.remove("set-cookie").unwrap(), "d=4");
+        assert!(!headers.contains_key("set-cookie"));
+        assert_eq!(headers.insert("content-type", "text/html").unwrap(), "text/plain");
+        assert_eq!(headers.keys().collect::<Vec<_>>(), ["Content-Type"]);
+    }
+
+    #[test]
+    fn duplicated_field() {
+        let headers: HeaderMap =
+            [("content-type", "text/plain"), ("via", "1.1 a"), ("content-type", "text/html")]
+                .into_iter()
+                .collect();
+        assert_eq!(headers.get("content-type").unwrap(), "text/html");
+        assert_eq!(headers.to_map()["content-type"], "text/html");
+        assert_eq!(BTreeMap::from(headers)["content-type"], "text/html");
+    }
+}
@@ -58,8 +58,8 @@
 //! [`send_lazy_async()`](struct.Request.html#method.send_lazy_async) methods
 //! that return futures for non-blocking operation.
 //!
-//! It also enables [`Client`](struct.Client.html) to reuse TCP connections
-//! across requests.
+//! It also enables [`Client`](struct.Client.html), which reuses TCP
+//! connections across blocking requests, to do so for async requests.
 //!
 //! ## `async-https` or `async-https-rustls`
 //!
@@ -81,9 +81,22 @@
 //! (provided by the `tokio` team) to provide HTTPS support for async
 //! connections.
 //!
+//! With any of the HTTPS features, [`TlsConfig`](struct.TlsConfig.html)
+//! sets the root certificates to trust, a client certificate for mutual
+//! TLS and public keys to pin.
+//!
 //! ## `proxy`
 //!
-//! This feature enables HTTP proxy support.
+//! This feature enables HTTP CONNECT and SOCKS5 proxy support.
+//!
+//! ## `compression`
+//!
+//! This feature uses the [`flate2`](https://crates.io/crates/flate2)
+//! crate to decode response bodies sent with a `gzip` or `deflate`
+//! `Content-Encoding`, and asks for them with an `Accept-Encoding`
+//! header. The limit set with
+//! [`with_max_body_size`](struct.Request.html#method.with_max_body_size)
+//! applies to the decoded body.
 //!
 //! # Examples
 //!
@@ -140,6 +153,10 @@
 //! # fn main() -> Result<(), Box<dyn std::error::Error>> { Ok(()) }
 //! ```
 //!
+//! Large bodies, like files, can be read while they are sent with
+//! [`with_body_reader`](struct.Request.html#method.with_body_reader)
+//! instead of being loaded in memory first.
+//!
 //! ## Headers (sending)
 //!
 //! To add a header, add `with_header("Key", "Value")` before
@@ -160,8 +177,9 @@
 //!
 //! Reading the headers sent by the servers is done via the
 //! [`headers`](struct.Response.html#structfield.headers) field of the
-//! [`Response`](struct.Response.html). Note: the header field names
-//! (that is, the *keys* of the `HashMap`) are all lowercase: this is
+//! [`Response`](struct.Response.html), a [`HeaderMap`] keeping repeated
+//! fields in the order they were received. Note: the header field names
+//! (that is, the *keys* of the `HeaderMap`) are all lowercase: this is
 //! because the names are case-insensitive according to the spec, and
 //! this unifies the casings for easier `get()`ing.
 //!
@@ -199,8 +217,10 @@
 //! `.with_proxy()` on your request.
 //!
 //! Supported proxy formats are `host:port` and
-//! `user:password@proxy:host`. Only HTTP CONNECT proxies are
-//! supported at this time.
+//! `user:password@proxy:host`. HTTP CONNECT proxies are created with
+//! [`Proxy::new_http`](struct.Proxy.html#method.new_http), SOCKS5 proxies
+//! such as Tor with
+//! [`Proxy::new_socks5`](struct.Proxy.html#method.new_socks5).
 //!
 //! ```no_run
 //! # #[cfg(feature = "std")]
@@ -218,6 +238,13 @@
 //! # fn main() -> Result<(), Box<dyn std::error::Error>> { Ok(()) }
 //! ```
 //!
+//! ## Downloads
+//!
+//! [`Download`] streams a large body into a file instead of memory,
+//! resumes the transfer with `Range` requests when the connection
+//! drops, and can check the SHA-256 hash of the file before moving it
+//! into place.
+//!
 //! # Timeouts
 //!
 //! By default, a request has no timeout. You can change this in two
@@ -241,6 +268,15 @@
 //!   ```
 //! If the timeout is set with `with_timeout`, the environment
 //! variable will be ignored.
+//!
+//! The timeout covers the whole request. To fail fast when a server
+//! can't be reached while still allowing slow responses, use
+//! [`with_connect_timeout`](struct.Request.html#method.with_connect_timeout),
+//! and to only give up on a server that stopped sending anything, use
+//! [`with_read_timeout`](struct.Request.html#method.with_read_timeout).
+//! These take a `Duration`, as does
+//! [`with_total_timeout`](struct.Request.html#method.with_total_timeout),
+//! and fail with [`Error::ConnectTimeout`] and [`Error::ReadTimeout`].
 
 #![cfg_attr(all(not(feature = "std"), not(test)), no_std)]
 #![deny(missing_docs)]
@@ -250,26 +286,44 @@
 
 extern crate alloc;
 
+#[cfg(feature = "std")]
+mod body;
 #[cfg(feature = "std")]
 mod client;
 #[cfg(feature = "std")]
 mod connection;
+#[cfg(feature = "std")]
+mod download;
 mod error;
+#[cfg(feature = "std")]
+mod header;
+#[cfg(feature = "std")]
+mod middleware;
 #[cfg(feature = "proxy")]
 mod proxy;
 #[cfg(feature = "std")]
 mod request;
 #[cfg(feature = "std")]
 mod response;
+#[cfg(all(feature = "std", any(feature = "rustls", feature = "native-tls")))]
+mod tls;
 mod url;
 
-#[cfg(feature = "async")]
+#[cfg(feature = "std")]
 pub use client::{Client, RequestExt};
+#[cfg(feature = "std")]
+pub use download::{Download, DownloadProgress};
 pub use error::*;
+#[cfg(feature = "std")]
+pub use header::HeaderMap;
+#[cfg(feature = "std")]
+pub use middleware::Middleware;
 #[cfg(feature = "proxy")]
 pub use proxy::*;
 #[cfg(feature = "std")]
 pub use request::*;
 #[cfg(feature = "std")]
 pub use response::{Response, ResponseLazy};
+#[cfg(all(feature = "std", any(feature = "rustls", feature = "native-tls")))]
+pub use tls::TlsConfig;
 pub use url::{ParseError as UrlParseError, Url};
@@ -0,0 +1,106 @@
+//! Hooks run around the requests sent by bitreq.
+
+use core::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::{Error, Request, Response};
+
+/// A hook run around requests, to log them, collect metrics, add headers or sign them.
+///
+/// Middlewares are added to a [`Client`](crate::Client) with
+/// [`Client::with_middleware`](crate::Client::with_middleware), or to a single request with
+/// [`Request::with_middleware`]. Those of the client run before those of the request, and each
+/// list runs in the order it was added in.
+///
+/// The hooks run once per call to `send`, around everything it does: redirects are followed and
+/// pipelined requests are queued after [`on_request`](Middleware::on_request), and
+/// [`on_response`](Middleware::on_response) sees the final response. The
+/// [`Response::url`](crate::Response::url) tells which URL it came from.
+///
+/// # Example
+///
+/// ```
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// use bitreq::{Error, Middleware, Request, Response};
+///
+/// struct Auth(String);
+///
+/// impl Middleware for Auth {
+///     fn on_request(&self, request: Request) -> Result<Request, Error> {
+///         Ok(request.with_header("Authorization", format!("Bearer {}", self.0)))
+///     }
+///
+///     fn on_response(&self, response: Result<&Response, &Error>, elapsed: Duration) {
+///         if let Ok(response) = response {
+///             println!("{} answered {} in {:?}", response.url, response.status_code, elapsed);
+///         }
+///     }
+/// }
+///
+/// let client = bitreq::Client::new(10).with_middleware(Arc::new(Auth("token".to_string())));
+/// ```
+pub trait Middleware: Send + Sync {
+    /// Called before the request is sent, returns the request to send instead.
+    ///
+    /// Returning an error aborts the request, the error is returned by `send` without calling
+    /// any [`on_response`](Middleware::on_response).
+    fn on_request(&self, request: Request) -> Result<Request, Error> { Ok(request) }
+
+    /// Called with the outcome of a request and how long it took, in the reverse order of
+    /// [`on_request`](Middleware::on_request).
+    ///
+    /// This is not called for [`Request::send_lazy`], as the response is only read later.
+    fn on_response(&self, response: Result<&Response, &Error>, elapsed: Duration) {
+        let _ = (response, elapsed);
+    }
+}
+
+/// The middlewares of a [`Request`], compared by identity.
+#[derive(Clone, Default)]
+pub(crate) struct Middlewares(pub(crate) Vec<Arc<dyn Middleware>>);
+
+impl Middlewares {
+    /// Runs [`Middleware::on_request`] of the middlewares of `request`, returns the request to send
+    /// and the middlewares to tell about the response.
+    pub(crate) fn start(mut request: Request) -> Result<(Request, Sending), Error> {
+        let middlewares = core::mem::take(&mut request.middlewares.0);
+        for middleware in &middlewares {
+            request = middleware.on_request(request)?;
+        }
+        Ok((request, Sending { middlewares, started_at: Instant::now() }))
+    }
+}
+
+impl PartialEq for Middlewares {
+    fn eq(&self, other: &Middlewares) -> bool {
+        self.0.len() == other.0.len() && self.0.iter().zip(&other.0).all(|(a, b)| Arc::ptr_eq(a, b))
+    }
+}
+
+impl Eq for Middlewares {}
+
+impl fmt::Debug for Middlewares {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Middlewares({})", self.0.len())
+    }
+}
+
+/// A request whose middlewares were run, waiting for the response.
+pub(crate) struct Sending {
+    middlewares: Vec<Arc<dyn Middleware>>,
+    started_at: Instant,
+}
+
+impl Sending {
+    /// Runs [`Middleware::on_response`] and passes `response` through.
+    pub(crate) fn finish(self, response: Result<Response, Error>) -> Result<Response, Error> {
+        let elapsed = self.started_at.elapsed();
+        for middleware in self.middlewares.iter().rev() {
+            middleware.on_response(response.as_ref(), elapsed);
+        }
+        response
+    }
+}
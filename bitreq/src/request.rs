@@ -1,4 +1,3 @@
-use alloc::collections::BTreeMap;
 use core::fmt;
 #[cfg(feature = "std")]
 use core::fmt::Write;
@@ -6,18 +5,33 @@ use core::time::Duration;
 #[cfg(feature = "std")]
 use std::env;
 #[cfg(feature = "std")]
+use std::io::Read;
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+#[cfg(feature = "std")]
+use std::sync::Arc;
+#[cfg(feature = "std")]
 use std::time::Instant;
 
+#[cfg(feature = "async")]
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+
+#[cfg(feature = "std")]
+use crate::body::{self, Body, BodyStream, Reader};
 #[cfg(feature = "async")]
 use crate::connection::AsyncConnection;
 #[cfg(feature = "std")]
-use crate::connection::Connection;
+use crate::connection::{Connection, Timeouts};
+#[cfg(feature = "std")]
+use crate::middleware::{Middleware, Middlewares};
 #[cfg(feature = "proxy")]
 use crate::proxy::Proxy;
+#[cfg(any(feature = "rustls", feature = "native-tls"))]
+use crate::tls::TlsConfig;
 #[cfg(feature = "std")]
 use crate::url::Url;
 #[cfg(feature = "std")]
-use crate::{Error, Response, ResponseLazy};
+use crate::{Error, HeaderMap, Response, ResponseLazy};
 
 /// A URL type for requests.
 pub type URL = String;
@@ -67,6 +81,22 @@ impl fmt::Display for Method {
     }
 }
 
+impl Method {
+    /// Returns whether sending a request with this method twice has the same effect as sending it
+    /// once, see RFC 9110 section 9.2.2.
+    pub(crate) fn is_idempotent(&self) -> bool {
+        matches!(
+            self,
+            Method::Get
+                | Method::Head
+                | Method::Put
+                | Method::Delete
+                | Method::Options
+                | Method::Trace
+        )
+    }
+}
+
 /// An HTTP request.
 ///
 /// Generally created by the [`bitreq::get`](fn.get.html)-style
@@ -87,9 +117,11 @@ pub struct Request {
     pub(crate) method: Method,
     url: URL,
     params: Vec<(String, String)>,
-    headers: BTreeMap<String, String>,
-    body: Option<Vec<u8>>,
-    timeout: Option<u64>,
+    pub(crate) headers: HeaderMap,
+    pub(crate) body: Option<Body>,
+    timeout: Option<Duration>,
+    pub(crate) connect_timeout: Option<Duration>,
+    pub(crate) read_timeout: Option<Duration>,
     pub(crate) pipelining: bool,
     pub(crate) max_headers_size: Option<usize>,
     pub(crate) max_status_line_len: Option<usize>,
@@ -97,6 +129,11 @@ pub struct Request {
     max_redirects: usize,
     #[cfg(feature = "proxy")]
     pub(crate) proxy: Option<Proxy>,
+    #[cfg(any(feature = "rustls", feature = "native-tls"))]
+    pub(crate) tls: Option<TlsConfig>,
+    #[cfg(unix)]
+    pub(crate) unix_socket: Option<PathBuf>,
+    pub(crate) middlewares: Middlewares,
 }
 
 impl Request {
@@ -112,9 +149,11 @@ impl Request {
             method,
             url: url.into(),
             params: Vec::new(),
-            headers: BTreeMap::new(),
+            headers: HeaderMap::new(),
             body: None,
             timeout: None,
+            connect_timeout: None,
+            read_timeout: None,
             pipelining: false,
             // Default matches chrome as of 2022-11:
             // https://groups.google.com/a/chromium.org/g/chromium-os-discuss/c/in-f59OKYAE/m/uVanwcXkAgAJ
@@ -127,26 +166,52 @@ impl Request {
             max_redirects: 100,
             #[cfg(feature = "proxy")]
             proxy: None,
+            #[cfg(any(feature = "rustls", feature = "native-tls"))]
+            tls: None,
+            #[cfg(unix)]
+            unix_socket: None,
+            middlewares: Middlewares::default(),
         }
     }
 
     /// Add headers to the request this is called on. Use this
     /// function to add headers to your requests.
+    ///
+    /// Headers set before with the same names are replaced, repeated
+    /// names in `headers` are all sent.
     pub fn with_headers<T, K, V>(mut self, headers: T) -> Request
     where
         T: IntoIterator<Item = (K, V)>,
         K: Into<String>,
         V: Into<String>,
     {
-        let headers = headers.into_iter().map(|(k, v)| (k.into(), v.into()));
+        let headers: HeaderMap = headers.into_iter().collect();
+        for name in headers.keys() {
+            self.headers.remove(name);
+        }
         self.headers.extend(headers);
         self
     }
 
     /// Adds a header to the request this is called on. Use this
     /// function to add headers to your requests.
+    ///
+    /// Headers set before with the same name are replaced, see
+    /// [`with_appended_header`](Request::with_appended_header) to send
+    /// several.
     pub fn with_header<T: Into<String>, U: Into<String>>(mut self, key: T, value: U) -> Request {
-        self.headers.insert(key.into(), value.into());
+        self.headers.insert(key, value);
+        self
+    }
+
+    /// Adds a header to the request this is called on, keeping those
+    /// set before with the same name.
+    pub fn with_appended_header<T: Into<String>, U: Into<String>>(
+        mut self,
+        key: T,
+        value: U,
+    ) -> Request {
+        self.headers.append(key, value);
         self
     }
 
@@ -154,10 +219,72 @@ impl Request {
     pub fn with_body<T: Into<Vec<u8>>>(mut self, body: T) -> Request {
         let body = body.into();
         let body_length = body.len();
-        self.body = Some(body);
+        self.body = Some(Body::Bytes(body));
+        self.headers.remove("Transfer-Encoding");
         self.with_header("Content-Length", format!("{}", body_length))
     }
 
+    /// Sets the request body to the bytes read from `reader` while the
+    /// request is sent, instead of holding them in memory.
+    ///
+    /// If the `length` of the body is given, only that many bytes are
+    /// read and they are sent with a `Content-Length`, otherwise the
+    /// reader is read to its end and the body is sent with
+    /// `Transfer-Encoding: chunked`, which not all servers support.
+    ///
+    /// The body can only be sent once, clones of the request share the
+    /// reader. A request sent again, to follow a redirect that keeps the
+    /// method or to retry on a new connection, fails with
+    /// [`Error::BodyNotReplayable`](crate::Error::BodyNotReplayable)
+    /// instead. The async methods read `reader` on a blocking thread, see
+    /// [`with_body_async_reader`](Request::with_body_async_reader) for
+    /// async readers.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let file = std::fs::File::open("block.hex")?;
+    /// let length = file.metadata()?.len();
+    /// let response = bitreq::post("http://example.com/upload")
+    ///     .with_body_reader(file, length)
+    ///     .send()?;
+    /// # Ok(()) }
+    /// ```
+    pub fn with_body_reader<R, L>(self, reader: R, length: L) -> Request
+    where
+        R: Read + Send + 'static,
+        L: Into<Option<u64>>,
+    {
+        self.with_body_stream(BodyStream::new(Reader::Blocking(Box::new(reader)), length.into()))
+    }
+
+    /// Sets the request body to the bytes read from an async `reader`
+    /// while the request is sent, see
+    /// [`with_body_reader`](Request::with_body_reader).
+    ///
+    /// The request can then only be sent with the async methods, the
+    /// others fail with [`Error::AsyncBodyReader`](crate::Error::AsyncBodyReader).
+    #[cfg(feature = "async")]
+    pub fn with_body_async_reader<R, L>(self, reader: R, length: L) -> Request
+    where
+        R: AsyncRead + Send + 'static,
+        L: Into<Option<u64>>,
+    {
+        self.with_body_stream(BodyStream::new(Reader::Async(Box::pin(reader)), length.into()))
+    }
+
+    fn with_body_stream(mut self, stream: BodyStream) -> Request {
+        self.headers.remove("Content-Length");
+        self.headers.remove("Transfer-Encoding");
+        match stream.length {
+            Some(length) => self.headers.insert("Content-Length", length.to_string()),
+            None => self.headers.insert("Transfer-Encoding", "chunked"),
+        };
+        self.body = Some(Body::Stream(stream));
+        self
+    }
+
     /// Adds given key and value as query parameter to request url
     /// (resource).
     ///
@@ -177,20 +304,54 @@ impl Request {
     /// string.
     #[cfg(feature = "json-using-serde")]
     pub fn with_json<T: serde::ser::Serialize>(mut self, body: &T) -> Result<Request, Error> {
-        self.headers
-            .insert("Content-Type".to_string(), "application/json; charset=UTF-8".to_string());
+        self.headers.insert("Content-Type", "application/json; charset=UTF-8");
         match serde_json::to_vec(&body) {
             Ok(json) => Ok(self.with_body(json)),
             Err(err) => Err(Error::SerdeJsonError(err)),
         }
     }
 
-    /// Sets the request timeout in seconds.
-    pub fn with_timeout(mut self, timeout: u64) -> Request {
+    /// Sets the request timeout in seconds, see
+    /// [`with_total_timeout`](Request::with_total_timeout).
+    pub fn with_timeout(self, timeout: u64) -> Request {
+        self.with_total_timeout(Duration::from_secs(timeout))
+    }
+
+    /// Sets how long the whole request may take, from connecting to reading the last byte of
+    /// the response, redirects included.
+    ///
+    /// Reaching it fails the request with an [`Error::IoError`] of kind
+    /// [`TimedOut`](std::io::ErrorKind::TimedOut). If this isn't set, the `BITREQ_TIMEOUT`
+    /// environment variable is read as a number of seconds instead.
+    pub fn with_total_timeout(mut self, timeout: Duration) -> Request {
         self.timeout = Some(timeout);
         self
     }
 
+    /// Sets how long connecting to the server may take, including resolving its name, the
+    /// handshake with a proxy and the TLS handshake.
+    ///
+    /// Reaching it fails the request with [`Error::ConnectTimeout`]. A reused connection of a
+    /// [`Client`](crate::Client) is already connected.
+    ///
+    /// Resolving a name can't be interrupted, so with this or the total timeout set,
+    /// [`send`](Request::send) looks up a host name on a new thread each time it connects. If the
+    /// timeout is reached, that thread is left to finish in the background.
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Request {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how long the server may send nothing while the response is read, after which the
+    /// request fails with [`Error::ReadTimeout`].
+    ///
+    /// Unlike [`with_total_timeout`](Request::with_total_timeout), a slow response succeeds as
+    /// long as some of it keeps arriving.
+    pub fn with_read_timeout(mut self, timeout: Duration) -> Request {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
     /// Sets the max redirects we follow until giving up. 100 by
     /// default.
     ///
@@ -268,10 +429,37 @@ impl Request {
         self
     }
 
+    /// Sets the TLS settings of HTTPS connections, replacing those of the
+    /// [`Client`](crate::Client) sending the request.
+    #[cfg(any(feature = "rustls", feature = "native-tls"))]
+    pub fn with_tls_config(mut self, tls: TlsConfig) -> Request {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Sends the request to the HTTP server listening on the Unix domain socket at `path`, instead
+    /// of connecting to the host of the URL.
+    ///
+    /// The URL is still used for the request line and the `Host` header, it must start with
+    /// `http://` as the connection is not encrypted. Proxies are not used.
+    #[cfg(unix)]
+    pub fn with_unix_socket<P: Into<PathBuf>>(mut self, path: P) -> Request {
+        self.unix_socket = Some(path.into());
+        self
+    }
+
+    /// Adds a [`Middleware`] run around sending this request, after those of the
+    /// [`Client`](crate::Client) sending it.
+    pub fn with_middleware(mut self, middleware: Arc<dyn Middleware>) -> Request {
+        self.middlewares.0.push(middleware);
+        self
+    }
+
     /// Enables HTTP request pipelining for this request.
     ///
     /// Note that because pipelined requests may be replayed in case of failure, you should only
-    /// set this on idempotent requests.
+    /// set this on idempotent requests. A request whose body is read from a stream, set with
+    /// [`with_body_reader`](Request::with_body_reader), can't be replayed and fails instead.
     ///
     /// This is only used if the request is sent using a [`Client`] and an existing connection to
     /// the same server with the same proxy exists.
@@ -283,6 +471,25 @@ impl Request {
         self
     }
 
+    /// Returns the method of this request.
+    pub fn method(&self) -> &Method { &self.method }
+
+    /// Returns the URL of this request, without the parameters added with
+    /// [`with_param`](Request::with_param).
+    pub fn url(&self) -> &str { &self.url }
+
+    /// Returns the headers of this request.
+    pub fn headers(&self) -> &HeaderMap { &self.headers }
+
+    /// Returns the body of this request, unless it is read from a stream set with
+    /// [`with_body_reader`](Request::with_body_reader).
+    pub fn body(&self) -> Option<&[u8]> {
+        match &self.body {
+            Some(Body::Bytes(bytes)) => Some(bytes),
+            _ => None,
+        }
+    }
+
     /// Sends this request to the host.
     ///
     /// # Errors
@@ -294,11 +501,17 @@ impl Request {
     /// [`InvalidUtf8InBody`](enum.Error.html#variant.InvalidUtf8InBody).
     #[cfg(feature = "std")]
     pub fn send(self) -> Result<Response, Error> {
+        let (request, sending) = Middlewares::start(self)?;
+        sending.finish(request.send_inner())
+    }
+
+    #[cfg(feature = "std")]
+    fn send_inner(self) -> Result<Response, Error> {
         let parsed_request = ParsedRequest::new(self)?;
         let is_head = parsed_request.config.method == Method::Head;
         let max_body_size = parsed_request.config.max_body_size;
         let connection =
-            Connection::new(parsed_request.connection_params(), parsed_request.timeout_at)?;
+            Connection::new(parsed_request.connection_params(), parsed_request.timeouts())?;
         let response = connection.send(parsed_request)?;
         Response::create(response, is_head, max_body_size)
     }
@@ -310,8 +523,9 @@ impl Request {
     /// See [`send`](struct.Request.html#method.send).
     #[cfg(feature = "std")]
     pub fn send_lazy(self) -> Result<ResponseLazy, Error> {
-        let parsed_request = ParsedRequest::new(self)?;
-        Connection::new(parsed_request.connection_params(), parsed_request.timeout_at)?
+        let (request, _) = Middlewares::start(self)?;
+        let parsed_request = ParsedRequest::new(request)?;
+        Connection::new(parsed_request.connection_params(), parsed_request.timeouts())?
             .send(parsed_request)
     }
 
@@ -326,8 +540,14 @@ impl Request {
     /// [`InvalidUtf8InBody`](enum.Error.html#variant.InvalidUtf8InBody).
     #[cfg(feature = "async")]
     pub async fn send_async(self) -> Result<Response, Error> {
+        let (request, sending) = Middlewares::start(self)?;
+        sending.finish(request.send_async_inner().await)
+    }
+
+    #[cfg(feature = "async")]
+    async fn send_async_inner(self) -> Result<Response, Error> {
         let parsed_request = ParsedRequest::new(self)?;
-        AsyncConnection::new(parsed_request.connection_params(), parsed_request.timeout_at)
+        AsyncConnection::new(parsed_request.connection_params(), parsed_request.timeouts())
             .await?
             .send(parsed_request)
             .await
@@ -402,10 +622,10 @@ impl ParsedRequest {
         }
 
         let timeout = config.timeout.or_else(|| match env::var("BITREQ_TIMEOUT") {
-            Ok(t) => t.parse::<u64>().ok(),
+            Ok(t) => t.parse::<u64>().ok().map(Duration::from_secs),
             Err(_) => None,
         });
-        let timeout_at = timeout.map(|t| Instant::now() + Duration::from_secs(t));
+        let timeout_at = timeout.map(|t| Instant::now() + t);
 
         Ok(ParsedRequest { url, redirects: Vec::new(), config, timeout_at })
     }
@@ -441,6 +661,11 @@ impl ParsedRequest {
             write!(http, "{}: {}\r\n", k, v).unwrap();
         }
 
+        #[cfg(feature = "compression")]
+        if self.config.headers.keys().all(|key| !key.eq_ignore_ascii_case("accept-encoding")) {
+            http += "Accept-Encoding: gzip, deflate\r\n";
+        }
+
         if self.config.method == Method::Post
             || self.config.method == Method::Put
             || self.config.method == Method::Patch
@@ -467,15 +692,47 @@ impl ParsedRequest {
     }
 
     /// Returns the HTTP request as bytes, ready to be sent to
-    /// the server.
+    /// the server, without a body read from a stream.
     pub(crate) fn as_bytes(&self) -> Vec<u8> {
         let mut head = self.get_http_head().into_bytes();
-        if let Some(body) = &self.config.body {
+        if let Some(Body::Bytes(body)) = &self.config.body {
             head.extend(body);
         }
         head
     }
 
+    /// Writes the HTTP request to `stream`, reading its body from its
+    /// stream if it has one.
+    pub(crate) fn write_to<W: std::io::Write>(&self, stream: &mut W) -> Result<(), Error> {
+        match &self.config.body {
+            Some(Body::Stream(body)) => {
+                let reader = body.take_blocking()?;
+                stream.write_all(self.get_http_head().as_bytes())?;
+                body::write_blocking(reader, body.length, stream)?;
+            }
+            _ => stream.write_all(&self.as_bytes())?,
+        }
+        Ok(())
+    }
+
+    /// Writes the HTTP request to `stream` asynchronously, see
+    /// [`write_to`](ParsedRequest::write_to).
+    #[cfg(feature = "async")]
+    pub(crate) async fn write_to_async<W: AsyncWrite + Unpin>(
+        &self,
+        stream: &mut W,
+    ) -> Result<(), Error> {
+        match &self.config.body {
+            Some(Body::Stream(body)) => {
+                let reader = body.take()?;
+                stream.write_all(self.get_http_head().as_bytes()).await?;
+                body::write_async(reader, body.length, stream).await?;
+            }
+            _ => stream.write_all(&self.as_bytes()).await?,
+        }
+        Ok(())
+    }
+
     /// Returns the redirected version of this Request, unless an
     /// infinite redirection loop was detected, or the redirection
     /// limit was reached.
@@ -503,10 +760,7 @@ impl ParsedRequest {
         } else {
             // The url does not have the protocol part, assuming it's
             // a relative resource.
-            let mut absolute_url = String::new();
-            self.url.write_base_url_to(&mut absolute_url).unwrap();
-            absolute_url.push_str(url);
-            let mut new_url = Url::parse(&absolute_url)?;
+            let mut new_url = self.url.join(url)?;
 
             // Preserve fragment from original URL if new URL doesn't have one (RFC 7231 section 7.1.2)
             new_url.preserve_fragment_from(&self.url);
@@ -526,6 +780,14 @@ impl ParsedRequest {
     pub(crate) fn connection_params(&self) -> ConnectionParams<'_> {
         ConnectionParams::from_request(self)
     }
+
+    pub(crate) fn timeouts(&self) -> Timeouts {
+        Timeouts {
+            deadline: self.timeout_at,
+            connect: self.config.connect_timeout,
+            read: self.config.read_timeout,
+        }
+    }
 }
 
 /// A key which determines whether an existing connection can be reused
@@ -537,6 +799,10 @@ pub(crate) struct ConnectionParams<'a> {
     pub(crate) port: u16,
     #[cfg(feature = "proxy")]
     pub(crate) proxy: Option<&'a Proxy>,
+    #[cfg(any(feature = "rustls", feature = "native-tls"))]
+    pub(crate) tls: Option<&'a TlsConfig>,
+    #[cfg(unix)]
+    pub(crate) unix_socket: Option<&'a Path>,
 }
 
 #[cfg(feature = "std")]
@@ -548,6 +814,10 @@ impl<'a> ConnectionParams<'a> {
             port: request.url.port(),
             #[cfg(feature = "proxy")]
             proxy: request.config.proxy.as_ref(),
+            #[cfg(any(feature = "rustls", feature = "native-tls"))]
+            tls: request.config.tls.as_ref(),
+            #[cfg(unix)]
+            unix_socket: request.config.unix_socket.as_deref(),
         }
     }
 }
@@ -561,6 +831,10 @@ pub(crate) struct OwnedConnectionParams {
     pub(crate) port: u16,
     #[cfg(feature = "proxy")]
     pub(crate) proxy: Option<Proxy>,
+    #[cfg(any(feature = "rustls", feature = "native-tls"))]
+    pub(crate) tls: Option<TlsConfig>,
+    #[cfg(unix)]
+    pub(crate) unix_socket: Option<PathBuf>,
 }
 
 #[cfg(feature = "std")]
@@ -569,6 +843,14 @@ impl PartialEq<ConnectionParams<'_>> for OwnedConnectionParams {
         if self.https != other.https || self.host != other.host || self.port != other.port {
             return false;
         }
+        #[cfg(any(feature = "rustls", feature = "native-tls"))]
+        if self.tls.as_ref() != other.tls {
+            return false;
+        }
+        #[cfg(unix)]
+        if self.unix_socket.as_deref() != other.unix_socket {
+            return false;
+        }
         #[cfg(feature = "proxy")]
         {
             self.proxy.as_ref() == other.proxy
@@ -589,6 +871,10 @@ impl From<ConnectionParams<'_>> for OwnedConnectionParams {
             port: other.port,
             #[cfg(feature = "proxy")]
             proxy: other.proxy.cloned(),
+            #[cfg(any(feature = "rustls", feature = "native-tls"))]
+            tls: other.tls.cloned(),
+            #[cfg(unix)]
+            unix_socket: other.unix_socket.map(Path::to_path_buf),
         }
     }
 }
@@ -635,7 +921,7 @@ mod parsing_tests {
 
     use alloc::collections::BTreeMap;
 
-    use super::{get, ParsedRequest};
+    use super::{get, post, ParsedRequest};
 
     #[test]
     fn test_headers() {
@@ -645,7 +931,37 @@ mod parsing_tests {
 
         let req = get("http://www.example.org/test/res").with_headers(headers.clone());
 
-        assert_eq!(req.headers, headers);
+        assert_eq!(req.headers.to_map(), headers);
+    }
+
+    #[test]
+    fn test_repeated_headers() {
+        let req = get("http://www.example.org/")
+            .with_header("Foo", "bar")
+            .with_headers([("foo", "baz"), ("Via", "a")])
+            .with_appended_header("via", "b");
+        let req = ParsedRequest::new(req).unwrap();
+        let http = String::from_utf8(req.as_bytes()).unwrap();
+        assert!(http.contains("\r\nfoo: baz\r\nVia: a\r\nvia: b\r\n"), "{}", http);
+        assert!(!http.contains("bar"));
+    }
+
+    #[test]
+    fn test_body_framing_headers() {
+        let req = post("http://www.example.org/").with_body_reader(&b"foo"[..], None);
+        assert_eq!(req.headers.get("transfer-encoding").map(String::as_str), Some("chunked"));
+        assert_eq!(req.headers.get("content-length"), None);
+        assert_eq!(req.body(), None);
+
+        let req = req.with_body_reader(&b"foo"[..], 3);
+        assert_eq!(req.headers.get("content-length").map(String::as_str), Some("3"));
+        assert_eq!(req.headers.get("transfer-encoding"), None);
+
+        let req =
+            post("http://www.example.org/").with_body_reader(&b"foo"[..], None).with_body("foo");
+        assert_eq!(req.headers.get("content-length").map(String::as_str), Some("3"));
+        assert_eq!(req.headers.get("transfer-encoding"), None);
+        assert_eq!(req.body(), Some(&b"foo"[..]));
     }
 
     #[test]
@@ -1,21 +1,28 @@
-use alloc::collections::BTreeMap;
+#[cfg(feature = "compression")]
+use alloc::collections::VecDeque;
 use core::str;
 #[cfg(feature = "async")]
 use std::future::Future;
+#[cfg(feature = "compression")]
+use std::io::Write;
 #[cfg(feature = "std")]
-use std::io::{self, BufReader, Bytes, Read};
+use std::io::{self, BufRead, BufReader, Read};
 
 #[cfg(feature = "async")]
 use tokio::io::{AsyncRead, AsyncReadExt};
 
 #[cfg(feature = "std")]
 use crate::connection::HttpStream;
-use crate::Error;
+use crate::{Error, HeaderMap};
 
 #[cfg(feature = "std")]
 const BACKING_READ_BUFFER_LENGTH: usize = 16 * 1024;
 #[cfg(feature = "std")]
 const MAX_CONTENT_LENGTH: usize = 16 * 1024;
+/// How many bytes of an encoded body are decoded at once. Bounds the memory used by a small body
+/// that decodes to a huge one before [`Error::BodyOverflow`] is returned.
+#[cfg(feature = "compression")]
+const DECODE_CHUNK_LENGTH: usize = 1024;
 
 /// An HTTP response.
 ///
@@ -38,9 +45,10 @@ pub struct Response {
     pub status_code: i32,
     /// The reason phrase of the response, eg. "Not Found".
     pub reason_phrase: String,
-    /// The headers of the response. The header field names (the
-    /// keys) are all lowercase.
-    pub headers: BTreeMap<String, String>,
+    /// The headers of the response, in the order they were received. The
+    /// header field names (the keys) are all lowercase. Of repeated fields
+    /// [`HeaderMap::get`] returns the last value.
+    pub headers: HeaderMap,
     /// The URL of the resource returned in this response. May differ from the
     /// request URL if it was redirected or typo corrections were applied (e.g.
     /// <http://example.com?foo=bar> would be corrected to
@@ -53,12 +61,24 @@ pub struct Response {
 impl Response {
     #[cfg(feature = "std")]
     pub(crate) fn create(
-        mut parent: ResponseLazy,
+        parent: ResponseLazy,
         is_head: bool,
         max_body_size: Option<usize>,
     ) -> Result<Response, Error> {
+        Ok(Response::create_keeping_stream(parent, is_head, max_body_size)?.0)
+    }
+
+    /// Like [`Response::create`], but also returns the stream if the response was read to its
+    /// end and nothing was received after it, i.e., another request can be sent on the stream.
+    #[cfg(feature = "std")]
+    pub(crate) fn create_keeping_stream(
+        mut parent: ResponseLazy,
+        is_head: bool,
+        max_body_size: Option<usize>,
+    ) -> Result<(Response, Option<HttpStream>), Error> {
+        let has_body = has_body(is_head, parent.status_code);
         let mut body = Vec::new();
-        if !is_head && parent.status_code != 204 && parent.status_code != 304 {
+        if has_body {
             for byte in &mut parent {
                 let (byte, length) = byte?;
                 if max_body_size.is_some_and(|max| body.len().saturating_add(length) > max) {
@@ -69,9 +89,19 @@ impl Response {
             }
         }
 
-        let ResponseLazy { status_code, reason_phrase, headers, url, .. } = parent;
+        let ResponseLazy { status_code, reason_phrase, headers, url, stream, state, .. } = parent;
+        let finished = !has_body
+            || matches!(
+                state,
+                HttpStreamState::ContentLength(0) | HttpStreamState::Chunked(false, _, _)
+            );
+        let stream = if finished && stream.0.buffer().is_empty() {
+            Some(stream.0.into_inner())
+        } else {
+            None
+        };
 
-        Ok(Response { status_code, reason_phrase, headers, url, body })
+        Ok((Response { status_code, reason_phrase, headers, url, body }, stream))
     }
 
     #[cfg(feature = "async")]
@@ -101,7 +131,7 @@ impl Response {
         } = read_metadata_async(&mut stream, max_headers_size, max_status_line_len).await?;
 
         let mut body = Vec::new();
-        if !is_head && status_code != 204 && status_code != 304 {
+        if has_body(is_head, status_code) {
             match state {
                 EndOnClose => {
                     while let Some(byte_result) = read_until_closed_async(&mut stream).await {
@@ -150,6 +180,15 @@ impl Response {
             }
         }
 
+        // Responses without a body keep the headers of the body they would have.
+        #[cfg(feature = "compression")]
+        if !body.is_empty() {
+            if let Some(decoder) = Decoder::new(&mut headers) {
+                body = decoder.decode_all(&body, max_body_size)?;
+                headers.insert("content-length".to_string(), body.len().to_string());
+            }
+        }
+
         Ok(Response { status_code, reason_phrase, headers, url: String::new(), body })
     }
 
@@ -299,9 +338,10 @@ pub struct ResponseLazy {
     pub status_code: i32,
     /// The reason phrase of the response, eg. "Not Found".
     pub reason_phrase: String,
-    /// The headers of the response. The header field names (the
-    /// keys) are all lowercase.
-    pub headers: BTreeMap<String, String>,
+    /// The headers of the response, in the order they were received. The
+    /// header field names (the keys) are all lowercase. Of repeated fields
+    /// [`HeaderMap::get`] returns the last value.
+    pub headers: HeaderMap,
     /// The URL of the resource returned in this response. May differ from the
     /// request URL if it was redirected or typo corrections were applied (e.g.
     /// <http://example.com?foo=bar> would be corrected to
@@ -313,20 +353,47 @@ pub struct ResponseLazy {
     max_trailing_headers_size: Option<usize>,
     max_body_size: Option<usize>,
     bytes_read: usize,
+    #[cfg(feature = "compression")]
+    decoder: Option<Decoder>,
 }
 
+/// The bytes of a buffered [`HttpStream`].
+///
+/// Unlike [`io::Bytes`], gives access to the stream to reuse it once a response was read.
+#[cfg(feature = "std")]
+struct HttpStreamBytes(BufReader<HttpStream>);
+
 #[cfg(feature = "std")]
-type HttpStreamBytes = Bytes<BufReader<HttpStream>>;
+impl Iterator for HttpStreamBytes {
+    type Item = io::Result<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.0.fill_buf() {
+                Ok([]) => return None,
+                Ok(buf) => {
+                    let byte = buf[0];
+                    self.0.consume(1);
+                    return Some(Ok(byte));
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
 
 #[cfg(feature = "std")]
 impl ResponseLazy {
     pub(crate) fn from_stream(
         stream: HttpStream,
+        is_head: bool,
         max_headers_size: Option<usize>,
         max_status_line_len: Option<usize>,
         max_body_size: Option<usize>,
     ) -> Result<ResponseLazy, Error> {
-        let mut stream = BufReader::with_capacity(BACKING_READ_BUFFER_LENGTH, stream).bytes();
+        let mut stream =
+            HttpStreamBytes(BufReader::with_capacity(BACKING_READ_BUFFER_LENGTH, stream));
         let ResponseMetadata {
             status_code,
             reason_phrase,
@@ -334,8 +401,12 @@ impl ResponseLazy {
             state,
             max_trailing_headers_size,
         } = read_metadata(&mut stream, max_headers_size, max_status_line_len)?;
+        // HEAD, 204 and 304 responses end with their headers, whatever these say about the body.
+        let state =
+            if has_body(is_head, status_code) { state } else { HttpStreamState::ContentLength(0) };
 
-        Ok(ResponseLazy {
+        #[allow(unused_mut)]
+        let mut response = ResponseLazy {
             status_code,
             reason_phrase,
             headers,
@@ -345,7 +416,15 @@ impl ResponseLazy {
             max_trailing_headers_size,
             max_body_size,
             bytes_read: 0,
-        })
+            #[cfg(feature = "compression")]
+            decoder: None,
+        };
+        // Responses without a body keep the headers of the body they would have.
+        #[cfg(feature = "compression")]
+        if !matches!(response.state, HttpStreamState::ContentLength(0)) {
+            response.decoder = Decoder::new(&mut response.headers);
+        }
+        Ok(response)
     }
 
     #[cfg(feature = "async")]
@@ -356,23 +435,21 @@ impl ResponseLazy {
             reason_phrase: response.reason_phrase,
             headers: response.headers,
             url: response.url,
-            stream: BufReader::with_capacity(1, http_stream).bytes(),
+            stream: HttpStreamBytes(BufReader::with_capacity(1, http_stream)),
             state: HttpStreamState::EndOnClose,
             max_trailing_headers_size: None,
             // Body was already fully loaded and size-checked by send_async
             max_body_size: None,
             bytes_read: 0,
+            #[cfg(feature = "compression")]
+            decoder: None,
         }
     }
-}
 
-#[cfg(feature = "std")]
-impl Iterator for ResponseLazy {
-    type Item = Result<(u8, usize), Error>;
-
-    fn next(&mut self) -> Option<Self::Item> {
+    /// Reads the next byte of the body as it was sent.
+    fn read_body_byte(&mut self) -> Option<<Self as Iterator>::Item> {
         use HttpStreamState::*;
-        let result = match self.state {
+        match self.state {
             EndOnClose => read_until_closed(&mut self.stream),
             ContentLength(ref mut length) => read_with_content_length(&mut self.stream, length),
             Chunked(ref mut expecting_chunks, ref mut length, ref mut content_length) =>
@@ -384,7 +461,57 @@ impl Iterator for ResponseLazy {
                     content_length,
                     self.max_trailing_headers_size,
                 ),
-        };
+        }
+    }
+
+    /// Returns the next byte of the decoded body, decoding more of the body as needed.
+    #[cfg(feature = "compression")]
+    fn next_decoded(&mut self) -> Option<<Self as Iterator>::Item> {
+        loop {
+            let decoder = self.decoder.as_mut()?;
+            if let Some(byte) = decoder.decoded.pop_front() {
+                self.bytes_read += 1;
+                return Some(Ok((byte, decoder.decoded.len().min(MAX_CONTENT_LENGTH) + 1)));
+            }
+            if decoder.is_finished() {
+                return None;
+            }
+
+            let mut encoded = Vec::with_capacity(DECODE_CHUNK_LENGTH);
+            while encoded.len() < DECODE_CHUNK_LENGTH {
+                match self.read_body_byte() {
+                    Some(Ok((byte, _))) => encoded.push(byte),
+                    Some(Err(err)) => return Some(Err(err)),
+                    None => break,
+                }
+            }
+            let decoder = self.decoder.as_mut()?;
+            if let Err(err) = decoder.decode(&encoded) {
+                return Some(Err(Error::IoError(err)));
+            }
+            let decoded_len = self.bytes_read.saturating_add(decoder.decoded.len());
+            if self.max_body_size.is_some_and(|max| decoded_len > max || decoder.encoded_len > max)
+            {
+                return Some(Err(Error::BodyOverflow));
+            }
+            if decoder.is_finished() {
+                // Like for chunked bodies, give the length once the whole body was read.
+                self.headers.insert("content-length".to_string(), decoded_len.to_string());
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Iterator for ResponseLazy {
+    type Item = Result<(u8, usize), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        #[cfg(feature = "compression")]
+        if self.decoder.is_some() {
+            return self.next_decoded();
+        }
+        let result = self.read_body_byte();
 
         // Check body size limit before returning the byte
         if let Some(Ok((_, expected_length))) = &result {
@@ -407,6 +534,7 @@ impl Read for ResponseLazy {
             // so it is ignored.
             let (byte, _) = res.map_err(|e| match e {
                 Error::IoError(e) => e,
+                Error::ReadTimeout => io::Error::new(io::ErrorKind::TimedOut, e),
                 _ => io::Error::new(io::ErrorKind::Other, e),
             })?;
 
@@ -424,6 +552,95 @@ impl Read for ResponseLazy {
     }
 }
 
+/// Decodes a body sent with a `Content-Encoding`.
+#[cfg(feature = "compression")]
+struct Decoder {
+    /// `None` once the whole body was decoded.
+    inner: Option<DecoderKind>,
+    /// The decoded bytes that were not returned yet.
+    decoded: VecDeque<u8>,
+    /// How many bytes of the encoded body were decoded.
+    encoded_len: usize,
+}
+
+#[cfg(feature = "compression")]
+enum DecoderKind {
+    Gzip(flate2::write::GzDecoder<Vec<u8>>),
+    /// The `deflate` encoding is the zlib format, see RFC 9110 section 8.4.1.2.
+    Deflate(flate2::write::ZlibDecoder<Vec<u8>>),
+}
+
+#[cfg(feature = "compression")]
+impl Decoder {
+    /// Returns a decoder if the body was sent with a `Content-Encoding` we can decode.
+    ///
+    /// The `content-encoding` and `content-length` headers are removed then, as they describe the
+    /// encoded body.
+    fn new(headers: &mut HeaderMap) -> Option<Decoder> {
+        let inner = match headers.get("content-encoding")?.trim().to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => DecoderKind::Gzip(flate2::write::GzDecoder::new(Vec::new())),
+            "deflate" => DecoderKind::Deflate(flate2::write::ZlibDecoder::new(Vec::new())),
+            _ => return None,
+        };
+        headers.remove("content-encoding");
+        headers.remove("content-length");
+        Some(Decoder { inner: Some(inner), decoded: VecDeque::new(), encoded_len: 0 })
+    }
+
+    fn is_finished(&self) -> bool { self.inner.is_none() }
+
+    /// Decodes the next part of the body, or checks that the body is complete if `encoded` is
+    /// empty.
+    fn decode(&mut self, encoded: &[u8]) -> io::Result<()> {
+        if encoded.is_empty() && self.encoded_len == 0 {
+            // An empty body, e.g. a chunked one without chunks, isn't encoded.
+            self.inner = None;
+            return Ok(());
+        }
+        let decoded = match self.inner {
+            Some(DecoderKind::Gzip(ref mut decoder)) => {
+                if encoded.is_empty() {
+                    decoder.try_finish()?;
+                } else {
+                    decoder.write_all(encoded)?;
+                }
+                decoder.get_mut()
+            }
+            Some(DecoderKind::Deflate(ref mut decoder)) => {
+                if encoded.is_empty() {
+                    decoder.try_finish()?;
+                } else {
+                    decoder.write_all(encoded)?;
+                }
+                decoder.get_mut()
+            }
+            None => return Ok(()),
+        };
+        self.decoded.extend(decoded.drain(..));
+        self.encoded_len = self.encoded_len.saturating_add(encoded.len());
+        if encoded.is_empty() {
+            self.inner = None;
+        }
+        Ok(())
+    }
+
+    /// Decodes the whole body, failing if it decodes to more than `max_body_size` bytes.
+    #[cfg(feature = "async")]
+    fn decode_all(
+        mut self,
+        encoded: &[u8],
+        max_body_size: Option<usize>,
+    ) -> Result<Vec<u8>, Error> {
+        for chunk in encoded.chunks(DECODE_CHUNK_LENGTH).chain([&[][..]]) {
+            self.decode(chunk)?;
+            if max_body_size.is_some_and(|max| self.decoded.len() > max) {
+                return Err(Error::BodyOverflow);
+            }
+        }
+        Ok(self.decoded.into())
+    }
+}
+
 #[cfg(feature = "std")]
 enum HttpStreamState {
     // No Content-Length, and Transfer-Encoding != chunked, so we just
@@ -448,7 +665,7 @@ enum HttpStreamState {
 struct ResponseMetadata {
     status_code: i32,
     reason_phrase: String,
-    headers: BTreeMap<String, String>,
+    headers: HeaderMap,
     state: HttpStreamState,
     max_trailing_headers_size: Option<usize>,
 }
@@ -481,7 +698,7 @@ macro_rules! define_read_methods {
             if let Some(byte) = maybe_await!(bytes.next(), $($await)?) {
                 match byte {
                     Ok(byte) => Some(Ok((byte, 1))),
-                    Err(err) => Some(Err(Error::IoError(err))),
+                    Err(err) => Some(Err(err.into())),
                 }
             } else {
                 None
@@ -499,7 +716,7 @@ macro_rules! define_read_methods {
                     match byte {
                         // Cap Content-Length to 16KiB, to avoid out-of-memory issues.
                         Ok(byte) => return Some(Ok((byte, (*content_length).min(MAX_CONTENT_LENGTH) + 1))),
-                        Err(err) => return Some(Err(Error::IoError(err))),
+                        Err(err) => return Some(Err(err.into())),
                     }
                 }
             }
@@ -508,7 +725,7 @@ macro_rules! define_read_methods {
 
         $($async)? fn $read_trailers<$($arg: $($argty +)*),*>(
             bytes: &mut $stream_type,
-            headers: &mut BTreeMap<String, String>,
+            headers: &mut HeaderMap,
             mut max_headers_size: Option<usize>,
         ) -> Result<(), Error> {
             loop {
@@ -517,7 +734,7 @@ macro_rules! define_read_methods {
                     *max_headers_size -= trailer_line.len() + 2;
                 }
                 if let Some((header, value)) = parse_header(trailer_line) {
-                    headers.insert(header, value);
+                    headers.append(header, value);
                 } else {
                     break;
                 }
@@ -527,7 +744,7 @@ macro_rules! define_read_methods {
 
         $($async)? fn $read_chunked<$($arg: $($argty +)*),*>(
             bytes: &mut $stream_type,
-            headers: &mut BTreeMap<String, String>,
+            headers: &mut HeaderMap,
             expecting_more_chunks: &mut bool,
             chunk_length: &mut usize,
             content_length: &mut usize,
@@ -599,7 +816,7 @@ macro_rules! define_read_methods {
 
                             return Some(Ok((byte, (*chunk_length).min(MAX_CONTENT_LENGTH) + 1)));
                         }
-                        Err(err) => return Some(Err(Error::IoError(err))),
+                        Err(err) => return Some(Err(err.into())),
                     }
                 }
             }
@@ -616,7 +833,7 @@ macro_rules! define_read_methods {
             let line = maybe_await!($read_line(stream, max_status_line_len, Error::StatusLineOverflow), $($await)?)?;
             let (status_code, reason_phrase) = parse_status_line(&line);
 
-            let mut headers = BTreeMap::new();
+            let mut headers = HeaderMap::new();
             loop {
                 let line = maybe_await!($read_line(stream, max_headers_size, Error::HeadersOverflow), $($await)?)?;
                 if line.is_empty() {
@@ -627,7 +844,7 @@ macro_rules! define_read_methods {
                     *max_headers_size -= line.len() + 2;
                 }
                 if let Some(header) = parse_header(line) {
-                    headers.insert(header.0, header.1);
+                    headers.append(header.0, header.1);
                 }
             }
 
@@ -691,7 +908,7 @@ macro_rules! define_read_methods {
                             bytes.push(byte);
                         }
                     }
-                    Err(err) => return Err(Error::IoError(err)),
+                    Err(err) => return Err(err.into()),
                 }
             }
             String::from_utf8(bytes).map_err(|_error| Error::InvalidUtf8InResponse)
@@ -705,6 +922,12 @@ define_read_methods!((read_until_closed, read_with_content_length, read_trailers
 define_read_methods!((read_until_closed_async, read_with_content_length_async, read_trailers_async, read_chunked_async, read_metadata_async, read_line_async)<R: AsyncRead | Unpin>, R, async, await);
 
 #[cfg(feature = "std")]
+/// Returns whether a response to a request can have a body, see RFC 9110 section 6.4.1.
+#[cfg(feature = "std")]
+fn has_body(is_head: bool, status_code: i32) -> bool {
+    !is_head && status_code != 204 && status_code != 304
+}
+
 fn parse_status_line(line: &str) -> (i32, String) {
     // sample status line format
     // HTTP/1.1 200 OK
@@ -0,0 +1,327 @@
+//! TLS settings for HTTPS connections.
+
+use core::fmt;
+
+use crate::Error;
+
+/// TLS settings for HTTPS requests, replacing the defaults of the enabled
+/// TLS backend.
+///
+/// Attach it to a request with
+/// [`Request::with_tls_config`](struct.Request.html#method.with_tls_config),
+/// or to all the requests of a [`Client`](struct.Client.html) with
+/// [`Client::with_tls_config`](struct.Client.html#method.with_tls_config).
+///
+/// # Example
+///
+/// ```no_run
+/// # fn main() -> Result<(), bitreq::Error> {
+/// // Trust only the private CA of the node, and authenticate with a client certificate.
+/// let tls = bitreq::TlsConfig::new()
+///     .with_root_certificates_pem(&std::fs::read("ca.pem")?)?
+///     .without_built_in_roots()
+///     .with_client_identity_pem(&std::fs::read("client.pem")?, &std::fs::read("client.key")?)?;
+/// let response = bitreq::get("https://node.example:8332").with_tls_config(tls).send()?;
+/// # Ok(()) }
+/// ```
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct TlsConfig {
+    /// DER-encoded certificates trusted in addition to (or instead of) the built-in ones.
+    pub(crate) root_certificates: Vec<Vec<u8>>,
+    pub(crate) built_in_roots: bool,
+    pub(crate) identity: Option<Identity>,
+    /// DER-encoded `SubjectPublicKeyInfo`s the server's certificate must have one of.
+    pub(crate) pinned_public_keys: Vec<Vec<u8>>,
+    pub(crate) accept_invalid_certs: bool,
+}
+
+/// A client certificate and its key, for mutual TLS.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub(crate) struct Identity {
+    /// The DER-encoded certificate chain, the client certificate first.
+    pub(crate) certificates: Vec<Vec<u8>>,
+    /// The DER-encoded PKCS#8 private key.
+    pub(crate) key: Vec<u8>,
+}
+
+impl TlsConfig {
+    /// Creates the settings the TLS backend uses by default: the built-in
+    /// root certificates and no client certificate.
+    pub fn new() -> TlsConfig {
+        TlsConfig {
+            root_certificates: Vec::new(),
+            built_in_roots: true,
+            identity: None,
+            pinned_public_keys: Vec::new(),
+            accept_invalid_certs: false,
+        }
+    }
+
+    /// Trusts the DER-encoded root certificate `der`.
+    pub fn with_root_certificate_der<T: Into<Vec<u8>>>(mut self, der: T) -> TlsConfig {
+        self.root_certificates.push(der.into());
+        self
+    }
+
+    /// Trusts all the root certificates in the PEM file `pem`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MalformedPem`](enum.Error.html#variant.MalformedPem) if
+    /// `pem` can't be decoded or holds no certificate.
+    pub fn with_root_certificates_pem(mut self, pem: &[u8]) -> Result<TlsConfig, Error> {
+        self.root_certificates.extend(pem_decode(pem, "CERTIFICATE")?);
+        Ok(self)
+    }
+
+    /// Trusts only the root certificates added to this config, not those
+    /// built into the TLS backend or the operating system.
+    pub fn without_built_in_roots(mut self) -> TlsConfig {
+        self.built_in_roots = false;
+        self
+    }
+
+    /// Authenticates with a client certificate, for servers that require
+    /// mutual TLS.
+    ///
+    /// `certificates` is the DER-encoded certificate chain starting with
+    /// the client certificate, `key` its DER-encoded PKCS#8 private key.
+    pub fn with_client_identity_der(
+        mut self,
+        certificates: Vec<Vec<u8>>,
+        key: Vec<u8>,
+    ) -> TlsConfig {
+        self.identity = Some(Identity { certificates, key });
+        self
+    }
+
+    /// Authenticates with a client certificate, for servers that require
+    /// mutual TLS.
+    ///
+    /// `certificates` is a PEM file holding the certificate chain starting
+    /// with the client certificate, `key` a PEM file holding its PKCS#8
+    /// private key (`BEGIN PRIVATE KEY`).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MalformedPem`](enum.Error.html#variant.MalformedPem) if
+    /// either file can't be decoded or holds no certificate or key.
+    pub fn with_client_identity_pem(
+        self,
+        certificates: &[u8],
+        key: &[u8],
+    ) -> Result<TlsConfig, Error> {
+        let certificates = pem_decode(certificates, "CERTIFICATE")?;
+        let key = pem_decode(key, "PRIVATE KEY")?.swap_remove(0);
+        Ok(self.with_client_identity_der(certificates, key))
+    }
+
+    /// Accepts only servers whose certificate holds the public key
+    /// `public_key`, or any other pinned with this method.
+    ///
+    /// `public_key` is the DER-encoded `SubjectPublicKeyInfo`, as printed by
+    /// `openssl x509 -pubkey -noout | openssl pkey -pubin -outform DER`.
+    /// The certificate is still verified as usual, unless
+    /// [`danger_accept_invalid_certs`](#method.danger_accept_invalid_certs)
+    /// is set.
+    pub fn with_pinned_public_key<T: Into<Vec<u8>>>(mut self, public_key: T) -> TlsConfig {
+        self.pinned_public_keys.push(public_key.into());
+        self
+    }
+
+    /// Accepts any server certificate, whoever issued it and whatever host
+    /// it is for.
+    ///
+    /// Anyone on the network path can then read and change the traffic, use
+    /// this only in tests. Pinned public keys are still checked.
+    pub fn danger_accept_invalid_certs(mut self) -> TlsConfig {
+        self.accept_invalid_certs = true;
+        self
+    }
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self { TlsConfig::new() }
+}
+
+impl fmt::Debug for TlsConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // The private key is left out.
+        f.debug_struct("TlsConfig")
+            .field("root_certificates", &self.root_certificates.len())
+            .field("built_in_roots", &self.built_in_roots)
+            .field("client_identity", &self.identity.is_some())
+            .field("pinned_public_keys", &self.pinned_public_keys.len())
+            .field("accept_invalid_certs", &self.accept_invalid_certs)
+            .finish()
+    }
+}
+
+/// Returns whether the DER-encoded `certificate` holds one of the `pinned` public keys.
+pub(crate) fn is_pinned(certificate: &[u8], pinned: &[Vec<u8>]) -> bool {
+    match public_key_info(certificate) {
+        Some(public_key) => pinned.iter().any(|pin| pin == public_key),
+        None => false,
+    }
+}
+
+/// Returns the `SubjectPublicKeyInfo` element of a DER-encoded X.509 certificate.
+fn public_key_info(certificate: &[u8]) -> Option<&[u8]> {
+    const SEQUENCE: u8 = 0x30;
+    // The optional `[0] EXPLICIT Version` of the `TBSCertificate`.
+    const VERSION: u8 = 0xa0;
+
+    let certificate = DerElement::parse(certificate).filter(|e| e.tag == SEQUENCE)?;
+    let tbs_certificate = DerElement::parse(certificate.contents).filter(|e| e.tag == SEQUENCE)?;
+    let mut fields = tbs_certificate.contents;
+    if fields.first() == Some(&VERSION) {
+        fields = DerElement::parse(fields)?.rest;
+    }
+    // Skip the serial number, signature algorithm, issuer, validity and subject.
+    for _ in 0..5 {
+        fields = DerElement::parse(fields)?.rest;
+    }
+    DerElement::parse(fields).filter(|e| e.tag == SEQUENCE).map(|e| e.element)
+}
+
+/// A DER element split off the start of some bytes.
+struct DerElement<'a> {
+    tag: u8,
+    /// The whole element, including the tag and length.
+    element: &'a [u8],
+    contents: &'a [u8],
+    /// The bytes after the element.
+    rest: &'a [u8],
+}
+
+impl<'a> DerElement<'a> {
+    fn parse(der: &'a [u8]) -> Option<DerElement<'a>> {
+        let tag = *der.first()?;
+        let first = *der.get(1)?;
+        let (header_len, len) = if first < 0x80 {
+            (2, usize::from(first))
+        } else {
+            let len_len = usize::from(first & 0x7f);
+            if len_len == 0 || len_len > core::mem::size_of::<usize>() {
+                return None;
+            }
+            let len = der.get(2..2 + len_len)?.iter().fold(0, |len, b| len << 8 | usize::from(*b));
+            (2 + len_len, len)
+        };
+        let end = header_len.checked_add(len)?;
+        let element = der.get(..end)?;
+        Some(DerElement { tag, element, contents: &element[header_len..], rest: &der[end..] })
+    }
+}
+
+/// Returns the contents of the `label` blocks of a PEM file, failing if there is none.
+fn pem_decode(pem: &[u8], label: &str) -> Result<Vec<Vec<u8>>, Error> {
+    let pem = core::str::from_utf8(pem).map_err(|_| Error::MalformedPem)?;
+    let begin = format!("-----BEGIN {}-----", label);
+    let end = format!("-----END {}-----", label);
+
+    let mut decoded = Vec::new();
+    let mut block: Option<String> = None;
+    for line in pem.lines().map(str::trim) {
+        match block {
+            None if line == begin => block = Some(String::new()),
+            None => {}
+            Some(ref base64) if line == end => {
+                decoded.push(base64_decode(base64).ok_or(Error::MalformedPem)?);
+                block = None;
+            }
+            Some(ref mut base64) => base64.push_str(line),
+        }
+    }
+    if block.is_some() || decoded.is_empty() {
+        return Err(Error::MalformedPem);
+    }
+    Ok(decoded)
+}
+
+/// Returns `der` as a PEM block labelled `label`.
+#[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+pub(crate) fn pem_encode(der: &[u8], label: &str) -> String {
+    let mut pem = format!("-----BEGIN {}-----\n", label);
+    for chunk in der.chunks(48) {
+        for group in chunk.chunks(3) {
+            let bits = group
+                .iter()
+                .enumerate()
+                .fold(0u32, |bits, (i, b)| bits | u32::from(*b) << (16 - 8 * i));
+            for i in 0..4 {
+                if i <= group.len() {
+                    pem.push(BASE64_ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize].into());
+                } else {
+                    pem.push('=');
+                }
+            }
+        }
+        pem.push('\n');
+    }
+    pem.push_str(&format!("-----END {}-----\n", label));
+    pem
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_decode(base64: &str) -> Option<Vec<u8>> {
+    let base64 = base64.trim_end_matches('=').as_bytes();
+    let mut decoded = Vec::with_capacity(base64.len() * 3 / 4);
+    let mut bits = 0u32;
+    let mut bit_count = 0;
+    for c in base64 {
+        let value = BASE64_ALPHABET.iter().position(|a| a == c)?;
+        bits = bits << 6 | value as u32;
+        bit_count += 6;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            decoded.push((bits >> bit_count) as u8);
+        }
+    }
+    Some(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CA: &[u8] = include_bytes!("../tests/tls/ca.pem");
+    const SERVER: &[u8] = include_bytes!("../tests/tls/server.pem");
+    const SERVER_PUBLIC_KEY: &[u8] = include_bytes!("../tests/tls/server.spki.der");
+    const CLIENT_KEY: &[u8] = include_bytes!("../tests/tls/client.key");
+
+    #[test]
+    fn decode_pem() {
+        assert_eq!(pem_decode(CA, "CERTIFICATE").unwrap().len(), 1);
+        let bundle = [CA, SERVER].concat();
+        assert_eq!(pem_decode(&bundle, "CERTIFICATE").unwrap().len(), 2);
+        assert_eq!(pem_decode(CLIENT_KEY, "PRIVATE KEY").unwrap().len(), 1);
+
+        assert!(matches!(pem_decode(CLIENT_KEY, "CERTIFICATE"), Err(Error::MalformedPem)));
+        let truncated = &CA[..CA.len() / 2];
+        assert!(matches!(pem_decode(truncated, "CERTIFICATE"), Err(Error::MalformedPem)));
+    }
+
+    #[test]
+    fn pinned_public_key() {
+        let server = pem_decode(SERVER, "CERTIFICATE").unwrap().swap_remove(0);
+        let ca = pem_decode(CA, "CERTIFICATE").unwrap().swap_remove(0);
+        assert_eq!(public_key_info(&server), Some(SERVER_PUBLIC_KEY));
+
+        let pinned = [SERVER_PUBLIC_KEY.to_vec()];
+        assert!(is_pinned(&server, &pinned));
+        assert!(!is_pinned(&ca, &pinned));
+        assert!(!is_pinned(&server[..server.len() - 1], &pinned));
+    }
+
+    #[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+    #[test]
+    fn encode_pem() {
+        for der in pem_decode(&[CA, SERVER].concat(), "CERTIFICATE").unwrap() {
+            let pem = pem_encode(&der, "CERTIFICATE");
+            assert_eq!(pem_decode(pem.as_bytes(), "CERTIFICATE").unwrap(), [der]);
+        }
+    }
+}
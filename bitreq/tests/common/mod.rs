@@ -200,3 +200,38 @@ pub fn special_url_string_strategy() -> impl Strategy<Value = String> {
             url_string
         })
 }
+
+/// Strategy for generating relative references to resolve against a URL.
+/// Covers network-path, absolute-path and relative-path references, dot segments, and
+/// references made only of a query or fragment.
+pub fn relative_reference_strategy() -> impl Strategy<Value = String> {
+    let authority = prop::option::of(host_strategy());
+    let prefix = prop::sample::select(vec!["", "/", "./", "../", "../../", "."]);
+    let path = prop::string::string_regex("([a-zA-Z0-9_~-]{1,8}(/[a-zA-Z0-9_~-]{0,8}){0,2})?")
+        .expect("valid regex");
+    (authority, prefix, path, query_strategy(), fragment_strategy()).prop_map(
+        |(authority, prefix, path, query, fragment)| {
+            let mut reference = String::new();
+            match authority {
+                Some(host) => {
+                    reference.push_str("//");
+                    reference.push_str(&host);
+                    if !path.is_empty() {
+                        reference.push('/');
+                    }
+                }
+                None => reference.push_str(prefix),
+            }
+            reference.push_str(&path);
+            if let Some(q) = query {
+                reference.push('?');
+                reference.push_str(&q);
+            }
+            if let Some(f) = fragment {
+                reference.push('#');
+                reference.push_str(&f);
+            }
+            reference
+        },
+    )
+}
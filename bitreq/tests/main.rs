@@ -4,6 +4,7 @@ extern crate bitreq;
 mod setup;
 
 use std::io;
+use std::time::Duration;
 
 use self::setup::*;
 
@@ -48,6 +49,44 @@ async fn test_timeout_high_enough() {
     assert_eq!(result.as_str().unwrap(), "j: Q");
 }
 
+#[tokio::test]
+async fn test_read_timeout() {
+    let slow = trickle_server(Duration::from_secs(1));
+    let request = bitreq::get(&slow).with_read_timeout(Duration::from_millis(300));
+    let result = maybe_make_request(request, true).await;
+    assert!(matches!(result, Err(bitreq::Error::ReadTimeout)), "{:?}", result);
+
+    // The timeout of the whole request is reached first.
+    let request = bitreq::get(&slow)
+        .with_read_timeout(Duration::from_secs(5))
+        .with_total_timeout(Duration::from_millis(500));
+    match maybe_make_request(request, true).await {
+        Err(bitreq::Error::IoError(err)) => assert_eq!(err.kind(), io::ErrorKind::TimedOut),
+        result => panic!("expected the request to time out: {:?}", result),
+    }
+
+    // The response takes longer than the read timeout, but keeps arriving.
+    let trickle = trickle_server(Duration::from_millis(50));
+    let request = bitreq::get(&trickle).with_read_timeout(Duration::from_millis(300));
+    let response = maybe_make_request(request, true).await.unwrap();
+    assert_eq!(response.as_str().unwrap(), "0123456789");
+}
+
+#[tokio::test]
+#[cfg(feature = "proxy")]
+async fn test_connect_timeout() {
+    setup();
+    // The proxy accepts the connection but never answers the CONNECT request.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let proxy = bitreq::Proxy::new_http(listener.local_addr().unwrap().to_string()).unwrap();
+    let request = bitreq::get(url("/a"))
+        .with_proxy(proxy)
+        .with_connect_timeout(Duration::from_millis(200))
+        .with_total_timeout(Duration::from_secs(5));
+    let result = maybe_make_request(request, true).await;
+    assert!(matches!(result, Err(bitreq::Error::ConnectTimeout)), "{:?}", result);
+}
+
 #[tokio::test]
 async fn test_headers() {
     setup();
@@ -55,6 +94,23 @@ async fn test_headers() {
     assert_eq!("Qwerty", body);
 }
 
+#[tokio::test]
+async fn test_repeated_headers() {
+    setup();
+    let response = make_request(bitreq::get(url("/repeated_headers"))).await;
+    let cookies: Vec<_> = response.headers.get_all("Set-Cookie").collect();
+    assert_eq!(cookies, ["a=1", "b=2"]);
+    assert_eq!(response.headers.get("set-cookie").unwrap(), "b=2");
+    let names = response.headers.keys().filter(|name| *name == "set-cookie" || *name == "via");
+    assert_eq!(names.collect::<Vec<_>>(), ["set-cookie", "via", "set-cookie"]);
+    assert_eq!(response.headers.to_map()["set-cookie"], "b=2");
+
+    let mut lazy = bitreq::get(url("/repeated_headers")).send_lazy().unwrap();
+    assert_eq!(lazy.headers.get_all("set-cookie").count(), 2);
+    assert_eq!(lazy.headers.get("set-cookie").unwrap(), "b=2");
+    std::io::copy(&mut lazy, &mut std::io::sink()).unwrap();
+}
+
 #[tokio::test]
 async fn test_custom_method() {
     use bitreq::Method;
@@ -175,6 +231,51 @@ async fn test_patch() {
     assert_eq!(body, "r: O");
 }
 
+#[tokio::test]
+async fn test_body_reader() {
+    setup();
+    let body: Vec<u8> = (0..100_000u32).map(|i| b'a' + (i % 26) as u8).collect();
+    for length in [Some(body.len() as u64), None] {
+        let request =
+            || bitreq::post(url("/echo")).with_body_reader(io::Cursor::new(body.clone()), length);
+        assert_eq!(request().send().unwrap().as_bytes(), body);
+        let mut lazy = Vec::new();
+        io::Read::read_to_end(&mut request().send_lazy().unwrap(), &mut lazy).unwrap();
+        assert_eq!(lazy, body);
+        assert_eq!(bitreq::Client::new(1).send(request()).unwrap().as_bytes(), body);
+        #[cfg(feature = "async")]
+        {
+            assert_eq!(request().send_async().await.unwrap().as_bytes(), body);
+            let request = bitreq::post(url("/echo"))
+                .with_body_async_reader(io::Cursor::new(body.clone()), length);
+            assert_eq!(request.send_async().await.unwrap().as_bytes(), body);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_body_reader_not_replayable() {
+    setup();
+    let request = bitreq::post(url("/echo")).with_body_reader(&b"Q"[..], 1);
+    assert_eq!(request.clone().send().unwrap().as_str().unwrap(), "Q");
+    assert!(matches!(request.send(), Err(bitreq::Error::BodyNotReplayable)));
+
+    // A 301 sends the body again, a 303 turns the POST into a GET without one.
+    let request = bitreq::get(url("/redirect")).with_body_reader(&b"Q"[..], None);
+    assert!(matches!(request.send(), Err(bitreq::Error::BodyNotReplayable)));
+    let request = bitreq::post(url("/redirect")).with_body_reader(&b"Q"[..], 1);
+    assert_eq!(request.send().unwrap().as_str().unwrap(), "j: ");
+
+    #[cfg(feature = "async")]
+    {
+        let request = bitreq::post(url("/echo")).with_body_async_reader(&b"Q"[..], 1);
+        assert!(matches!(request.clone().send(), Err(bitreq::Error::AsyncBodyReader)));
+        assert_eq!(request.send_async().await.unwrap().as_str().unwrap(), "Q");
+        let request = bitreq::get(url("/redirect")).with_body_async_reader(&b"Q"[..], 1);
+        assert!(matches!(request.send_async().await, Err(bitreq::Error::BodyNotReplayable)));
+    }
+}
+
 #[tokio::test]
 async fn tcp_connect_timeout() {
     let _listener = std::net::TcpListener::bind("127.0.0.1:32162").unwrap();
@@ -235,7 +336,7 @@ async fn test_massive_content_length() {
 async fn test_future_drop_doesnt_hang() {
     // Test that if a pipelined request on a connection isn't read (by dropping the `Future`) later
     // requests on the same connection immediately get retried on a fresh connection.
-    use std::time::{Duration, Instant};
+    use std::time::Instant;
 
     setup();
     let client = bitreq::Client::new(2);
@@ -267,3 +368,421 @@ async fn test_future_drop_doesnt_hang() {
         "Request should complete quickly, and certainly not have to wait for its timeout to try again"
     );
 }
+
+#[test]
+fn test_client_reuses_connections() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    use bitreq::RequestExt;
+
+    // Answers two requests per connection, then closes it.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    let connections = Arc::new(AtomicUsize::new(0));
+    let accepted = Arc::clone(&connections);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            accepted.fetch_add(1, Ordering::SeqCst);
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            for _ in 0..2 {
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 0 && line != "\r\n" {
+                    line.clear();
+                }
+                stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok").unwrap();
+            }
+        }
+    });
+
+    let client = bitreq::Client::new(1);
+    for i in 1..=4_usize {
+        let response = bitreq::get(&url).with_timeout(5).send_with_client(&client).unwrap();
+        assert_eq!(response.as_str().unwrap(), "ok");
+        assert_eq!(connections.load(Ordering::SeqCst), i.div_ceil(2));
+    }
+}
+
+#[test]
+fn test_client_matches_send() {
+    setup();
+    let client = bitreq::Client::new(4);
+    let requests = [
+        bitreq::get(url("/a")).with_body("Q"),
+        bitreq::post(url("/c")).with_body("E"),
+        bitreq::head(url("/b")),
+        bitreq::post(url("/redirect")).with_body("Q"),
+        bitreq::get(url("/relativeredirect")).with_body("Q"),
+        bitreq::get(url("/header_pong")).with_header("Ping", "Qwerty"),
+        bitreq::get(url("/infiniteredirect")),
+    ];
+    // Twice, to send on the connections kept by the client too.
+    for request in requests.iter().chain(&requests) {
+        match (request.clone().send(), client.send(request.clone())) {
+            (Ok(resp), Ok(client_resp)) => {
+                assert_eq!(client_resp.status_code, resp.status_code);
+                assert_eq!(client_resp.reason_phrase, resp.reason_phrase);
+                assert_eq!(client_resp.as_bytes(), resp.as_bytes());
+            }
+            (Err(e), Err(client_e)) => assert_eq!(format!("{e:?}"), format!("{client_e:?}")),
+            (res, client_res) => panic!("{res:?} != {client_res:?}"),
+        }
+    }
+}
+
+#[test]
+fn test_client_resends_only_idempotent_requests() {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    // Answers the first request of each connection, then reads the second one and closes the
+    // connection without answering.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    let requests = Arc::new(AtomicUsize::new(0));
+    let received = Arc::clone(&requests);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let received = Arc::clone(&received);
+            thread::spawn(move || {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                for answer in [true, false] {
+                    let mut content_length = 0;
+                    let mut line = String::new();
+                    while reader.read_line(&mut line).unwrap() > 0 && line != "\r\n" {
+                        if let Some(length) =
+                            line.to_ascii_lowercase().strip_prefix("content-length:")
+                        {
+                            content_length = length.trim().parse().unwrap();
+                        }
+                        line.clear();
+                    }
+                    reader.read_exact(&mut vec![0; content_length]).unwrap();
+                    received.fetch_add(1, Ordering::SeqCst);
+                    if answer {
+                        stream
+                            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+                            .unwrap();
+                    }
+                }
+            });
+        }
+    });
+
+    let client = bitreq::Client::new(1);
+    for _ in 0..2 {
+        let response = client.send(bitreq::get(&url).with_timeout(5)).unwrap();
+        assert_eq!(response.as_str().unwrap(), "ok");
+    }
+    // The second GET was sent again on a new connection.
+    assert_eq!(requests.load(Ordering::SeqCst), 3);
+
+    let client = bitreq::Client::new(1);
+    client.send(bitreq::post(&url).with_body("1").with_timeout(5)).unwrap();
+    assert!(client.send(bitreq::post(&url).with_body("2").with_timeout(5)).is_err());
+    // The server may have acted on the second POST, so it wasn't sent again.
+    assert_eq!(requests.load(Ordering::SeqCst), 5);
+}
+
+#[tokio::test]
+async fn test_middleware() {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    /// Adds a header and records the URLs and status codes of responses.
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<(String, i32)>>);
+
+    impl bitreq::Middleware for Recorder {
+        fn on_request(&self, request: bitreq::Request) -> Result<bitreq::Request, bitreq::Error> {
+            Ok(request.with_header("Ping", "Middleware"))
+        }
+
+        fn on_response(&self, response: Result<&bitreq::Response, &bitreq::Error>, _: Duration) {
+            let response = response.unwrap();
+            self.0.lock().unwrap().push((response.url.clone(), response.status_code));
+        }
+    }
+
+    struct Reject;
+
+    impl bitreq::Middleware for Reject {
+        fn on_request(&self, _: bitreq::Request) -> Result<bitreq::Request, bitreq::Error> {
+            Err(bitreq::Error::Other("rejected"))
+        }
+
+        fn on_response(&self, _: Result<&bitreq::Response, &bitreq::Error>, _: Duration) {
+            panic!("the request was rejected");
+        }
+    }
+
+    setup();
+    let recorder = Arc::new(Recorder::default());
+    let request = bitreq::get(url("/header_pong")).with_middleware(recorder.clone());
+    assert_eq!(get_body(request).await, "Middleware");
+
+    // The hooks run once around redirects, and see the final response.
+    recorder.0.lock().unwrap().clear();
+    let client = bitreq::Client::new(1).with_middleware(recorder.clone());
+    let response = client.send(bitreq::get(url("/redirect"))).unwrap();
+    assert_eq!(response.as_str().unwrap(), "j: ");
+    bitreq::get(url("/redirect")).with_middleware(recorder.clone()).send().unwrap();
+    assert_eq!(*recorder.0.lock().unwrap(), vec![(url("/a"), 200), (url("/a"), 200)]);
+
+    // A failing middleware aborts the request, no response hook runs.
+    let request = bitreq::get(url("/header_pong")).with_middleware(Arc::new(Reject));
+    assert!(matches!(client.send(request), Err(bitreq::Error::Other("rejected"))));
+    assert_eq!(recorder.0.lock().unwrap().len(), 2);
+}
+
+#[tokio::test]
+#[cfg(unix)]
+async fn test_unix_socket() {
+    let path = unix_server();
+    let request = bitreq::get("http://localhost/a?b=c").with_unix_socket(&path);
+    assert_eq!(get_body(request).await, "localhost /a?b=c");
+    let request = bitreq::post("http://example.com:8080/").with_unix_socket(&path);
+    assert_eq!(get_body(request).await, "example.com:8080 /");
+
+    let client = bitreq::Client::new(1);
+    for resource in ["/a", "/b"] {
+        let request = bitreq::get(format!("http://localhost{}", resource)).with_unix_socket(&path);
+        assert_eq!(
+            client.send(request).unwrap().as_str().unwrap(),
+            format!("localhost {}", resource)
+        );
+    }
+
+    let request = bitreq::get("https://localhost/").with_unix_socket(&path);
+    assert!(matches!(request.send(), Err(bitreq::Error::HttpsOverUnixSocket)));
+    let request = bitreq::get("http://localhost/").with_unix_socket(path.join("missing"));
+    assert!(matches!(request.send(), Err(bitreq::Error::IoError(_))));
+}
+
+#[tokio::test]
+#[cfg(any(feature = "rustls", feature = "native-tls"))]
+async fn test_tls_config() {
+    use bitreq::TlsConfig;
+
+    let url = https_server(false);
+    let ca = TlsConfig::new().with_root_certificates_pem(include_bytes!("tls/ca.pem")).unwrap();
+
+    // The test CA is not trusted by default.
+    assert!(bitreq::get(&url).with_timeout(5).send().is_err());
+    assert_eq!(get_body(bitreq::get(&url).with_tls_config(ca.clone())).await, "ok");
+    let only_ca = TlsConfig::new()
+        .with_root_certificate_der(&include_bytes!("tls/ca.der")[..])
+        .without_built_in_roots();
+    assert_eq!(get_body(bitreq::get(&url).with_tls_config(only_ca)).await, "ok");
+    let insecure = TlsConfig::new().danger_accept_invalid_certs();
+    assert_eq!(get_body(bitreq::get(&url).with_tls_config(insecure)).await, "ok");
+
+    let pinned = ca.clone().with_pinned_public_key(&include_bytes!("tls/server.spki.der")[..]);
+    assert_eq!(get_body(bitreq::get(&url).with_tls_config(pinned)).await, "ok");
+    let wrong_pin = ca.clone().with_pinned_public_key(&b"not the server key"[..]);
+    assert!(bitreq::get(&url).with_tls_config(wrong_pin).with_timeout(5).send().is_err());
+
+    // Requests without their own settings use those of the client.
+    let client = bitreq::Client::new(1).with_tls_config(ca);
+    assert_eq!(client.send(bitreq::get(&url)).unwrap().as_str().unwrap(), "ok");
+    let insecure = TlsConfig::new().danger_accept_invalid_certs().without_built_in_roots();
+    let response = client.send(bitreq::get(&url).with_tls_config(insecure)).unwrap();
+    assert_eq!(response.as_str().unwrap(), "ok");
+}
+
+#[tokio::test]
+#[cfg(feature = "rustls")]
+async fn test_tls_client_certificate() {
+    use bitreq::TlsConfig;
+
+    let url = https_server(true);
+    let ca = TlsConfig::new().with_root_certificates_pem(include_bytes!("tls/ca.pem")).unwrap();
+    assert!(bitreq::get(&url).with_tls_config(ca.clone()).with_timeout(5).send().is_err());
+
+    let identity = ca
+        .with_client_identity_pem(
+            include_bytes!("tls/client.pem"),
+            include_bytes!("tls/client.key"),
+        )
+        .unwrap();
+    assert_eq!(get_body(bitreq::get(&url).with_tls_config(identity)).await, "ok");
+}
+
+#[tokio::test]
+#[cfg(feature = "compression")]
+async fn test_compression() {
+    setup();
+    for path in ["/gzip", "/deflate"] {
+        let response = make_request(bitreq::get(url(path)).with_body("Q")).await;
+        assert_eq!(response.as_str().unwrap(), "j: Q".repeat(1000));
+        assert_eq!(response.headers.get("content-encoding"), None);
+
+        // The limit applies to the decoded body, which is larger than the encoded one.
+        let request = bitreq::get(url(path)).with_body("Q").with_max_body_size(3999);
+        assert!(matches!(request.clone().send(), Err(bitreq::Error::BodyOverflow)));
+        let mut lazy = request.clone().send_lazy().unwrap();
+        assert!(matches!(lazy.find_map(Result::err), Some(bitreq::Error::BodyOverflow)));
+        #[cfg(feature = "async")]
+        assert!(matches!(request.send_async().await, Err(bitreq::Error::BodyOverflow)));
+    }
+}
+
+#[tokio::test]
+#[cfg(feature = "compression")]
+async fn test_compression_without_body() {
+    setup();
+    // A HEAD response describes the encoded body it would have.
+    let response = make_request(bitreq::head(url("/gzip")).with_body("Q")).await;
+    assert_eq!(response.status_code, 200);
+    assert_eq!(response.as_bytes(), b"");
+    assert_eq!(response.headers.get("content-encoding").map(String::as_str), Some("gzip"));
+    let content_length = response.headers.get("content-length").unwrap();
+    assert_ne!(content_length, "0");
+    let lazy = bitreq::head(url("/gzip")).with_body("Q").send_lazy().unwrap();
+    assert_eq!(lazy.headers.get("content-length"), Some(content_length));
+    assert_eq!(lazy.count(), 0);
+
+    for (path, status_code) in [("/gzip_empty", 200), ("/gzip_no_content", 204)] {
+        let response = make_request(bitreq::get(url(path))).await;
+        assert_eq!(response.status_code, status_code);
+        assert_eq!(response.as_bytes(), b"");
+        assert_eq!(response.headers.get("content-encoding").map(String::as_str), Some("gzip"));
+        let mut lazy = bitreq::get(url(path)).send_lazy().unwrap();
+        assert!(lazy.next().is_none());
+    }
+}
+
+#[tokio::test]
+#[cfg(feature = "proxy")]
+async fn test_socks5_proxy() {
+    setup();
+    let addr = socks5_proxy("user", "pass");
+
+    let proxy = bitreq::Proxy::new_socks5(format!("socks5h://user:pass@{}", addr)).unwrap();
+    let body = get_body(bitreq::get(url("/a")).with_body("Q").with_proxy(proxy)).await;
+    assert_eq!(body, "j: Q");
+
+    let proxy = bitreq::Proxy::new_socks5(format!("user:wrong@{}", addr)).unwrap();
+    let err = bitreq::get(url("/a")).with_proxy(proxy).send().unwrap_err();
+    assert!(matches!(err, bitreq::Error::InvalidProxyCreds));
+}
+
+/// The body served to downloads and its SHA-256 hash.
+fn download_body() -> (Vec<u8>, [u8; 32]) {
+    let body = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+    let hex = "cd2df694e424bc7968cc37f47751019e5ca0cd1bdf2e479ea537c3a1c32ee1aa";
+    let mut hash = [0; 32];
+    for (i, byte) in hash.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).unwrap();
+    }
+    (body, hash)
+}
+
+/// Returns where to download to, and the `.part` file next to it.
+fn download_paths(name: &str) -> (std::path::PathBuf, std::path::PathBuf) {
+    let path = std::env::temp_dir().join(format!("bitreq-{}-{}", std::process::id(), name));
+    (path.clone(), path.with_extension("part"))
+}
+
+#[test]
+fn test_download_resumes() {
+    use std::sync::{Arc, Mutex};
+
+    let (body, hash) = download_body();
+    let url = flaky_server(body.clone(), 30_000);
+    let (path, part_path) = download_paths("resumes");
+    let progress = Arc::new(Mutex::new(Vec::new()));
+    let reported = progress.clone();
+
+    let length = bitreq::Download::new(bitreq::get(url), &path)
+        .with_sha256(hash)
+        .with_progress(move |progress| reported.lock().unwrap().push(progress))
+        .send()
+        .unwrap();
+    assert_eq!(length, 100_000);
+    assert_eq!(std::fs::read(&path).unwrap(), body);
+    assert!(!part_path.exists());
+
+    // Each of the four requests reports where it starts from.
+    let progress = progress.lock().unwrap();
+    let mut starts: Vec<_> = progress
+        .iter()
+        .filter(|progress| progress.downloaded % 30_000 == 0)
+        .map(|progress| progress.downloaded)
+        .collect();
+    starts.dedup();
+    assert_eq!(starts, [0, 30_000, 60_000, 90_000]);
+    assert!(progress.iter().all(|progress| progress.total == Some(100_000)));
+    assert_eq!(progress.last().unwrap().downloaded, 100_000);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_download_resumes_part_file() {
+    use std::sync::{Arc, Mutex};
+
+    let (body, hash) = download_body();
+    let url = flaky_server(body.clone(), usize::MAX);
+    let (path, part_path) = download_paths("part-file");
+    let validator_path = part_path.with_extension("part.validator");
+
+    // Only the version of the body the `.part` file holds is resumed, an unknown one is
+    // downloaded again.
+    for (validator, start) in [(Some("\"v1\""), 50_000), (Some("\"v0\""), 0), (None, 0)] {
+        std::fs::write(&part_path, &body[..50_000]).unwrap();
+        match validator {
+            Some(validator) => std::fs::write(&validator_path, validator).unwrap(),
+            None => assert!(!validator_path.exists()),
+        }
+        let progress = Arc::new(Mutex::new(Vec::new()));
+        let reported = progress.clone();
+
+        bitreq::Download::new(bitreq::get(&url), &path)
+            .with_sha256(hash)
+            .with_progress(move |progress| reported.lock().unwrap().push(progress.downloaded))
+            .send()
+            .unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), body);
+        assert_eq!(progress.lock().unwrap().first(), Some(&start), "{:?}", validator);
+        assert!(!validator_path.exists());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
+
+#[test]
+fn test_download_checksum_mismatch() {
+    let (body, _) = download_body();
+    let url = flaky_server(body, 60_000);
+    let (path, part_path) = download_paths("mismatch");
+
+    let result = bitreq::Download::new(bitreq::get(url), &path).with_sha256([0; 32]).send();
+    assert!(matches!(result, Err(bitreq::Error::ChecksumMismatch)), "{:?}", result);
+    assert!(!path.exists());
+    assert!(!part_path.exists());
+    assert!(!part_path.with_extension("part.validator").exists());
+}
+
+#[test]
+fn test_download_gives_up() {
+    let (body, _) = download_body();
+    let url = flaky_server(body, 0);
+    let (path, part_path) = download_paths("gives-up");
+
+    let result = bitreq::Download::new(bitreq::get(url), &path).with_max_retries(3).send();
+    assert!(
+        matches!(&result, Err(bitreq::Error::IoError(err)) if err.kind() == io::ErrorKind::UnexpectedEof),
+        "{:?}",
+        result
+    );
+    assert!(!path.exists());
+    std::fs::remove_file(part_path.with_extension("part.validator")).unwrap();
+    std::fs::remove_file(part_path).unwrap();
+}
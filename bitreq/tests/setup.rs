@@ -59,14 +59,11 @@ pub fn setup() {
                 }
                 match request.method() {
                     Method::Get if url == "/header_pong" => {
-                        for header in headers {
-                            if header.field.as_str() == "Ping" {
-                                let response = Response::from_string(format!("{}", header.value));
-                                respond!(response);
-                                return;
-                            }
+                        match headers.iter().find(|header| header.field.as_str() == "Ping") {
+                            Some(header) =>
+                                respond!(Response::from_string(format!("{}", header.value))),
+                            None => respond!(Response::from_string("No header!")),
                         }
-                        respond!(Response::from_string("No header!"));
                     }
 
                     Method::Get if url == "/slow_a" => {
@@ -154,6 +151,55 @@ pub fn setup() {
                         respond!(response);
                     }
 
+                    #[cfg(feature = "compression")]
+                    Method::Get | Method::Head if url == "/gzip" || url == "/deflate" => {
+                        use std::io::Write;
+
+                        let encoding = &url[1..];
+                        let accepted = headers.iter().any(|header| {
+                            header.field.equiv("Accept-Encoding")
+                                && header.value.as_str().split(',').any(|e| e.trim() == encoding)
+                        });
+                        if !accepted {
+                            respond!(Response::from_string("Not accepted").with_status_code(406));
+                            continue;
+                        }
+                        let body = format!("j: {}", content).repeat(1000);
+                        let compressed = if encoding == "gzip" {
+                            let mut encoder = flate2::write::GzEncoder::new(
+                                Vec::new(),
+                                flate2::Compression::default(),
+                            );
+                            encoder.write_all(body.as_bytes()).unwrap();
+                            encoder.finish().unwrap()
+                        } else {
+                            let mut encoder = flate2::write::ZlibEncoder::new(
+                                Vec::new(),
+                                flate2::Compression::default(),
+                            );
+                            encoder.write_all(body.as_bytes()).unwrap();
+                            encoder.finish().unwrap()
+                        };
+                        let header =
+                            Header::from_bytes(&b"Content-Encoding"[..], encoding).unwrap();
+                        respond!(Response::from_data(compressed).with_header(header));
+                    }
+
+                    #[cfg(feature = "compression")]
+                    Method::Get if url == "/gzip_empty" || url == "/gzip_no_content" => {
+                        let status = if url == "/gzip_empty" { 200 } else { 204 };
+                        let header = Header::from_bytes(&b"Content-Encoding"[..], "gzip").unwrap();
+                        respond!(Response::empty(status).with_header(header));
+                    }
+
+                    Method::Get if url == "/repeated_headers" => {
+                        let mut response = Response::from_string("cookies");
+                        for header in ["Set-Cookie: a=1", "Via: 1.1 first", "Set-Cookie: b=2"] {
+                            response.add_header(Header::from_str(header).unwrap());
+                        }
+                        respond!(response);
+                    }
+
                     Method::Post if url == "/echo" => {
                         respond!(Response::from_string(content));
                     }
@@ -201,6 +247,259 @@ pub fn setup() {
 
 pub fn url(req: &str) -> String { format!("http://localhost:35562{}", req) }
 
+/// Starts a SOCKS5 proxy accepting `user` and `pass`, returns its address.
+///
+/// Host names are resolved by the proxy, like Tor does for `.onion` hosts.
+#[cfg(feature = "proxy")]
+pub fn socks5_proxy(user: &'static str, pass: &'static str) -> std::net::SocketAddr {
+    use std::io::{self, Write};
+    use std::net::{TcpListener, TcpStream};
+
+    fn handshake(client: &mut TcpStream, user: &str, pass: &str) -> io::Result<TcpStream> {
+        let read = |client: &mut TcpStream, len: usize| -> io::Result<Vec<u8>> {
+            let mut buf = vec![0; len];
+            client.read_exact(&mut buf)?;
+            Ok(buf)
+        };
+
+        let greeting = read(client, 2)?;
+        let methods = read(client, greeting[1].into())?;
+        if !methods.contains(&2) {
+            client.write_all(&[5, 0xff])?;
+            return Err(io::ErrorKind::PermissionDenied.into());
+        }
+        client.write_all(&[5, 2])?;
+
+        let user_len = read(client, 2)?[1];
+        let client_user = read(client, user_len.into())?;
+        let pass_len = read(client, 1)?[0];
+        let client_pass = read(client, pass_len.into())?;
+        if client_user != user.as_bytes() || client_pass != pass.as_bytes() {
+            client.write_all(&[1, 1])?;
+            return Err(io::ErrorKind::PermissionDenied.into());
+        }
+        client.write_all(&[1, 0])?;
+
+        let request = read(client, 5)?;
+        assert_eq!(request[..4], [5, 1, 0, 3], "expected a host name to resolve");
+        let host = String::from_utf8(read(client, request[4].into())?).unwrap();
+        let port = read(client, 2)?;
+        let server = TcpStream::connect((host.as_str(), u16::from_be_bytes([port[0], port[1]])))?;
+        client.write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 0])?;
+        Ok(server)
+    }
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for client in listener.incoming() {
+            let mut client = client.unwrap();
+            thread::spawn(move || {
+                if let Ok(mut server) = handshake(&mut client, user, pass) {
+                    let mut client_reader = client.try_clone().unwrap();
+                    let mut server_writer = server.try_clone().unwrap();
+                    thread::spawn(move || io::copy(&mut client_reader, &mut server_writer));
+                    let _ = io::copy(&mut server, &mut client);
+                }
+            });
+        }
+    });
+    addr
+}
+
+/// Starts an HTTP server sending its response slowly, returns its URL.
+///
+/// It answers every request with `0123456789`, waiting `delay` before sending the headers and
+/// each byte of the body.
+pub fn trickle_server(delay: Duration) -> String {
+    use std::io::Write;
+    use std::net::{TcpListener, TcpStream};
+
+    fn respond(mut client: TcpStream, delay: Duration) -> std::io::Result<()> {
+        let mut request = Vec::new();
+        let mut byte = [0];
+        while !request.ends_with(b"\r\n\r\n") {
+            client.read_exact(&mut byte)?;
+            request.push(byte[0]);
+        }
+        thread::sleep(delay);
+        client.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\nConnection: close\r\n\r\n")?;
+        for byte in b"0123456789" {
+            thread::sleep(delay);
+            client.write_all(&[*byte])?;
+        }
+        Ok(())
+    }
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for client in listener.incoming() {
+            let client = client.unwrap();
+            thread::spawn(move || respond(client, delay));
+        }
+    });
+    format!("http://{}", addr)
+}
+
+/// Starts an HTTP server dropping connections mid-transfer, returns its URL.
+///
+/// It answers every request with `body`, or the part of it asked for with a `Range: bytes=<start>-`
+/// header unless an `If-Range` header names another version than its `ETag` of `"v1"`, but closes
+/// the connection after sending `drop_after` bytes of it.
+pub fn flaky_server(body: Vec<u8>, drop_after: usize) -> String {
+    use std::io::Write;
+    use std::net::{TcpListener, TcpStream};
+
+    fn respond(mut client: TcpStream, body: &[u8], drop_after: usize) -> std::io::Result<()> {
+        let mut request = Vec::new();
+        let mut byte = [0];
+        while !request.ends_with(b"\r\n\r\n") {
+            client.read_exact(&mut byte)?;
+            request.push(byte[0]);
+        }
+        let request = String::from_utf8_lossy(&request).to_lowercase();
+        let header = |name: &str| request.lines().find_map(|line| line.strip_prefix(name));
+        let start = header("range: bytes=")
+            .and_then(|range| range.trim_end_matches('-').parse::<usize>().ok())
+            .filter(|_| header("if-range: ").map_or(true, |version| version == "\"v1\""));
+
+        let part = match start {
+            Some(start) => {
+                write!(
+                    client,
+                    "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}-{}/{}\r\n",
+                    start,
+                    body.len() - 1,
+                    body.len()
+                )?;
+                &body[start..]
+            }
+            None => {
+                write!(client, "HTTP/1.1 200 OK\r\n")?;
+                body
+            }
+        };
+        write!(client, "Content-Length: {}\r\nETag: \"v1\"\r\n\r\n", part.len())?;
+        client.write_all(&part[..part.len().min(drop_after)])
+    }
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let body = Arc::new(body);
+    thread::spawn(move || {
+        for client in listener.incoming() {
+            let client = client.unwrap();
+            let body = body.clone();
+            thread::spawn(move || respond(client, &body, drop_after));
+        }
+    });
+    format!("http://{}", addr)
+}
+
+/// Starts an HTTP server on a new Unix domain socket, returns the socket's path.
+///
+/// It answers every request with its `Host` header and resource.
+#[cfg(unix)]
+pub fn unix_server() -> std::path::PathBuf {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static SERVERS: AtomicUsize = AtomicUsize::new(0);
+    let name =
+        format!("bitreq-{}-{}.sock", std::process::id(), SERVERS.fetch_add(1, Ordering::Relaxed));
+    let path = std::env::temp_dir().join(name);
+    let _ = std::fs::remove_file(&path);
+    let server = Arc::new(Server::http_unix(&path).unwrap());
+    for _ in 0..4 {
+        let server = server.clone();
+        thread::spawn(move || {
+            while let Ok(request) = server.recv() {
+                let host = request.headers().iter().find(|header| header.field.equiv("Host"));
+                let host = host.map(|header| header.value.to_string()).unwrap_or_default();
+                let body = format!("{} {}", host, request.url());
+                request.respond(Response::from_string(body)).unwrap();
+            }
+        });
+    }
+    path
+}
+
+/// Starts an HTTPS server for `localhost` using the certificates in `tests/tls`, returns its URL.
+///
+/// It answers every request with `ok`. If `client_auth` is set, it only accepts clients
+/// authenticating with a certificate issued by the test CA.
+#[cfg(any(feature = "rustls", feature = "native-tls"))]
+pub fn https_server(client_auth: bool) -> String {
+    use std::io::Write;
+    use std::net::{TcpListener, TcpStream};
+
+    fn serve<S: Read + Write>(stream: &mut S) -> std::io::Result<()> {
+        let mut request = Vec::new();
+        let mut buf = [0; 1024];
+        while !request.ends_with(b"\r\n\r\n") {
+            let n = stream.read(&mut buf)?;
+            if n == 0 {
+                return Ok(());
+            }
+            request.extend_from_slice(&buf[..n]);
+        }
+        stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok")?;
+        stream.flush()
+    }
+
+    #[cfg(feature = "rustls")]
+    let accept = {
+        let builder = rustls::ServerConfig::builder().with_safe_defaults();
+        let builder = if client_auth {
+            let mut roots = rustls::RootCertStore::empty();
+            roots.add(&rustls::Certificate(include_bytes!("tls/ca.der").to_vec())).unwrap();
+            let verifier = rustls::server::AllowAnyAuthenticatedClient::new(roots).boxed();
+            builder.with_client_cert_verifier(verifier)
+        } else {
+            builder.with_no_client_auth()
+        };
+        let certificates = vec![rustls::Certificate(include_bytes!("tls/server.der").to_vec())];
+        let key = rustls::PrivateKey(include_bytes!("tls/server.key.der").to_vec());
+        let config = Arc::new(builder.with_single_cert(certificates, key).unwrap());
+        move |tcp: TcpStream| {
+            let connection = rustls::ServerConnection::new(Arc::clone(&config)).unwrap();
+            let mut stream = rustls::StreamOwned::new(connection, tcp);
+            if serve(&mut stream).is_ok() {
+                stream.conn.send_close_notify();
+                let _ = stream.flush();
+            }
+        }
+    };
+    #[cfg(not(feature = "rustls"))]
+    let accept = {
+        // native-tls can't ask for client certificates.
+        assert!(!client_auth, "client authentication requires the rustls feature");
+        let identity = native_tls::Identity::from_pkcs8(
+            include_bytes!("tls/server.pem"),
+            include_bytes!("tls/server.key"),
+        )
+        .unwrap();
+        let acceptor = native_tls::TlsAcceptor::new(identity).unwrap();
+        move |tcp: TcpStream| {
+            if let Ok(mut stream) = acceptor.accept(tcp) {
+                let _ = serve(&mut stream);
+            }
+        }
+    };
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("https://localhost:{}/", listener.local_addr().unwrap().port());
+    let accept = Arc::new(accept);
+    thread::spawn(move || {
+        for tcp in listener.incoming() {
+            let accept = Arc::clone(&accept);
+            let tcp = tcp.unwrap();
+            thread::spawn(move || accept(tcp));
+        }
+    });
+    url
+}
+
 #[cfg(feature = "async")]
 static CLIENT: std::sync::OnceLock<bitreq::Client> = std::sync::OnceLock::new();
 #[cfg(feature = "async")]
@@ -0,0 +1,44 @@
+#!/usr/bin/env bash
+#
+# Generates the certificates used by the TLS tests: a CA, a certificate for
+# `localhost` signed by it and a client certificate for mutual TLS.
+
+set -euo pipefail
+
+cd "$(dirname "$0")"
+tmp=$(mktemp -d)
+trap 'rm -rf "$tmp"' EXIT
+
+key() { openssl genpkey -algorithm EC -pkeyopt ec_paramgen_curve:P-256 -out "$1"; }
+
+key ca.key
+openssl req -x509 -new -key ca.key -subj "/CN=bitreq test CA" -days 36500 -out ca.pem \
+    -addext "basicConstraints=critical,CA:TRUE" -addext "keyUsage=critical,keyCertSign,cRLSign"
+
+# sign <name> <subject> <extensions>
+sign() {
+    key "$1.key"
+    openssl req -new -key "$1.key" -subj "/CN=$2" -out "$tmp/$1.csr"
+    printf '%s\n' "$3" > "$tmp/$1.ext"
+    openssl x509 -req -in "$tmp/$1.csr" -CA ca.pem -CAkey ca.key -CAcreateserial \
+        -CAserial "$tmp/ca.srl" -days 36500 -extfile "$tmp/$1.ext" -out "$1.pem"
+}
+
+sign server localhost "basicConstraints=CA:FALSE
+keyUsage=critical,digitalSignature
+extendedKeyUsage=serverAuth
+subjectAltName=DNS:localhost,IP:127.0.0.1"
+
+sign client "bitreq test client" "basicConstraints=CA:FALSE
+keyUsage=critical,digitalSignature
+extendedKeyUsage=clientAuth"
+
+rm ca.key
+
+# The public key of the server certificate, for pinning.
+openssl x509 -in server.pem -pubkey -noout | openssl pkey -pubin -outform DER -out server.spki.der
+
+# DER copies for the test server.
+openssl x509 -in ca.pem -outform DER -out ca.der
+openssl x509 -in server.pem -outform DER -out server.der
+openssl pkey -in server.key -outform DER -out server.key.der
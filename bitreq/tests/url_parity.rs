@@ -8,7 +8,7 @@
 mod common;
 
 use bitreq::Url as BitreqUrl;
-use common::special_url_string_strategy;
+use common::{host_strategy, relative_reference_strategy, special_url_string_strategy};
 use proptest::prelude::*;
 use url::Url as MaxUrl;
 
@@ -594,3 +594,152 @@ mod percent_encoding_parity {
         );
     }
 }
+
+/// Asserts that both URLs have the same components.
+///
+/// Components are compared rather than serializations, as bitreq keeps an explicit default port
+/// and an empty path where the url crate drops the former and writes "/" for the latter.
+fn assert_same_components(bitreq: &BitreqUrl, max: &MaxUrl) -> Result<(), TestCaseError> {
+    prop_assert_eq!(bitreq.scheme(), max.scheme());
+    prop_assert_eq!(bitreq.username(), max.username());
+    prop_assert_eq!(bitreq.password(), max.password());
+    prop_assert_eq!(Some(bitreq.base_url()), max.host_str());
+    prop_assert_eq!(Some(bitreq.port()), max.port_or_known_default());
+    let path = if bitreq.path().is_empty() { "/" } else { bitreq.path() };
+    prop_assert_eq!(path, max.path());
+    prop_assert_eq!(bitreq.query(), max.query());
+    prop_assert_eq!(bitreq.fragment(), max.fragment());
+    Ok(())
+}
+
+// Parity tests for resolving references and modifying URLs
+proptest! {
+    /// Test that join() resolves relative references like url::Url::join().
+    #[test]
+    fn join_parity(
+        url_string in special_url_string_strategy(),
+        reference in relative_reference_strategy(),
+    ) {
+        let bitreq_url = BitreqUrl::parse(&url_string).expect("bitreq should parse");
+        let max_url = MaxUrl::parse(&url_string).expect("url crate should parse");
+
+        let bitreq_joined = bitreq_url.join(&reference).expect("bitreq should join");
+        let max_joined = max_url.join(&reference).expect("url crate should join");
+        assert_same_components(&bitreq_joined, &max_joined)?;
+    }
+
+    /// Test that set_path() percent-encodes like url::Url::set_path().
+    #[test]
+    fn set_path_parity(
+        url_string in special_url_string_strategy(),
+        path in "[a-zA-Z0-9 _~/<>\"{}`?#%^|-]{0,30}",
+    ) {
+        let mut bitreq_url = BitreqUrl::parse(&url_string).expect("bitreq should parse");
+        let mut max_url = MaxUrl::parse(&url_string).expect("url crate should parse");
+
+        bitreq_url.set_path(&path);
+        max_url.set_path(&path);
+        assert_same_components(&bitreq_url, &max_url)?;
+    }
+
+    /// Test that set_query() percent-encodes like url::Url::set_query().
+    #[test]
+    fn set_query_parity(
+        url_string in special_url_string_strategy(),
+        query in prop::option::of("[a-zA-Z0-9 _~/<>\"'`?#=&%-]{0,30}"),
+    ) {
+        let mut bitreq_url = BitreqUrl::parse(&url_string).expect("bitreq should parse");
+        let mut max_url = MaxUrl::parse(&url_string).expect("url crate should parse");
+
+        bitreq_url.set_query(query.as_deref());
+        max_url.set_query(query.as_deref());
+        assert_same_components(&bitreq_url, &max_url)?;
+    }
+
+    /// Test that set_fragment() percent-encodes like url::Url::set_fragment().
+    #[test]
+    fn set_fragment_parity(
+        url_string in special_url_string_strategy(),
+        fragment in prop::option::of("[a-zA-Z0-9 _~/<>\"'`?#=&%-]{0,30}"),
+    ) {
+        let mut bitreq_url = BitreqUrl::parse(&url_string).expect("bitreq should parse");
+        let mut max_url = MaxUrl::parse(&url_string).expect("url crate should parse");
+
+        bitreq_url.set_fragment(fragment.as_deref());
+        max_url.set_fragment(fragment.as_deref());
+        assert_same_components(&bitreq_url, &max_url)?;
+    }
+
+    /// Test that set_host() and set_port() match their url::Url counterparts.
+    #[test]
+    fn set_host_and_port_parity(
+        url_string in special_url_string_strategy(),
+        host in host_strategy(),
+        uppercase in any::<bool>(),
+        port in any::<Option<u16>>(),
+    ) {
+        let mut bitreq_url = BitreqUrl::parse(&url_string).expect("bitreq should parse");
+        let mut max_url = MaxUrl::parse(&url_string).expect("url crate should parse");
+        let host = if uppercase { host.to_ascii_uppercase() } else { host };
+
+        bitreq_url.set_host(&host).expect("bitreq should set host");
+        max_url.set_host(Some(&host)).expect("url crate should set host");
+        bitreq_url.set_port(port).expect("bitreq should set port");
+        max_url.set_port(port).expect("url crate should set port");
+        assert_same_components(&bitreq_url, &max_url)?;
+    }
+
+    /// Test that appended query pairs read back like those appended by the url crate.
+    #[test]
+    fn append_query_pairs_parity(
+        url_string in special_url_string_strategy(),
+        pairs in prop::collection::vec(("[a-zA-Z0-9&=+%\u{e9}]{1,8}", "[a-zA-Z0-9&=+%\u{e9}]{0,8}"), 0..4),
+    ) {
+        let mut bitreq_url = BitreqUrl::parse(&url_string).expect("bitreq should parse");
+        let mut max_url = MaxUrl::parse(&url_string).expect("url crate should parse");
+
+        bitreq_url.append_query_pairs(pairs.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+        max_url.query_pairs_mut().extend_pairs(&pairs);
+
+        let bitreq_pairs: Vec<(String, String)> = bitreq_url.query_pairs().collect();
+        // bitreq filters out pairs with empty keys, which the generated URL may have
+        let max_pairs: Vec<(String, String)> = max_url
+            .query_pairs()
+            .filter(|(k, _)| !k.is_empty())
+            .map(|(k, v)| (k.into_owned(), v.into_owned()))
+            .collect();
+        prop_assert_eq!(&bitreq_pairs, &max_pairs);
+        prop_assert!(bitreq_pairs.ends_with(&pairs));
+    }
+}
+
+// Parity tests for internationalized host names
+#[cfg(test)]
+mod idna {
+    use super::*;
+
+    #[test]
+    fn set_host_idna_parity() {
+        for host in ["bücher.example", "Bücher.Example", "münchen.de", "例え.テスト", "ñandú.com"]
+        {
+            let mut bitreq = BitreqUrl::parse("http://example.com/p?q#f").unwrap();
+            let mut max = MaxUrl::parse("http://example.com/p?q#f").unwrap();
+
+            bitreq.set_host(host).unwrap();
+            max.set_host(Some(host)).unwrap();
+            assert_eq!(Some(bitreq.base_url()), max.host_str(), "host: {}", host);
+            assert_eq!(bitreq.as_str(), max.as_str());
+        }
+    }
+
+    #[test]
+    fn join_idna_parity() {
+        let bitreq = BitreqUrl::parse("https://example.com/a/b").unwrap();
+        let max = MaxUrl::parse("https://example.com/a/b").unwrap();
+
+        let bitreq_joined = bitreq.join("//bücher.example/c").unwrap();
+        let max_joined = max.join("//bücher.example/c").unwrap();
+        assert_eq!(bitreq_joined.as_str(), max_joined.as_str());
+        assert_eq!(bitreq_joined.base_url(), "xn--bcher-kva.example");
+    }
+}
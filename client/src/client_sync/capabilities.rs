@@ -0,0 +1,219 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Discovering the methods a node supports from the output of `help`.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::client_sync::{Result, Rpc};
+
+/// The methods, and their arguments, supported by a node.
+///
+/// Created with `Client::capabilities` or parsed from the output of `help` with
+/// [`Capabilities::from_help`]. Hidden methods are not listed by `help` and hence not supported
+/// according to this type.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    methods: BTreeMap<String, MethodInfo>,
+}
+
+/// A method listed by `help`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MethodInfo {
+    /// The section the method is listed under e.g., `Wallet`.
+    pub section: String,
+    /// The positional arguments, in order.
+    pub arguments: Vec<String>,
+    /// The fields of object arguments and the named-only arguments e.g., the `options` of `send`.
+    pub options: BTreeSet<String>,
+}
+
+impl Capabilities {
+    /// Queries `help` and `help <method>` for every method.
+    pub fn discover<C: Rpc>(client: &C) -> Result<Self> {
+        let help: String = client.call("help", &[])?;
+        let mut capabilities = Self::from_help(&help);
+        for (name, method) in capabilities.methods.iter_mut() {
+            let help: String = client.call("help", &[name.as_str().into()])?;
+            method.parse_arguments(&help);
+        }
+        Ok(capabilities)
+    }
+
+    /// Parses the method list printed by `help` without arguments.
+    ///
+    /// The arguments of the methods are not known, see [`Capabilities::add_method_help`].
+    pub fn from_help(help: &str) -> Self {
+        let mut methods = BTreeMap::new();
+        let mut section = String::new();
+        for line in help.lines().map(str::trim) {
+            if let Some(header) = line.strip_prefix("==").and_then(|s| s.strip_suffix("==")) {
+                section = header.trim().to_owned();
+            } else if let Some(name) = line.split_whitespace().next() {
+                let method = MethodInfo { section: section.clone(), ..Default::default() };
+                methods.insert(name.to_owned(), method);
+            }
+        }
+        Capabilities { methods }
+    }
+
+    /// Parses the output of `help <method>` and adds the arguments to `method`.
+    ///
+    /// Does nothing if `method` is not supported.
+    pub fn add_method_help(&mut self, method: &str, help: &str) {
+        if let Some(method) = self.methods.get_mut(method) {
+            method.parse_arguments(help);
+        }
+    }
+
+    /// Returns `true` if the node supports `method`.
+    pub fn supports(&self, method: &str) -> bool { self.methods.contains_key(method) }
+
+    /// Returns `true` if `method` accepts `argument`, either positional or as an option.
+    pub fn has_argument(&self, method: &str, argument: &str) -> bool {
+        self.methods.get(method).is_some_and(|m| {
+            m.arguments.iter().any(|a| a == argument) || m.options.contains(argument)
+        })
+    }
+
+    /// Returns the method called `name`.
+    pub fn method(&self, name: &str) -> Option<&MethodInfo> { self.methods.get(name) }
+
+    /// Returns the names of all supported methods, sorted.
+    pub fn methods(&self) -> impl Iterator<Item = &str> { self.methods.keys().map(String::as_str) }
+
+    /// Returns the names of the sections, sorted.
+    pub fn sections(&self) -> BTreeSet<&str> {
+        self.methods.values().map(|m| m.section.as_str()).collect()
+    }
+
+    /// Returns the names of the methods in `section`, sorted.
+    pub fn section(&self, section: &str) -> impl Iterator<Item = &str> + '_ {
+        let section = section.to_owned();
+        self.methods.iter().filter(move |(_, m)| m.section == section).map(|(n, _)| n.as_str())
+    }
+}
+
+impl MethodInfo {
+    /// Parses the `Arguments:` and `Named Arguments:` sections of the output of `help <method>`.
+    fn parse_arguments(&mut self, help: &str) {
+        #[derive(PartialEq)]
+        enum Part {
+            Other,
+            Arguments,
+            Named,
+        }
+
+        let mut part = Part::Other;
+        for line in help.lines() {
+            match line.trim_end() {
+                "Arguments:" => part = Part::Arguments,
+                "Named Arguments:" => part = Part::Named,
+                l if l.starts_with("Result") || l.starts_with("Examples:") => part = Part::Other,
+                _ if part == Part::Other => {}
+                l if !l.starts_with(' ') => {
+                    // `1. name  (type, ...)` or, for named arguments, `name  (type, ...)`.
+                    let mut words = l.split_whitespace();
+                    let first = words.next().unwrap_or_default();
+                    if part == Part::Arguments && first.ends_with('.') {
+                        let name = words.next().unwrap_or_default().trim_matches('"');
+                        if !name.is_empty() {
+                            self.arguments.push(name.to_owned());
+                        }
+                    } else if part == Part::Named && !first.is_empty() {
+                        self.options.insert(first.to_owned());
+                    }
+                }
+                l => {
+                    // `"name": value,  (type, ...)` inside an object argument.
+                    let field = l.trim_start().strip_prefix('"').and_then(|s| s.split_once("\":"));
+                    if let Some((name, _)) = field {
+                        self.options.insert(name.to_owned());
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HELP: &str = "\
+== Blockchain ==
+getbestblockhash
+getblock \"blockhash\" ( verbosity )
+
+== Wallet ==
+send [{\"address\":amount,...},{\"data\":\"hex\"},...] ( conf_target \"estimate_mode\" fee_rate options version )
+";
+
+    const HELP_SEND: &str = r#"send [{"address":amount,...},{"data":"hex"},...] ( conf_target "estimate_mode" fee_rate options version )
+
+Send a transaction.
+
+Arguments:
+1. outputs                               (json array, required) The outputs specified as key-value pairs.
+     [
+       {                                 (json object)
+         "address": amount,              (numeric or string, required) A key-value pair.
+       },
+       ...
+     ]
+2. conf_target                           (numeric, optional, default=wallet -txconfirmtarget) Confirmation target in blocks
+4. fee_rate                              (numeric or string, optional, default=not set) Specify a fee rate in sat/vB.
+5. options                               (json object, optional)
+     {
+       "add_inputs": bool,               (boolean, optional) Automatically include coins from the wallet to cover the target amount.
+       "max_tx_weight": n,               (numeric, optional, default=400000) The maximum acceptable transaction weight.
+     }
+
+Named Arguments:
+include_watching                         (boolean, optional) Same as options.include_watching
+
+Result:
+{                             (json object)
+  "complete" : true|false,    (boolean) If the transaction has a complete set of signatures
+}
+
+Examples:
+> bitcoin-cli send '{"bc1q09vm5lfy0j5reeulh4x5752q25uqqvz34hufdl": 0.1}'
+"#;
+
+    #[test]
+    fn parses_method_list() {
+        let caps = Capabilities::from_help(HELP);
+        assert!(caps.supports("getblock"));
+        assert!(!caps.supports("getdescriptoractivity"));
+        assert_eq!(caps.sections(), ["Blockchain", "Wallet"].into_iter().collect());
+        assert_eq!(
+            caps.section("Blockchain").collect::<Vec<_>>(),
+            ["getbestblockhash", "getblock"]
+        );
+        assert_eq!(caps.method("send").unwrap().section, "Wallet");
+    }
+
+    #[test]
+    fn parses_method_arguments() {
+        let mut caps = Capabilities::from_help(HELP);
+        caps.add_method_help("send", HELP_SEND);
+
+        let send = caps.method("send").unwrap();
+        assert_eq!(send.arguments, ["outputs", "conf_target", "fee_rate", "options"]);
+        assert!(caps.has_argument("send", "conf_target"));
+        assert!(caps.has_argument("send", "max_tx_weight"));
+        assert!(caps.has_argument("send", "include_watching"));
+        assert!(!caps.has_argument("send", "complete"));
+        assert!(!caps.has_argument("getblock", "verbosity"));
+    }
+
+    #[test]
+    fn parses_quoted_arguments() {
+        // Core v0.17 quotes the names of string arguments.
+        let mut method = MethodInfo::default();
+        method.parse_arguments(
+            "getblock \"blockhash\" ( verbosity )\n\nArguments:\n1. \"blockhash\"    (string, required) The block hash\n2. verbosity        (numeric, optional, default=1)\n",
+        );
+        assert_eq!(method.arguments, ["blockhash", "verbosity"]);
+    }
+}
@@ -22,6 +22,16 @@ pub enum Error {
     ServerVersion(UnexpectedServerVersionError),
     /// Missing user/password.
     MissingUserPassword,
+    /// The JSON result could not be converted into the version nonspecific model type.
+    IntoModel(String),
+    /// The UTXOs available to a spend do not cover its outputs and fee.
+    InsufficientFunds(InsufficientFundsError),
+    /// The node could not estimate a fee rate, with the errors it gave.
+    NoFeeEstimate(String),
+    /// The node could not sign every input of a transaction, with the errors it gave.
+    IncompleteSignature(String),
+    /// The source of ZMQ `sequence` messages disconnected, e.g. its thread exited.
+    SubscriberDisconnected,
 }
 
 impl From<jsonrpc::error::Error> for Error {
@@ -64,6 +74,11 @@ impl fmt::Display for Error {
             Returned(ref s) => write!(f, "the daemon returned an error string: {}", s),
             ServerVersion(ref e) => write!(f, "server version: {}", e),
             MissingUserPassword => write!(f, "missing user and/or password"),
+            IntoModel(ref s) => write!(f, "failed to convert JSON result into model type: {}", s),
+            InsufficientFunds(ref e) => write!(f, "insufficient funds: {}", e),
+            NoFeeEstimate(ref s) => write!(f, "no fee rate estimate: {}", s),
+            IncompleteSignature(ref s) => write!(f, "transaction not fully signed: {}", s),
+            SubscriberDisconnected => write!(f, "ZMQ sequence subscriber disconnected"),
         }
     }
 }
@@ -80,7 +95,15 @@ impl error::Error for Error {
             BitcoinSerialization(ref e) => Some(e),
             Io(ref e) => Some(e),
             ServerVersion(ref e) => Some(e),
-            InvalidCookieFile | UnexpectedStructure | Returned(_) | MissingUserPassword => None,
+            InsufficientFunds(ref e) => Some(e),
+            InvalidCookieFile
+            | UnexpectedStructure
+            | Returned(_)
+            | MissingUserPassword
+            | IntoModel(_)
+            | NoFeeEstimate(_)
+            | IncompleteSignature(_)
+            | SubscriberDisconnected => None,
         }
     }
 }
@@ -110,3 +133,24 @@ impl error::Error for UnexpectedServerVersionError {}
 impl From<UnexpectedServerVersionError> for Error {
     fn from(e: UnexpectedServerVersionError) -> Self { Self::ServerVersion(e) }
 }
+
+/// Error returned when the UTXOs available to a spend do not cover its outputs and fee.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InsufficientFundsError {
+    /// Total value of the UTXOs that were found.
+    pub available: bitcoin::Amount,
+    /// Value needed to pay the outputs and the fee.
+    pub required: bitcoin::Amount,
+}
+
+impl fmt::Display for InsufficientFundsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "required {} but only {} available", self.required, self.available)
+    }
+}
+
+impl error::Error for InsufficientFundsError {}
+
+impl From<InsufficientFundsError> for Error {
+    fn from(e: InsufficientFundsError) -> Self { Self::InsufficientFunds(e) }
+}
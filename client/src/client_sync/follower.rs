@@ -0,0 +1,341 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Following the best chain of a node, including reorgs.
+
+use std::thread;
+use std::time::Duration;
+
+use bitcoin::{consensus, Block, BlockHash};
+use serde::Deserialize;
+
+use crate::client_sync::{into_json, Error, Result, Rpc};
+
+/// A change to the best chain of the node.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChainEvent {
+    /// The block was added to the tip of the best chain.
+    Connected(Block),
+    /// The block with this hash was removed from the tip of the best chain.
+    Disconnected(BlockHash),
+}
+
+/// Follows the best chain of a node starting from a checkpoint.
+///
+/// Emits a [`ChainEvent::Connected`] for every block added to the best chain after the
+/// checkpoint. If the node reorgs, the blocks that are no longer in the best chain are first
+/// disconnected one by one, tip first, down to the fork point. Reorgs of any depth are handled,
+/// including ones that disconnect the checkpoint.
+///
+/// Works with any [`Rpc`] client i.e., the `Client` of any version module.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use corepc_client::client_sync::{v30::Client, ChainEvent, ChainFollower};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let client = Client::new("http://127.0.0.1:18443");
+/// let genesis = client.get_block_hash(0)?.block_hash()?;
+///
+/// for event in ChainFollower::new(&client, 0, genesis) {
+///     match event? {
+///         ChainEvent::Connected(block) => println!("connected {}", block.block_hash()),
+///         ChainEvent::Disconnected(hash) => println!("disconnected {}", hash),
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct ChainFollower<C> {
+    client: C,
+    height: u32,
+    hash: BlockHash,
+    poll_interval: Duration,
+    long_poll: Option<Duration>,
+}
+
+impl<C: Rpc> ChainFollower<C> {
+    /// Creates a follower whose last processed block is `hash` at `height`.
+    ///
+    /// The checkpoint block itself is not emitted. It must be known to the node but does not need
+    /// to be in the best chain.
+    pub fn new(client: C, height: u32, hash: BlockHash) -> Self {
+        ChainFollower {
+            client,
+            height,
+            hash,
+            poll_interval: Duration::from_secs(1),
+            long_poll: None,
+        }
+    }
+
+    /// Sets how long [`ChainFollower::next_event`] sleeps between polls (default: 1 second).
+    pub fn with_poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Waits for new blocks with `waitfornewblock` instead of sleeping between polls.
+    ///
+    /// `timeout` bounds each wait, keep it below the timeout of the client's transport.
+    pub fn with_long_poll(mut self, timeout: Duration) -> Self {
+        self.long_poll = Some(timeout);
+        self
+    }
+
+    /// Returns the height and hash of the last processed block.
+    pub fn tip(&self) -> (u32, BlockHash) { (self.height, self.hash) }
+
+    /// Returns the client used by this follower.
+    pub fn client(&self) -> &C { &self.client }
+
+    /// Returns the next event if the best chain differs from the last processed block.
+    ///
+    /// Does not block, returns `Ok(None)` if the follower is at the tip of the best chain.
+    pub fn poll(&mut self) -> Result<Option<ChainEvent>> {
+        let header: HeaderInfo =
+            self.client.call("getblockheader", &[into_json(self.hash)?, true.into()])?;
+
+        // Core reports blocks that are not in the best chain with -1 confirmations.
+        if header.confirmations < 0 {
+            let prev = header.previous_block_hash.ok_or(Error::UnexpectedStructure)?;
+            let disconnected = self.hash;
+            self.height = header.height.saturating_sub(1);
+            self.hash = prev;
+            return Ok(Some(ChainEvent::Disconnected(disconnected)));
+        }
+
+        let next = match header.next_block_hash {
+            Some(next) => next,
+            None => return Ok(None),
+        };
+        let hex: String = self.client.call("getblock", &[into_json(next)?, 0.into()])?;
+        let block: Block = consensus::encode::deserialize_hex(&hex)?;
+        if block.header.prev_blockhash != self.hash {
+            return Err(Error::UnexpectedStructure);
+        }
+
+        self.height = header.height + 1;
+        self.hash = next;
+        Ok(Some(ChainEvent::Connected(block)))
+    }
+
+    /// Returns the next event, blocking until the best chain changes.
+    pub fn next_event(&mut self) -> Result<ChainEvent> {
+        loop {
+            if let Some(event) = self.poll()? {
+                return Ok(event);
+            }
+            self.wait()?;
+        }
+    }
+
+    /// Waits for a new block, either by long-polling the node or by sleeping.
+    ///
+    /// A block found between the last poll and the start of the wait is only seen once the wait
+    /// times out, which `with_long_poll` bounds.
+    fn wait(&self) -> Result<()> {
+        match self.long_poll {
+            Some(timeout) => {
+                let millis = u64::try_from(timeout.as_millis()).unwrap_or(u64::MAX);
+                let _: serde_json::Value = self.client.call("waitfornewblock", &[millis.into()])?;
+            }
+            None => thread::sleep(self.poll_interval),
+        }
+        Ok(())
+    }
+}
+
+impl<C: Rpc> Iterator for ChainFollower<C> {
+    type Item = Result<ChainEvent>;
+
+    /// Blocks until the next event, never returns `None`.
+    fn next(&mut self) -> Option<Self::Item> { Some(self.next_event()) }
+}
+
+/// The fields of the verbose `getblockheader` result used by the follower.
+#[derive(Deserialize)]
+struct HeaderInfo {
+    height: u32,
+    confirmations: i64,
+    #[serde(rename = "previousblockhash")]
+    previous_block_hash: Option<BlockHash>,
+    #[serde(rename = "nextblockhash")]
+    next_block_hash: Option<BlockHash>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+    use std::collections::HashMap;
+
+    use bitcoin::block::{Header, Version};
+    use bitcoin::constants::genesis_block;
+    use bitcoin::hashes::Hash as _;
+    use bitcoin::{CompactTarget, Network, TxMerkleNode};
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    /// An in-memory stand-in for the chain state of a node.
+    struct FakeChain {
+        blocks: RefCell<HashMap<BlockHash, (u32, Block)>>,
+        best: RefCell<Vec<BlockHash>>,
+        nonce: Cell<u32>,
+        waits: Cell<usize>,
+    }
+
+    impl FakeChain {
+        fn new() -> Self {
+            let genesis = genesis_block(Network::Regtest);
+            let hash = genesis.block_hash();
+            FakeChain {
+                blocks: RefCell::new([(hash, (0, genesis))].into_iter().collect()),
+                best: RefCell::new(vec![hash]),
+                nonce: Cell::new(0),
+                waits: Cell::new(0),
+            }
+        }
+
+        fn genesis(&self) -> BlockHash { self.best.borrow()[0] }
+
+        /// Mines `n` blocks on top of the best chain, returns their hashes.
+        fn mine(&self, n: usize) -> Vec<BlockHash> {
+            (0..n)
+                .map(|_| {
+                    let prev = *self.best.borrow().last().unwrap();
+                    let height = self.best.borrow().len() as u32;
+                    self.nonce.set(self.nonce.get() + 1);
+                    let block = Block {
+                        header: Header {
+                            version: Version::TWO,
+                            prev_blockhash: prev,
+                            merkle_root: TxMerkleNode::all_zeros(),
+                            time: height,
+                            bits: CompactTarget::from_consensus(0x207fffff),
+                            nonce: self.nonce.get(),
+                        },
+                        txdata: vec![],
+                    };
+                    let hash = block.block_hash();
+                    self.blocks.borrow_mut().insert(hash, (height, block));
+                    self.best.borrow_mut().push(hash);
+                    hash
+                })
+                .collect()
+        }
+
+        /// Removes the best chain above `height` i.e., like `invalidateblock`.
+        fn rewind(&self, height: u32) { self.best.borrow_mut().truncate(height as usize + 1); }
+    }
+
+    impl Rpc for FakeChain {
+        fn call<T: for<'a> serde::de::Deserialize<'a>>(
+            &self,
+            method: &str,
+            args: &[Value],
+        ) -> Result<T> {
+            let hash = || args[0].as_str().unwrap().parse::<BlockHash>().unwrap();
+            let res = match method {
+                "getblockheader" => {
+                    let hash = hash();
+                    let (height, block) = self.blocks.borrow()[&hash].clone();
+                    let best = self.best.borrow();
+                    let in_best = best.get(height as usize) == Some(&hash);
+                    let confirmations =
+                        if in_best { best.len() as i64 - height as i64 } else { -1 };
+                    let next = if in_best { best.get(height as usize + 1) } else { None };
+                    let prev = if height > 0 { Some(block.header.prev_blockhash) } else { None };
+                    json!({
+                        "height": height,
+                        "confirmations": confirmations,
+                        "previousblockhash": prev,
+                        "nextblockhash": next,
+                    })
+                }
+                "getblock" => {
+                    let (_, block) = self.blocks.borrow()[&hash()].clone();
+                    json!(consensus::encode::serialize_hex(&block))
+                }
+                "waitfornewblock" => {
+                    self.waits.set(self.waits.get() + 1);
+                    self.mine(1);
+                    Value::Null
+                }
+                _ => panic!("unexpected method {}", method),
+            };
+            Ok(serde_json::from_value(res)?)
+        }
+    }
+
+    fn drain(follower: &mut ChainFollower<&FakeChain>) -> Vec<ChainEvent> {
+        std::iter::from_fn(|| follower.poll().unwrap()).collect()
+    }
+
+    fn connected(events: &[ChainEvent]) -> Vec<BlockHash> {
+        events
+            .iter()
+            .filter_map(|e| match e {
+                ChainEvent::Connected(block) => Some(block.block_hash()),
+                ChainEvent::Disconnected(_) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn connects_new_blocks() {
+        let chain = FakeChain::new();
+        let mut follower = ChainFollower::new(&chain, 0, chain.genesis());
+        assert!(follower.poll().unwrap().is_none());
+
+        let mined = chain.mine(3);
+        let events = drain(&mut follower);
+        assert_eq!(connected(&events), mined);
+        assert_eq!(follower.tip(), (3, mined[2]));
+    }
+
+    #[test]
+    fn handles_reorg() {
+        let chain = FakeChain::new();
+        let old = chain.mine(3);
+        let mut follower = ChainFollower::new(&chain, 3, old[2]);
+
+        chain.rewind(1);
+        let new = chain.mine(3);
+        let events = drain(&mut follower);
+
+        assert_eq!(
+            events[..2],
+            [ChainEvent::Disconnected(old[2]), ChainEvent::Disconnected(old[1])]
+        );
+        assert_eq!(connected(&events[2..]), new);
+        assert_eq!(follower.tip(), (4, new[2]));
+    }
+
+    #[test]
+    fn handles_reorg_to_shorter_chain() {
+        let chain = FakeChain::new();
+        let old = chain.mine(5);
+        let mut follower = ChainFollower::new(&chain, 5, old[4]);
+
+        chain.rewind(0);
+        let new = chain.mine(2);
+        let events = drain(&mut follower);
+
+        let disconnected: Vec<_> = old.iter().rev().map(|h| ChainEvent::Disconnected(*h)).collect();
+        assert_eq!(events[..5], disconnected[..]);
+        assert_eq!(connected(&events[5..]), new);
+        assert_eq!(follower.tip(), (2, new[1]));
+    }
+
+    #[test]
+    fn long_polls_for_new_blocks() {
+        let chain = FakeChain::new();
+        let mut follower =
+            ChainFollower::new(&chain, 0, chain.genesis()).with_long_poll(Duration::from_secs(10));
+
+        let event = follower.next_event().unwrap();
+        assert_eq!(chain.waits.get(), 1);
+        assert_eq!(connected(&[event]), chain.best.borrow()[1..]);
+    }
+}
@@ -0,0 +1,550 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Mirroring the mempool of a node.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc;
+use std::{fmt, mem};
+
+use bitcoin::hashes::Hash as _;
+use bitcoin::{BlockHash, Txid};
+use types::model::{GetRawMempoolSequence, MempoolEntry};
+
+use crate::client_sync::{Error, Result, Rpc};
+
+/// Error code returned by Bitcoin Core for a transaction that is not in the mempool.
+const RPC_INVALID_ADDRESS_OR_KEY: i32 = -5;
+
+/// A client that can fetch the data of a mempool entry.
+///
+/// Implemented by the `Client` of the version modules that support `getrawmempool` with
+/// `mempool_sequence` i.e., v21 and later.
+pub trait MempoolRpc: Rpc {
+    /// Returns the data of the mempool entry for `txid`.
+    fn mempool_entry(&self, txid: Txid) -> Result<MempoolEntry>;
+}
+
+impl<R: MempoolRpc + ?Sized> MempoolRpc for &R {
+    fn mempool_entry(&self, txid: Txid) -> Result<MempoolEntry> { (**self).mempool_entry(txid) }
+}
+
+/// A change to the mempool of the mirror.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MempoolEvent {
+    /// The transaction was added to the mempool.
+    Added(Txid),
+    /// The transaction was removed from the mempool.
+    Removed(Txid),
+}
+
+/// A notification published by bitcoind on the ZMQ `sequence` topic.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SequenceNotification {
+    /// A block was connected (`C`).
+    BlockConnected(BlockHash),
+    /// A block was disconnected (`D`).
+    BlockDisconnected(BlockHash),
+    /// A transaction was added to the mempool (`A`).
+    TransactionAdded {
+        /// The id of the transaction.
+        txid: Txid,
+        /// The mempool sequence after adding the transaction.
+        mempool_sequence: u64,
+    },
+    /// A transaction was removed from the mempool for a reason other than block inclusion (`R`).
+    TransactionRemoved {
+        /// The id of the transaction.
+        txid: Txid,
+        /// The mempool sequence after removing the transaction.
+        mempool_sequence: u64,
+    },
+}
+
+/// A message received on the ZMQ `sequence` topic.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SequenceMessage {
+    /// The notification in the body of the message.
+    pub notification: SequenceNotification,
+    /// The ZMQ message sequence number, incremented by bitcoind for each message on the topic.
+    pub message_sequence: u32,
+}
+
+impl SequenceMessage {
+    /// Parses the frames of a ZMQ multipart message i.e., `[topic, body, sequence]`.
+    pub fn from_multipart<F: AsRef<[u8]>>(frames: &[F]) -> Result<Self> {
+        let (topic, body, sequence) = match frames {
+            [topic, body, sequence] => (topic.as_ref(), body.as_ref(), sequence.as_ref()),
+            _ => return Err(Error::UnexpectedStructure),
+        };
+        if topic != b"sequence" {
+            return Err(Error::UnexpectedStructure);
+        }
+        let sequence = <[u8; 4]>::try_from(sequence).map_err(|_| Error::UnexpectedStructure)?;
+
+        Ok(SequenceMessage {
+            notification: SequenceNotification::from_body(body)?,
+            message_sequence: u32::from_le_bytes(sequence),
+        })
+    }
+}
+
+impl SequenceNotification {
+    /// Parses the body of a message i.e., `<32-byte hash><1-byte label>[<8-byte LE sequence>]`.
+    pub fn from_body(body: &[u8]) -> Result<Self> {
+        if body.len() < 33 {
+            return Err(Error::UnexpectedStructure);
+        }
+        // The hash is sent in the same byte order as it is displayed.
+        let mut hash = <[u8; 32]>::try_from(&body[..32]).expect("length checked above");
+        hash.reverse();
+        let mempool_sequence = || {
+            <[u8; 8]>::try_from(&body[33..])
+                .map(u64::from_le_bytes)
+                .map_err(|_| Error::UnexpectedStructure)
+        };
+
+        match (body[32], body.len()) {
+            (b'C', 33) => Ok(Self::BlockConnected(BlockHash::from_byte_array(hash))),
+            (b'D', 33) => Ok(Self::BlockDisconnected(BlockHash::from_byte_array(hash))),
+            (b'A', _) => Ok(Self::TransactionAdded {
+                txid: Txid::from_byte_array(hash),
+                mempool_sequence: mempool_sequence()?,
+            }),
+            (b'R', _) => Ok(Self::TransactionRemoved {
+                txid: Txid::from_byte_array(hash),
+                mempool_sequence: mempool_sequence()?,
+            }),
+            _ => Err(Error::UnexpectedStructure),
+        }
+    }
+}
+
+/// A source of ZMQ `sequence` messages, e.g. a wrapper around a ZMQ subscriber socket.
+pub trait SequenceSubscriber {
+    /// Returns the next message if one is available, does not block.
+    fn try_recv(&mut self) -> Result<Option<SequenceMessage>>;
+}
+
+/// Receives messages parsed by another thread, e.g. one that reads a ZMQ socket.
+impl SequenceSubscriber for mpsc::Receiver<SequenceMessage> {
+    fn try_recv(&mut self) -> Result<Option<SequenceMessage>> {
+        match mpsc::Receiver::try_recv(self) {
+            Ok(msg) => Ok(Some(msg)),
+            Err(mpsc::TryRecvError::Empty) => Ok(None),
+            Err(mpsc::TryRecvError::Disconnected) => Err(Error::SubscriberDisconnected),
+        }
+    }
+}
+
+/// A local copy of the mempool of a node.
+///
+/// Bootstraps from `getrawmempool` with `mempool_sequence` set and then applies incremental
+/// updates, either by polling `getrawmempool` or from the notifications of a ZMQ `sequence`
+/// subscriber. Whenever the notifications cannot be applied in order, i.e. a message or a
+/// mempool sequence was skipped, the mirror re-syncs by polling.
+///
+/// Transactions removed from the mempool because they were mined are not notified individually
+/// by bitcoind, the mirror re-syncs when a block is connected or disconnected.
+pub struct MempoolMirror<C> {
+    client: C,
+    entries: HashMap<Txid, MempoolEntry>,
+    mempool_sequence: Option<u64>,
+    message_sequence: Option<u32>,
+    subscriber: Option<Box<dyn SequenceSubscriber + Send>>,
+}
+
+impl<C: MempoolRpc> MempoolMirror<C> {
+    /// Creates a mirror of the mempool of the node `client` is connected to.
+    pub fn new(client: C) -> Result<Self> {
+        let mut mirror = MempoolMirror {
+            client,
+            entries: HashMap::new(),
+            mempool_sequence: None,
+            message_sequence: None,
+            subscriber: None,
+        };
+        mirror.resync(&mut vec![])?;
+        Ok(mirror)
+    }
+
+    /// Applies updates from a ZMQ `sequence` subscriber instead of polling.
+    ///
+    /// Subscribe before creating the mirror, notifications that are already part of the
+    /// bootstrapped mempool are ignored.
+    pub fn with_subscriber<S: SequenceSubscriber + Send + 'static>(
+        mut self,
+        subscriber: S,
+    ) -> Self {
+        self.subscriber = Some(Box::new(subscriber));
+        self
+    }
+
+    /// Updates the mirror, returns the changes to the mempool in the order they were applied.
+    ///
+    /// Applies all messages available from the subscriber, or polls the node if there is none.
+    pub fn update(&mut self) -> Result<Vec<MempoolEvent>> {
+        let mut events = vec![];
+        match self.subscriber.take() {
+            Some(mut subscriber) => {
+                let res = self.drain(&mut *subscriber, &mut events);
+                self.subscriber = Some(subscriber);
+                res?;
+            }
+            None => self.resync(&mut events)?,
+        }
+        Ok(events)
+    }
+
+    /// Applies a single notification from the ZMQ `sequence` topic.
+    pub fn apply(&mut self, msg: SequenceMessage) -> Result<Vec<MempoolEvent>> {
+        let mut events = vec![];
+        self.apply_message(msg, &mut events)?;
+        Ok(events)
+    }
+
+    /// Returns the ids of the transactions in the mempool.
+    pub fn txids(&self) -> impl Iterator<Item = &Txid> { self.entries.keys() }
+
+    /// Returns the data of the mempool entry for `txid`.
+    pub fn entry(&self, txid: &Txid) -> Option<&MempoolEntry> { self.entries.get(txid) }
+
+    /// Returns the mempool entries.
+    pub fn entries(&self) -> &HashMap<Txid, MempoolEntry> { &self.entries }
+
+    /// Returns `true` if `txid` is in the mempool.
+    pub fn contains(&self, txid: &Txid) -> bool { self.entries.contains_key(txid) }
+
+    /// Returns the number of transactions in the mempool.
+    pub fn len(&self) -> usize { self.entries.len() }
+
+    /// Returns `true` if the mempool is empty.
+    pub fn is_empty(&self) -> bool { self.entries.is_empty() }
+
+    /// Returns the mempool sequence the mirror is at.
+    pub fn mempool_sequence(&self) -> u64 {
+        self.mempool_sequence.expect("set when the mirror is created")
+    }
+
+    /// Returns the client used by this mirror.
+    pub fn client(&self) -> &C { &self.client }
+
+    fn drain(
+        &mut self,
+        subscriber: &mut dyn SequenceSubscriber,
+        events: &mut Vec<MempoolEvent>,
+    ) -> Result<()> {
+        while let Some(msg) = subscriber.try_recv()? {
+            self.apply_message(msg, events)?;
+        }
+        Ok(())
+    }
+
+    fn apply_message(
+        &mut self,
+        msg: SequenceMessage,
+        events: &mut Vec<MempoolEvent>,
+    ) -> Result<()> {
+        let expected = self.message_sequence.map(|seq| seq.wrapping_add(1));
+        self.message_sequence = Some(msg.message_sequence);
+        if expected.is_some_and(|seq| seq != msg.message_sequence) {
+            log::debug!(target: "corepc", "missed ZMQ sequence messages, re-syncing mempool");
+            return self.resync(events);
+        }
+
+        let (txid, seq, added) = match msg.notification {
+            SequenceNotification::BlockConnected(_)
+            | SequenceNotification::BlockDisconnected(_) => return self.resync(events),
+            SequenceNotification::TransactionAdded { txid, mempool_sequence } =>
+                (txid, mempool_sequence, true),
+            SequenceNotification::TransactionRemoved { txid, mempool_sequence } =>
+                (txid, mempool_sequence, false),
+        };
+
+        let current = self.mempool_sequence();
+        if seq <= current {
+            // Already part of the mempool we synced.
+            return Ok(());
+        }
+        if seq != current + 1 {
+            log::debug!(target: "corepc", "missed mempool sequence {}, re-syncing mempool", current + 1);
+            return self.resync(events);
+        }
+
+        self.mempool_sequence = Some(seq);
+        if added {
+            if let Some(entry) = self.fetch_entry(txid)? {
+                self.entries.insert(txid, entry);
+                events.push(MempoolEvent::Added(txid));
+            }
+        } else if self.entries.remove(&txid).is_some() {
+            events.push(MempoolEvent::Removed(txid));
+        }
+        Ok(())
+    }
+
+    /// Polls the mempool of the node and applies the difference to the mirror.
+    fn resync(&mut self, events: &mut Vec<MempoolEvent>) -> Result<()> {
+        let res: GetRawMempoolSequence =
+            self.client.call("getrawmempool", &[false.into(), true.into()])?;
+        if self.mempool_sequence == Some(res.mempool_sequence) {
+            return Ok(());
+        }
+
+        let txids: HashSet<Txid> = res.txids.into_iter().collect();
+        let entries = mem::take(&mut self.entries);
+        for (txid, entry) in entries {
+            if txids.contains(&txid) {
+                self.entries.insert(txid, entry);
+            } else {
+                events.push(MempoolEvent::Removed(txid));
+            }
+        }
+        for txid in txids {
+            if self.entries.contains_key(&txid) {
+                continue;
+            }
+            if let Some(entry) = self.fetch_entry(txid)? {
+                self.entries.insert(txid, entry);
+                events.push(MempoolEvent::Added(txid));
+            }
+        }
+
+        self.mempool_sequence = Some(res.mempool_sequence);
+        Ok(())
+    }
+
+    /// Fetches the entry for `txid`, returns `None` if it already left the mempool.
+    fn fetch_entry(&self, txid: Txid) -> Result<Option<MempoolEntry>> {
+        match self.client.mempool_entry(txid) {
+            Ok(entry) => Ok(Some(entry)),
+            Err(Error::JsonRpc(jsonrpc::Error::Rpc(ref e)))
+                if e.code == RPC_INVALID_ADDRESS_OR_KEY =>
+                Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl<C: fmt::Debug> fmt::Debug for MempoolMirror<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MempoolMirror")
+            .field("client", &self.client)
+            .field("len", &self.entries.len())
+            .field("mempool_sequence", &self.mempool_sequence)
+            .field("message_sequence", &self.message_sequence)
+            .field("subscriber", &self.subscriber.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+    use std::collections::BTreeMap;
+
+    use bitcoin::{Amount, Wtxid};
+    use serde_json::{json, Value};
+    use types::model::MempoolEntryFees;
+
+    use super::*;
+
+    /// An in-memory stand-in for the mempool of a node.
+    #[derive(Default)]
+    struct FakeMempool {
+        txids: RefCell<BTreeMap<Txid, ()>>,
+        sequence: Cell<u64>,
+        polls: Cell<usize>,
+    }
+
+    impl FakeMempool {
+        fn add(&self, n: u8) -> (Txid, u64) {
+            let txid = Txid::from_byte_array([n; 32]);
+            self.txids.borrow_mut().insert(txid, ());
+            self.sequence.set(self.sequence.get() + 1);
+            (txid, self.sequence.get())
+        }
+
+        fn remove(&self, txid: Txid) -> u64 {
+            self.txids.borrow_mut().remove(&txid);
+            self.sequence.set(self.sequence.get() + 1);
+            self.sequence.get()
+        }
+    }
+
+    impl Rpc for FakeMempool {
+        fn call<T: for<'a> serde::de::Deserialize<'a>>(
+            &self,
+            method: &str,
+            _args: &[Value],
+        ) -> Result<T> {
+            assert_eq!(method, "getrawmempool");
+            self.polls.set(self.polls.get() + 1);
+            let txids: Vec<_> = self.txids.borrow().keys().copied().collect();
+            Ok(serde_json::from_value(
+                json!({ "txids": txids, "mempool_sequence": self.sequence.get() }),
+            )?)
+        }
+    }
+
+    impl MempoolRpc for FakeMempool {
+        fn mempool_entry(&self, txid: Txid) -> Result<MempoolEntry> {
+            if !self.txids.borrow().contains_key(&txid) {
+                return Err(Error::JsonRpc(jsonrpc::Error::Rpc(jsonrpc::error::RpcError {
+                    code: RPC_INVALID_ADDRESS_OR_KEY,
+                    message: "Transaction not in mempool".to_string(),
+                    data: None,
+                })));
+            }
+            let fee = Amount::from_sat(1000);
+            Ok(MempoolEntry {
+                vsize: Some(100),
+                size: None,
+                weight: Some(400),
+                time: 0,
+                height: 0,
+                descendant_count: 1,
+                descendant_size: 100,
+                ancestor_count: 1,
+                ancestor_size: 100,
+                wtxid: Wtxid::from_byte_array(txid.to_byte_array()),
+                fees: MempoolEntryFees { base: fee, modified: fee, ancestor: fee, descendant: fee },
+                depends: vec![],
+                spent_by: vec![],
+                bip125_replaceable: None,
+                unbroadcast: None,
+            })
+        }
+    }
+
+    fn added(txid: Txid, mempool_sequence: u64, message_sequence: u32) -> SequenceMessage {
+        SequenceMessage {
+            notification: SequenceNotification::TransactionAdded { txid, mempool_sequence },
+            message_sequence,
+        }
+    }
+
+    fn removed(txid: Txid, mempool_sequence: u64, message_sequence: u32) -> SequenceMessage {
+        SequenceMessage {
+            notification: SequenceNotification::TransactionRemoved { txid, mempool_sequence },
+            message_sequence,
+        }
+    }
+
+    #[test]
+    fn bootstraps_and_polls() {
+        let node = FakeMempool::default();
+        let (a, _) = node.add(1);
+        let (b, _) = node.add(2);
+
+        let mut mirror = MempoolMirror::new(&node).unwrap();
+        assert_eq!(mirror.len(), 2);
+        assert!(mirror.entry(&a).is_some());
+        assert_eq!(mirror.mempool_sequence(), 2);
+        assert!(mirror.update().unwrap().is_empty());
+
+        let (c, _) = node.add(3);
+        node.remove(b);
+        let mut events = mirror.update().unwrap();
+        events.sort_by_key(|e| matches!(e, MempoolEvent::Added(_)));
+        assert_eq!(events, [MempoolEvent::Removed(b), MempoolEvent::Added(c)]);
+        assert_eq!(mirror.mempool_sequence(), 4);
+        assert!(mirror.contains(&a) && mirror.contains(&c) && !mirror.contains(&b));
+    }
+
+    #[test]
+    fn applies_sequence_notifications() {
+        let node = FakeMempool::default();
+        let (a, _) = node.add(1);
+        let (tx, rx) = mpsc::channel();
+        let mut mirror = MempoolMirror::new(&node).unwrap().with_subscriber(rx);
+
+        // Already part of the bootstrapped mempool.
+        tx.send(added(a, 1, 7)).unwrap();
+        let (b, seq) = node.add(2);
+        tx.send(added(b, seq, 8)).unwrap();
+        let seq = node.remove(a);
+        tx.send(removed(a, seq, 9)).unwrap();
+
+        let polls = node.polls.get();
+        let events = mirror.update().unwrap();
+        assert_eq!(events, [MempoolEvent::Added(b), MempoolEvent::Removed(a)]);
+        assert_eq!(node.polls.get(), polls);
+        assert_eq!(mirror.txids().collect::<Vec<_>>(), [&b]);
+        assert_eq!(mirror.mempool_sequence(), 3);
+    }
+
+    #[test]
+    fn reports_disconnected_subscriber() {
+        let node = FakeMempool::default();
+        let (tx, rx) = mpsc::channel();
+        let mut mirror = MempoolMirror::new(&node).unwrap().with_subscriber(rx);
+
+        drop(tx);
+        assert!(matches!(mirror.update(), Err(Error::SubscriberDisconnected)));
+    }
+
+    #[test]
+    fn resyncs_on_gap() {
+        let node = FakeMempool::default();
+        let mut mirror = MempoolMirror::new(&node).unwrap();
+        mirror.apply(added(Txid::all_zeros(), 0, 1)).unwrap();
+
+        // A missed mempool sequence.
+        let (a, _) = node.add(1);
+        let (b, seq) = node.add(2);
+        let polls = node.polls.get();
+        let events = mirror.apply(added(b, seq, 2)).unwrap();
+        assert_eq!(node.polls.get(), polls + 1);
+        assert_eq!(events.len(), 2);
+        assert!(mirror.contains(&a) && mirror.contains(&b));
+
+        // A missed ZMQ message.
+        let seq = node.remove(a);
+        let events = mirror.apply(removed(Txid::all_zeros(), seq, 4)).unwrap();
+        assert_eq!(node.polls.get(), polls + 2);
+        assert_eq!(events, [MempoolEvent::Removed(a)]);
+    }
+
+    #[test]
+    fn resyncs_on_block() {
+        let node = FakeMempool::default();
+        let (a, _) = node.add(1);
+        let mut mirror = MempoolMirror::new(&node).unwrap();
+
+        // Mined transactions are removed without an `R` notification.
+        node.remove(a);
+        let msg = SequenceMessage {
+            notification: SequenceNotification::BlockConnected(BlockHash::all_zeros()),
+            message_sequence: 0,
+        };
+        assert_eq!(mirror.apply(msg).unwrap(), [MempoolEvent::Removed(a)]);
+        assert!(mirror.is_empty());
+    }
+
+    #[test]
+    fn parses_multipart_message() {
+        let mut body = [0x11; 32].to_vec();
+        body[0] = 0xaa;
+        body.push(b'A');
+        body.extend_from_slice(&42u64.to_le_bytes());
+        let frames = [b"sequence".to_vec(), body, 7u32.to_le_bytes().to_vec()];
+
+        let msg = SequenceMessage::from_multipart(&frames).unwrap();
+        let txid = match msg.notification {
+            SequenceNotification::TransactionAdded { txid, mempool_sequence: 42 } => txid,
+            n => panic!("unexpected notification {:?}", n),
+        };
+        assert!(txid.to_string().starts_with("aa11"));
+        assert_eq!(msg.message_sequence, 7);
+
+        let mut body = [0; 32].to_vec();
+        body.push(b'C');
+        assert_eq!(
+            SequenceNotification::from_body(&body).unwrap(),
+            SequenceNotification::BlockConnected(BlockHash::all_zeros())
+        );
+        body.push(0);
+        assert!(SequenceNotification::from_body(&body).is_err());
+        assert!(SequenceMessage::from_multipart(&[b"hashtx".as_slice()]).is_err());
+    }
+}
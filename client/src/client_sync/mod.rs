@@ -2,7 +2,12 @@
 
 //! JSON-RPC clients for testing against specific versions of Bitcoin Core.
 
+mod capabilities;
 mod error;
+mod follower;
+mod mempool;
+mod retry;
+mod spend;
 pub mod v17;
 pub mod v18;
 pub mod v19;
@@ -20,15 +25,123 @@ pub mod v30;
 
 use std::fs::File;
 use std::io::{BufRead, BufReader};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-pub use crate::client_sync::error::Error;
+use jsonrpc::cassette::{Recorder, Replay};
+use jsonrpc::failover::Failover;
+use jsonrpc::http::bitreq_http::BitreqHttpTransport;
+
+pub use crate::client_sync::capabilities::{Capabilities, MethodInfo};
+pub use crate::client_sync::error::{Error, InsufficientFundsError};
+pub use crate::client_sync::follower::{ChainEvent, ChainFollower};
+pub use crate::client_sync::mempool::{
+    MempoolEvent, MempoolMirror, MempoolRpc, SequenceMessage, SequenceNotification,
+    SequenceSubscriber,
+};
+pub use crate::client_sync::retry::{is_idempotent, RetryOn, RetryPolicy, RPC_IN_WARMUP};
+pub use crate::client_sync::spend::{KeySpend, SignedSpend, Utxo};
 
 /// Crate-specific Result type.
 ///
 /// Shorthand for `std::result::Result` with our crate-specific [`Error`] type.
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// A client that can call Bitcoin Core JSON-RPC methods.
+///
+/// Implemented by the `Client` and `WalletClient` of every version module, lets utilities such
+/// as [`ChainFollower`] work with any of them.
+pub trait Rpc {
+    /// Call an RPC `method` with given `args` list.
+    fn call<T: for<'a> serde::de::Deserialize<'a>>(
+        &self,
+        method: &str,
+        args: &[serde_json::Value],
+    ) -> Result<T>;
+}
+
+impl<R: Rpc + ?Sized> Rpc for &R {
+    fn call<T: for<'a> serde::de::Deserialize<'a>>(
+        &self,
+        method: &str,
+        args: &[serde_json::Value],
+    ) -> Result<T> {
+        (**self).call(method, args)
+    }
+}
+
+/// A transport to a Bitcoin Core node that can also reach the node's wallet endpoints.
+///
+/// Implement this to use a custom transport with `Client::with_transport`.
+pub trait NodeTransport: jsonrpc::Transport {
+    /// Returns a transport for the `/wallet/<name>` endpoint of the same node.
+    ///
+    /// By default the transport fails every request, i.e. wallet clients are not supported.
+    fn wallet_transport(&self, name: &str) -> Box<dyn jsonrpc::Transport> {
+        Box::new(NoWalletTransport(name.to_owned()))
+    }
+}
+
+impl NodeTransport for BitreqHttpTransport {
+    fn wallet_transport(&self, name: &str) -> Box<dyn jsonrpc::Transport> {
+        Box::new(self.with_url(&wallet_url(self.url(), name)))
+    }
+}
+
+/// Records the requests of wallet clients to the same cassette, as endpoint `wallet/<name>`.
+impl<T: NodeTransport> NodeTransport for Recorder<T> {
+    fn wallet_transport(&self, name: &str) -> Box<dyn jsonrpc::Transport> {
+        Box::new(self.endpoint(wallet_endpoint(name), self.inner().wallet_transport(name)))
+    }
+}
+
+/// Replays the requests of wallet clients from the same cassette, as endpoint `wallet/<name>`.
+impl NodeTransport for Replay {
+    fn wallet_transport(&self, name: &str) -> Box<dyn jsonrpc::Transport> {
+        Box::new(self.endpoint(wallet_endpoint(name)))
+    }
+}
+
+/// Fails over between the wallet endpoints of the same nodes, sharing their health.
+impl<T: NodeTransport> NodeTransport for Failover<T> {
+    fn wallet_transport(&self, name: &str) -> Box<dyn jsonrpc::Transport> {
+        Box::new(self.map(|node| node.wallet_transport(name)))
+    }
+}
+
+/// Returns the cassette endpoint for the requests of wallet `name`.
+fn wallet_endpoint(name: &str) -> String { format!("wallet/{}", name) }
+
+/// The wallet transport of a [`NodeTransport`] that does not support wallets.
+#[derive(Debug)]
+struct NoWalletTransport(String);
+
+impl NoWalletTransport {
+    fn error(&self) -> jsonrpc::Error {
+        let msg = format!("transport does not support wallet endpoints, wallet: {}", self.0);
+        jsonrpc::Error::Transport(msg.into())
+    }
+}
+
+impl jsonrpc::Transport for NoWalletTransport {
+    fn send_request(
+        &self,
+        _: jsonrpc::Request,
+    ) -> std::result::Result<jsonrpc::Response, jsonrpc::Error> {
+        Err(self.error())
+    }
+
+    fn send_batch(
+        &self,
+        _: &[jsonrpc::Request],
+    ) -> std::result::Result<Vec<jsonrpc::Response>, jsonrpc::Error> {
+        Err(self.error())
+    }
+
+    fn fmt_target(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "unsupported wallet endpoint {}", self.0)
+    }
+}
+
 /// The different authentication methods for the client.
 #[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
 pub enum Auth {
@@ -44,37 +157,44 @@ impl Auth {
             Auth::None => Ok((None, None)),
             Auth::UserPass(u, p) => Ok((Some(u), Some(p))),
             Auth::CookieFile(path) => {
-                let line = BufReader::new(File::open(path)?)
-                    .lines()
-                    .next()
-                    .ok_or(Error::InvalidCookieFile)??;
-                let colon = line.find(':').ok_or(Error::InvalidCookieFile)?;
-                Ok((Some(line[..colon].into()), Some(line[colon + 1..].into())))
+                let (user, pass) = read_cookie_file(&path)?;
+                Ok((Some(user), Some(pass)))
             }
         }
     }
 }
 
+/// Reads the user and password from the first line ('user:pass') of the cookie file at `path`.
+#[doc(hidden)]
+pub fn read_cookie_file(path: &Path) -> Result<(String, String)> {
+    let line =
+        BufReader::new(File::open(path)?).lines().next().ok_or(Error::InvalidCookieFile)??;
+    let colon = line.find(':').ok_or(Error::InvalidCookieFile)?;
+    Ok((line[..colon].into(), line[colon + 1..].into()))
+}
+
 /// Defines a `jsonrpc::Client` using `bitreq`.
+///
+/// Also defines a `WalletClient`, see `impl_wallet_client!` for adding the wallet methods to it.
 #[macro_export]
 macro_rules! define_jsonrpc_bitreq_client {
     ($version:literal) => {
         use std::fmt;
 
-        use $crate::client_sync::{log_response, Auth, Result};
         use $crate::client_sync::error::Error;
+        use $crate::client_sync::{Auth, Result};
 
         /// Client implements a JSON-RPC client for the Bitcoin Core daemon or compatible APIs.
         pub struct Client {
             inner: jsonrpc::client::Client,
+            /// The transport used by `inner`, kept so we can derive wallet clients from it.
+            transport: std::sync::Arc<dyn $crate::client_sync::NodeTransport>,
+            retry: $crate::client_sync::RetryPolicy,
         }
 
         impl fmt::Debug for Client {
             fn fmt(&self, f: &mut fmt::Formatter) -> core::fmt::Result {
-                write!(
-                    f,
-                    "corepc_client::client_sync::{}::Client({:?})", $version, self.inner
-                )
+                write!(f, "corepc_client::client_sync::{}::Client({:?})", $version, self.inner)
             }
         }
 
@@ -86,27 +206,83 @@ macro_rules! define_jsonrpc_bitreq_client {
                     .expect("jsonrpc v0.19, this function does not error")
                     .timeout(std::time::Duration::from_secs(60))
                     .build();
-                let inner = jsonrpc::client::Client::with_transport(transport);
 
-                Self { inner }
+                Self::with_transport(transport)
             }
 
             /// Creates a client to a bitcoind JSON-RPC server with authentication.
+            ///
+            /// With [`Auth::CookieFile`] the cookie file is read again, and the call retried once,
+            /// if the server rejects the credentials e.g., because bitcoind restarted.
             pub fn new_with_auth(url: &str, auth: Auth) -> Result<Self> {
-                if matches!(auth, Auth::None) {
-                    return Err(Error::MissingUserPassword);
-                }
-                let (user, pass) = auth.get_user_pass()?;
-
-                let transport = jsonrpc::http::bitreq_http::Builder::new()
+                let builder = jsonrpc::http::bitreq_http::Builder::new()
                     .url(url)
                     .expect("jsonrpc v0.19, this function does not error")
-                    .timeout(std::time::Duration::from_secs(60))
-                    .basic_auth(user.unwrap(), pass)
-                    .build();
+                    .timeout(std::time::Duration::from_secs(60));
+
+                let builder = match auth {
+                    Auth::None => return Err(Error::MissingUserPassword),
+                    Auth::UserPass(user, pass) => builder.basic_auth(user, Some(pass)),
+                    Auth::CookieFile(path) => {
+                        let (user, pass) = $crate::client_sync::read_cookie_file(&path)?;
+                        // Re-reads the cookie if bitcoind restarts and rejects the credentials.
+                        builder.basic_auth(user, Some(pass)).reload_cookie_file(path)
+                    }
+                };
+
+                Ok(Self::with_transport(builder.build()))
+            }
+
+            /// Creates a client that sends its requests over `transport`.
+            ///
+            /// Wallet clients created with `Client::wallet` use the transport returned by
+            /// `NodeTransport::wallet_transport`.
+            pub fn with_transport<T: $crate::client_sync::NodeTransport>(transport: T) -> Self {
+                let transport = std::sync::Arc::new(transport);
+                let inner = jsonrpc::client::Client::with_transport(transport.clone());
+                Self { inner, transport, retry: $crate::client_sync::RetryPolicy::none() }
+            }
+
+            /// Sets the policy for retrying calls that failed with a transient error.
+            ///
+            /// By default calls are not retried. Wallet clients inherit the policy.
+            pub fn with_retry_policy(mut self, policy: $crate::client_sync::RetryPolicy) -> Self {
+                self.retry = policy;
+                self
+            }
+
+            /// Waits until the node is reachable and no longer warming up.
+            ///
+            /// Retries while the node is warming up (`RPC_IN_WARMUP`), its work queue is full
+            /// (HTTP 503) or the call fails with a transport error e.g., a refused or reset
+            /// connection. Any other error is returned immediately. If `timeout` elapses the last
+            /// error is returned.
+            pub fn wait_until_ready(&self, timeout: std::time::Duration) -> Result<()> {
+                let none = $crate::client_sync::RetryPolicy::none();
+                $crate::client_sync::retry::wait_until_ready(
+                    |method| $crate::client_sync::call(&self.inner, &none, method, &[]),
+                    timeout,
+                )
+            }
+
+            /// Discovers the methods, and their arguments, supported by the node.
+            ///
+            /// Calls `help` and then `help <method>` for every method listed.
+            pub fn capabilities(&self) -> Result<$crate::client_sync::Capabilities> {
+                $crate::client_sync::Capabilities::discover(self)
+            }
+
+            /// Returns a client for calling wallet RPC methods on the loaded wallet `name`.
+            ///
+            /// Requests are sent to the `/wallet/<name>` endpoint of the node this client is
+            /// connected to, using the same authentication and timeout. The wallet name is
+            /// URL-encoded. `self` must be connected to the node's base URL i.e., not to a
+            /// wallet endpoint.
+            pub fn wallet(&self, name: &str) -> WalletClient {
+                let transport = self.transport.wallet_transport(name);
                 let inner = jsonrpc::client::Client::with_transport(transport);
 
-                Ok(Self { inner })
+                WalletClient { inner, name: name.to_owned(), retry: self.retry.clone() }
             }
 
             /// Call an RPC `method` with given `args` list.
@@ -115,18 +291,85 @@ macro_rules! define_jsonrpc_bitreq_client {
                 method: &str,
                 args: &[serde_json::Value],
             ) -> Result<T> {
-                let raw = serde_json::value::to_raw_value(args)?;
-                let req = self.inner.build_request(&method, Some(&*raw));
-                if log::log_enabled!(log::Level::Debug) {
-                    log::debug!(target: "corepc", "request: {} {}", method, serde_json::Value::from(args));
-                }
+                $crate::client_sync::call(&self.inner, &self.retry, method, args)
+            }
+        }
 
-                let resp = self.inner.send_request(req).map_err(Error::from);
-                log_response(method, &resp);
-                Ok(resp?.result()?)
+        impl $crate::client_sync::Rpc for Client {
+            fn call<T: for<'a> serde::de::Deserialize<'a>>(
+                &self,
+                method: &str,
+                args: &[serde_json::Value],
+            ) -> Result<T> {
+                Client::call(self, method, args)
             }
         }
-    }
+
+        /// A JSON-RPC client for a single wallet loaded on a Bitcoin Core daemon.
+        ///
+        /// Created with `Client::wallet`, only exposes the methods that act on a wallet.
+        pub struct WalletClient {
+            inner: jsonrpc::client::Client,
+            name: String,
+            retry: $crate::client_sync::RetryPolicy,
+        }
+
+        impl fmt::Debug for WalletClient {
+            fn fmt(&self, f: &mut fmt::Formatter) -> core::fmt::Result {
+                write!(
+                    f,
+                    "corepc_client::client_sync::{}::WalletClient({:?})",
+                    $version, self.inner
+                )
+            }
+        }
+
+        impl WalletClient {
+            /// Returns the name of the wallet this client is for.
+            pub fn name(&self) -> &str { &self.name }
+
+            /// Call an RPC `method` with given `args` list on this wallet.
+            pub fn call<T: for<'a> serde::de::Deserialize<'a>>(
+                &self,
+                method: &str,
+                args: &[serde_json::Value],
+            ) -> Result<T> {
+                $crate::client_sync::call(&self.inner, &self.retry, method, args)
+            }
+        }
+
+        impl $crate::client_sync::Rpc for WalletClient {
+            fn call<T: for<'a> serde::de::Deserialize<'a>>(
+                &self,
+                method: &str,
+                args: &[serde_json::Value],
+            ) -> Result<T> {
+                WalletClient::call(self, method, args)
+            }
+        }
+    };
+}
+
+/// Implements the wallet methods on `WalletClient`, they are not available on `Client`.
+///
+/// Requires `WalletClient` to be in scope, see `define_jsonrpc_bitreq_client!`.
+///
+/// # Parameters
+///
+/// - `$wallet_methods`: A local macro that takes no arguments and invokes all the
+///   `impl_client_vXX__foo!()` macros for wallet-scoped methods.
+#[macro_export]
+macro_rules! impl_wallet_client {
+    ($wallet_methods:ident) => {
+        mod wallet_client {
+            use super::*;
+
+            // The `impl_client_*` macros implement methods on `Client`.
+            type Client = super::WalletClient;
+
+            $wallet_methods!();
+        }
+    };
 }
 
 /// Implements the `check_expected_server_version()` on `Client`.
@@ -156,6 +399,26 @@ macro_rules! impl_client_check_expected_server_version {
     };
 }
 
+/// Implements [`MempoolRpc`] on `Client`.
+///
+/// Requires `Client` to be in scope and implement `get_mempool_entry()`.
+/// See and/or use `impl_client_v17__get_mempool_entry`.
+#[macro_export]
+macro_rules! impl_client_mempool_rpc {
+    () => {
+        impl $crate::client_sync::MempoolRpc for Client {
+            fn mempool_entry(
+                &self,
+                txid: $crate::bitcoin::Txid,
+            ) -> Result<$crate::types::model::MempoolEntry> {
+                let json = self.get_mempool_entry(txid)?;
+                let model = json.into_model().map_err(|e| Error::IntoModel(e.to_string()))?;
+                Ok(model.0)
+            }
+        }
+    };
+}
+
 /// Shorthand for converting a variable into a `serde_json::Value`.
 fn into_json<T>(val: T) -> Result<serde_json::Value>
 where
@@ -164,6 +427,54 @@ where
     Ok(serde_json::to_value(val)?)
 }
 
+/// Calls an RPC `method` with given `args` list using the `inner` client.
+///
+/// Retries the call as long as `retry` allows it.
+fn call<T: for<'a> serde::de::Deserialize<'a>>(
+    inner: &jsonrpc::client::Client,
+    retry: &RetryPolicy,
+    method: &str,
+    args: &[serde_json::Value],
+) -> Result<T> {
+    let raw = serde_json::value::to_raw_value(args)?;
+    let mut attempt = 1;
+    loop {
+        let req = inner.build_request(method, Some(&*raw));
+        if log::log_enabled!(log::Level::Debug) {
+            log::debug!(target: "corepc", "request: {} {}", method, serde_json::Value::from(args));
+        }
+
+        let resp = inner.send_request(req).map_err(Error::from);
+        log_response(method, &resp);
+        let res = resp.and_then(|resp| Ok(resp.result()?));
+        match res {
+            Err(ref e) if attempt < retry.max_attempts && retry.should_retry(method, e) => {
+                let backoff = retry.backoff(attempt);
+                log::debug!(target: "corepc", "retrying {} in {:?} after attempt {}: {}", method, backoff, attempt, e);
+                std::thread::sleep(backoff);
+                attempt += 1;
+            }
+            res => return res,
+        }
+    }
+}
+
+/// Returns the URL of the endpoint for `wallet` on the node at `url`.
+///
+/// The wallet name is percent-encoded, Core URL-decodes everything after `/wallet/`.
+pub fn wallet_url(url: &str, wallet: &str) -> String {
+    let mut s = url.trim_end_matches('/').to_owned();
+    s.push_str("/wallet/");
+    for byte in wallet.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' =>
+                s.push(byte as char),
+            _ => s.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    s
+}
+
 /// Helper to log an RPC response.
 fn log_response(method: &str, resp: &Result<jsonrpc::Response>) {
     use log::Level::{Debug, Trace, Warn};
@@ -188,3 +499,124 @@ fn log_response(method: &str, resp: &Result<jsonrpc::Response>) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replays_node_and_wallet_requests() {
+        let cassette = concat!(
+            r#"{"method":"getblockcount","params":[],"id":1,"response":{"result":101,"error":null,"id":1}}"#,
+            "\n",
+            r#"{"endpoint":"wallet/alice","method":"getbalance","params":[],"id":1,"response":{"result":1.5,"error":null,"id":1}}"#,
+        );
+        let replay = Replay::from_reader(cassette.as_bytes(), "test".to_owned()).unwrap();
+
+        let client = v30::Client::with_transport(replay.clone());
+        assert_eq!(client.call::<u64>("getblockcount", &[]).unwrap(), 101);
+        assert_eq!(client.wallet("alice").call::<f64>("getbalance", &[]).unwrap(), 1.5);
+        assert!(client.wallet("bob").call::<f64>("getbalance", &[]).is_err());
+        assert!(replay.is_finished());
+    }
+
+    #[test]
+    fn wallets_not_supported_by_default() {
+        struct NoWallets(Replay);
+
+        impl jsonrpc::Transport for NoWallets {
+            fn send_request(
+                &self,
+                req: jsonrpc::Request,
+            ) -> std::result::Result<jsonrpc::Response, jsonrpc::Error> {
+                self.0.send_request(req)
+            }
+
+            fn send_batch(
+                &self,
+                reqs: &[jsonrpc::Request],
+            ) -> std::result::Result<Vec<jsonrpc::Response>, jsonrpc::Error> {
+                self.0.send_batch(reqs)
+            }
+
+            fn fmt_target(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                self.0.fmt_target(f)
+            }
+        }
+
+        impl NodeTransport for NoWallets {}
+
+        let replay = Replay::from_reader(&b""[..], "empty".to_owned()).unwrap();
+        let client = v30::Client::with_transport(NoWallets(replay));
+        let err = client.wallet("alice").call::<f64>("getbalance", &[]).unwrap_err();
+        assert!(err.to_string().contains("does not support wallet endpoints"));
+    }
+
+    #[test]
+    fn calls_mock_server() {
+        use jsonrpc::mock::{self, MockServer};
+
+        let server = MockServer::start().unwrap().with_auth("user", "pass");
+        server.set_response("getblockcount", &types::v30::GetBlockCount(101));
+        server.set_handler("getbalance", |req| match req.wallet.as_deref() {
+            Some("alice") => Ok(serde_json::json!(1.5)),
+            _ => Err(mock::wallet_not_found()),
+        });
+
+        let auth = Auth::UserPass("user".to_owned(), "pass".to_owned());
+        let client = v30::Client::new_with_auth(&server.url(), auth).unwrap();
+        assert_eq!(client.get_block_count().unwrap().0, 101);
+        assert_eq!(client.wallet("alice").get_balance().unwrap().0, 1.5);
+        let err = client.wallet("bob").get_balance().unwrap_err();
+        assert!(matches!(err, Error::JsonRpc(jsonrpc::Error::Rpc(ref e)) if e.code == -18));
+
+        let auth = Auth::UserPass("user".to_owned(), "wrong".to_owned());
+        let client = v30::Client::new_with_auth(&server.url(), auth).unwrap();
+        assert!(client.get_block_count().is_err());
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[test]
+    fn fails_over_to_replica() {
+        use jsonrpc::mock::MockServer;
+
+        // Nothing listens on the port of a dropped server.
+        let down = MockServer::start().unwrap().url();
+        let server = MockServer::start().unwrap();
+        server.set_response("getblockcount", &101);
+        server.set_handler("getbalance", |req| Ok(serde_json::json!(req.wallet)));
+
+        let transport = |url: &str| BitreqHttpTransport::builder().url(url).unwrap().build();
+        let failover = Failover::new(vec![transport(&down), transport(&server.url())])
+            .with_idempotent(is_idempotent);
+        let client = v30::Client::with_transport(failover);
+        assert_eq!(client.get_block_count().unwrap().0, 101);
+        assert_eq!(client.wallet("alice").call::<String>("getbalance", &[]).unwrap(), "alice");
+    }
+
+    #[test]
+    fn wallet_url_appends_wallet_path() {
+        assert_eq!(
+            wallet_url("http://127.0.0.1:18443", "alice"),
+            "http://127.0.0.1:18443/wallet/alice"
+        );
+        assert_eq!(
+            wallet_url("http://127.0.0.1:18443/", "alice"),
+            "http://127.0.0.1:18443/wallet/alice"
+        );
+    }
+
+    #[test]
+    fn wallet_url_encodes_wallet_name() {
+        assert_eq!(wallet_url("http://localhost", ""), "http://localhost/wallet/");
+        assert_eq!(
+            wallet_url("http://localhost", "my wallet"),
+            "http://localhost/wallet/my%20wallet"
+        );
+        assert_eq!(
+            wallet_url("http://localhost", "a/b?c#d%"),
+            "http://localhost/wallet/a%2Fb%3Fc%23d%25"
+        );
+        assert_eq!(wallet_url("http://localhost", "café"), "http://localhost/wallet/caf%C3%A9");
+    }
+}
@@ -0,0 +1,389 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Retrying RPC calls that failed with a transient error.
+
+use std::time::{Duration, Instant};
+use std::{error, io, thread};
+
+use crate::client_sync::{Error, Result};
+
+/// Error code returned by Bitcoin Core while it is starting up (`RPC_IN_WARMUP`).
+pub const RPC_IN_WARMUP: i32 = -28;
+
+/// Methods that are not safe to send twice.
+///
+/// These are only retried if the error shows that the node did not execute the request.
+const NON_IDEMPOTENT_METHODS: &[&str] = &[
+    "abandontransaction",
+    "bumpfee",
+    "createwallet",
+    "createwalletdescriptor",
+    "encryptwallet",
+    "generate",
+    "generateblock",
+    "generatetoaddress",
+    "generatetodescriptor",
+    "getnewaddress",
+    "getrawchangeaddress",
+    "migratewallet",
+    "psbtbumpfee",
+    "restorewallet",
+    "send",
+    "sendall",
+    "sendmany",
+    "sendtoaddress",
+    "sethdseed",
+    "stop",
+    "submitpackage",
+    "walletpassphrasechange",
+];
+
+/// Returns `true` if calling `method` twice has the same effect as calling it once.
+pub fn is_idempotent(method: &str) -> bool { !NON_IDEMPOTENT_METHODS.contains(&method) }
+
+/// Policy for retrying RPC calls that failed with a transient error.
+///
+/// Clients do not retry by default, see [`RetryPolicy::none`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts for a call, including the first one.
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled for each subsequent retry.
+    pub initial_backoff: Duration,
+    /// Upper bound on the delay between two attempts.
+    pub max_backoff: Duration,
+    /// The errors that are retried.
+    pub retry_on: RetryOn,
+}
+
+impl RetryPolicy {
+    /// Returns a policy that never retries.
+    pub fn none() -> Self { RetryPolicy { max_attempts: 1, ..Default::default() } }
+
+    /// Returns the delay before retry number `retry`, the first retry is number 1.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        self.initial_backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+
+    /// Returns `true` if a call to `method` that failed with `error` should be retried.
+    ///
+    /// Does not take the number of attempts into account.
+    pub fn should_retry(&self, method: &str, error: &Error) -> bool {
+        match Transient::from_error(error) {
+            Some(Transient::Warmup) => self.retry_on.warmup,
+            Some(Transient::ConnectionRefused) => self.retry_on.connection_refused,
+            Some(Transient::WorkQueueFull) => self.retry_on.work_queue_full,
+            Some(Transient::Transport) => self.retry_on.transport && is_idempotent(method),
+            None => false,
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    /// Makes up to 5 attempts, waiting 100ms, 200ms, 400ms and 800ms between them.
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+            retry_on: RetryOn::default(),
+        }
+    }
+}
+
+/// The kinds of errors a [`RetryPolicy`] retries.
+///
+/// All kinds are retried by default.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RetryOn {
+    /// The node is still starting up (`RPC_IN_WARMUP`).
+    pub warmup: bool,
+    /// The connection to the node was refused or timed out, e.g. because it is (re)starting.
+    pub connection_refused: bool,
+    /// The node's RPC work queue is full (HTTP 503).
+    pub work_queue_full: bool,
+    /// Any other transport error, e.g. a timeout or a reset connection.
+    ///
+    /// The node may have executed the request so these are only retried for idempotent methods.
+    pub transport: bool,
+}
+
+impl Default for RetryOn {
+    fn default() -> Self {
+        RetryOn { warmup: true, connection_refused: true, work_queue_full: true, transport: true }
+    }
+}
+
+/// A transient error, i.e. one that may go away if the call is retried.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Transient {
+    /// The node is still starting up, the request was not executed.
+    Warmup,
+    /// The connection was refused or timed out, the request was not sent.
+    ConnectionRefused,
+    /// The node's work queue is full, the request was not executed.
+    WorkQueueFull,
+    /// Any other transport error, the request may have been executed.
+    Transport,
+}
+
+impl Transient {
+    fn from_error(error: &Error) -> Option<Self> {
+        let e = match error {
+            Error::JsonRpc(jsonrpc::Error::Rpc(ref e)) if e.code == RPC_IN_WARMUP =>
+                return Some(Transient::Warmup),
+            Error::JsonRpc(jsonrpc::Error::Transport(ref e)) => e,
+            _ => return None,
+        };
+
+        if let Some(jsonrpc::bitreq_http::Error::Http(ref e)) = e.downcast_ref() {
+            return match e.status_code {
+                503 => Some(Transient::WorkQueueFull),
+                _ => None,
+            };
+        }
+
+        let mut source: Option<&(dyn error::Error + 'static)> = Some(&**e);
+        while let Some(e) = source {
+            if let Some(e) = e.downcast_ref::<io::Error>() {
+                if e.kind() == io::ErrorKind::ConnectionRefused {
+                    return Some(Transient::ConnectionRefused);
+                }
+            }
+            // Carries no `io::Error` to look at.
+            if let Some(jsonrpc::bitreq::Error::ConnectTimeout) = e.downcast_ref() {
+                return Some(Transient::ConnectionRefused);
+            }
+            source = e.source();
+        }
+        Some(Transient::Transport)
+    }
+}
+
+/// Calls `getblockchaininfo` using `call` until the node is reachable and no longer warming up.
+///
+/// Retries all transient errors: warmup, a full work queue (HTTP 503) and transport errors, the
+/// node may reset connections or close them without a reply while it binds its RPC port. Any other
+/// error is returned immediately, if `timeout` elapses the last error is returned.
+pub(crate) fn wait_until_ready<F>(mut call: F, timeout: Duration) -> Result<()>
+where
+    F: FnMut(&str) -> Result<serde_json::Value>,
+{
+    let policy = RetryPolicy { max_backoff: Duration::from_secs(1), ..Default::default() };
+    let start = Instant::now();
+    let mut retry = 0;
+    loop {
+        match call("getblockchaininfo") {
+            Ok(_) => return Ok(()),
+            Err(e) => match Transient::from_error(&e) {
+                Some(_) => {
+                    let remaining = match timeout.checked_sub(start.elapsed()) {
+                        Some(remaining) if !remaining.is_zero() => remaining,
+                        _ => return Err(e),
+                    };
+                    retry += 1;
+                    log::debug!(target: "corepc", "node not ready, retrying: {}", e);
+                    thread::sleep(policy.backoff(retry).min(remaining));
+                }
+                None => return Err(e),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::client_sync::v17::Client;
+
+    const WARMUP: &str =
+        r#"{"result":null,"error":{"code":-28,"message":"Loading block index…"},"id":1}"#;
+    const READY: &str = r#"{"result":{"chain":"regtest"},"error":null,"id":1}"#;
+
+    /// Serves a JSON-RPC error `-28` for the first `warmup` requests and a result afterwards.
+    ///
+    /// Returns the URL of the server and a counter of the requests it received.
+    fn serve(warmup: usize) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let count = Arc::new(AtomicUsize::new(0));
+        let counter = count.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" || line.is_empty() {
+                        break;
+                    }
+                    let line = line.to_ascii_lowercase();
+                    if let Some(len) = line.strip_prefix("content-length:") {
+                        content_length = len.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+
+                let n = counter.fetch_add(1, Ordering::SeqCst);
+                let (status, body) = if n < warmup {
+                    ("500 Internal Server Error", WARMUP)
+                } else {
+                    ("200 OK", READY)
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+        (url, count)
+    }
+
+    fn fast_policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(10),
+            ..Default::default()
+        }
+    }
+
+    fn is_warmup(e: &Error) -> bool { Transient::from_error(e) == Some(Transient::Warmup) }
+
+    fn transport_error(kind: io::ErrorKind) -> Error {
+        Error::JsonRpc(jsonrpc::Error::Transport(Box::new(io::Error::new(kind, "test"))))
+    }
+
+    #[test]
+    fn backoff_is_exponential_and_capped() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3), Duration::from_millis(400));
+        assert_eq!(policy.backoff(10), Duration::from_secs(2));
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn non_idempotent_methods_are_not_retried_blindly() {
+        let policy = RetryPolicy::default();
+        let timeout = transport_error(io::ErrorKind::TimedOut);
+        let refused = transport_error(io::ErrorKind::ConnectionRefused);
+
+        assert!(policy.should_retry("getblockchaininfo", &timeout));
+        assert!(!policy.should_retry("sendtoaddress", &timeout));
+        // The request never reached the node so it is safe to retry.
+        assert!(policy.should_retry("sendtoaddress", &refused));
+        let connect_timeout = Error::JsonRpc(jsonrpc::Error::Transport(Box::new(
+            jsonrpc::bitreq_http::Error::Bitreq(jsonrpc::bitreq::Error::ConnectTimeout),
+        )));
+        assert!(policy.should_retry("sendtoaddress", &connect_timeout));
+    }
+
+    #[test]
+    fn retry_on_selects_errors() {
+        let policy = RetryPolicy {
+            retry_on: RetryOn { connection_refused: false, ..Default::default() },
+            ..Default::default()
+        };
+        let refused = transport_error(io::ErrorKind::ConnectionRefused);
+        let rpc = Error::JsonRpc(jsonrpc::Error::Rpc(jsonrpc::error::RpcError {
+            code: -5,
+            message: "Invalid address".to_owned(),
+            data: None,
+        }));
+
+        assert!(!policy.should_retry("getblockchaininfo", &refused));
+        assert!(!policy.should_retry("getblockchaininfo", &rpc));
+    }
+
+    #[test]
+    fn does_not_retry_by_default() {
+        let (url, count) = serve(1);
+        let client = Client::new(&url);
+
+        let err = client.call::<serde_json::Value>("getblockchaininfo", &[]).unwrap_err();
+        assert!(is_warmup(&err));
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn retries_warmup() {
+        let (url, count) = serve(3);
+        let client = Client::new(&url).with_retry_policy(fast_policy(5));
+
+        client.call::<serde_json::Value>("getblockchaininfo", &[]).unwrap();
+        assert_eq!(count.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn gives_up_after_max_attempts() {
+        let (url, count) = serve(10);
+        let client = Client::new(&url).with_retry_policy(fast_policy(3));
+
+        let err = client.call::<serde_json::Value>("getblockchaininfo", &[]).unwrap_err();
+        assert!(is_warmup(&err));
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn wait_until_ready_polls_during_warmup() {
+        let (url, count) = serve(2);
+        let client = Client::new(&url);
+
+        client.wait_until_ready(Duration::from_secs(10)).unwrap();
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn wait_until_ready_retries_transport_errors() {
+        let mut calls = 0;
+        let call = |_: &str| {
+            calls += 1;
+            match calls {
+                1 => Err(transport_error(io::ErrorKind::ConnectionReset)),
+                2 => Err(transport_error(io::ErrorKind::UnexpectedEof)),
+                _ => Ok(serde_json::Value::Null),
+            }
+        };
+
+        wait_until_ready(call, Duration::from_secs(10)).unwrap();
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn wait_until_ready_returns_rpc_errors() {
+        let call = |_: &str| {
+            Err(Error::JsonRpc(jsonrpc::Error::Rpc(jsonrpc::error::RpcError {
+                code: -1,
+                message: "test".to_owned(),
+                data: None,
+            })))
+        };
+
+        let err = wait_until_ready(call, Duration::from_secs(10)).unwrap_err();
+        assert!(matches!(err, Error::JsonRpc(jsonrpc::Error::Rpc(_))));
+    }
+
+    #[test]
+    fn wait_until_ready_times_out() {
+        let (url, _) = serve(usize::MAX);
+        let client = Client::new(&url);
+
+        let err = client.wait_until_ready(Duration::from_millis(300)).unwrap_err();
+        assert!(is_warmup(&err));
+    }
+}
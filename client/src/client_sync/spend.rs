@@ -0,0 +1,497 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Spending UTXOs without a wallet, using `scantxoutset` and `signrawtransactionwithkey`.
+
+use bitcoin::{
+    absolute, consensus, transaction, Amount, FeeRate, OutPoint, PrivateKey, ScriptBuf, Sequence,
+    Transaction, TxIn, TxOut, Txid, Witness,
+};
+use serde::Deserialize;
+
+use crate::client_sync::error::InsufficientFundsError;
+use crate::client_sync::{into_json, Error, Result, Rpc};
+
+/// A UTXO found by `scantxoutset`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Utxo {
+    /// The outpoint of the UTXO.
+    pub outpoint: OutPoint,
+    /// The script pubkey of the UTXO.
+    pub script_pubkey: ScriptBuf,
+    /// The value of the UTXO.
+    pub amount: Amount,
+    /// The descriptor that matched the UTXO, with key origin information. v0.18 and later only.
+    pub descriptor: Option<String>,
+    /// The height of the block containing the UTXO.
+    pub height: u64,
+}
+
+/// A signed transaction created by [`KeySpend`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignedSpend {
+    /// The signed transaction.
+    pub tx: Transaction,
+    /// The UTXOs spent by the transaction, in input order.
+    pub inputs: Vec<Utxo>,
+    /// The fee paid by the transaction.
+    pub fee: Amount,
+    /// The fee rate the fee was calculated with.
+    pub fee_rate: FeeRate,
+}
+
+impl SignedSpend {
+    /// Returns the id of the transaction.
+    pub fn txid(&self) -> Txid { self.tx.compute_txid() }
+}
+
+/// Spends the UTXOs of a set of descriptors with their private keys, without a wallet.
+///
+/// Finds the UTXOs with `scantxoutset`, selects the largest ones first, signs the transaction
+/// with `signrawtransactionwithkey` and optionally broadcasts it with `sendrawtransaction`. Works
+/// against nodes running with `-disablewallet`.
+///
+/// Any value not sent to the outputs, minus the fee, goes to the change script. Without outputs
+/// all UTXOs are swept to the change script. Change below the dust limit is added to the fee.
+///
+/// The fee rate is estimated with `estimatesmartfee` unless set with [`KeySpend::fee_rate`].
+///
+/// # Examples
+///
+/// ```no_run
+/// # use corepc_client::bitcoin::{PrivateKey, ScriptBuf};
+/// # use corepc_client::client_sync::{v30::Client, KeySpend};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let client = Client::new("http://127.0.0.1:8332");
+/// # let (key, destination): (PrivateKey, ScriptBuf) = todo!();
+/// // Sweep a cold storage key to a new address.
+/// let spend = KeySpend::new(destination)
+///     .descriptor(format!("wpkh({})", key))
+///     .key(key)
+///     .send(&client)?;
+/// println!("swept {} with txid {}", spend.inputs.len(), spend.txid());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct KeySpend {
+    descriptors: Vec<String>,
+    keys: Vec<PrivateKey>,
+    outputs: Vec<TxOut>,
+    change: ScriptBuf,
+    fee_rate: Option<FeeRate>,
+    conf_target: u32,
+}
+
+impl KeySpend {
+    /// Creates a spend that sends the change to `change`.
+    pub fn new(change: ScriptBuf) -> Self {
+        KeySpend {
+            descriptors: vec![],
+            keys: vec![],
+            outputs: vec![],
+            change,
+            fee_rate: None,
+            conf_target: 6,
+        }
+    }
+
+    /// Adds a descriptor whose UTXOs can be spent, e.g. `wpkh(<WIF>)` or `tr(<xprv>/0/*)`.
+    ///
+    /// Ranged descriptors are scanned with the default range of `scantxoutset` i.e., 0 to 1000.
+    pub fn descriptor<S: Into<String>>(mut self, descriptor: S) -> Self {
+        self.descriptors.push(descriptor.into());
+        self
+    }
+
+    /// Adds a private key used to sign the inputs.
+    pub fn key(mut self, key: PrivateKey) -> Self {
+        self.keys.push(key);
+        self
+    }
+
+    /// Adds an output paying `amount` to `script_pubkey`.
+    pub fn pay_to(mut self, script_pubkey: ScriptBuf, amount: Amount) -> Self {
+        self.outputs.push(TxOut { value: amount, script_pubkey });
+        self
+    }
+
+    /// Sets the fee rate instead of estimating it.
+    pub fn fee_rate(mut self, fee_rate: FeeRate) -> Self {
+        self.fee_rate = Some(fee_rate);
+        self
+    }
+
+    /// Sets the confirmation target passed to `estimatesmartfee` (default: 6 blocks).
+    pub fn conf_target(mut self, blocks: u32) -> Self {
+        self.conf_target = blocks;
+        self
+    }
+
+    /// Returns the UTXOs of the descriptors, largest first.
+    pub fn utxos<C: Rpc>(&self, client: &C) -> Result<Vec<Utxo>> {
+        let res: ScanResult =
+            client.call("scantxoutset", &["start".into(), into_json(&self.descriptors)?])?;
+        let mut utxos = res
+            .unspents
+            .into_iter()
+            .map(|u| {
+                Ok(Utxo {
+                    outpoint: OutPoint { txid: u.txid, vout: u.vout },
+                    script_pubkey: ScriptBuf::from_hex(&u.script_pubkey)?,
+                    amount: u.amount,
+                    descriptor: u.desc,
+                    height: u.height,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        utxos.sort_by_key(|u| std::cmp::Reverse(u.amount));
+        Ok(utxos)
+    }
+
+    /// Selects the UTXOs, builds and signs the transaction without broadcasting it.
+    pub fn build<C: Rpc>(&self, client: &C) -> Result<SignedSpend> {
+        let utxos = self.utxos(client)?;
+        let fee_rate = match self.fee_rate {
+            Some(fee_rate) => fee_rate,
+            None => self.estimate_fee_rate(client)?,
+        };
+        let available = utxos.iter().map(|u| u.amount).sum::<Amount>();
+        let target = self.outputs.iter().map(|o| o.value).sum::<Amount>();
+        // A sweep has to leave a change output that is not dust.
+        let sweep = self.outputs.is_empty();
+        let minimum = if sweep { target + self.dust() } else { target };
+        if utxos.is_empty() {
+            return Err(InsufficientFundsError { available, required: minimum }.into());
+        }
+
+        // Sweeps spend everything, otherwise start with the fewest UTXOs that cover the outputs.
+        let mut count = if sweep {
+            utxos.len()
+        } else {
+            let mut total = Amount::ZERO;
+            utxos
+                .iter()
+                .position(|u| {
+                    total += u.amount;
+                    total >= target
+                })
+                .map_or(utxos.len(), |i| i + 1)
+        };
+
+        'inputs: loop {
+            let inputs = &utxos[..count];
+            let total = inputs.iter().map(|u| u.amount).sum::<Amount>();
+
+            // The fee depends on the size of the signed transaction, and the length of the
+            // signatures varies between signings, so sign again until the fee paid covers the
+            // transaction that is returned.
+            let mut fee = Amount::ZERO;
+            loop {
+                let required = minimum + fee;
+                if total < required {
+                    if count < utxos.len() {
+                        count += 1;
+                        continue 'inputs;
+                    }
+                    return Err(InsufficientFundsError { available, required }.into());
+                }
+
+                let change = Some(total - target - fee).filter(|change| *change >= self.dust());
+                let tx = self.sign(client, inputs, change)?;
+                let paid = total - target - change.unwrap_or(Amount::ZERO);
+                let needed =
+                    fee_rate.fee_vb(tx.vsize() as u64).ok_or(Error::UnexpectedStructure)?;
+                if paid >= needed {
+                    return Ok(SignedSpend { tx, inputs: inputs.to_vec(), fee: paid, fee_rate });
+                }
+                fee = fee.max(needed);
+            }
+        }
+    }
+
+    /// Builds the transaction, see [`KeySpend::build`], and broadcasts it.
+    pub fn send<C: Rpc>(&self, client: &C) -> Result<SignedSpend> {
+        let spend = self.build(client)?;
+        let hex = consensus::encode::serialize_hex(&spend.tx);
+        let _: Txid = client.call("sendrawtransaction", &[hex.into()])?;
+        Ok(spend)
+    }
+
+    /// Returns the smallest change value that is not dust.
+    fn dust(&self) -> Amount { self.change.minimal_non_dust() }
+
+    /// Returns the fee rate estimated by the node for the confirmation target.
+    fn estimate_fee_rate<C: Rpc>(&self, client: &C) -> Result<FeeRate> {
+        let res: EstimateResult = client.call("estimatesmartfee", &[self.conf_target.into()])?;
+        match res.fee_rate {
+            // The estimate is per kvB, there are 4 kWU per kvB.
+            Some(per_kvb) => Ok(FeeRate::from_sat_per_kwu(per_kvb.to_sat() / 4)),
+            None => Err(Error::NoFeeEstimate(res.errors.unwrap_or_default().join(", "))),
+        }
+    }
+
+    /// Signs a transaction spending `inputs` to the outputs and, if set, `change`.
+    fn sign<C: Rpc>(
+        &self,
+        client: &C,
+        inputs: &[Utxo],
+        change: Option<Amount>,
+    ) -> Result<Transaction> {
+        let mut output = self.outputs.clone();
+        if let Some(value) = change {
+            output.push(TxOut { value, script_pubkey: self.change.clone() });
+        }
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: inputs
+                .iter()
+                .map(|u| TxIn {
+                    previous_output: u.outpoint,
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output,
+        };
+
+        let hex = consensus::encode::serialize_hex(&tx);
+        let keys = self.keys.iter().map(|k| k.to_string()).collect::<Vec<_>>();
+        let res: SignResult =
+            client.call("signrawtransactionwithkey", &[hex.into(), into_json(keys)?])?;
+        if !res.complete {
+            let errors = res.errors.iter().map(|e| e.error.as_str()).collect::<Vec<_>>();
+            return Err(Error::IncompleteSignature(errors.join(", ")));
+        }
+        Ok(consensus::encode::deserialize_hex(&res.hex)?)
+    }
+}
+
+/// The fields of the `scantxoutset start` result used by [`KeySpend`].
+#[derive(Deserialize)]
+struct ScanResult {
+    unspents: Vec<ScanUnspent>,
+}
+
+#[derive(Deserialize)]
+struct ScanUnspent {
+    txid: Txid,
+    vout: u32,
+    #[serde(rename = "scriptPubKey")]
+    script_pubkey: String,
+    desc: Option<String>,
+    #[serde(with = "bitcoin::amount::serde::as_btc")]
+    amount: Amount,
+    height: u64,
+}
+
+/// The fields of the `estimatesmartfee` result used by [`KeySpend`].
+#[derive(Deserialize)]
+struct EstimateResult {
+    #[serde(rename = "feerate", default, with = "bitcoin::amount::serde::as_btc::opt")]
+    fee_rate: Option<Amount>,
+    errors: Option<Vec<String>>,
+}
+
+/// The fields of the `signrawtransactionwithkey` result used by [`KeySpend`].
+#[derive(Deserialize)]
+struct SignResult {
+    hex: String,
+    complete: bool,
+    #[serde(default)]
+    errors: Vec<SignError>,
+}
+
+#[derive(Deserialize)]
+struct SignError {
+    error: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use bitcoin::hashes::Hash as _;
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    /// A stand-in for a node that pretends every input adds 100 vbytes when signed.
+    struct FakeNode {
+        unspents: Vec<u64>,
+        fee_rate: Option<f64>,
+        /// The script length of each signing, the last one is used for the following ones.
+        script_sig_lens: RefCell<Vec<usize>>,
+        sent: RefCell<Vec<Transaction>>,
+    }
+
+    impl FakeNode {
+        fn new(unspents: &[u64]) -> Self {
+            FakeNode {
+                unspents: unspents.to_vec(),
+                fee_rate: Some(0.0001),
+                script_sig_lens: RefCell::new(vec![100]),
+                sent: RefCell::default(),
+            }
+        }
+    }
+
+    impl Rpc for FakeNode {
+        fn call<T: for<'a> serde::de::Deserialize<'a>>(
+            &self,
+            method: &str,
+            args: &[Value],
+        ) -> Result<T> {
+            let res = match method {
+                "scantxoutset" => {
+                    let unspents = self
+                        .unspents
+                        .iter()
+                        .enumerate()
+                        .map(|(i, sat)| {
+                            json!({
+                                "txid": Txid::from_byte_array([i as u8; 32]),
+                                "vout": 0,
+                                "scriptPubKey": "0014".to_string() + &"00".repeat(20),
+                                "desc": "wpkh(...)",
+                                "amount": Amount::from_sat(*sat).to_btc(),
+                                "height": 100,
+                            })
+                        })
+                        .collect::<Vec<_>>();
+                    json!({ "success": true, "unspents": unspents })
+                }
+                "estimatesmartfee" => match self.fee_rate {
+                    Some(rate) => json!({ "feerate": rate, "blocks": 6 }),
+                    None =>
+                        json!({ "errors": ["Insufficient data or no feerate found"], "blocks": 0 }),
+                },
+                "signrawtransactionwithkey" => {
+                    let mut tx: Transaction =
+                        consensus::encode::deserialize_hex(args[0].as_str().unwrap())?;
+                    let mut lens = self.script_sig_lens.borrow_mut();
+                    let len = if lens.len() > 1 { lens.remove(0) } else { lens[0] };
+                    for input in &mut tx.input {
+                        input.script_sig = ScriptBuf::from_bytes(vec![0; len]);
+                    }
+                    json!({ "hex": consensus::encode::serialize_hex(&tx), "complete": true })
+                }
+                "sendrawtransaction" => {
+                    let tx: Transaction =
+                        consensus::encode::deserialize_hex(args[0].as_str().unwrap())?;
+                    let txid = tx.compute_txid();
+                    self.sent.borrow_mut().push(tx);
+                    json!(txid)
+                }
+                _ => panic!("unexpected method {}", method),
+            };
+            Ok(serde_json::from_value(res)?)
+        }
+    }
+
+    fn sat_per_vb(rate: u64) -> FeeRate { FeeRate::from_sat_per_kwu(rate * 250) }
+
+    fn destination() -> ScriptBuf {
+        ScriptBuf::from_hex(&("0014".to_string() + &"11".repeat(20))).unwrap()
+    }
+
+    fn change() -> ScriptBuf {
+        ScriptBuf::from_hex(&("0014".to_string() + &"22".repeat(20))).unwrap()
+    }
+
+    #[test]
+    fn pays_outputs_with_change() {
+        let node = FakeNode::new(&[10_000, 50_000, 30_000]);
+        let spend = KeySpend::new(change())
+            .pay_to(destination(), Amount::from_sat(60_000))
+            .fee_rate(sat_per_vb(2))
+            .build(&node)
+            .unwrap();
+
+        // Largest first: 50k + 30k cover the output.
+        assert_eq!(
+            spend.inputs.iter().map(|u| u.amount.to_sat()).collect::<Vec<_>>(),
+            [50_000, 30_000]
+        );
+        assert_eq!(spend.fee, sat_per_vb(2).fee_vb(spend.tx.vsize() as u64).unwrap());
+        assert_eq!(spend.tx.output[0].value, Amount::from_sat(60_000));
+        assert_eq!(spend.tx.output[1].script_pubkey, change());
+        assert_eq!(spend.tx.output[1].value, Amount::from_sat(20_000) - spend.fee);
+    }
+
+    #[test]
+    fn pays_fee_of_final_transaction() {
+        let node = FakeNode::new(&[50_000]);
+        // The signatures get longer after the first signing.
+        *node.script_sig_lens.borrow_mut() = vec![100, 102];
+        let spend = KeySpend::new(change())
+            .pay_to(destination(), Amount::from_sat(30_000))
+            .fee_rate(sat_per_vb(2))
+            .build(&node)
+            .unwrap();
+
+        assert_eq!(spend.tx.input[0].script_sig.len(), 102);
+        assert_eq!(spend.fee, sat_per_vb(2).fee_vb(spend.tx.vsize() as u64).unwrap());
+        assert_eq!(spend.tx.output[1].value, Amount::from_sat(20_000) - spend.fee);
+    }
+
+    #[test]
+    fn adds_inputs_to_pay_fee() {
+        let node = FakeNode::new(&[50_000, 10_000]);
+        let spend = KeySpend::new(change())
+            .pay_to(destination(), Amount::from_sat(49_900))
+            .fee_rate(sat_per_vb(1))
+            .build(&node)
+            .unwrap();
+        assert_eq!(spend.inputs.len(), 2);
+    }
+
+    #[test]
+    fn drops_dust_change() {
+        let node = FakeNode::new(&[50_000]);
+        let spend = KeySpend::new(change())
+            .pay_to(destination(), Amount::from_sat(49_500))
+            .fee_rate(sat_per_vb(1))
+            .build(&node)
+            .unwrap();
+        assert_eq!(spend.tx.output.len(), 1);
+        assert_eq!(spend.fee, Amount::from_sat(500));
+    }
+
+    #[test]
+    fn sweeps_with_estimated_fee() {
+        let node = FakeNode::new(&[10_000, 20_000]);
+        let spend = KeySpend::new(change()).send(&node).unwrap();
+
+        // 0.0001 BTC/kvB is 10 sat/vB.
+        assert_eq!(spend.fee_rate, sat_per_vb(10));
+        assert_eq!(spend.tx.output.len(), 1);
+        assert_eq!(spend.tx.output[0].value + spend.fee, Amount::from_sat(30_000));
+        assert_eq!(node.sent.borrow()[..], [spend.tx]);
+    }
+
+    #[test]
+    fn insufficient_funds() {
+        let node = FakeNode::new(&[10_000]);
+        let err = KeySpend::new(change())
+            .pay_to(destination(), Amount::from_sat(10_000))
+            .fee_rate(sat_per_vb(1))
+            .build(&node)
+            .unwrap_err();
+        match err {
+            Error::InsufficientFunds(e) => {
+                assert_eq!(e.available, Amount::from_sat(10_000));
+                assert!(e.required > e.available);
+            }
+            e => panic!("unexpected error {}", e),
+        }
+    }
+
+    #[test]
+    fn missing_fee_estimate() {
+        let mut node = FakeNode::new(&[10_000]);
+        node.fee_rate = None;
+        assert!(matches!(KeySpend::new(change()).build(&node), Err(Error::NoFeeEstimate(_))));
+    }
+}
@@ -129,60 +129,67 @@ crate::impl_client_v17__validate_address!();
 crate::impl_client_v17__verify_message!();
 
 // == Wallet ==
-crate::impl_client_v17__abandon_transaction!();
-crate::impl_client_v17__abort_rescan!();
-crate::impl_client_v17__add_multisig_address!();
-crate::impl_client_v17__backup_wallet!();
-crate::impl_client_v17__bump_fee!();
 crate::impl_client_v17__create_wallet!();
-crate::impl_client_v17__dump_priv_key!();
-crate::impl_client_v17__dump_wallet!();
-crate::impl_client_v17__encrypt_wallet!();
-crate::impl_client_v17__get_addresses_by_label!();
-crate::impl_client_v17__get_address_info!();
-crate::impl_client_v17__get_balance!();
-crate::impl_client_v18__get_received_by_label!();
-crate::impl_client_v19__get_balances!();
-crate::impl_client_v17__get_new_address!();
-crate::impl_client_v17__get_raw_change_address!();
-crate::impl_client_v17__get_received_by_address!();
-crate::impl_client_v17__get_transaction!();
-crate::impl_client_v17__get_unconfirmed_balance!();
-crate::impl_client_v17__get_wallet_info!();
-crate::impl_client_v17__import_address!();
-crate::impl_client_v17__import_multi!();
-crate::impl_client_v17__import_privkey!();
-crate::impl_client_v17__import_pruned_funds!();
-crate::impl_client_v17__import_pubkey!();
-crate::impl_client_v17__import_wallet!();
-crate::impl_client_v17__key_pool_refill!();
-crate::impl_client_v17__list_address_groupings!();
-crate::impl_client_v17__list_labels!();
-crate::impl_client_v18__list_received_by_label!();
-crate::impl_client_v17__list_lock_unspent!();
-crate::impl_client_v17__list_received_by_address!();
-crate::impl_client_v17__list_since_block!();
-crate::impl_client_v17__list_transactions!();
-crate::impl_client_v17__list_unspent!();
 crate::impl_client_v18__list_wallet_dir!();
 crate::impl_client_v17__list_wallets!();
 crate::impl_client_v17__load_wallet!();
-crate::impl_client_v17__lock_unspent!();
-crate::impl_client_v17__remove_pruned_funds!();
-crate::impl_client_v17__rescan_blockchain!();
-crate::impl_client_v17__send_many!();
-crate::impl_client_v17__send_to_address!();
-crate::impl_client_v17__set_hd_seed!();
-crate::impl_client_v17__set_tx_fee!();
-crate::impl_client_v19__set_wallet_flag!();
-crate::impl_client_v17__sign_message!();
-crate::impl_client_v17__sign_raw_transaction_with_wallet!();
 crate::impl_client_v17__unload_wallet!();
-crate::impl_client_v17__wallet_create_funded_psbt!();
-crate::impl_client_v17__wallet_lock!();
-crate::impl_client_v17__wallet_passphrase!();
-crate::impl_client_v17__wallet_passphrase_change!();
-crate::impl_client_v17__wallet_process_psbt!();
+
+/// Invokes the macros for all wallet-scoped methods, see `impl_wallet_client!`.
+macro_rules! impl_wallet_methods {
+    () => {
+        crate::impl_client_v17__abandon_transaction!();
+        crate::impl_client_v17__abort_rescan!();
+        crate::impl_client_v17__add_multisig_address!();
+        crate::impl_client_v17__backup_wallet!();
+        crate::impl_client_v17__bump_fee!();
+        crate::impl_client_v17__dump_priv_key!();
+        crate::impl_client_v17__dump_wallet!();
+        crate::impl_client_v17__encrypt_wallet!();
+        crate::impl_client_v17__get_addresses_by_label!();
+        crate::impl_client_v17__get_address_info!();
+        crate::impl_client_v17__get_balance!();
+        crate::impl_client_v18__get_received_by_label!();
+        crate::impl_client_v19__get_balances!();
+        crate::impl_client_v17__get_new_address!();
+        crate::impl_client_v17__get_raw_change_address!();
+        crate::impl_client_v17__get_received_by_address!();
+        crate::impl_client_v17__get_transaction!();
+        crate::impl_client_v17__get_unconfirmed_balance!();
+        crate::impl_client_v17__get_wallet_info!();
+        crate::impl_client_v17__import_address!();
+        crate::impl_client_v17__import_multi!();
+        crate::impl_client_v17__import_privkey!();
+        crate::impl_client_v17__import_pruned_funds!();
+        crate::impl_client_v17__import_pubkey!();
+        crate::impl_client_v17__import_wallet!();
+        crate::impl_client_v17__key_pool_refill!();
+        crate::impl_client_v17__list_address_groupings!();
+        crate::impl_client_v17__list_labels!();
+        crate::impl_client_v18__list_received_by_label!();
+        crate::impl_client_v17__list_lock_unspent!();
+        crate::impl_client_v17__list_received_by_address!();
+        crate::impl_client_v17__list_since_block!();
+        crate::impl_client_v17__list_transactions!();
+        crate::impl_client_v17__list_unspent!();
+        crate::impl_client_v17__lock_unspent!();
+        crate::impl_client_v17__remove_pruned_funds!();
+        crate::impl_client_v17__rescan_blockchain!();
+        crate::impl_client_v17__send_many!();
+        crate::impl_client_v17__send_to_address!();
+        crate::impl_client_v17__set_hd_seed!();
+        crate::impl_client_v17__set_tx_fee!();
+        crate::impl_client_v19__set_wallet_flag!();
+        crate::impl_client_v17__sign_message!();
+        crate::impl_client_v17__sign_raw_transaction_with_wallet!();
+        crate::impl_client_v17__wallet_create_funded_psbt!();
+        crate::impl_client_v17__wallet_lock!();
+        crate::impl_client_v17__wallet_passphrase!();
+        crate::impl_client_v17__wallet_passphrase_change!();
+        crate::impl_client_v17__wallet_process_psbt!();
+    };
+}
+crate::impl_wallet_client!(impl_wallet_methods);
 
 // == Zmq ==
 crate::impl_client_v17__get_zmq_notifications!();
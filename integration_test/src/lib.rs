@@ -76,22 +76,22 @@ impl NodeExt for Node {
     }
 
     fn fund_wallet(&self) {
-        let address = self.client.new_address().expect("failed to get new address");
+        let address = self.wallet().new_address().expect("failed to get new address");
         self.client.generate_to_address(101, &address).expect("failed to generate to address");
     }
 
     fn mine_a_block(&self) {
-        let address = self.client.new_address().expect("failed to get new address");
+        let address = self.wallet().new_address().expect("failed to get new address");
         self.client.generate_to_address(1, &address).expect("failed to generate to address");
     }
 
     fn create_mempool_transaction(&self) -> (bitcoin::Address, bitcoin::Txid) {
         const MILLION_SATS: bitcoin::Amount = bitcoin::Amount::from_sat(1000000);
 
-        let address = self.client.new_address().expect("failed to get new address");
+        let address = self.wallet().new_address().expect("failed to get new address");
 
         let txid = self
-            .client
+            .wallet()
             .send_to_address(&address, MILLION_SATS)
             .expect("failed to send to address")
             .txid()
@@ -546,7 +546,7 @@ fn blockchain__prune_blockchain() {
     const NBLOCKS: usize = 1;
 
     let node = Node::with_wallet(Wallet::Default, &["-prune=550"]);
-    let address = node.client.new_address().expect("Failed to get new address");
+    let address = node.wallet().new_address().expect("Failed to get new address");
 
     let gen_result = node
         .client
@@ -712,7 +712,7 @@ fn blockchain__wait_for_new_block__modelled() {
 /// Returns the child's txid.
 fn create_child_spending_parent(node: &Node, parent_txid: bitcoin::Txid) -> bitcoin::Txid {
     let inputs = vec![Input { txid: parent_txid, vout: 0, sequence: None }];
-    let spend_address = node.client.new_address().expect("newaddress");
+    let spend_address = node.wallet().new_address().expect("newaddress");
     let outputs = vec![Output::new(spend_address, bitcoin::Amount::from_sat(100_000))];
 
     let raw: CreateRawTransaction =
@@ -724,7 +724,7 @@ fn create_child_spending_parent(node: &Node, parent_txid: bitcoin::Txid) -> bitc
     let funded_tx = funded.transaction().expect("funded.transaction");
 
     let signed: SignRawTransaction = node
-        .client
+        .wallet()
         .sign_raw_transaction_with_wallet(&funded_tx)
         .expect("signrawtransactionwithwallet");
     let sign_raw_transaction =
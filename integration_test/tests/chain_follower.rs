@@ -0,0 +1,62 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Tests for the `ChainFollower` utility.
+
+#![allow(non_snake_case)] // Test names intentionally use double underscore.
+
+use std::time::Duration;
+
+use bitcoin::BlockHash;
+use integration_test::{Node, NodeExt as _, Wallet};
+use node::client::client_sync::{ChainEvent, ChainFollower};
+
+fn block_hash(event: ChainEvent) -> BlockHash {
+    match event {
+        ChainEvent::Connected(block) => block.block_hash(),
+        ChainEvent::Disconnected(hash) => panic!("unexpected disconnect of {}", hash),
+    }
+}
+
+#[test]
+fn chain_follower__follows_reorgs() {
+    let node = Node::with_wallet(Wallet::Default, &[]);
+    let height = node.client.get_block_count().expect("getblockcount").0 as u32;
+    let checkpoint = node.client.best_block_hash().expect("bestblockhash");
+    let mut follower = ChainFollower::new(&node.client, height, checkpoint);
+    assert!(follower.poll().expect("poll").is_none());
+
+    node.mine_a_block();
+    node.mine_a_block();
+    let first = block_hash(follower.poll().expect("poll").expect("first block"));
+    let second = block_hash(follower.poll().expect("poll").expect("second block"));
+    assert_eq!(follower.tip(), (height + 2, second));
+    assert!(follower.poll().expect("poll").is_none());
+
+    // Replace both blocks with a shorter fork.
+    node.client.invalidate_block(first).expect("invalidateblock");
+    node.mine_a_block();
+    assert_eq!(follower.poll().expect("poll"), Some(ChainEvent::Disconnected(second)));
+    assert_eq!(follower.poll().expect("poll"), Some(ChainEvent::Disconnected(first)));
+    let fork = block_hash(follower.poll().expect("poll").expect("fork block"));
+    assert_eq!(follower.tip(), (height + 1, fork));
+
+    // The original chain has more work once it is valid again.
+    node.client.reconsider_block(first).expect("reconsiderblock");
+    assert_eq!(follower.poll().expect("poll"), Some(ChainEvent::Disconnected(fork)));
+    assert_eq!(block_hash(follower.poll().expect("poll").expect("first block")), first);
+    assert_eq!(block_hash(follower.poll().expect("poll").expect("second block")), second);
+    assert!(follower.poll().expect("poll").is_none());
+}
+
+#[test]
+fn chain_follower__long_polls() {
+    let node = Node::with_wallet(Wallet::Default, &[]);
+    let height = node.client.get_block_count().expect("getblockcount").0 as u32;
+    let checkpoint = node.client.best_block_hash().expect("bestblockhash");
+    let mut follower = ChainFollower::new(&node.client, height, checkpoint)
+        .with_long_poll(Duration::from_millis(100));
+
+    node.mine_a_block();
+    let best = node.client.best_block_hash().expect("bestblockhash");
+    assert_eq!(block_hash(follower.next_event().expect("next event")), best);
+}
@@ -26,6 +26,17 @@ fn control__help() {
     let _ = node.client.help().unwrap();
 }
 
+#[test]
+fn control__capabilities() {
+    let node = Node::with_wallet(Wallet::None, &["-disablewallet"]);
+    let caps = node.client.capabilities().unwrap();
+
+    assert!(caps.supports("getblock"));
+    assert!(caps.has_argument("getblock", "blockhash"));
+    assert!(caps.sections().contains("Blockchain"));
+    assert!(!caps.supports("getnewaddress"));
+}
+
 #[test]
 fn control__logging() {
     let node = Node::with_wallet(Wallet::None, &[]);
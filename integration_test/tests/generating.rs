@@ -14,11 +14,11 @@ use node::vtype::*; // All the version specific types.
 fn generating__generate_block__modelled() {
     let node = Node::with_wallet(Wallet::Default, &[]);
     node.fund_wallet();
-    let mining_addr = node.client.new_address().expect("failed to get new address");
-    let dest_addr = node.client.new_address().expect("failed to get new address");
+    let mining_addr = node.wallet().new_address().expect("failed to get new address");
+    let dest_addr = node.wallet().new_address().expect("failed to get new address");
     let amount = bitcoin::Amount::from_sat(1_000_000);
     let txid = node
-        .client
+        .wallet()
         .send_to_address_rbf(&dest_addr, amount)
         .expect("sendtoaddressrbf")
         .txid()
@@ -66,7 +66,7 @@ fn generating__generate_to_address__modelled() {
     const NBLOCKS: usize = 1;
 
     let node = Node::with_wallet(Wallet::Default, &[]);
-    let address = node.client.new_address().expect("failed to get new address");
+    let address = node.wallet().new_address().expect("failed to get new address");
 
     let json: GenerateToAddress =
         node.client.generate_to_address(NBLOCKS, &address).expect("generatetoaddress");
@@ -81,7 +81,7 @@ fn generating__generate_to_descriptor__modelled() {
     const NBLOCKS: usize = 1;
 
     let node = Node::with_wallet(Wallet::Default, &[]);
-    let address = node.client.new_address().expect("failed to get new address");
+    let address = node.wallet().new_address().expect("failed to get new address");
     let descriptor = format!("addr({})", address);
 
     let json: GenerateToDescriptor =
@@ -97,7 +97,7 @@ fn generating__invalidate_block() {
 
     let node = Node::with_wallet(Wallet::Default, &[]);
 
-    let address = node.client.new_address().expect("failed to get new address");
+    let address = node.wallet().new_address().expect("failed to get new address");
     let old_best_block =
         node.client.get_best_block_hash().expect("getbestblockhash").into_model().unwrap().0;
     node.client
@@ -105,7 +105,7 @@ fn hidden__get_orphan_txs__modelled() {
 
     // Generate a couple of orphan transactions by spending from non-existing UTXOs.
     const NUM_ORPHANS: u8 = 3;
-    let address = node1.client.new_address().expect("failed to get new address");
+    let address = node1.wallet().new_address().expect("failed to get new address");
     let orphans: Vec<Transaction> = (0..NUM_ORPHANS)
         .map(|i| Transaction {
             version: transaction::Version::ONE,
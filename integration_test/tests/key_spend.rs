@@ -0,0 +1,41 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Tests for the `KeySpend` utility.
+
+#![allow(non_snake_case)] // Test names intentionally use double underscore.
+
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::{Address, CompressedPublicKey, FeeRate, Network, PrivateKey};
+use integration_test::{test_keys, Node, NodeExt as _, Wallet};
+use node::client::client_sync::KeySpend;
+
+#[test]
+fn key_spend__sweeps_key_without_wallet() {
+    let node = Node::with_wallet(Wallet::Default, &[]);
+    let secp = Secp256k1::new();
+
+    // A key the wallet of the node does not know about.
+    let key = PrivateKey::new(test_keys().xprv.private_key, Network::Regtest);
+    let pubkey = CompressedPublicKey::from_private_key(&secp, &key).unwrap();
+    let address = Address::p2wpkh(&pubkey, Network::Regtest);
+    node.client.generate_to_address(2, &address).expect("generatetoaddress");
+    node.client
+        .generate_to_address(100, &node.wallet().new_address().expect("getnewaddress"))
+        .expect("generatetoaddress");
+
+    let destination = node.wallet().new_address().expect("getnewaddress");
+    let spend = KeySpend::new(destination.script_pubkey())
+        .descriptor(format!("wpkh({})", pubkey))
+        .key(key)
+        .fee_rate(FeeRate::from_sat_per_kwu(500))
+        .send(&node.client)
+        .expect("sweep");
+
+    assert_eq!(spend.inputs.len(), 2);
+    assert_eq!(spend.tx.output.len(), 1);
+    let total = spend.inputs.iter().map(|u| u.amount).sum::<bitcoin::Amount>();
+    assert_eq!(spend.tx.output[0].value + spend.fee, total);
+
+    let mempool = node.client.get_raw_mempool().expect("getrawmempool").into_model().unwrap();
+    assert!(mempool.0.contains(&spend.txid()));
+}
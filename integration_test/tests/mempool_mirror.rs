@@ -0,0 +1,32 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Tests for the `MempoolMirror` utility.
+
+#![allow(non_snake_case)] // Test names intentionally use double underscore.
+
+#[cfg(not(feature = "v20_and_below"))]
+use integration_test::{Node, NodeExt as _, Wallet};
+#[cfg(not(feature = "v20_and_below"))]
+use node::client::client_sync::{MempoolEvent, MempoolMirror};
+
+#[test]
+#[cfg(not(feature = "v20_and_below"))]
+fn mempool_mirror__polls_mempool() {
+    let node = Node::with_wallet(Wallet::Default, &[]);
+    node.fund_wallet();
+    let (_, first) = node.create_mempool_transaction();
+
+    let mut mirror = MempoolMirror::new(&node.client).expect("bootstrap mirror");
+    assert!(mirror.contains(&first));
+    assert!(mirror.entry(&first).is_some());
+
+    let (_, second) = node.create_mempool_transaction();
+    assert_eq!(mirror.update().expect("update"), [MempoolEvent::Added(second)]);
+
+    node.mine_a_block();
+    let events = mirror.update().expect("update");
+    assert_eq!(events.len(), 2);
+    assert!(events.contains(&MempoolEvent::Removed(first)));
+    assert!(events.contains(&MempoolEvent::Removed(second)));
+    assert!(mirror.is_empty());
+}
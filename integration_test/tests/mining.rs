@@ -168,7 +168,7 @@ fn mining__submit_block_with_dummy_coinbase(node: &Node, bt: &mtype::GetBlockTem
         TxIn, TxMerkleNode, TxOut, Witness,
     };
 
-    let address = node.client.new_address().expect("failed to get new address");
+    let address = node.wallet().new_address().expect("failed to get new address");
 
     let coinbase = Transaction {
         version: transaction::Version::ONE,
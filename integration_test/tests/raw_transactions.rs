@@ -53,11 +53,11 @@ fn raw_transactions__combine_psbt__modelled() {
     let mut outputs = vec![];
 
     // Just send back to ourself.
-    let spend_address = node.client.new_address().expect("failed to create new address");
+    let spend_address = node.wallet().new_address().expect("failed to create new address");
     outputs.push(Output::new(spend_address, spend_amount));
 
     let change_address = node
-        .client
+        .wallet()
         .get_raw_change_address()
         .expect("getrawchangeaddress")
         .into_model()
@@ -401,7 +401,7 @@ fn raw_transactions__test_mempool_accept__modelled() {
 
     // Sign (but don't broadcast).
     let signed: SignRawTransactionWithWallet =
-        node.client.sign_raw_transaction_with_wallet(&tx).expect("signrawtransactionwithwallet");
+        node.wallet().sign_raw_transaction_with_wallet(&tx).expect("signrawtransactionwithwallet");
     let signed_model: mtype::SignRawTransactionWithWallet =
         signed.into_model().expect("SignRawTransaction into model");
     let signed_tx = signed_model.tx;
@@ -455,11 +455,11 @@ fn create_sign_send(node: &Node) {
     let mut outputs = vec![];
 
     // Just send back to ourself.
-    let spend_address = node.client.new_address().expect("failed to create new address");
+    let spend_address = node.wallet().new_address().expect("failed to create new address");
     outputs.push(Output::new(spend_address, spend_amount));
 
     let change_address = node
-        .client
+        .wallet()
         .get_raw_change_address()
         .expect("getrawchangeaddress")
         .into_model()
@@ -478,7 +478,7 @@ fn create_sign_send(node: &Node) {
     // wallet.rs expects this call to exist, if you change it then you'll need to update the test
     // `wallet__sign_raw_transaction_with_wallet__modelled`.
     let json: SignRawTransactionWithWallet =
-        node.client.sign_raw_transaction_with_wallet(&tx).expect("signrawtransactionwithwallet");
+        node.wallet().sign_raw_transaction_with_wallet(&tx).expect("signrawtransactionwithwallet");
 
     let model: Result<mtype::SignRawTransactionWithWallet, SignRawTransactionError> =
         json.into_model();
@@ -513,11 +513,11 @@ fn create_sign_with_key_send(node: &Node) {
     let mut outputs = vec![];
 
     // Just send back to ourself.
-    let spend_address = node.client.new_address().expect("failed to create new address");
+    let spend_address = node.wallet().new_address().expect("failed to create new address");
     outputs.push(Output::new(spend_address, spend_amount));
 
     let change_address = node
-        .client
+        .wallet()
         .get_raw_change_address()
         .expect("getrawchangeaddress")
         .into_model()
@@ -533,7 +533,7 @@ fn create_sign_with_key_send(node: &Node) {
     model.unwrap();
     let tx = json.transaction().unwrap();
 
-    let json: DumpPrivKey = node.client.dump_priv_key(&addr).expect("dumpprivkey");
+    let json: DumpPrivKey = node.wallet().dump_priv_key(&addr).expect("dumpprivkey");
     let model: mtype::DumpPrivKey = json.into_model().expect("DumpPrivKey");
     let key = model.0;
 
@@ -567,7 +567,7 @@ fn create_fund_sign_send(node: &Node) {
 
     let spend_amount = Amount::from_sat(50_00_000_000);
     // Just send back to ourself.
-    let spend_address = node.client.new_address().expect("failed to create new address");
+    let spend_address = node.wallet().new_address().expect("failed to create new address");
     outputs.push(Output::new(spend_address, spend_amount));
 
     let json: CreateRawTransaction =
@@ -586,7 +586,7 @@ fn create_fund_sign_send(node: &Node) {
 
     // This method is from the wallet section.
     let json: SignRawTransactionWithWallet = node
-        .client
+        .wallet()
         .sign_raw_transaction_with_wallet(&funded)
         .expect("signrawtransactionwithwallet");
     // This proves we did everything correctly.
@@ -611,11 +611,11 @@ fn create_a_raw_transaction(node: &Node) -> Transaction {
     let mut outputs = vec![];
 
     // Just send back to ourself.
-    let spend_address = node.client.new_address().expect("failed to create new address");
+    let spend_address = node.wallet().new_address().expect("failed to create new address");
     outputs.push(Output::new(spend_address, spend_amount));
 
     let change_address = node
-        .client
+        .wallet()
         .get_raw_change_address()
         .expect("getrawchangeaddress")
         .into_model()
@@ -681,11 +681,11 @@ fn create_a_psbt(node: &Node) -> bitcoin::Psbt {
     let mut outputs = vec![];
 
     // Just send back to ourself.
-    let spend_address = node.client.new_address().expect("failed to create new address");
+    let spend_address = node.wallet().new_address().expect("failed to create new address");
     outputs.push(Output::new(spend_address, spend_amount));
 
     let change_address = node
-        .client
+        .wallet()
         .get_raw_change_address()
         .expect("getrawchangeaddress")
         .into_model()
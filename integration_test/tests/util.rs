@@ -129,7 +129,7 @@ fn util__validate_address__modelled() {
     let node = Node::with_wallet(Wallet::Default, &[]);
     node.fund_wallet();
 
-    let addr = node.client.new_address().expect("new_address");
+    let addr = node.wallet().new_address().expect("new_address");
     let json: ValidateAddress = node.client.validate_address(&addr).expect("validateaddress");
     let model: Result<mtype::ValidateAddress, ValidateAddressError> = json.into_model();
     model.unwrap();
@@ -28,18 +28,18 @@ use node::{
 fn wallet__abandon_transaction() {
     let node = Node::with_wallet(Wallet::Default, &[]);
 
-    let mining_addr = node.client.new_address().expect("newaddress");
+    let mining_addr = node.wallet().new_address().expect("newaddress");
     let json: GenerateToAddress =
         node.client.generate_to_address(101, &mining_addr).expect("generatetoaddress");
     let block_hashes = json.into_model();
 
     let block_hash = block_hashes.expect("blockhash").0[0];
 
-    let dest_addr = node.client.new_address().expect("newaddress");
+    let dest_addr = node.wallet().new_address().expect("newaddress");
     let amount = bitcoin::Amount::from_sat(1_000_000);
 
     let txid = node
-        .client
+        .wallet()
         .send_to_address_rbf(&dest_addr, amount)
         .expect("sendtoaddressrbf")
         .txid()
@@ -47,14 +47,14 @@ fn wallet__abandon_transaction() {
 
     node.client.invalidate_block(block_hash).expect("invalidateblock");
 
-    let _: () = node.client.abandon_transaction(txid).expect("abandontransaction");
+    let _: () = node.wallet().abandon_transaction(txid).expect("abandontransaction");
 }
 
 #[test]
 fn wallet__abort_rescan() {
     let node = Node::with_wallet(Wallet::Default, &[]);
 
-    let json: AbortRescan = node.client.abort_rescan().expect("abortrescan");
+    let json: AbortRescan = node.wallet().abort_rescan().expect("abortrescan");
     assert!(!json.0); // No rescan running, abort should return false
 }
 
@@ -63,22 +63,26 @@ fn wallet__abort_rescan() {
 fn wallet__add_multisig_address__modelled() {
     let nrequired = 2;
 
-    let node = match () {
+    let (_node, wallet) = match () {
         #[cfg(feature = "v22_and_below")]
-        () => Node::with_wallet(Wallet::Default, &[]),
+        () => {
+            let node = Node::with_wallet(Wallet::Default, &[]);
+            let wallet = node.client.wallet("default");
+            (node, wallet)
+        }
         #[cfg(not(feature = "v22_and_below"))]
         () => {
             let node = Node::with_wallet(Wallet::None, &["-deprecatedrpc=create_bdb"]);
             node.client.create_legacy_wallet("wallet_name").expect("createlegacywallet");
-            node
+            let wallet = node.client.wallet("wallet_name");
+            (node, wallet)
         }
     };
 
-    let addr1 = node.client.new_address().expect("new_address");
-    let addr2 = node.client.new_address().expect("new_address");
+    let addr1 = wallet.new_address().expect("new_address");
+    let addr2 = wallet.new_address().expect("new_address");
 
-    let json: AddMultisigAddress = node
-        .client
+    let json: AddMultisigAddress = wallet
         .add_multisig_address_with_addresses(nrequired, vec![addr1, addr2])
         .expect("addmultisigaddress");
 
@@ -93,7 +97,7 @@ fn backup_and_restore_wallet() {
     let node = Node::with_wallet(Wallet::Default, &[]);
     let file_path = integration_test::random_tmp_file();
 
-    let _: () = node.client.backup_wallet(&file_path).expect("backupwallet");
+    let _: () = node.wallet().backup_wallet(&file_path).expect("backupwallet");
     assert!(file_path.exists(), "Backup file should exist at destination");
     assert!(file_path.is_file(), "Backup destination should be a file");
 
@@ -113,17 +117,17 @@ fn backup_and_restore_wallet() {
 #[test]
 fn wallet__bump_fee__modelled() {
     let node = Node::with_wallet(Wallet::Default, &[]);
-    let address = node.client.new_address().expect("failed to create new address");
+    let address = node.wallet().new_address().expect("failed to create new address");
     let _ = node.client.generate_to_address(101, &address).expect("generatetoaddress");
 
     let txid = node
-        .client
+        .wallet()
         .send_to_address_rbf(&address, Amount::from_sat(10_000))
         .expect("sendtoaddress")
         .txid()
         .unwrap();
 
-    let json: BumpFee = node.client.bump_fee(txid).expect("bumpfee");
+    let json: BumpFee = node.wallet().bump_fee(txid).expect("bumpfee");
     let model: Result<mtype::BumpFee, BumpFeeError> = json.into_model();
     model.unwrap();
 }
@@ -158,10 +162,10 @@ fn wallet__create_wallet_descriptor() {
     let descriptor = format!("{}#{}", raw_descriptor, info.checksum);
 
     let import_req = ImportDescriptorsRequest::new(descriptor, 0);
-    node.client.import_descriptors(&[import_req]).expect("importdescriptors");
+    node.wallet().import_descriptors(&[import_req]).expect("importdescriptors");
 
     let json: CreateWalletDescriptor =
-        node.client.create_wallet_descriptor("bech32", &hdkey).expect("createwalletdescriptor");
+        node.wallet().create_wallet_descriptor("bech32", &hdkey).expect("createwalletdescriptor");
 
     // Check that a SigWit descriptor was created.
     let prefix = &json.descriptors[0][0..4];
@@ -178,14 +182,14 @@ fn wallet__dump_priv_key__modelled() {
         let node = Node::with_wallet(Wallet::None, &[]);
 
         node.client.create_legacy_wallet("legacy_wallet").expect("legacy create_wallet");
-        let address = node
-            .client
+        let wallet = node.client.wallet("legacy_wallet");
+        let address = wallet
             .get_new_address(Some("label"), Some(AddressType::Legacy))
             .expect("legacy get_new_address");
         let model: Result<mtype::GetNewAddress, address::ParseError> = address.into_model();
         let address = model.unwrap().0.assume_checked();
 
-        let json: DumpPrivKey = node.client.dump_priv_key(&address).expect("dumpprivkey");
+        let json: DumpPrivKey = wallet.dump_priv_key(&address).expect("dumpprivkey");
         let model: Result<mtype::DumpPrivKey, key::FromWifError> = json.into_model();
         model.unwrap();
     }
@@ -193,9 +197,9 @@ fn wallet__dump_priv_key__modelled() {
     #[cfg(feature = "v22_and_below")]
     {
         let node = Node::with_wallet(Wallet::Default, &[]);
-        let address = node.client.new_address().expect("failed to get new address");
+        let address = node.wallet().new_address().expect("failed to get new address");
 
-        let json: DumpPrivKey = node.client.dump_priv_key(&address).expect("dumpprivkey");
+        let json: DumpPrivKey = node.wallet().dump_priv_key(&address).expect("dumpprivkey");
         let model: Result<mtype::DumpPrivKey, key::FromWifError> = json.into_model();
         model.unwrap();
     }
@@ -213,7 +217,8 @@ fn wallet__dump_wallet() {
         node.client.create_legacy_wallet("legacy_wallet").expect("legacy create_wallet");
         let out = integration_test::random_tmp_file();
 
-        let _: DumpWallet = node.client.dump_wallet(&out).expect("dumpwallet");
+        let _: DumpWallet =
+            node.client.wallet("legacy_wallet").dump_wallet(&out).expect("dumpwallet");
     }
 
     #[cfg(feature = "v22_and_below")]
@@ -221,7 +226,7 @@ fn wallet__dump_wallet() {
         let node = Node::with_wallet(Wallet::Default, &[]);
         let out = integration_test::random_tmp_file();
 
-        let _: DumpWallet = node.client.dump_wallet(&out).expect("dumpwallet");
+        let _: DumpWallet = node.wallet().dump_wallet(&out).expect("dumpwallet");
     }
 }
 
@@ -229,17 +234,17 @@ fn wallet__dump_wallet() {
 fn wallet__encrypt_wallet() {
     let node = Node::with_wallet(Wallet::Default, &[]);
 
-    let _: EncryptWallet = node.client.encrypt_wallet("test-passphrase").expect("encryptwallet");
+    let _: EncryptWallet = node.wallet().encrypt_wallet("test-passphrase").expect("encryptwallet");
 }
 
 #[test]
 fn wallet__get_addresses_by_label__modelled() {
     let node = Node::with_wallet(Wallet::Default, &[]);
     let label = "some-label";
-    let addr = node.client.new_address_with_label(label).expect("failed to get new address");
+    let addr = node.wallet().new_address_with_label(label).expect("failed to get new address");
 
     let json: GetAddressesByLabel =
-        node.client.get_addresses_by_label(label).expect("getaddressesbylabel");
+        node.wallet().get_addresses_by_label(label).expect("getaddressesbylabel");
     let model: Result<mtype::GetAddressesByLabel, address::ParseError> = json.into_model();
     let map = model.unwrap();
 
@@ -254,17 +259,18 @@ fn wallet__get_address_info__modelled() {
 
     // Test an address with a label.
     let label_name = "test-label";
-    let addr = node.client.new_address_with_label(label_name).unwrap().assume_checked();
-    let json: GetAddressInfo = node.client.get_address_info(&addr).expect("getaddressinfo legacy");
+    let addr = node.wallet().new_address_with_label(label_name).unwrap().assume_checked();
+    let json: GetAddressInfo =
+        node.wallet().get_address_info(&addr).expect("getaddressinfo legacy");
     let model: Result<mtype::GetAddressInfo, GetAddressInfoError> = json.into_model();
     let address_info = model.unwrap();
     assert_eq!(address_info.address.assume_checked(), addr);
     assert_eq!(address_info.labels[0], label_name);
 
     // Test a SegWit address with embedded information.
-    let addr_p2sh = node.client.new_address_with_type(AddressType::P2shSegwit).unwrap();
+    let addr_p2sh = node.wallet().new_address_with_type(AddressType::P2shSegwit).unwrap();
     let json: GetAddressInfo =
-        node.client.get_address_info(&addr_p2sh).expect("getaddressinfo p2sh-segwit");
+        node.wallet().get_address_info(&addr_p2sh).expect("getaddressinfo p2sh-segwit");
     let model: Result<mtype::GetAddressInfo, GetAddressInfoError> = json.into_model();
     let address_info = model.unwrap();
     let embedded = address_info.embedded.unwrap();
@@ -273,9 +279,9 @@ fn wallet__get_address_info__modelled() {
     assert!(embedded.address.is_valid_for_network(Network::Regtest));
 
     // Test a Bech32 address.
-    let addr_bech32 = node.client.new_address_with_type(AddressType::Bech32).unwrap();
+    let addr_bech32 = node.wallet().new_address_with_type(AddressType::Bech32).unwrap();
     let json: GetAddressInfo =
-        node.client.get_address_info(&addr_bech32).expect("getaddressinfo bech32");
+        node.wallet().get_address_info(&addr_bech32).expect("getaddressinfo bech32");
     let model: Result<mtype::GetAddressInfo, GetAddressInfoError> = json.into_model();
     let address_info = model.unwrap();
     assert_eq!(address_info.address.assume_checked(), addr_bech32);
@@ -285,13 +291,13 @@ fn wallet__get_address_info__modelled() {
 fn wallet__get_balance__modelled() {
     let node = Node::with_wallet(Wallet::Default, &[]);
 
-    let json: GetBalance = node.client.get_balance().expect("getbalance");
+    let json: GetBalance = node.wallet().get_balance().expect("getbalance");
     let model: Result<mtype::GetBalance, amount::ParseAmountError> = json.into_model();
     model.unwrap();
 
     // Check non-zero balance just for giggles.
     node.fund_wallet();
-    let json: GetBalance = node.client.get_balance().expect("getbalance");
+    let json: GetBalance = node.wallet().get_balance().expect("getbalance");
     let model: Result<mtype::GetBalance, amount::ParseAmountError> = json.into_model();
     model.unwrap();
 }
@@ -302,7 +308,7 @@ fn wallet__get_balances() {
     let node = Node::with_wallet(Wallet::Default, &[]);
     node.fund_wallet();
 
-    let json: GetBalances = node.client.get_balances().expect("getbalances");
+    let json: GetBalances = node.wallet().get_balances().expect("getbalances");
     let model: Result<mtype::GetBalances, GetBalancesError> = json.into_model();
     model.unwrap();
 }
@@ -312,7 +318,7 @@ fn wallet__get_balances() {
 fn wallet__get_hd_keys__modelled() {
     let node = Node::with_wallet(Wallet::Default, &[]);
 
-    let json: GetHdKeys = node.client.get_hd_keys().expect("gethdkeys");
+    let json: GetHdKeys = node.wallet().get_hd_keys().expect("gethdkeys");
     let model: Result<mtype::GetHdKeys, GetHdKeysError> = json.into_model();
     let hdkey = model.unwrap().0;
 
@@ -325,19 +331,19 @@ fn wallet__get_new_address__modelled() {
     let node = Node::with_wallet(Wallet::Default, &[]);
 
     // Implicitly tests `getnewaddress`.
-    let _ = node.client.new_address().unwrap();
+    let _ = node.wallet().new_address().unwrap();
 
     // Exhaustively test address types with helper.
-    let _ = node.client.new_address_with_type(AddressType::Legacy).unwrap();
-    let _ = node.client.new_address_with_type(AddressType::P2shSegwit).unwrap();
-    let _ = node.client.new_address_with_type(AddressType::Bech32).unwrap();
+    let _ = node.wallet().new_address_with_type(AddressType::Legacy).unwrap();
+    let _ = node.wallet().new_address_with_type(AddressType::P2shSegwit).unwrap();
+    let _ = node.wallet().new_address_with_type(AddressType::Bech32).unwrap();
 }
 
 #[test]
 fn wallet__get_raw_change_address__modelled() {
     let node = Node::with_wallet(Wallet::Default, &[]);
     let json: GetRawChangeAddress =
-        node.client.get_raw_change_address().expect("getrawchangeaddress");
+        node.wallet().get_raw_change_address().expect("getrawchangeaddress");
     let model: Result<mtype::GetRawChangeAddress, address::ParseError> = json.into_model();
     model.unwrap();
 }
@@ -348,14 +354,14 @@ fn wallet__get_received_by_address__modelled() {
 
     let node = Node::with_wallet(Wallet::Default, &[]);
     node.fund_wallet();
-    let address = node.client.new_address().expect("failed to create new address");
+    let address = node.wallet().new_address().expect("failed to create new address");
 
     let _txid =
-        node.client.send_to_address(&address, amount).expect("sendtoaddress").txid().unwrap();
+        node.wallet().send_to_address(&address, amount).expect("sendtoaddress").txid().unwrap();
     node.mine_a_block();
 
     let json: GetReceivedByAddress =
-        node.client.get_received_by_address(&address).expect("getreceivedbyaddress");
+        node.wallet().get_received_by_address(&address).expect("getreceivedbyaddress");
     let model: Result<mtype::GetReceivedByAddress, amount::ParseAmountError> = json.into_model();
     let received_by_address = model.unwrap();
 
@@ -371,12 +377,12 @@ fn wallet__get_received_by_label__modelled() {
 
     // Send some coins to the label
     let amount = Amount::from_sat(10_000);
-    let address = node.client.new_address_with_label(label).unwrap().assume_checked();
-    let _ = node.client.send_to_address(&address, amount).unwrap();
+    let address = node.wallet().new_address_with_label(label).unwrap().assume_checked();
+    let _ = node.wallet().send_to_address(&address, amount).unwrap();
     node.mine_a_block();
 
     let json: GetReceivedByLabel =
-        node.client.get_received_by_label(label).expect("getreceivedbylabel");
+        node.wallet().get_received_by_label(label).expect("getreceivedbylabel");
     let model: Result<mtype::GetReceivedByLabel, amount::ParseAmountError> = json.into_model();
     let received = model.unwrap();
     assert_eq!(received.0, amount);
@@ -386,16 +392,16 @@ fn wallet__get_received_by_label__modelled() {
 fn wallet__get_transaction__modelled() {
     let node = Node::with_wallet(Wallet::Default, &[]);
     node.fund_wallet();
-    let address = node.client.new_address().expect("failed to create new address");
+    let address = node.wallet().new_address().expect("failed to create new address");
 
     let txid = node
-        .client
+        .wallet()
         .send_to_address(&address, Amount::from_sat(10_000))
         .expect("sendtoaddress")
         .txid()
         .unwrap();
 
-    let json: GetTransaction = node.client.get_transaction(txid).expect("gettransaction");
+    let json: GetTransaction = node.wallet().get_transaction(txid).expect("gettransaction");
     let model: Result<mtype::GetTransaction, GetTransactionError> = json.into_model();
     model.unwrap();
 }
@@ -405,7 +411,7 @@ fn wallet__get_transaction__modelled() {
 fn wallet__get_unconfirmed_balance__modelled() {
     let node = Node::with_wallet(Wallet::Default, &[]);
     let json: GetUnconfirmedBalance =
-        node.client.get_unconfirmed_balance().expect("getunconfirmedbalance");
+        node.wallet().get_unconfirmed_balance().expect("getunconfirmedbalance");
     let model: Result<mtype::GetUnconfirmedBalance, amount::ParseAmountError> = json.into_model();
     model.unwrap();
 }
@@ -415,7 +421,7 @@ fn wallet__get_wallet_info__modelled() {
     let node = Node::with_wallet(Wallet::Default, &[]);
     node.mine_a_block();
 
-    let json: GetWalletInfo = node.client.get_wallet_info().expect("getwalletinfo");
+    let json: GetWalletInfo = node.wallet().get_wallet_info().expect("getwalletinfo");
     let model: Result<mtype::GetWalletInfo, GetWalletInfoError> = json.into_model();
     let wallet_info = model.unwrap();
 
@@ -439,14 +445,19 @@ fn wallet__get_wallet_info__modelled() {
 #[test]
 #[cfg(feature = "v29_and_below")]
 fn wallet__import_address() {
-    let node = match () {
+    let (_node, wallet) = match () {
         #[cfg(feature = "v22_and_below")]
-        () => Node::with_wallet(Wallet::Default, &[]),
+        () => {
+            let node = Node::with_wallet(Wallet::Default, &[]);
+            let wallet = node.client.wallet("default");
+            (node, wallet)
+        }
         #[cfg(not(feature = "v22_and_below"))]
         () => {
             let node = Node::with_wallet(Wallet::None, &["-deprecatedrpc=create_bdb"]);
             node.client.create_legacy_wallet("wallet_name").expect("createlegacywallet");
-            node
+            let wallet = node.client.wallet("wallet_name");
+            (node, wallet)
         }
     };
 
@@ -458,7 +469,7 @@ fn wallet__import_address() {
     let pubkey = privkey.public_key(&secp);
     let addr = bitcoin::Address::p2pkh(pubkey, privkey.network);
 
-    let _: () = node.client.import_address(&addr).expect("importaddress");
+    let _: () = wallet.import_address(&addr).expect("importaddress");
 }
 
 #[test]
@@ -473,8 +484,10 @@ fn wallet__import_descriptors() {
     // v23 onwards uses descriptor wallets by default.
     #[cfg(not(feature = "v22_and_below"))]
     node.client.create_wallet(wallet_name).expect("create wallet");
+    let wallet = node.client.wallet(wallet_name);
 
-    node.fund_wallet();
+    let address = wallet.new_address().expect("failed to get new address");
+    node.client.generate_to_address(101, &address).expect("generatetoaddress");
 
     // 1. Get the current time
     let start_time =
@@ -487,7 +500,7 @@ fn wallet__import_descriptors() {
     let pubkey = privkey.public_key(&secp);
     let address = Address::p2wpkh(&CompressedPublicKey(pubkey.inner), KnownHrp::Regtest);
     let amount = Amount::from_sat(10_000);
-    let _txid = node.client.send_to_address(&address, amount).expect("sendtoaddress");
+    let _txid = wallet.send_to_address(&address, amount).expect("sendtoaddress");
 
     // 3. Get the descriptor from the private key.
     let raw_descriptor = format!("wpkh({})", privkey.to_wif());
@@ -495,13 +508,13 @@ fn wallet__import_descriptors() {
     let descriptor = format!("{}#{}", raw_descriptor, info.checksum);
 
     // 4. Mine 100 blocks
-    let mining_address = node.client.new_address().expect("failed to get mining address");
+    let mining_address = wallet.new_address().expect("failed to get mining address");
     let _blocks = node.client.generate_to_address(100, &mining_address).expect("generatetoaddress");
 
     // 5. Scan for the descriptor using the time from (1)
     let request = ImportDescriptorsRequest::new(descriptor, start_time);
     let result: ImportDescriptors =
-        node.client.import_descriptors(&[request]).expect("importdescriptors");
+        wallet.import_descriptors(&[request]).expect("importdescriptors");
     assert_eq!(result.0.len(), 1, "should have exactly one import result");
     assert!(result.0[0].success);
 }
@@ -518,37 +531,42 @@ fn wallet__import_pruned_funds() {
     let tx_out_proof = node.client.get_tx_out_proof(&[txid]).expect("gettxoutproof");
 
     let _: () =
-        node.client.import_pruned_funds(&raw_tx.0, &tx_out_proof).expect("importprunedfunds");
+        node.wallet().import_pruned_funds(&raw_tx.0, &tx_out_proof).expect("importprunedfunds");
 }
 
 #[test]
 #[cfg(feature = "v29_and_below")]
 fn wallet__import_wallet() {
-    let node = match () {
+    let (_node, wallet) = match () {
         #[cfg(feature = "v22_and_below")]
-        () => Node::with_wallet(Wallet::Default, &[]),
+        () => {
+            let node = Node::with_wallet(Wallet::Default, &[]);
+            let wallet = node.client.wallet("default");
+            (node, wallet)
+        }
         #[cfg(not(feature = "v22_and_below"))]
         () => {
             let node = Node::with_wallet(Wallet::None, &["-deprecatedrpc=create_bdb"]);
             node.client.create_legacy_wallet("wallet_name").expect("createlegacywallet");
-            node
+            let wallet = node.client.wallet("wallet_name");
+            (node, wallet)
         }
     };
 
-    node.client.new_address().expect("newaddress");
+    wallet.new_address().expect("newaddress");
     let dump_file_path = integration_test::random_tmp_file();
 
-    node.client.dump_wallet(&dump_file_path).expect("dumpwallet");
+    wallet.dump_wallet(&dump_file_path).expect("dumpwallet");
     assert!(dump_file_path.exists());
 
-    let _: () = node.client.import_wallet(&dump_file_path).expect("importwallet");
+    let _: () = wallet.import_wallet(&dump_file_path).expect("importwallet");
 }
 
 #[test]
 fn wallet__keypool_refill() {
     let node = Node::with_wallet(Wallet::Default, &[]);
 
-    let _: () = node.client.key_pool_refill().expect("keypoolrefill");
+    let _: () = node.wallet().key_pool_refill().expect("keypoolrefill");
 }
 
 #[test]
@@ -556,13 +574,13 @@ fn wallet__list_address_groupings__modelled() {
     let node = Node::with_wallet(Wallet::Default, &[]);
     node.fund_wallet();
 
-    let address = node.client.new_address().expect("failed to create new address");
+    let address = node.wallet().new_address().expect("failed to create new address");
     let amount = Amount::from_sat(10_000);
-    node.client.send_to_address(&address, amount).expect("sendtoaddress").txid().unwrap();
+    node.wallet().send_to_address(&address, amount).expect("sendtoaddress").txid().unwrap();
     node.mine_a_block();
 
     let json: ListAddressGroupings =
-        node.client.list_address_groupings().expect("listaddressgroupings");
+        node.wallet().list_address_groupings().expect("listaddressgroupings");
     let model: Result<mtype::ListAddressGroupings, ListAddressGroupingsError> = json.into_model();
     let groupings = model.unwrap();
 
@@ -573,9 +591,9 @@ fn wallet__list_address_groupings__modelled() {
 fn wallet__list_labels__modelled() {
     let node = Node::with_wallet(Wallet::Default, &[]);
     let label = "list-label-test";
-    let _ = node.client.new_address_with_label(label).expect("newaddress");
+    let _ = node.wallet().new_address_with_label(label).expect("newaddress");
 
-    let json: ListLabels = node.client.list_labels().expect("listlabels");
+    let json: ListLabels = node.wallet().list_labels().expect("listlabels");
 
     assert!(json.0.iter().any(|s| s == label));
 }
@@ -589,12 +607,12 @@ fn wallet__list_received_by_label__modelled() {
 
     // Send some coins to the label
     let amount = Amount::from_sat(10_000);
-    let address = node.client.new_address_with_label(label).unwrap().assume_checked();
-    let _ = node.client.send_to_address(&address, amount).unwrap();
+    let address = node.wallet().new_address_with_label(label).unwrap().assume_checked();
+    let _ = node.wallet().send_to_address(&address, amount).unwrap();
     node.mine_a_block();
 
     let json: ListReceivedByLabel =
-        node.client.list_received_by_label().expect("listreceivedbylabel");
+        node.wallet().list_received_by_label().expect("listreceivedbylabel");
     let model: Result<mtype::ListReceivedByLabel, ListReceivedByLabelError> = json.into_model();
     let received_by_label = model.unwrap();
     assert!(received_by_label.0.iter().any(|item| item.label == label));
@@ -604,13 +622,13 @@ fn wallet__list_received_by_label__modelled() {
 fn wallet__list_received_by_address__modelled() {
     let node = Node::with_wallet(Wallet::Default, &[]);
     node.fund_wallet();
-    let address = node.client.new_address().expect("failed to create new address");
+    let address = node.wallet().new_address().expect("failed to create new address");
     let amount = Amount::from_sat(10_000);
-    let _ = node.client.send_to_address(&address, amount).expect("sendtoaddress");
+    let _ = node.wallet().send_to_address(&address, amount).expect("sendtoaddress");
     node.mine_a_block();
 
     let json: ListReceivedByAddress =
-        node.client.list_received_by_address().expect("listreceivedbyaddress");
+        node.wallet().list_received_by_address().expect("listreceivedbyaddress");
     let model: Result<mtype::ListReceivedByAddress, ListReceivedByAddressError> = json.into_model();
     let received_by_address = model.unwrap();
 
@@ -622,12 +640,12 @@ fn wallet__list_received_by_address__modelled() {
 fn wallet__list_since_block__modelled() {
     let node = Node::with_wallet(Wallet::Default, &[]);
     node.fund_wallet();
-    let addr = node.client.new_address().expect("newaddress");
+    let addr = node.wallet().new_address().expect("newaddress");
     let amount = Amount::from_sat(5_000);
-    node.client.send_to_address(&addr, amount).expect("sendtoaddress");
+    node.wallet().send_to_address(&addr, amount).expect("sendtoaddress");
     node.mine_a_block();
 
-    let json: ListSinceBlock = node.client.list_since_block().expect("listsinceblock");
+    let json: ListSinceBlock = node.wallet().list_since_block().expect("listsinceblock");
     let model: Result<mtype::ListSinceBlock, ListSinceBlockError> = json.into_model();
     let list_since_block = model.unwrap();
 
@@ -640,12 +658,12 @@ fn wallet__list_transactions__modelled() {
     let node = Node::with_wallet(Wallet::Default, &[]);
 
     node.fund_wallet();
-    let addr = node.client.new_address().expect("newaddress");
+    let addr = node.wallet().new_address().expect("newaddress");
     let amount = Amount::from_sat(5_000);
-    node.client.send_to_address(&addr, amount).expect("sendtoaddress");
+    node.wallet().send_to_address(&addr, amount).expect("sendtoaddress");
     node.mine_a_block();
 
-    let json: ListTransactions = node.client.list_transactions().expect("listtransactions");
+    let json: ListTransactions = node.wallet().list_transactions().expect("listtransactions");
     let model: Result<mtype::ListTransactions, TransactionItemError> = json.into_model();
     let list_transactions = model.unwrap();
 
@@ -656,19 +674,24 @@ fn wallet__list_transactions__modelled() {
 #[test]
 #[cfg(feature = "v29_and_below")]
 fn wallet__import_multi() {
-    let node = match () {
+    let (_node, wallet) = match () {
         #[cfg(feature = "v22_and_below")]
-        () => Node::with_wallet(Wallet::Default, &[]),
+        () => {
+            let node = Node::with_wallet(Wallet::Default, &[]);
+            let wallet = node.client.wallet("default");
+            (node, wallet)
+        }
         #[cfg(not(feature = "v22_and_below"))]
         () => {
             let node = Node::with_wallet(Wallet::None, &["-deprecatedrpc=create_bdb"]);
             node.client.create_legacy_wallet("wallet_name").expect("createlegacywallet");
-            node
+            let wallet = node.client.wallet("wallet_name");
+            (node, wallet)
         }
     };
 
     let dummy_script_hex = "76a914aabbccddeeff00112233445566778899aabbccdd88ac";
-    let addr = node.client.new_address().expect("newaddress");
+    let addr = wallet.new_address().expect("newaddress");
     let dummy_desc =
         "pkh(02c6047f9441ed7d6d3045406e95c07cd85a2a0e5c1e507a7a7e3d2f0d6c3d8ef8)#tp9h0863";
 
@@ -697,7 +720,7 @@ fn wallet__import_multi() {
         timestamp: ImportMultiTimestamp::Time(1_700_000_000),
     };
 
-    let json: ImportMulti = node.client.import_multi(&[req1, req2, req3]).expect("importmulti");
+    let json: ImportMulti = wallet.import_multi(&[req1, req2, req3]).expect("importmulti");
 
     #[cfg(not(feature = "v17"))]
     {
@@ -720,34 +743,44 @@ fn wallet__import_multi() {
 #[test]
 #[cfg(feature = "v29_and_below")]
 fn wallet__import_privkey() {
-    let node = match () {
+    let (_node, wallet) = match () {
         #[cfg(feature = "v22_and_below")]
-        () => Node::with_wallet(Wallet::Default, &[]),
+        () => {
+            let node = Node::with_wallet(Wallet::Default, &[]);
+            let wallet = node.client.wallet("default");
+            (node, wallet)
+        }
         #[cfg(not(feature = "v22_and_below"))]
         () => {
             let node = Node::with_wallet(Wallet::None, &["-deprecatedrpc=create_bdb"]);
             node.client.create_legacy_wallet("wallet_name").expect("createlegacywallet");
-            node
+            let wallet = node.client.wallet("wallet_name");
+            (node, wallet)
         }
     };
 
     let privkey =
         PrivateKey::from_wif("cVt4o7BGAig1UXywgGSmARhxMdzP5qvQsxKkSsc1XEkw3tDTQFpy").unwrap();
 
-    let _: () = node.client.import_privkey(&privkey).expect("importprivkey");
+    let _: () = wallet.import_privkey(&privkey).expect("importprivkey");
 }
 
 #[test]
 #[cfg(feature = "v29_and_below")]
 fn wallet__import_pubkey() {
-    let node = match () {
+    let (_node, wallet) = match () {
         #[cfg(feature = "v22_and_below")]
-        () => Node::with_wallet(Wallet::Default, &[]),
+        () => {
+            let node = Node::with_wallet(Wallet::Default, &[]);
+            let wallet = node.client.wallet("default");
+            (node, wallet)
+        }
         #[cfg(not(feature = "v22_and_below"))]
         () => {
             let node = Node::with_wallet(Wallet::None, &["-deprecatedrpc=create_bdb"]);
             node.client.create_legacy_wallet("wallet_name").expect("createlegacywallet");
-            node
+            let wallet = node.client.wallet("wallet_name");
+            (node, wallet)
         }
     };
 
@@ -755,7 +788,7 @@ fn wallet__import_pubkey() {
         .parse::<PublicKey>()
         .unwrap();
 
-    let _: () = node.client.import_pubkey(&pubkey).expect("importpubkey");
+    let _: () = wallet.import_pubkey(&pubkey).expect("importpubkey");
 }
 
 #[test]
@@ -771,7 +804,8 @@ fn wallet__list_descriptors() {
     #[cfg(not(feature = "v22_and_below"))]
     node.client.create_wallet(wallet_name).expect("create wallet");
 
-    let json: ListDescriptors = node.client.list_descriptors().expect("listdescriptors");
+    let json: ListDescriptors =
+        node.client.wallet(wallet_name).list_descriptors().expect("listdescriptors");
 
     let has_descriptor = json.descriptors.iter().any(|desc_info| {
         desc_info.descriptor.starts_with("wpkh(") || desc_info.descriptor.starts_with("pkh(")
@@ -784,13 +818,13 @@ fn wallet__list_lock_unspent__modelled() {
     let node = Node::with_wallet(Wallet::Default, &[]);
     node.fund_wallet();
 
-    let json: ListUnspent = node.client.list_unspent().expect("listunspent");
+    let json: ListUnspent = node.wallet().list_unspent().expect("listunspent");
     let utxos: mtype::ListUnspent = json.into_model().unwrap();
     let txid = utxos.0[0].txid;
     let vout = utxos.0[0].vout;
-    node.client.lock_unspent(&[(txid, vout)]).expect("lockunspent");
+    node.wallet().lock_unspent(&[(txid, vout)]).expect("lockunspent");
 
-    let json: ListLockUnspent = node.client.list_lock_unspent().expect("listlockunspent");
+    let json: ListLockUnspent = node.wallet().list_lock_unspent().expect("listlockunspent");
     let model: Result<mtype::ListLockUnspent, ListLockUnspentItemError> = json.into_model();
     let lock_unspent = model.unwrap();
 
@@ -808,7 +842,7 @@ fn wallet__list_unspent__modelled() {
 
     node.fund_wallet();
 
-    let json: ListUnspent = node.client.list_unspent().expect("listunspent");
+    let json: ListUnspent = node.wallet().list_unspent().expect("listunspent");
     let model: Result<mtype::ListUnspent, ListUnspentItemError> = json.into_model();
     model.unwrap();
 }
@@ -849,15 +883,16 @@ fn wallet__lock_unspent() {
     let node = Node::with_wallet(Wallet::Default, &[]);
     node.fund_wallet();
 
-    let json: ListUnspent = node.client.list_unspent().expect("listunspent");
+    let json: ListUnspent = node.wallet().list_unspent().expect("listunspent");
     let utxos: mtype::ListUnspent = json.into_model().unwrap();
     let txid = utxos.0[0].txid;
     let vout = utxos.0[0].vout;
 
-    let locked: LockUnspent = node.client.lock_unspent(&[(txid, vout)]).expect("lockunspent");
+    let locked: LockUnspent = node.wallet().lock_unspent(&[(txid, vout)]).expect("lockunspent");
     assert!(locked.0, "lock_unspent");
 
-    let unlocked: LockUnspent = node.client.unlock_unspent(&[(txid, vout)]).expect("unlockunspent");
+    let unlocked: LockUnspent =
+        node.wallet().unlock_unspent(&[(txid, vout)]).expect("unlockunspent");
     assert!(unlocked.0, "unlock_unspent");
 }
 
@@ -880,24 +915,24 @@ fn wallet__migrate_wallet() {
 fn wallet__new_keypool() {
     let node = Node::with_wallet(Wallet::None, &["-deprecatedrpc=create_bdb"]);
     node.client.create_legacy_wallet("legacy_wallet").expect("createlegacywallet");
-    let _: () = node.client.new_keypool().expect("newkeypool");
+    let _: () = node.client.wallet("legacy_wallet").new_keypool().expect("newkeypool");
 }
 
 #[test]
 #[cfg(not(feature = "v20_and_below"))]
 fn wallet__psbt_bump_fee__modelled() {
     let node = Node::with_wallet(Wallet::Default, &[]);
-    let address = node.client.new_address().expect("failed to create new address");
+    let address = node.wallet().new_address().expect("failed to create new address");
     let _ = node.client.generate_to_address(101, &address).expect("generatetoaddress");
 
     let txid = node
-        .client
+        .wallet()
         .send_to_address_rbf(&address, Amount::from_sat(10_000))
         .expect("sendtoaddress")
         .txid()
         .unwrap();
 
-    let json: PsbtBumpFee = node.client.psbt_bump_fee(&txid).expect("psbtbumpfee");
+    let json: PsbtBumpFee = node.wallet().psbt_bump_fee(&txid).expect("psbtbumpfee");
     let model: Result<mtype::PsbtBumpFee, PsbtBumpFeeError> = json.into_model();
     model.unwrap();
 }
@@ -914,19 +949,19 @@ fn wallet__remove_pruned_funds() {
     let tx_out_proof = node.client.get_tx_out_proof(&[txid]).expect("gettxoutproof");
 
     let _: () =
-        node.client.import_pruned_funds(&raw_tx.0, &tx_out_proof).expect("importprunedfunds");
+        node.wallet().import_pruned_funds(&raw_tx.0, &tx_out_proof).expect("importprunedfunds");
 
-    let _: () = node.client.remove_pruned_funds(txid).expect("removeprunedfunds");
+    let _: () = node.wallet().remove_pruned_funds(txid).expect("removeprunedfunds");
 }
 
 #[test]
 fn wallet__rescan_blockchain__modelled() {
     let node = Node::with_wallet(Wallet::Default, &[]);
 
-    let mining_addr = node.client.new_address().expect("newaddress");
+    let mining_addr = node.wallet().new_address().expect("newaddress");
     let _ = node.client.generate_to_address(3, &mining_addr).expect("generatetoaddress");
 
-    let json: RescanBlockchain = node.client.rescan_blockchain().expect("rescanblockchain");
+    let json: RescanBlockchain = node.wallet().rescan_blockchain().expect("rescanblockchain");
     let model: Result<mtype::RescanBlockchain, NumericError> = json.into_model();
     let rescan = model.unwrap();
 
@@ -950,21 +985,21 @@ fn wallet__send_many__modelled() {
     let node = Node::with_wallet(Wallet::Default, &[]);
     node.fund_wallet();
 
-    let addr1 = node.client.new_address().expect("newaddress");
-    let addr2 = node.client.new_address().expect("newaddress");
+    let addr1 = node.wallet().new_address().expect("newaddress");
+    let addr2 = node.wallet().new_address().expect("newaddress");
 
     let mut amounts = BTreeMap::new();
     amounts.insert(addr1, Amount::from_sat(100_000));
     amounts.insert(addr2, Amount::from_sat(100_000));
 
-    let json: SendMany = node.client.send_many(amounts.clone()).expect("sendmany");
+    let json: SendMany = node.wallet().send_many(amounts.clone()).expect("sendmany");
     let model: Result<mtype::SendMany, hex::HexToArrayError> = json.into_model();
     model.unwrap();
 
     #[cfg(not(feature = "v20_and_below"))]
     {
         let json_verbose: SendManyVerbose =
-            node.client.send_many_verbose(amounts).expect("sendmany verbose");
+            node.wallet().send_many_verbose(amounts).expect("sendmany verbose");
         let model_verbose: Result<mtype::SendManyVerbose, hex::HexToArrayError> =
             json_verbose.into_model();
         model_verbose.unwrap();
@@ -978,12 +1013,12 @@ fn wallet__send__modelled() {
 
     let node = Node::with_wallet(Wallet::Default, &[]);
     node.fund_wallet();
-    let address = node.client.new_address().expect("failed to create new address");
+    let address = node.wallet().new_address().expect("failed to create new address");
 
     let mut outputs = BTreeMap::new();
     outputs.insert(address.to_string(), 0.001);
 
-    let json: Send = node.client.send(&outputs).expect("send");
+    let json: Send = node.wallet().send(&outputs).expect("send");
     let model: Result<mtype::Send, SendError> = json.into_model();
     model.unwrap();
 }
@@ -993,9 +1028,9 @@ fn wallet__send__modelled() {
 fn wallet__send_all__modelled() {
     let node = Node::with_wallet(Wallet::Default, &[]);
     node.fund_wallet();
-    let address = node.client.new_address().expect("failed to create new address");
+    let address = node.wallet().new_address().expect("failed to create new address");
 
-    let json: SendAll = node.client.send_all(&[address]).expect("sendall");
+    let json: SendAll = node.wallet().send_all(&[address]).expect("sendall");
     let model: Result<mtype::SendAll, SendAllError> = json.into_model();
     model.unwrap();
 }
@@ -1004,10 +1039,10 @@ fn wallet__send_all__modelled() {
 fn wallet__send_to_address__modelled() {
     let node = Node::with_wallet(Wallet::Default, &[]);
     node.fund_wallet();
-    let address = node.client.new_address().expect("failed to create new address");
+    let address = node.wallet().new_address().expect("failed to create new address");
 
     let json: SendToAddress =
-        node.client.send_to_address(&address, Amount::from_sat(10_000)).expect("sendtddress");
+        node.wallet().send_to_address(&address, Amount::from_sat(10_000)).expect("sendtddress");
     let model: Result<mtype::SendToAddress, hex::HexToArrayError> = json.into_model();
     model.unwrap();
 }
@@ -1022,7 +1057,7 @@ fn wallet__set_tx_fee() {
 
     let fee_rate = FeeRate::from_sat_per_vb(2).expect("2 sat/vb is valid");
 
-    let json: SetTxFee = node.client.set_tx_fee(fee_rate).expect("settxfee");
+    let json: SetTxFee = node.wallet().set_tx_fee(fee_rate).expect("settxfee");
     assert!(json.0);
 }
 
@@ -1031,7 +1066,7 @@ fn wallet__set_tx_fee() {
 fn wallet__set_wallet_flag() {
     let node = Node::with_wallet(Wallet::Default, &[]);
 
-    let json: SetWalletFlag = node.client.set_wallet_flag("avoid_reuse").expect("setwalletflag");
+    let json: SetWalletFlag = node.wallet().set_wallet_flag("avoid_reuse").expect("setwalletflag");
     assert_eq!(json.flag_name, "avoid_reuse");
     assert!(json.flag_state);
 }
@@ -1039,20 +1074,26 @@ fn wallet__set_wallet_flag() {
 #[test]
 #[cfg(feature = "v29_and_below")]
 fn wallet__set_hd_seed() {
-    let node = match () {
+    let (node, wallet) = match () {
         #[cfg(feature = "v22_and_below")]
-        () => Node::with_wallet(Wallet::Default, &[]),
+        () => {
+            let node = Node::with_wallet(Wallet::Default, &[]);
+            let wallet = node.client.wallet("default");
+            (node, wallet)
+        }
         #[cfg(not(feature = "v22_and_below"))]
         () => {
             let node = Node::with_wallet(Wallet::None, &["-deprecatedrpc=create_bdb"]);
             node.client.create_legacy_wallet("wallet_name").expect("createlegacywallet");
-            node
+            let wallet = node.client.wallet("wallet_name");
+            (node, wallet)
         }
     };
 
-    node.fund_wallet();
+    let address = wallet.new_address().expect("failed to get new address");
+    node.client.generate_to_address(101, &address).expect("generatetoaddress");
 
-    let _: () = node.client.set_hd_seed().expect("sethdseed");
+    let _: () = wallet.set_hd_seed().expect("sethdseed");
 }
 
 #[test]
@@ -1060,11 +1101,11 @@ fn wallet__sign_message__modelled() {
     let node = Node::with_wallet(Wallet::Default, &[]);
     node.fund_wallet();
 
-    let address = node.client.new_address_with_type(AddressType::Legacy).unwrap();
+    let address = node.wallet().new_address_with_type(AddressType::Legacy).unwrap();
     let message = "integration test message";
 
     // Sign the message with the address key
-    let json: SignMessage = node.client.sign_message(&address, message).expect("signmessage");
+    let json: SignMessage = node.wallet().sign_message(&address, message).expect("signmessage");
     let model: Result<mtype::SignMessage, sign_message::MessageSignatureError> = json.into_model();
     model.unwrap();
 }
@@ -1075,21 +1116,21 @@ fn wallet__simulate_raw_transaction() {
     let node = Node::with_wallet(Wallet::Default, &[]);
     node.fund_wallet();
 
-    let address = node.client.new_address().expect("failed to create new address");
+    let address = node.wallet().new_address().expect("failed to create new address");
     let amount = Amount::from_sat(10_000);
 
     let txid1 =
-        node.client.send_to_address(&address, amount).expect("sendtoaddress").txid().unwrap();
+        node.wallet().send_to_address(&address, amount).expect("sendtoaddress").txid().unwrap();
     let raw_tx1 = node.client.get_raw_transaction(txid1).expect("getrawtransaction");
 
     let txid2 =
-        node.client.send_to_address(&address, amount).expect("sendtoaddress").txid().unwrap();
+        node.wallet().send_to_address(&address, amount).expect("sendtoaddress").txid().unwrap();
     let raw_tx2 = node.client.get_raw_transaction(txid2).expect("getrawtransaction");
 
     // Simulate raw transaction with the 2 transactions
     let rawtxs = vec![raw_tx1.0, raw_tx2.0];
     let json: SimulateRawTransaction =
-        node.client.simulate_raw_transaction(&rawtxs).expect("simulaterawtransaction");
+        node.wallet().simulate_raw_transaction(&rawtxs).expect("simulaterawtransaction");
 
     let model: Result<mtype::SimulateRawTransaction, amount::ParseAmountError> = json.into_model();
     let raw_transaction = model.unwrap();
@@ -1103,10 +1144,10 @@ fn wallet__wallet_create_funded_psbt__modelled() {
     let node = Node::with_wallet(Wallet::Default, &[]);
     node.fund_wallet();
 
-    let addr = node.client.new_address().expect("newaddress");
+    let addr = node.wallet().new_address().expect("newaddress");
     let outputs = BTreeMap::from([(addr, Amount::from_sat(100_000))]);
     let json: WalletCreateFundedPsbt = node
-        .client
+        .wallet()
         .wallet_create_funded_psbt(vec![], vec![outputs])
         .expect("walletcreatefundedpsbt");
 
@@ -1122,10 +1163,10 @@ fn wallet__wallet_process_psbt__modelled() {
     let node = Node::with_wallet(Wallet::Default, &[]);
     node.fund_wallet();
 
-    let addr = node.client.new_address().expect("newaddress");
+    let addr = node.wallet().new_address().expect("newaddress");
     let outputs = BTreeMap::from([(addr, Amount::from_sat(50_000))]);
     let funded_psbt: WalletCreateFundedPsbt = node
-        .client
+        .wallet()
         .wallet_create_funded_psbt(vec![], vec![outputs])
         .expect("walletcreatefundedpsbt");
     let model: Result<mtype::WalletCreateFundedPsbt, WalletCreateFundedPsbtError> =
@@ -1133,7 +1174,7 @@ fn wallet__wallet_process_psbt__modelled() {
     let funded_psbt_model = model.unwrap();
 
     let json: WalletProcessPsbt =
-        node.client.wallet_process_psbt(&funded_psbt_model.psbt).expect("walletprocesspsbt");
+        node.wallet().wallet_process_psbt(&funded_psbt_model.psbt).expect("walletprocesspsbt");
     #[cfg(feature = "v25_and_below")]
     type WalletProcessPsbtError = psbt::PsbtParseError;
 
@@ -1148,9 +1189,9 @@ fn wallet__wallet_lock() {
     let node = Node::with_wallet(Wallet::Default, &[]);
 
     node.client.create_wallet("wallet_name").expect("createwallet");
-    node.client.encrypt_wallet("passphrase").expect("encryptwallet");
+    node.wallet().encrypt_wallet("passphrase").expect("encryptwallet");
 
-    let _: () = node.client.wallet_lock().expect("walletlock");
+    let _: () = node.wallet().wallet_lock().expect("walletlock");
 }
 
 #[test]
@@ -1158,10 +1199,10 @@ fn wallet__wallet_passphrase() {
     let node = Node::with_wallet(Wallet::Default, &[]);
 
     node.client.create_wallet("wallet_name").expect("createwallet");
-    node.client.encrypt_wallet("passphrase").expect("encryptwallet");
+    node.wallet().encrypt_wallet("passphrase").expect("encryptwallet");
 
     let timeout = 60u64;
-    let _: () = node.client.wallet_passphrase("passphrase", timeout).expect("walletpassphrase");
+    let _: () = node.wallet().wallet_passphrase("passphrase", timeout).expect("walletpassphrase");
 }
 
 #[test]
@@ -1169,10 +1210,10 @@ fn wallet__wallet_passphrase_change() {
     let node = Node::with_wallet(Wallet::Default, &[]);
 
     node.client.create_wallet("wallet name").expect("createwallet");
-    node.client.encrypt_wallet("old passphrase").expect("encryptwallet");
+    node.wallet().encrypt_wallet("old passphrase").expect("encryptwallet");
 
     let _: () = node
-        .client
+        .wallet()
         .wallet_passphrase_change("old passphrase", "new passphrase")
         .expect("walletpassphrasechange");
 }
@@ -1202,5 +1243,5 @@ fn create_load_unload_wallet() {
 fn wallet__upgrade_wallet() {
     let node = Node::with_wallet(Wallet::Default, &[]);
 
-    let _: UpgradeWallet = node.client.upgrade_wallet().expect("upgradewallet");
+    let _: UpgradeWallet = node.wallet().upgrade_wallet().expect("upgradewallet");
 }
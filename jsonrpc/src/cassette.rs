@@ -0,0 +1,449 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Recording JSON-RPC sessions to a cassette file and replaying them without a server.
+//!
+//! A cassette is a JSONL file, each line holds a request and the response it got. Use a
+//! [`Recorder`] to wrap the transport of a live session and a [`Replay`] to serve the recorded
+//! responses later on.
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::{error, fmt};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::client::Transport;
+use crate::{Notification, Request, Response};
+
+/// A recorded request and the response it got.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct Entry {
+    /// The endpoint of the server the request was sent to, `None` for the main endpoint.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    endpoint: Option<String>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+    id: Value,
+    response: Response,
+}
+
+impl Entry {
+    fn new(endpoint: &Option<String>, req: &Request, response: Response) -> Result<Self, Error> {
+        let params = match req.params {
+            Some(params) => serde_json::from_str(params.get())?,
+            None => Value::Null,
+        };
+        Ok(Entry {
+            endpoint: endpoint.clone(),
+            method: req.method.to_owned(),
+            params,
+            id: req.id.clone(),
+            response,
+        })
+    }
+}
+
+/// A transport that records every request, and the response it got, to a cassette file.
+///
+/// Requests that fail in the inner transport are not recorded, nor are notifications since they
+/// get no response.
+#[derive(Debug)]
+pub struct Recorder<T> {
+    inner: T,
+    endpoint: Option<String>,
+    file: Arc<Mutex<File>>,
+    path: PathBuf,
+}
+
+impl<T: Transport> Recorder<T> {
+    /// Records the session of `inner` to a new cassette at `path`, truncating any existing file.
+    pub fn create<P: AsRef<Path>>(path: P, inner: T) -> io::Result<Self> {
+        Self::with_file(path.as_ref(), File::create(path.as_ref())?, inner)
+    }
+
+    /// Records the session of `inner` by appending to the cassette at `path`.
+    pub fn append<P: AsRef<Path>>(path: P, inner: T) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path.as_ref())?;
+        Self::with_file(path.as_ref(), file, inner)
+    }
+
+    fn with_file(path: &Path, file: File, inner: T) -> io::Result<Self> {
+        Ok(Recorder {
+            inner,
+            endpoint: None,
+            file: Arc::new(Mutex::new(file)),
+            path: path.to_owned(),
+        })
+    }
+
+    /// Returns a recorder for another `endpoint` of the same server, e.g. a wallet endpoint.
+    ///
+    /// Records the session of `inner` to the same cassette, see [`Replay::endpoint`].
+    pub fn endpoint<U: Transport, S: Into<String>>(&self, endpoint: S, inner: U) -> Recorder<U> {
+        Recorder {
+            inner,
+            endpoint: Some(endpoint.into()),
+            file: Arc::clone(&self.file),
+            path: self.path.clone(),
+        }
+    }
+
+    /// Returns the inner transport.
+    pub fn inner(&self) -> &T { &self.inner }
+
+    fn record(&self, entries: &[Entry]) -> Result<(), Error> {
+        let mut buf = Vec::new();
+        for entry in entries {
+            serde_json::to_writer(&mut buf, entry)?;
+            buf.push(b'\n');
+        }
+        let mut file = self.file.lock().expect("poisoned mutex");
+        file.write_all(&buf)?;
+        file.flush()?;
+        Ok(())
+    }
+}
+
+impl<T: Transport> Transport for Recorder<T> {
+    fn send_request(&self, req: Request) -> Result<Response, crate::Error> {
+        let resp = self.inner.send_request(req.clone())?;
+        self.record(&[Entry::new(&self.endpoint, &req, resp.clone())?])?;
+        Ok(resp)
+    }
+
+    fn send_batch(&self, reqs: &[Request]) -> Result<Vec<Response>, crate::Error> {
+        let resps = self.inner.send_batch(reqs)?;
+        let mut entries = Vec::with_capacity(resps.len());
+        for req in reqs {
+            // Responses without a matching request are left to `Client::send_batch` to report.
+            if let Some(resp) = resps.iter().find(|resp| resp.id == req.id) {
+                entries.push(Entry::new(&self.endpoint, req, resp.clone())?);
+            }
+        }
+        self.record(&entries)?;
+        Ok(resps)
+    }
+
+    fn send_notification(&self, notification: Notification) -> Result<(), crate::Error> {
+        self.inner.send_notification(notification)
+    }
+
+    fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.inner.fmt_target(f)?;
+        write!(f, " (recording to {})", self.path.display())
+    }
+}
+
+/// How a [`Replay`] matches requests with the recorded ones.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Mode {
+    /// Requests must match the method, params and id of a recording.
+    ///
+    /// Every recording is served once, in the order they were recorded.
+    #[default]
+    Strict,
+    /// Requests must match the method and params of a recording, ids are ignored.
+    ///
+    /// The response gets the id of the request. Recordings are served in the order they were
+    /// recorded and the last one is repeated once all of them were served.
+    Tolerant,
+}
+
+/// The recordings of a cassette, grouped by endpoint, method and params.
+#[derive(Debug, Default)]
+struct Cassette {
+    recordings: HashMap<(Option<String>, String, String), Recordings>,
+}
+
+#[derive(Debug, Default)]
+struct Recordings {
+    entries: Vec<Entry>,
+    served: usize,
+}
+
+/// A transport that serves the responses recorded in a cassette file, without a server.
+///
+/// Cloning a replay shares the state of the cassette i.e., each recording is served once in
+/// [`Mode::Strict`], no matter which clone serves it. Notifications are accepted and ignored.
+#[derive(Clone, Debug)]
+pub struct Replay {
+    cassette: Arc<Mutex<Cassette>>,
+    endpoint: Option<String>,
+    mode: Mode,
+    name: String,
+}
+
+impl Replay {
+    /// Opens the cassette at `path` for replay in [`Mode::Strict`].
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path.as_ref())?;
+        Self::from_reader(BufReader::new(file), path.as_ref().display().to_string())
+    }
+
+    /// Reads a cassette from `reader` for replay in [`Mode::Strict`], `name` is used as target.
+    pub fn from_reader<R: BufRead>(reader: R, name: String) -> io::Result<Self> {
+        let mut cassette = Cassette::default();
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: Entry = serde_json::from_str(&line)?;
+            let key = (entry.endpoint.clone(), entry.method.clone(), entry.params.to_string());
+            cassette.recordings.entry(key).or_default().entries.push(entry);
+        }
+        Ok(Replay {
+            cassette: Arc::new(Mutex::new(cassette)),
+            endpoint: None,
+            mode: Mode::Strict,
+            name,
+        })
+    }
+
+    /// Sets how requests are matched with the recordings.
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Returns a replay of the recordings for another `endpoint`, see [`Recorder::endpoint`].
+    pub fn endpoint<S: Into<String>>(&self, endpoint: S) -> Replay {
+        Replay { endpoint: Some(endpoint.into()), ..self.clone() }
+    }
+
+    /// Returns `true` if every recording was served.
+    pub fn is_finished(&self) -> bool {
+        let cassette = self.cassette.lock().expect("poisoned mutex");
+        cassette.recordings.values().all(|r| r.served >= r.entries.len())
+    }
+
+    fn serve(&self, req: &Request) -> Result<Response, Error> {
+        let params = match req.params {
+            Some(params) => serde_json::from_str::<Value>(params.get())?,
+            None => Value::Null,
+        };
+        let key = (self.endpoint.clone(), req.method.to_owned(), params.to_string());
+        let unmatched =
+            || Error::Unmatched { method: req.method.to_owned(), params: params.clone() };
+
+        let mut cassette = self.cassette.lock().expect("poisoned mutex");
+        let recordings = cassette.recordings.get_mut(&key).ok_or_else(unmatched)?;
+        match self.mode {
+            Mode::Strict => {
+                let entry = recordings.entries.get(recordings.served).ok_or_else(unmatched)?;
+                if entry.id != req.id {
+                    return Err(unmatched());
+                }
+                recordings.served += 1;
+                Ok(entry.response.clone())
+            }
+            Mode::Tolerant => {
+                let last = recordings.entries.len() - 1;
+                let entry = &recordings.entries[recordings.served.min(last)];
+                recordings.served += 1;
+                Ok(Response { id: req.id.clone(), ..entry.response.clone() })
+            }
+        }
+    }
+}
+
+impl Transport for Replay {
+    fn send_request(&self, req: Request) -> Result<Response, crate::Error> { Ok(self.serve(&req)?) }
+
+    fn send_batch(&self, reqs: &[Request]) -> Result<Vec<Response>, crate::Error> {
+        Ok(reqs.iter().map(|req| self.serve(req)).collect::<Result<_, _>>()?)
+    }
+
+    fn send_notification(&self, _: Notification) -> Result<(), crate::Error> { Ok(()) }
+
+    fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "replay of {}", self.name)?;
+        if let Some(ref endpoint) = self.endpoint {
+            write!(f, " ({})", endpoint)?;
+        }
+        Ok(())
+    }
+}
+
+/// Error that can occur while recording or replaying a cassette.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing the cassette failed.
+    Io(io::Error),
+    /// JSON error.
+    Json(serde_json::Error),
+    /// The cassette has no recording for the request.
+    Unmatched {
+        /// The method of the request.
+        method: String,
+        /// The params of the request.
+        params: Value,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Error::*;
+
+        match *self {
+            Io(ref e) => write!(f, "cassette I/O error: {}", e),
+            Json(ref e) => write!(f, "JSON error: {}", e),
+            Unmatched { ref method, ref params } =>
+                write!(f, "no recording for request {} {}", method, params),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        use self::Error::*;
+
+        match *self {
+            Io(ref e) => Some(e),
+            Json(ref e) => Some(e),
+            Unmatched { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self { Error::Io(e) }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self { Error::Json(e) }
+}
+
+impl From<Error> for crate::Error {
+    fn from(e: Error) -> crate::Error {
+        match e {
+            Error::Json(e) => crate::Error::Json(e),
+            e => crate::Error::Transport(Box::new(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::{env, process};
+
+    use serde_json::value::RawValue;
+
+    use super::*;
+    use crate::Client;
+
+    /// A transport that answers every request with the number of requests it got.
+    #[derive(Debug, Default)]
+    struct Counter(AtomicUsize);
+
+    impl Transport for Counter {
+        fn send_request(&self, req: Request) -> Result<Response, crate::Error> {
+            let n = self.0.fetch_add(1, Ordering::SeqCst) + 1;
+            Ok(Response {
+                result: Some(RawValue::from_string(n.to_string()).unwrap()),
+                error: None,
+                id: req.id,
+                jsonrpc: Some("2.0".to_owned()),
+            })
+        }
+
+        fn send_batch(&self, reqs: &[Request]) -> Result<Vec<Response>, crate::Error> {
+            reqs.iter().map(|req| self.send_request(req.clone())).collect()
+        }
+
+        fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "counter") }
+    }
+
+    fn cassette_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("jsonrpc-cassette-{}-{}.jsonl", name, process::id()))
+    }
+
+    fn call(client: &Client, method: &str, arg: u32) -> Result<usize, crate::Error> {
+        let params = [crate::arg(arg)];
+        client.call(method, Some(&crate::try_arg(params).unwrap()))
+    }
+
+    fn record(path: &Path) {
+        let recorder = Recorder::create(path, Counter::default()).unwrap();
+        let wallet = recorder.endpoint("wallet/alice", Counter::default());
+        let client = Client::with_transport(recorder);
+        let wallet = Client::with_transport(wallet);
+
+        assert_eq!(call(&client, "getblockcount", 0).unwrap(), 1);
+        assert_eq!(call(&client, "getblockcount", 0).unwrap(), 2);
+        assert_eq!(call(&client, "getblockhash", 1).unwrap(), 3);
+        assert_eq!(call(&wallet, "getbalance", 0).unwrap(), 1);
+    }
+
+    #[test]
+    fn replays_strict() {
+        let path = cassette_path("strict");
+        record(&path);
+
+        let replay = Replay::open(&path).unwrap();
+        let client = Client::with_transport(replay.clone());
+        let wallet = Client::with_transport(replay.endpoint("wallet/alice"));
+        assert_eq!(call(&client, "getblockcount", 0).unwrap(), 1);
+        assert_eq!(call(&client, "getblockcount", 0).unwrap(), 2);
+        assert_eq!(call(&client, "getblockhash", 1).unwrap(), 3);
+        assert_eq!(call(&wallet, "getbalance", 0).unwrap(), 1);
+        assert!(replay.is_finished());
+
+        // Exhausted, unknown params and unknown endpoints.
+        assert!(call(&client, "getblockcount", 0).is_err());
+        assert!(call(&client, "getblockhash", 2).is_err());
+        assert!(call(&client, "getbalance", 0).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn strict_replay_checks_ids() {
+        let path = cassette_path("ids");
+        record(&path);
+
+        let client = Client::with_transport(Replay::open(&path).unwrap());
+        // The recorded session used nonce 1 for this request.
+        assert!(call(&client, "getblockhash", 1).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn replays_tolerant() {
+        let path = cassette_path("tolerant");
+        record(&path);
+
+        let replay = Replay::open(&path).unwrap().mode(Mode::Tolerant);
+        let client = Client::with_transport(replay);
+        assert_eq!(call(&client, "getblockhash", 1).unwrap(), 3);
+        assert_eq!(call(&client, "getblockcount", 0).unwrap(), 1);
+        assert_eq!(call(&client, "getblockcount", 0).unwrap(), 2);
+        assert_eq!(call(&client, "getblockcount", 0).unwrap(), 2);
+        assert!(call(&client, "getblockhash", 2).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn records_and_replays_batches() {
+        let path = cassette_path("batch");
+        let client = Client::with_transport(Recorder::create(&path, Counter::default()).unwrap());
+        let params = crate::try_arg([0]).unwrap();
+        let reqs = [client.build_request("a", Some(&params)), client.build_request("b", None)];
+        let resps = client.send_batch(&reqs).unwrap();
+        assert_eq!(resps.len(), 2);
+
+        let client = Client::with_transport(Replay::open(&path).unwrap());
+        let reqs = [client.build_request("a", Some(&params)), client.build_request("b", None)];
+        let replayed = client.send_batch(&reqs).unwrap();
+        let results = |resps: &[Option<Response>]| {
+            resps.iter().map(|r| r.as_ref().unwrap().result::<usize>().unwrap()).collect::<Vec<_>>()
+        };
+        assert_eq!(results(&replayed), results(&resps));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
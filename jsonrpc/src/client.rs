@@ -15,7 +15,7 @@ use serde_json::value::RawValue;
 use serde_json::Value;
 
 use crate::error::Error;
-use crate::{Request, Response};
+use crate::{Notification, Request, Response};
 
 /// An interface for a transport over which to use the JSONRPC protocol.
 pub trait Transport: Send + Sync + 'static {
@@ -23,10 +23,74 @@ pub trait Transport: Send + Sync + 'static {
     fn send_request(&self, _: Request) -> Result<Response, Error>;
     /// Sends a batch of RPC requests over the transport.
     fn send_batch(&self, _: &[Request]) -> Result<Vec<Response>, Error>;
+    /// Sends an RPC notification over the transport, any response is ignored.
+    ///
+    /// The default implementation returns an error without sending anything.
+    fn send_notification(&self, _: Notification) -> Result<(), Error> {
+        Err(Error::Transport("transport does not support notifications".into()))
+    }
     /// Formats the target of this transport. I.e. the URL/socket/...
     fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result;
 }
 
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn send_request(&self, req: Request) -> Result<Response, Error> { (**self).send_request(req) }
+
+    fn send_batch(&self, reqs: &[Request]) -> Result<Vec<Response>, Error> {
+        (**self).send_batch(reqs)
+    }
+
+    fn send_notification(&self, notification: Notification) -> Result<(), Error> {
+        (**self).send_notification(notification)
+    }
+
+    fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result { (**self).fmt_target(f) }
+}
+
+impl<T: Transport + ?Sized> Transport for std::sync::Arc<T> {
+    fn send_request(&self, req: Request) -> Result<Response, Error> { (**self).send_request(req) }
+
+    fn send_batch(&self, reqs: &[Request]) -> Result<Vec<Response>, Error> {
+        (**self).send_batch(reqs)
+    }
+
+    fn send_notification(&self, notification: Notification) -> Result<(), Error> {
+        (**self).send_notification(notification)
+    }
+
+    fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result { (**self).fmt_target(f) }
+}
+
+/// The version of the JSON-RPC protocol a [`Client`] speaks.
+///
+/// Bitcoin Core v28 and later use the version of each request to decide how to answer it, earlier
+/// versions always answer the legacy way.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Protocol {
+    /// JSON-RPC 1.0, requests carry `"jsonrpc": "1.0"`.
+    ///
+    /// Responses hold both `result` and `error`, errors come with an HTTP error status e.g., 500
+    /// or 404. Requests without an `id` are answered like any other request.
+    Legacy,
+    /// JSON-RPC 2.0, requests carry `"jsonrpc": "2.0"`.
+    ///
+    /// Servers that honour the version return errors with HTTP status 200, leave out `result` on
+    /// error and answer notifications with HTTP status 204. Responses without a `jsonrpc` field,
+    /// as sent by servers that don't, are accepted.
+    #[default]
+    V2,
+}
+
+impl Protocol {
+    /// Returns the value of the `jsonrpc` field of requests.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Protocol::Legacy => "1.0",
+            Protocol::V2 => "2.0",
+        }
+    }
+}
+
 /// A JSON-RPC client.
 ///
 /// Creates a new Client using one of the transport-specific constructors e.g.,
@@ -34,21 +98,45 @@ pub trait Transport: Send + Sync + 'static {
 pub struct Client {
     pub(crate) transport: Box<dyn Transport>,
     nonce: atomic::AtomicUsize,
+    protocol: Protocol,
 }
 
 impl Client {
     /// Creates a new client with the given transport.
     pub fn with_transport<T: Transport>(transport: T) -> Client {
-        Client { transport: Box::new(transport), nonce: atomic::AtomicUsize::new(1) }
+        Client {
+            transport: Box::new(transport),
+            nonce: atomic::AtomicUsize::new(1),
+            protocol: Protocol::default(),
+        }
+    }
+
+    /// Speaks `protocol` instead of the default JSON-RPC 2.0.
+    pub fn with_protocol(mut self, protocol: Protocol) -> Client {
+        self.protocol = protocol;
+        self
     }
 
+    /// Returns the version of the protocol the client speaks.
+    pub fn protocol(&self) -> Protocol { self.protocol }
+
     /// Builds a request.
     ///
     /// To construct the arguments, one can use one of the shorthand methods.
     /// [`crate::arg`] or [`crate::try_arg`].
     pub fn build_request<'a>(&self, method: &'a str, params: Option<&'a RawValue>) -> Request<'a> {
         let nonce = self.nonce.fetch_add(1, atomic::Ordering::Relaxed);
-        Request { method, params, id: serde_json::Value::from(nonce), jsonrpc: Some("2.0") }
+        let jsonrpc = Some(self.protocol.as_str());
+        Request { method, params, id: serde_json::Value::from(nonce), jsonrpc }
+    }
+
+    /// Builds a notification.
+    pub fn build_notification<'a>(
+        &self,
+        method: &'a str,
+        params: Option<&'a RawValue>,
+    ) -> Notification<'a> {
+        Notification { method, params, jsonrpc: Some(self.protocol.as_str()) }
     }
 
     /// Sends a request to a client.
@@ -113,7 +201,10 @@ impl Client {
         let id = request.id.clone();
 
         let response = self.send_request(request)?;
-        if response.jsonrpc.is_some() && response.jsonrpc != Some(From::from("2.0")) {
+        if self.protocol == Protocol::V2
+            && response.jsonrpc.is_some()
+            && response.jsonrpc != Some(From::from("2.0"))
+        {
             return Err(Error::VersionMismatch);
         }
         if response.id != id {
@@ -122,6 +213,14 @@ impl Client {
 
         response.result()
     }
+
+    /// Sends a notification, a call that gets no response.
+    ///
+    /// Only servers speaking JSON-RPC 2.0 treat it as a notification, with [`Protocol::Legacy`]
+    /// the server answers it like any other request and the response is ignored.
+    pub fn notify(&self, method: &str, args: Option<&RawValue>) -> Result<(), Error> {
+        self.transport.send_notification(self.build_notification(method, args))
+    }
 }
 
 impl fmt::Debug for crate::Client {
@@ -215,6 +314,21 @@ mod tests {
         assert!(req1.id != req2.id);
     }
 
+    #[test]
+    fn protocol_sets_version() {
+        let client = Client::with_transport(DummyTransport);
+        assert_eq!(client.build_request("test", None).jsonrpc, Some("2.0"));
+        assert!(client.notify("test", None).is_err());
+
+        let client = client.with_protocol(Protocol::Legacy);
+        assert_eq!(client.build_request("test", None).jsonrpc, Some("1.0"));
+        let notification = serde_json::to_value(client.build_notification("test", None)).unwrap();
+        assert_eq!(
+            notification,
+            serde_json::json!({"method": "test", "params": null, "jsonrpc": "1.0"})
+        );
+    }
+
     #[test]
     fn hash_value() {
         let val = HashableValue(Cow::Owned(Value::from_str("null").unwrap()));
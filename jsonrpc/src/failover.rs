@@ -0,0 +1,426 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! A transport that spreads requests over several endpoints and fails over between them.
+//!
+//! [`Failover`] wraps a transport per endpoint e.g., one per replica of a server. Endpoints are
+//! tried in priority or round-robin order, see [`Strategy`]. An endpoint is marked unhealthy
+//! after a number of consecutive transport errors and skipped until a cooldown elapses.
+//!
+//! A request that fails with a transport error is sent to the next endpoint if it is safe to send
+//! it twice: either the server did not receive it (e.g., the connection was refused) or all its
+//! methods are idempotent, see [`Failover::with_idempotent`].
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::{error, fmt, io};
+
+use crate::client::Transport;
+use crate::error::Error;
+use crate::{Notification, Request, Response};
+
+/// The default number of consecutive transport errors after which an endpoint is unhealthy.
+const DEFAULT_MAX_FAILURES: u32 = 3;
+
+/// The default time an unhealthy endpoint is skipped for.
+const DEFAULT_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// The order in which [`Failover`] tries the endpoints.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Strategy {
+    /// Sends every request to the first healthy endpoint, in the order they were given.
+    #[default]
+    Priority,
+    /// Sends each request to the next healthy endpoint in turn.
+    RoundRobin,
+}
+
+/// A transport that sends each request to one of several endpoints, see the
+/// [module docs](self).
+///
+/// If all endpoints are unhealthy requests are sent to them anyway, in order, rather than failing
+/// without trying.
+pub struct Failover<T> {
+    endpoints: Vec<T>,
+    /// Shared with the transports created by [`Failover::map`].
+    health: Arc<Health>,
+    strategy: Strategy,
+    max_failures: u32,
+    cooldown: Duration,
+    idempotent: fn(&str) -> bool,
+}
+
+/// The health of the endpoints and the round-robin position.
+#[derive(Debug)]
+struct Health {
+    endpoints: Mutex<Vec<EndpointHealth>>,
+    next: AtomicUsize,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct EndpointHealth {
+    /// Consecutive transport errors.
+    failures: u32,
+    /// Until when the endpoint is skipped, `None` if it is healthy.
+    unhealthy_until: Option<Instant>,
+}
+
+impl<T: Transport> Failover<T> {
+    /// Creates a transport that fails over between `endpoints`, in priority order.
+    ///
+    /// No request is retried on another endpoint unless the server did not receive it, see
+    /// [`Failover::with_idempotent`].
+    pub fn new(endpoints: Vec<T>) -> Self {
+        let health = Health {
+            endpoints: Mutex::new(vec![EndpointHealth::default(); endpoints.len()]),
+            next: AtomicUsize::new(0),
+        };
+        Failover {
+            endpoints,
+            health: Arc::new(health),
+            strategy: Strategy::default(),
+            max_failures: DEFAULT_MAX_FAILURES,
+            cooldown: DEFAULT_COOLDOWN,
+            idempotent: |_| false,
+        }
+    }
+
+    /// Tries the endpoints in the order given by `strategy`.
+    pub fn with_strategy(mut self, strategy: Strategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Marks an endpoint unhealthy after `max_failures` consecutive transport errors, at least 1.
+    ///
+    /// Defaults to 3.
+    pub fn with_max_failures(mut self, max_failures: u32) -> Self {
+        self.max_failures = max_failures.max(1);
+        self
+    }
+
+    /// Skips an unhealthy endpoint for `cooldown`, defaults to 30 seconds.
+    ///
+    /// Once the cooldown has elapsed the endpoint is tried again, a single transport error marks
+    /// it unhealthy again.
+    pub fn with_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Retries requests whose methods are all idempotent according to `idempotent` on another
+    /// endpoint if they fail with a transport error.
+    ///
+    /// By default no method is considered idempotent since the server may have executed a
+    /// request that failed with a transport error.
+    pub fn with_idempotent(mut self, idempotent: fn(&str) -> bool) -> Self {
+        self.idempotent = idempotent;
+        self
+    }
+
+    /// Returns the transports of the endpoints.
+    pub fn endpoints(&self) -> &[T] { &self.endpoints }
+
+    /// Returns `true` if endpoint `index` is not currently skipped.
+    ///
+    /// # Panics
+    ///
+    /// If `index` is out of bounds.
+    pub fn is_healthy(&self, index: usize) -> bool {
+        let health = self.health.endpoints.lock().expect("poisoned mutex");
+        health[index].unhealthy_until.map_or(true, |until| until <= Instant::now())
+    }
+
+    /// Returns a transport with an endpoint for each of `self`, created by `f`.
+    ///
+    /// The two transports share the health of their endpoints e.g., a wallet endpoint of a server
+    /// and the server itself.
+    pub fn map<U: Transport, F: FnMut(&T) -> U>(&self, f: F) -> Failover<U> {
+        Failover {
+            endpoints: self.endpoints.iter().map(f).collect(),
+            health: Arc::clone(&self.health),
+            strategy: self.strategy,
+            max_failures: self.max_failures,
+            cooldown: self.cooldown,
+            idempotent: self.idempotent,
+        }
+    }
+
+    /// Returns the indices of the endpoints to try, in order.
+    fn order(&self) -> Vec<usize> {
+        let len = self.endpoints.len();
+        let start = match self.strategy {
+            Strategy::Priority => 0,
+            Strategy::RoundRobin if len > 0 =>
+                self.health.next.fetch_add(1, Ordering::Relaxed) % len,
+            Strategy::RoundRobin => 0,
+        };
+        let order = (0..len).map(|i| (start + i) % len);
+
+        let now = Instant::now();
+        let health = self.health.endpoints.lock().expect("poisoned mutex");
+        let (healthy, unhealthy): (Vec<_>, Vec<_>) =
+            order.partition(|&i| health[i].unhealthy_until.map_or(true, |until| until <= now));
+        if healthy.is_empty() {
+            unhealthy
+        } else {
+            healthy
+        }
+    }
+
+    /// Records the outcome of a request sent to endpoint `index`.
+    fn record<R>(&self, index: usize, result: &Result<R, Error>) {
+        let mut health = self.health.endpoints.lock().expect("poisoned mutex");
+        let endpoint = &mut health[index];
+        match result {
+            Err(Error::Transport(_)) => {
+                endpoint.failures = endpoint.failures.saturating_add(1);
+                if endpoint.failures >= self.max_failures {
+                    endpoint.unhealthy_until = Some(Instant::now() + self.cooldown);
+                }
+            }
+            // Any response, even an error, shows the endpoint is reachable.
+            _ => *endpoint = EndpointHealth::default(),
+        }
+    }
+
+    /// Sends a request with `send`, failing over to the next endpoint if that is safe.
+    fn send<R, F>(&self, idempotent: bool, send: F) -> Result<R, Error>
+    where
+        F: Fn(&T) -> Result<R, Error>,
+    {
+        let order = self.order();
+        let mut last_error = None;
+        for (attempt, &index) in order.iter().enumerate() {
+            let result = send(&self.endpoints[index]);
+            self.record(index, &result);
+            match result {
+                Err(Error::Transport(e)) if attempt + 1 < order.len() => {
+                    if !(idempotent || not_received(&*e)) {
+                        return Err(Error::Transport(e));
+                    }
+                    last_error = Some(Error::Transport(e));
+                }
+                result => return result,
+            }
+        }
+        Err(last_error.unwrap_or_else(|| Error::Transport("no endpoints to send to".into())))
+    }
+}
+
+impl<T: Transport> Transport for Failover<T> {
+    fn send_request(&self, req: Request) -> Result<Response, Error> {
+        let idempotent = (self.idempotent)(req.method);
+        self.send(idempotent, |t| t.send_request(req.clone()))
+    }
+
+    fn send_batch(&self, reqs: &[Request]) -> Result<Vec<Response>, Error> {
+        let idempotent = reqs.iter().all(|req| (self.idempotent)(req.method));
+        self.send(idempotent, |t| t.send_batch(reqs))
+    }
+
+    fn send_notification(&self, notification: Notification) -> Result<(), Error> {
+        let idempotent = (self.idempotent)(notification.method);
+        self.send(idempotent, |t| t.send_notification(notification.clone()))
+    }
+
+    fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("failover(")?;
+        for (i, endpoint) in self.endpoints.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            endpoint.fmt_target(f)?;
+        }
+        f.write_str(")")
+    }
+}
+
+impl<T> fmt::Debug for Failover<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Failover")
+            .field("endpoints", &self.endpoints.len())
+            .field("health", &self.health)
+            .field("strategy", &self.strategy)
+            .field("max_failures", &self.max_failures)
+            .field("cooldown", &self.cooldown)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Returns `true` if the transport error shows that the server did not receive the request.
+fn not_received(e: &(dyn error::Error + Send + Sync + 'static)) -> bool {
+    let mut source: Option<&(dyn error::Error + 'static)> = Some(e);
+    while let Some(e) = source {
+        if let Some(e) = e.downcast_ref::<io::Error>() {
+            if e.kind() == io::ErrorKind::ConnectionRefused {
+                return true;
+            }
+        }
+        // Carries no `io::Error` to look at.
+        #[cfg(feature = "bitreq")]
+        if let Some(bitreq::Error::ConnectTimeout) = e.downcast_ref() {
+            return true;
+        }
+        source = e.source();
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicBool;
+    use std::thread;
+
+    use serde_json::value::RawValue;
+
+    use super::*;
+    use crate::Client;
+
+    /// A transport that answers with its name, or fails while it is down.
+    #[derive(Clone)]
+    struct Replica {
+        name: &'static str,
+        down: Arc<AtomicBool>,
+        refused: bool,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl Replica {
+        fn new(name: &'static str) -> Self {
+            Replica {
+                name,
+                down: Arc::new(AtomicBool::new(false)),
+                refused: false,
+                calls: Arc::new(AtomicUsize::new(0)),
+            }
+        }
+
+        fn set_down(&self, down: bool) { self.down.store(down, Ordering::SeqCst) }
+
+        fn calls(&self) -> usize { self.calls.load(Ordering::SeqCst) }
+    }
+
+    impl Transport for Replica {
+        fn send_request(&self, req: Request) -> Result<Response, Error> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.down.load(Ordering::SeqCst) {
+                let kind = if self.refused {
+                    io::ErrorKind::ConnectionRefused
+                } else {
+                    io::ErrorKind::ConnectionReset
+                };
+                return Err(Error::Transport(Box::new(io::Error::from(kind))));
+            }
+            Ok(Response {
+                result: Some(RawValue::from_string(format!("\"{}\"", self.name)).unwrap()),
+                error: None,
+                id: req.id,
+                jsonrpc: Some("2.0".to_owned()),
+            })
+        }
+
+        fn send_batch(&self, reqs: &[Request]) -> Result<Vec<Response>, Error> {
+            reqs.iter().map(|req| self.send_request(req.clone())).collect()
+        }
+
+        fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result { f.write_str(self.name) }
+    }
+
+    fn call(client: &Client) -> Result<String, Error> { client.call("getblockcount", None) }
+
+    #[test]
+    fn prefers_first_healthy_endpoint() {
+        let (a, b) = (Replica::new("a"), Replica::new("b"));
+        let failover =
+            Failover::new(vec![a.clone(), b.clone()]).with_idempotent(|m| m == "getblockcount");
+        let client = Client::with_transport(failover);
+        assert_eq!(call(&client).unwrap(), "a");
+        assert_eq!(call(&client).unwrap(), "a");
+
+        a.set_down(true);
+        assert_eq!(call(&client).unwrap(), "b");
+        assert_eq!(a.calls(), 3);
+        assert_eq!(format!("{:?}", client), "jsonrpc::Client(failover(a, b))");
+    }
+
+    #[test]
+    fn rotates_round_robin() {
+        let (a, b, c) = (Replica::new("a"), Replica::new("b"), Replica::new("c"));
+        let failover = Failover::new(vec![a, b, c]).with_strategy(Strategy::RoundRobin);
+        let client = Client::with_transport(failover);
+        let names = (0..4).map(|_| call(&client).unwrap()).collect::<Vec<_>>();
+        assert_eq!(names, ["a", "b", "c", "a"]);
+    }
+
+    #[test]
+    fn retries_only_when_safe() {
+        let (a, b) = (Replica::new("a"), Replica::new("b"));
+        a.set_down(true);
+        let client = Client::with_transport(Failover::new(vec![a.clone(), b.clone()]));
+
+        // The server may have executed the request.
+        assert!(matches!(call(&client), Err(Error::Transport(_))));
+        assert_eq!(b.calls(), 0);
+
+        // The server did not receive the request.
+        let a = Replica { refused: true, ..a };
+        let client = Client::with_transport(Failover::new(vec![a, b.clone()]));
+        assert_eq!(call(&client).unwrap(), "b");
+        assert_eq!(b.calls(), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "bitreq")]
+    fn connect_timeout_is_not_received() {
+        assert!(not_received(&bitreq::Error::ConnectTimeout));
+        assert!(!not_received(&bitreq::Error::ReadTimeout));
+    }
+
+    #[test]
+    fn recovers_after_cooldown() {
+        let (a, b) = (Replica::new("a"), Replica::new("b"));
+        let failover = Failover::new(vec![a.clone(), b.clone()])
+            .with_max_failures(2)
+            .with_cooldown(Duration::from_millis(50))
+            .with_idempotent(|_| true);
+        let wallet = failover.map(|replica| Replica { name: "w", ..replica.clone() });
+
+        a.set_down(true);
+        assert_eq!(
+            failover.send_request(request()).map(|r| r.result::<String>().unwrap()).unwrap(),
+            "b"
+        );
+        assert!(failover.is_healthy(0));
+        // The second failure, through the wallet endpoint, marks the replica unhealthy.
+        wallet.send_request(request()).unwrap();
+        assert!(!failover.is_healthy(0));
+        failover.send_request(request()).unwrap();
+        assert_eq!(a.calls(), 2);
+
+        a.set_down(false);
+        thread::sleep(Duration::from_millis(60));
+        assert!(failover.is_healthy(0));
+        assert_eq!(failover.send_request(request()).unwrap().result::<String>().unwrap(), "a");
+    }
+
+    #[test]
+    fn tries_unhealthy_endpoints_as_last_resort() {
+        let a = Replica::new("a");
+        a.set_down(true);
+        let failover = Failover::new(vec![a.clone()]).with_max_failures(1);
+        assert!(failover.send_request(request()).is_err());
+        assert!(!failover.is_healthy(0));
+
+        a.set_down(false);
+        assert!(failover.send_request(request()).is_ok());
+        assert!(failover.is_healthy(0));
+
+        let empty = Failover::<Replica>::new(vec![]);
+        assert!(empty.send_request(request()).is_err());
+    }
+
+    fn request() -> Request<'static> {
+        Request { method: "getblockcount", params: None, id: 1.into(), jsonrpc: Some("2.0") }
+    }
+}
@@ -3,18 +3,19 @@
 //!
 //! [bitreq]: <https://github.com/rust-bitcoin/corepc/bitreq>
 
+use std::io::{self, BufRead, BufReader};
 #[cfg(jsonrpc_fuzz)]
-use std::io::{self, Read, Write};
-#[cfg(jsonrpc_fuzz)]
-use std::sync::Mutex;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use std::{error, fmt};
+use std::{error, fmt, fs};
 
 use base64::engine::general_purpose::STANDARD as BASE64;
 use base64::Engine;
 
 use crate::client::Transport;
-use crate::{Request, Response};
+use crate::{Notification, Request, Response};
 
 const DEFAULT_URL: &str = "http://localhost";
 const DEFAULT_PORT: u16 = 8332; // the default RPC port for bitcoind.
@@ -22,16 +23,33 @@ const DEFAULT_PORT: u16 = 8332; // the default RPC port for bitcoind.
 const DEFAULT_TIMEOUT_SECONDS: u64 = 15;
 #[cfg(jsonrpc_fuzz)]
 const DEFAULT_TIMEOUT_SECONDS: u64 = 1;
+/// The number of idle connections kept open for later requests.
+const CONNECTION_POOL_SIZE: usize = 8;
 
 /// An HTTP transport that uses [`bitreq`] and is useful for running a bitcoind RPC client.
+///
+/// Connections are kept open and reused by later requests, also by those of clones.
 #[derive(Clone, Debug)]
 pub struct BitreqHttpTransport {
     /// URL of the RPC server.
     url: String,
-    /// Timeout only supports second granularity.
+    /// Timeout of each whole request.
     timeout: Duration,
+    /// Timeout of connecting to the server.
+    connect_timeout: Option<Duration>,
+    /// Timeout of waiting for the server to send more of its response.
+    read_timeout: Option<Duration>,
     /// The value of the `Authorization` HTTP header, i.e., a base64 encoding of 'user:password'.
-    basic_auth: Option<String>,
+    ///
+    /// Shared between clones so that credentials re-read from `cookie_file` are used by all.
+    basic_auth: Arc<Mutex<Option<String>>>,
+    /// Path of the cookie file to re-read the credentials from if the server rejects them.
+    cookie_file: Option<PathBuf>,
+    /// Keeps the connections open between requests, shared between clones.
+    client: bitreq::Client,
+    /// The proxy to connect to the RPC server through.
+    #[cfg(feature = "proxy")]
+    proxy: Option<bitreq::Proxy>,
 }
 
 impl Default for BitreqHttpTransport {
@@ -39,7 +57,13 @@ impl Default for BitreqHttpTransport {
         BitreqHttpTransport {
             url: format!("{}:{}", DEFAULT_URL, DEFAULT_PORT),
             timeout: Duration::from_secs(DEFAULT_TIMEOUT_SECONDS),
-            basic_auth: None,
+            connect_timeout: None,
+            read_timeout: None,
+            basic_auth: Arc::new(Mutex::new(None)),
+            cookie_file: None,
+            client: bitreq::Client::new(CONNECTION_POOL_SIZE),
+            #[cfg(feature = "proxy")]
+            proxy: None,
         }
     }
 }
@@ -51,24 +75,35 @@ impl BitreqHttpTransport {
     /// Returns a builder for [`BitreqHttpTransport`].
     pub fn builder() -> Builder { Builder::new() }
 
+    /// Returns the URL of the RPC server.
+    pub fn url(&self) -> &str { &self.url }
+
+    /// Returns a copy of this transport that sends requests to `url`.
+    ///
+    /// The timeout and authentication information are kept as is.
+    pub fn with_url(&self, url: &str) -> Self {
+        BitreqHttpTransport { url: url.to_owned(), ..self.clone() }
+    }
+
     fn request<R>(&self, req: impl serde::Serialize) -> Result<R, Error>
     where
         R: for<'a> serde::de::Deserialize<'a>,
     {
-        let req = match &self.basic_auth {
-            Some(auth) => bitreq::Request::new(bitreq::Method::Post, &self.url)
-                .with_timeout(self.timeout.as_secs())
-                .with_header("Authorization", auth)
-                .with_json(&req)?,
-            None => bitreq::Request::new(bitreq::Method::Post, &self.url)
-                .with_timeout(self.timeout.as_secs())
-                .with_json(&req)?,
-        };
+        let mut resp = self.send(&req)?;
+
+        // bitcoind rewrites the cookie file when it restarts, if the credentials were read from
+        // one re-read it and try again.
+        if resp.status_code == 401 {
+            if let Some(ref cookie_file) = self.cookie_file {
+                if self.reload_cookie(cookie_file) {
+                    resp = self.send(&req)?;
+                }
+            }
+        }
 
-        // Send the request and parse the response. If the response is an error that does not
-        // contain valid JSON in its body (for instance if the bitcoind HTTP server work queue
-        // depth is exceeded), return the raw HTTP error so users can match against it.
-        let resp = req.send()?;
+        // Parse the response. If the response is an error that does not contain valid JSON in
+        // its body (for instance if the bitcoind HTTP server work queue depth is exceeded),
+        // return the raw HTTP error so users can match against it.
         match resp.json() {
             Ok(json) => Ok(json),
             Err(bitreq_err) =>
@@ -82,6 +117,64 @@ impl BitreqHttpTransport {
                 },
         }
     }
+
+    fn notify(&self, notification: Notification) -> Result<(), Error> {
+        let mut resp = self.send(&notification)?;
+        if resp.status_code == 401 {
+            if let Some(ref cookie_file) = self.cookie_file {
+                if self.reload_cookie(cookie_file) {
+                    resp = self.send(&notification)?;
+                }
+            }
+        }
+
+        // Servers speaking JSON-RPC 2.0 answer with 204, servers speaking 1.0 with a response
+        // that is ignored.
+        if (200..300).contains(&resp.status_code) || resp.json::<serde::de::IgnoredAny>().is_ok() {
+            Ok(())
+        } else {
+            Err(Error::Http(HttpError {
+                status_code: resp.status_code,
+                body: resp.as_str().unwrap_or("").to_string(),
+            }))
+        }
+    }
+
+    fn send(&self, req: &impl serde::Serialize) -> Result<bitreq::Response, Error> {
+        let auth = self.basic_auth.lock().expect("poisoned mutex").clone();
+        let mut req = bitreq::Request::new(bitreq::Method::Post, &self.url)
+            .with_total_timeout(self.timeout)
+            .with_json(req)?;
+        if let Some(connect_timeout) = self.connect_timeout {
+            req = req.with_connect_timeout(connect_timeout);
+        }
+        if let Some(read_timeout) = self.read_timeout {
+            req = req.with_read_timeout(read_timeout);
+        }
+        if let Some(auth) = auth {
+            req = req.with_header("Authorization", auth);
+        }
+        #[cfg(feature = "proxy")]
+        if let Some(ref proxy) = self.proxy {
+            req = req.with_proxy(proxy.clone());
+        }
+        Ok(self.client.send(req)?)
+    }
+
+    /// Re-reads the credentials from `cookie_file`, returns `true` if they changed.
+    fn reload_cookie(&self, cookie_file: &Path) -> bool {
+        let cookie = match read_cookie(cookie_file) {
+            Ok(cookie) => cookie,
+            Err(_) => return false,
+        };
+        let auth = basic_auth_header(&cookie);
+        let mut basic_auth = self.basic_auth.lock().expect("poisoned mutex");
+        if basic_auth.as_ref() == Some(&auth) {
+            return false;
+        }
+        *basic_auth = Some(auth);
+        true
+    }
 }
 
 impl Transport for BitreqHttpTransport {
@@ -93,6 +186,10 @@ impl Transport for BitreqHttpTransport {
         Ok(self.request(reqs)?)
     }
 
+    fn send_notification(&self, notification: Notification) -> Result<(), crate::Error> {
+        Ok(self.notify(notification)?)
+    }
+
     fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "{}", self.url) }
 }
 
@@ -112,6 +209,20 @@ impl Builder {
         self
     }
 
+    /// Sets the timeout after which requests will abort if connecting to the server isn't done,
+    /// to fail fast when it is unreachable while allowing slow calls.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.tp.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the timeout after which requests will abort if the server sends nothing while
+    /// responding.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.tp.read_timeout = Some(timeout);
+        self
+    }
+
     /// Sets the URL of the server to the transport.
     #[allow(clippy::assigning_clones)] // clone_into is only available in Rust 1.63
     pub fn url(mut self, url: &str) -> Result<Self, Error> {
@@ -126,7 +237,7 @@ impl Builder {
         if let Some(ref pass) = pass {
             s.push_str(pass.as_ref());
         }
-        self.tp.basic_auth = Some(format!("Basic {}", &BASE64.encode(s.as_bytes())));
+        self.tp.basic_auth = Arc::new(Mutex::new(Some(basic_auth_header(&s))));
         self
     }
 
@@ -147,7 +258,47 @@ impl Builder {
     /// let client = BitreqHttpTransport::builder().cookie_auth(cookie);
     /// ```
     pub fn cookie_auth<S: AsRef<str>>(mut self, cookie: S) -> Self {
-        self.tp.basic_auth = Some(format!("Basic {}", &BASE64.encode(cookie.as_ref().as_bytes())));
+        self.tp.basic_auth = Arc::new(Mutex::new(Some(basic_auth_header(cookie.as_ref()))));
+        self
+    }
+
+    /// Adds authentication information to the transport using the cookie file at `path`.
+    ///
+    /// Reads the cookie ('user:pass') from the first line of the file. If the server rejects
+    /// the credentials (HTTP 401), for instance because bitcoind restarted and rewrote the
+    /// file, the file is read again and the request is retried once.
+    pub fn cookie_file<P: AsRef<Path>>(mut self, path: P) -> Result<Self, Error> {
+        let cookie = read_cookie(path.as_ref())?;
+        self.tp.basic_auth = Arc::new(Mutex::new(Some(basic_auth_header(&cookie))));
+        self.tp.cookie_file = Some(path.as_ref().to_owned());
+        Ok(self)
+    }
+
+    /// Re-reads the credentials from the cookie file at `path` if the server rejects them.
+    ///
+    /// Unlike [`Builder::cookie_file`] the file is not read now, for callers that already read
+    /// the credentials from it and passed them to [`Builder::basic_auth`].
+    pub fn reload_cookie_file<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.tp.cookie_file = Some(path.as_ref().to_owned());
+        self
+    }
+
+    /// Connects to the RPC server through `proxy`.
+    ///
+    /// Use [`bitreq::Proxy::new_socks5`] to reach a node over Tor, the `.onion` host name of
+    /// the URL is then resolved by the proxy.
+    #[cfg(feature = "proxy")]
+    pub fn proxy(mut self, proxy: bitreq::Proxy) -> Self {
+        self.tp.proxy = Some(proxy);
+        self
+    }
+
+    /// Adds a [`bitreq::Middleware`] run around every HTTP request of the transport.
+    ///
+    /// A request is sent again if the server rejects the credentials read from a cookie
+    /// file, the middleware then runs for both.
+    pub fn middleware(mut self, middleware: Arc<dyn bitreq::Middleware>) -> Self {
+        self.tp.client = self.tp.client.with_middleware(middleware);
         self
     }
 
@@ -159,6 +310,18 @@ impl Default for Builder {
     fn default() -> Self { Builder::new() }
 }
 
+/// Returns the value of the `Authorization` header for a 'user:pass' string.
+fn basic_auth_header(user_pass: &str) -> String {
+    format!("Basic {}", &BASE64.encode(user_pass.as_bytes()))
+}
+
+/// Reads the cookie ('user:pass') from the first line of `path`.
+fn read_cookie(path: &Path) -> Result<String, io::Error> {
+    let mut line = String::new();
+    BufReader::new(fs::File::open(path)?).read_line(&mut line)?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_owned())
+}
+
 /// An HTTP error.
 #[derive(Debug)]
 pub struct HttpError {
@@ -189,6 +352,8 @@ pub enum Error {
     Bitreq(bitreq::Error),
     /// HTTP error that does not contain valid JSON as body.
     Http(HttpError),
+    /// I/O error reading the cookie file.
+    Io(io::Error),
 }
 
 impl fmt::Display for Error {
@@ -197,6 +362,7 @@ impl fmt::Display for Error {
             Error::Json(ref e) => write!(f, "parsing JSON failed: {}", e),
             Error::Bitreq(ref e) => write!(f, "bitreq: {}", e),
             Error::Http(ref e) => write!(f, "http ({})", e),
+            Error::Io(ref e) => write!(f, "reading cookie file failed: {}", e),
         }
     }
 }
@@ -209,6 +375,7 @@ impl error::Error for Error {
             Json(ref e) => Some(e),
             Bitreq(ref e) => Some(e),
             Http(ref e) => Some(e),
+            Io(ref e) => Some(e),
         }
     }
 }
@@ -221,6 +388,10 @@ impl From<bitreq::Error> for Error {
     fn from(e: bitreq::Error) -> Self { Error::Bitreq(e) }
 }
 
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self { Error::Io(e) }
+}
+
 impl From<Error> for crate::Error {
     fn from(e: Error) -> crate::Error {
         match e {
@@ -258,9 +429,96 @@ mod impls {
 
 #[cfg(test)]
 mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
     use super::*;
     use crate::Client;
 
+    /// A bitcoind stand-in that only accepts requests authenticated with the current cookie.
+    struct CookieServer {
+        url: String,
+        cookie: Arc<Mutex<String>>,
+        requests: Arc<AtomicUsize>,
+    }
+
+    impl CookieServer {
+        fn start(cookie: &str) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            let server = CookieServer {
+                url,
+                cookie: Arc::new(Mutex::new(cookie.to_owned())),
+                requests: Arc::new(AtomicUsize::new(0)),
+            };
+
+            let cookie = server.cookie.clone();
+            let requests = server.requests.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let mut stream = stream.unwrap();
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    let mut authorization = None;
+                    let mut content_length = 0;
+                    loop {
+                        let mut line = String::new();
+                        reader.read_line(&mut line).unwrap();
+                        let line = line.trim_end();
+                        if line.is_empty() {
+                            break;
+                        }
+                        let (name, value) = line.split_once(':').unwrap_or((line, ""));
+                        match name.to_ascii_lowercase().as_str() {
+                            "authorization" => authorization = Some(value.trim().to_owned()),
+                            "content-length" => content_length = value.trim().parse().unwrap(),
+                            _ => {}
+                        }
+                    }
+                    let mut body = vec![0; content_length];
+                    reader.read_exact(&mut body).unwrap();
+                    requests.fetch_add(1, Ordering::SeqCst);
+
+                    let expected = basic_auth_header(&cookie.lock().unwrap());
+                    let (status, body) = if authorization.as_ref() == Some(&expected) {
+                        let req: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                        let resp =
+                            serde_json::json!({ "result": true, "error": null, "id": req["id"] });
+                        ("200 OK", resp.to_string())
+                    } else {
+                        ("401 Unauthorized", String::new())
+                    };
+                    write!(
+                        stream,
+                        "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        status,
+                        body.len(),
+                        body
+                    )
+                    .unwrap();
+                }
+            });
+            server
+        }
+
+        /// Changes the credentials the server accepts, as a restarted bitcoind would.
+        fn rotate(&self, cookie: &str) { *self.cookie.lock().unwrap() = cookie.to_owned(); }
+
+        fn requests(&self) -> usize { self.requests.load(Ordering::SeqCst) }
+    }
+
+    /// Returns the path of a cookie file, unique to `test`, containing `cookie`.
+    fn write_cookie(test: &str, cookie: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "jsonrpc-bitreq-http-{}-{}.cookie",
+            test,
+            std::process::id()
+        ));
+        fs::write(&path, cookie).unwrap();
+        path
+    }
+
     #[test]
     fn construct() {
         let tp = Builder::new()
@@ -271,4 +529,185 @@ mod tests {
             .build();
         let _ = Client::with_transport(tp);
     }
+
+    #[test]
+    fn with_url_keeps_settings() {
+        let tp = Builder::new()
+            .timeout(Duration::from_millis(100))
+            .url("http://localhost:22")
+            .unwrap()
+            .basic_auth("user".to_string(), None)
+            .build();
+        let other = tp.with_url("http://localhost:22/wallet/alice");
+
+        assert_eq!(tp.url(), "http://localhost:22");
+        assert_eq!(other.url(), "http://localhost:22/wallet/alice");
+        assert_eq!(other.timeout, tp.timeout);
+        assert!(Arc::ptr_eq(&other.basic_auth, &tp.basic_auth));
+    }
+
+    #[test]
+    fn read_timeout_is_shorter_than_timeout() {
+        // The server accepts the connection but never answers.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let tp = Builder::new()
+            .timeout(Duration::from_secs(60))
+            .connect_timeout(Duration::from_millis(100))
+            .read_timeout(Duration::from_millis(100))
+            .url(&format!("http://{}", listener.local_addr().unwrap()))
+            .unwrap()
+            .build();
+
+        match tp.send(&"getblockcount") {
+            Err(Error::Bitreq(bitreq::Error::ReadTimeout)) => {}
+            Err(e) => panic!("expected a read timeout: {}", e),
+            Ok(_) => panic!("expected a read timeout"),
+        }
+    }
+
+    #[test]
+    fn cookie_file_is_reread_after_401() {
+        let server = CookieServer::start("__cookie__:first");
+        let cookie_file = write_cookie("reread", "__cookie__:first\n");
+        let tp =
+            Builder::new().url(&server.url).unwrap().cookie_file(&cookie_file).unwrap().build();
+        let client = Client::with_transport(tp);
+
+        assert!(client.call::<bool>("getblockcount", None).unwrap());
+        assert_eq!(server.requests(), 1);
+
+        server.rotate("__cookie__:second");
+        fs::write(&cookie_file, "__cookie__:second\n").unwrap();
+
+        assert!(client.call::<bool>("getblockcount", None).unwrap());
+        assert_eq!(server.requests(), 3);
+        assert!(client.call::<bool>("getblockcount", None).unwrap());
+        assert_eq!(server.requests(), 4);
+
+        fs::remove_file(cookie_file).unwrap();
+    }
+
+    #[test]
+    fn middleware_runs_for_every_request() {
+        struct Auth(AtomicUsize);
+
+        impl bitreq::Middleware for Auth {
+            fn on_request(
+                &self,
+                request: bitreq::Request,
+            ) -> Result<bitreq::Request, bitreq::Error> {
+                Ok(request.with_header("Authorization", basic_auth_header("__cookie__:first")))
+            }
+
+            fn on_response(
+                &self,
+                response: Result<&bitreq::Response, &bitreq::Error>,
+                _: Duration,
+            ) {
+                assert_eq!(response.unwrap().status_code, 200);
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let server = CookieServer::start("__cookie__:first");
+        let auth = Arc::new(Auth(AtomicUsize::new(0)));
+        let tp = Builder::new().url(&server.url).unwrap().middleware(auth.clone()).build();
+        let client = Client::with_transport(tp);
+
+        assert!(client.call::<bool>("getblockcount", None).unwrap());
+        assert!(client.call::<bool>("getblockcount", None).unwrap());
+        assert_eq!(auth.0.load(Ordering::SeqCst), 2);
+        assert_eq!(server.requests(), 2);
+    }
+
+    #[test]
+    fn unchanged_cookie_file_is_not_retried() {
+        let server = CookieServer::start("__cookie__:first");
+        let cookie_file = write_cookie("unchanged", "__cookie__:first");
+        let tp =
+            Builder::new().url(&server.url).unwrap().cookie_file(&cookie_file).unwrap().build();
+        let client = Client::with_transport(tp);
+
+        server.rotate("__cookie__:second");
+        match client.call::<bool>("getblockcount", None) {
+            Err(crate::Error::Transport(e)) => match e.downcast_ref::<Error>() {
+                Some(Error::Http(e)) => assert_eq!(e.status_code, 401),
+                _ => panic!("unexpected error: {:?}", e),
+            },
+            res => panic!("unexpected result: {:?}", res),
+        }
+        assert_eq!(server.requests(), 1);
+
+        fs::remove_file(cookie_file).unwrap();
+    }
+
+    #[test]
+    fn reread_cookie_is_shared_with_clones() {
+        let server = CookieServer::start("__cookie__:first");
+        let cookie_file = write_cookie("shared", "__cookie__:first");
+        let tp =
+            Builder::new().url(&server.url).unwrap().cookie_file(&cookie_file).unwrap().build();
+        let other = Client::with_transport(tp.with_url(&server.url));
+        let client = Client::with_transport(tp);
+
+        server.rotate("__cookie__:second");
+        fs::write(&cookie_file, "__cookie__:second").unwrap();
+
+        assert!(client.call::<bool>("getblockcount", None).unwrap());
+        assert_eq!(server.requests(), 2);
+        assert!(other.call::<bool>("getblockcount", None).unwrap());
+        assert_eq!(server.requests(), 3);
+
+        fs::remove_file(cookie_file).unwrap();
+    }
+
+    #[test]
+    fn missing_cookie_file() {
+        let path = std::env::temp_dir().join("jsonrpc-bitreq-http-does-not-exist.cookie");
+        assert!(matches!(Builder::new().cookie_file(path), Err(Error::Io(_))));
+    }
+
+    #[cfg(feature = "proxy")]
+    #[test]
+    fn sends_through_socks5_proxy() {
+        let server = CookieServer::start("user:pass");
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let proxy = bitreq::Proxy::new_socks5(listener.local_addr().unwrap().to_string()).unwrap();
+        // A SOCKS5 proxy without authentication that resolves the host names it is sent.
+        thread::spawn(move || {
+            for client in listener.incoming() {
+                let mut client = client.unwrap();
+                let mut greeting = [0; 3];
+                client.read_exact(&mut greeting).unwrap();
+                client.write_all(&[5, 0]).unwrap();
+                let mut head = [0; 5];
+                client.read_exact(&mut head).unwrap();
+                assert_eq!(head[..4], [5, 1, 0, 3]);
+                let mut host = vec![0; head[4].into()];
+                client.read_exact(&mut host).unwrap();
+                let mut port = [0; 2];
+                client.read_exact(&mut port).unwrap();
+                let host = String::from_utf8(host).unwrap();
+                let mut server =
+                    std::net::TcpStream::connect((host.as_str(), u16::from_be_bytes(port)))
+                        .unwrap();
+                client.write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 0]).unwrap();
+                let mut client_reader = client.try_clone().unwrap();
+                let mut server_writer = server.try_clone().unwrap();
+                thread::spawn(move || io::copy(&mut client_reader, &mut server_writer));
+                thread::spawn(move || io::copy(&mut server, &mut client));
+            }
+        });
+
+        let url = server.url.replace("127.0.0.1", "localhost");
+        let tp = Builder::new()
+            .url(&url)
+            .unwrap()
+            .basic_auth("user".to_string(), Some("pass".to_string()))
+            .proxy(proxy)
+            .build();
+        let client = Client::with_transport(tp);
+        assert!(client.call::<bool>("getblockcount", None).unwrap());
+        assert_eq!(server.requests(), 1);
+    }
 }
@@ -23,7 +23,7 @@ use crate::client::Transport;
 use crate::http::DEFAULT_PORT;
 #[cfg(feature = "proxy")]
 use crate::http::DEFAULT_PROXY_PORT;
-use crate::{Request, Response};
+use crate::{Notification, Request, Response};
 
 /// Absolute maximum content length allowed before cutting off the response.
 const FINAL_RESP_ALLOC: u64 = 1024 * 1024 * 1024;
@@ -239,6 +239,12 @@ impl SimpleHttpTransport {
             // There is no body in a 401 response, so don't try to read it
             return Err(Error::HttpErrorCode(response_code));
         }
+        if response_code == 204 {
+            // There is no body in a 204 response either, it only answers notifications and these
+            // ignore the body, see `send_notification`.
+            return serde_json::from_value(serde_json::Value::Null)
+                .map_err(|_| Error::HttpErrorCode(response_code));
+        }
 
         // Read up to `content_length` bytes. Note that if there is no content-length
         // header, we will assume an effectively infinite content length, i.e. we will
@@ -345,6 +351,12 @@ impl Transport for SimpleHttpTransport {
         Ok(self.request(reqs)?)
     }
 
+    fn send_notification(&self, notification: Notification) -> Result<(), crate::Error> {
+        // Servers speaking JSON-RPC 1.0 answer with a response, which is read and ignored.
+        self.request::<serde::de::IgnoredAny>(notification)?;
+        Ok(())
+    }
+
     fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "http://{}:{}{}", self.addr.ip(), self.addr.port(), self.path)
     }
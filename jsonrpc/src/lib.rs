@@ -20,9 +20,17 @@ pub extern crate base64;
 #[cfg(feature = "bitreq")]
 pub extern crate bitreq;
 
+pub mod cassette;
 pub mod client;
 pub mod error;
+pub mod failover;
 pub mod http;
+#[cfg(feature = "mock")]
+pub mod mock;
+#[cfg(any(feature = "simple_tcp", all(feature = "simple_uds", not(windows))))]
+mod multiplex;
+#[cfg(feature = "server")]
+pub mod server;
 
 #[cfg(feature = "bitreq_http")]
 pub use http::bitreq_http;
@@ -38,7 +46,7 @@ pub mod simple_uds;
 use serde::{Deserialize, Serialize};
 use serde_json::value::RawValue;
 
-pub use crate::client::{Client, Transport};
+pub use crate::client::{Client, Protocol, Transport};
 pub use crate::error::Error;
 
 /// Shorthand method to convert an argument into a boxed [`serde_json::value::RawValue`].
@@ -75,6 +83,17 @@ pub struct Request<'a> {
     pub jsonrpc: Option<&'a str>,
 }
 
+/// A JSONRPC notification object, a request without an `id` that gets no response.
+#[derive(Debug, Clone, Serialize)]
+pub struct Notification<'a> {
+    /// The name of the RPC call.
+    pub method: &'a str,
+    /// Parameters to the RPC call.
+    pub params: Option<&'a RawValue>,
+    /// jsonrpc field, "2.0" for servers to treat the request as a notification.
+    pub jsonrpc: Option<&'a str>,
+}
+
 /// A JSONRPC response object.
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Response {
@@ -0,0 +1,522 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! An in-process server that speaks the JSON-RPC dialect of Bitcoin Core, for use in tests.
+//!
+//! The [`MockServer`] listens on a local port and answers requests the way `bitcoind` does:
+//! basic authentication, wallet endpoints at `/wallet/<name>`, batch requests and the HTTP status
+//! codes Core uses for errors. Responses come from handlers registered per method.
+//!
+//! ```
+//! # fn main() -> std::io::Result<()> {
+//! use jsonrpc::mock::MockServer;
+//!
+//! let server = MockServer::start()?.with_auth("user", "pass");
+//! server.set_response("getblockcount", &101);
+//! server.set_handler("getbalance", |req| match req.wallet.as_deref() {
+//!     Some("alice") => Ok(0.5.into()),
+//!     _ => Err(jsonrpc::mock::wallet_not_found()),
+//! });
+//! // Point a client at `server.url()`.
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::error::{standard_error, RpcError, StandardError};
+use crate::server::error_object;
+use crate::server::http::{serve_connection, HttpReply, HttpRequest};
+
+/// Core's `RPC_WALLET_NOT_FOUND`.
+const RPC_WALLET_NOT_FOUND: i32 = -18;
+
+/// A handler that answers calls to a method.
+type Handler = dyn Fn(&MockRequest) -> Result<Value, RpcError> + Send + Sync;
+
+/// A JSON-RPC call received by a [`MockServer`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct MockRequest {
+    /// The name of the method.
+    pub method: String,
+    /// The parameters, an array or an object. Missing parameters are an empty array.
+    pub params: Value,
+    /// The wallet the request was sent to, `None` if not sent to a wallet endpoint.
+    pub wallet: Option<String>,
+}
+
+/// A JSON-RPC server that mimics `bitcoind`, see the [module docs](self).
+///
+/// The server runs on a background thread until it is dropped.
+#[derive(Debug)]
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<State>,
+    shutdown: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// The state shared with the threads serving the connections.
+#[derive(Default)]
+struct State {
+    /// The expected value of the `Authorization` header, `None` to accept any request.
+    authorization: Mutex<Option<String>>,
+    /// Whether to ignore `"jsonrpc": "2.0"` like Core before v28.
+    legacy: AtomicBool,
+    handlers: Mutex<HashMap<String, Arc<Handler>>>,
+    requests: Mutex<Vec<MockRequest>>,
+}
+
+impl std::fmt::Debug for State {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut methods =
+            self.handlers.lock().expect("poisoned mutex").keys().cloned().collect::<Vec<_>>();
+        methods.sort();
+        f.debug_struct("State")
+            .field("legacy", &self.legacy.load(Ordering::Relaxed))
+            .field("methods", &methods)
+            .finish_non_exhaustive()
+    }
+}
+
+impl MockServer {
+    /// Starts a server listening on an unused port of `127.0.0.1`.
+    ///
+    /// The server accepts requests without credentials, see [`MockServer::with_auth`], and answers
+    /// all calls with "Method not found" until handlers are registered.
+    pub fn start() -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(State::default());
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let thread = {
+            let state = Arc::clone(&state);
+            let shutdown = Arc::clone(&shutdown);
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if shutdown.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    if let Ok(stream) = stream {
+                        let state = Arc::clone(&state);
+                        thread::spawn(move || {
+                            let _ = serve_connection(stream, |request| state.reply(request));
+                        });
+                    }
+                }
+            })
+        };
+
+        Ok(MockServer { addr, state, shutdown, thread: Some(thread) })
+    }
+
+    /// Rejects requests that don't authenticate with `user` and `pass`, like `-rpcauth`.
+    pub fn with_auth(self, user: &str, pass: &str) -> Self {
+        let auth = format!("Basic {}", BASE64.encode(format!("{}:{}", user, pass)));
+        *self.state.authorization.lock().expect("poisoned mutex") = Some(auth);
+        self
+    }
+
+    /// Ignores `"jsonrpc": "2.0"` in requests, like Core before v28.
+    ///
+    /// All errors are then returned with an HTTP error code and responses always have a `result`
+    /// and an `error` field.
+    pub fn with_legacy_errors(self) -> Self {
+        self.state.legacy.store(true, Ordering::SeqCst);
+        self
+    }
+
+    /// Answers calls to `method` with `handler`, replacing any previous handler.
+    pub fn set_handler<F>(&self, method: &str, handler: F)
+    where
+        F: Fn(&MockRequest) -> Result<Value, RpcError> + Send + Sync + 'static,
+    {
+        self.state
+            .handlers
+            .lock()
+            .expect("poisoned mutex")
+            .insert(method.to_owned(), Arc::new(handler));
+    }
+
+    /// Answers all calls to `method` with `response`, whatever the parameters.
+    ///
+    /// # Panics
+    ///
+    /// If `response` cannot be serialized to JSON.
+    pub fn set_response<T: Serialize>(&self, method: &str, response: &T) {
+        let response = serde_json::to_value(response).expect("failed to serialize response");
+        self.set_handler(method, move |_| Ok(response.clone()));
+    }
+
+    /// Answers all calls to `method` with an error.
+    pub fn set_error(&self, method: &str, code: i32, message: &str) {
+        let error = RpcError { code, message: message.to_owned(), data: None };
+        self.set_handler(method, move |_| Err(error.clone()));
+    }
+
+    /// Returns the address the server listens on.
+    pub fn addr(&self) -> SocketAddr { self.addr }
+
+    /// Returns the URL of the server e.g., `http://127.0.0.1:43521`.
+    pub fn url(&self) -> String { format!("http://{}", self.addr) }
+
+    /// Returns the calls received so far, in order.
+    ///
+    /// Requests rejected before their method was known, for instance because of bad credentials,
+    /// are not included.
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.state.requests.lock().expect("poisoned mutex").clone()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        // Wake up the accept loop so it sees the flag.
+        let _ = TcpStream::connect(self.addr);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Returns the error Core returns for calls to a wallet that is not loaded.
+pub fn wallet_not_found() -> RpcError {
+    RpcError {
+        code: RPC_WALLET_NOT_FOUND,
+        message: "Requested wallet does not exist or is not loaded".to_owned(),
+        data: None,
+    }
+}
+
+/// The answer to a single call.
+struct CallReply {
+    /// The JSON-RPC response, `None` for a notification.
+    response: Option<Value>,
+    /// The HTTP status Core uses when this is the only call of the request.
+    status: u16,
+}
+
+impl State {
+    /// Answers an HTTP request the way `bitcoind` does.
+    fn reply(&self, request: &HttpRequest) -> HttpReply {
+        let authorization = self.authorization.lock().expect("poisoned mutex").clone();
+        if authorization.is_some() && request.authorization != authorization {
+            return HttpReply::unauthorized();
+        }
+        if request.method != "POST" {
+            return HttpReply::new(405, b"JSONRPC server handles only POST requests".to_vec());
+        }
+        let wallet = match request.path.as_str() {
+            "/" | "" => None,
+            path => match path.strip_prefix("/wallet/") {
+                Some(name) => Some(percent_decode(name)),
+                None => return HttpReply::new(404, vec![]),
+            },
+        };
+
+        let body: Value = match serde_json::from_slice(&request.body) {
+            Ok(body) => body,
+            Err(_) => return legacy_error(standard_error(StandardError::ParseError, None)),
+        };
+        match body {
+            Value::Object(_) => {
+                let reply = self.call(body, &wallet);
+                match reply.response {
+                    Some(response) => HttpReply::json(reply.status, &response),
+                    None => HttpReply::new(204, vec![]),
+                }
+            }
+            Value::Array(calls) => {
+                let responses = calls
+                    .into_iter()
+                    .filter_map(|call| self.call(call, &wallet).response)
+                    .collect::<Vec<_>>();
+                if responses.is_empty() {
+                    HttpReply::new(204, vec![])
+                } else {
+                    HttpReply::json(200, &Value::Array(responses))
+                }
+            }
+            _ => legacy_error(RpcError {
+                code: -32700,
+                message: "Top-level object parse error".to_owned(),
+                data: None,
+            }),
+        }
+    }
+
+    /// Answers a single call of a request.
+    fn call(&self, call: Value, wallet: &Option<String>) -> CallReply {
+        let mut call = match call {
+            Value::Object(call) => call,
+            _ =>
+                return reply(
+                    false,
+                    Value::Null,
+                    Err(rpc_error(StandardError::InvalidRequest, "Invalid Request object")),
+                ),
+        };
+        let v2 = !self.legacy.load(Ordering::SeqCst)
+            && call.get("jsonrpc").and_then(Value::as_str) == Some("2.0");
+        let id = call.remove("id");
+        let notification = v2 && id.is_none();
+        let id = id.unwrap_or(Value::Null);
+
+        let method = match call.remove("method") {
+            Some(Value::String(method)) => method,
+            Some(_) =>
+                return reply(
+                    v2,
+                    id,
+                    Err(rpc_error(StandardError::InvalidRequest, "Method must be a string")),
+                ),
+            None =>
+                return reply(
+                    v2,
+                    id,
+                    Err(rpc_error(StandardError::InvalidRequest, "Missing method")),
+                ),
+        };
+        let params = match call.remove("params") {
+            None | Some(Value::Null) => Value::Array(vec![]),
+            Some(params @ (Value::Array(_) | Value::Object(_))) => params,
+            Some(_) =>
+                return reply(
+                    v2,
+                    id,
+                    Err(rpc_error(
+                        StandardError::InvalidRequest,
+                        "Params must be an array or object",
+                    )),
+                ),
+        };
+
+        let request = MockRequest { method, params, wallet: wallet.clone() };
+        self.requests.lock().expect("poisoned mutex").push(request.clone());
+        let handler = self.handlers.lock().expect("poisoned mutex").get(&request.method).cloned();
+        let result = match handler {
+            Some(handler) => handler(&request),
+            None => Err(rpc_error(StandardError::MethodNotFound, "Method not found")),
+        };
+
+        if notification {
+            CallReply { response: None, status: 204 }
+        } else {
+            reply(v2, id, result)
+        }
+    }
+}
+
+/// Builds the response to a call, and the HTTP status Core would use for it.
+fn reply(v2: bool, id: Value, result: Result<Value, RpcError>) -> CallReply {
+    let result = result.map_err(|e| (e.code, error_object(e)));
+    if v2 {
+        let response = match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "result": result, "id": id }),
+            Err((_, error)) => json!({ "jsonrpc": "2.0", "error": error, "id": id }),
+        };
+        return CallReply { response: Some(response), status: 200 };
+    }
+    match result {
+        Ok(result) => CallReply {
+            response: Some(json!({ "result": result, "error": null, "id": id })),
+            status: 200,
+        },
+        Err((code, error)) => {
+            let status = match code {
+                -32600 => 400,
+                -32601 => 404,
+                _ => 500,
+            };
+            CallReply {
+                response: Some(json!({ "result": null, "error": error, "id": id })),
+                status,
+            }
+        }
+    }
+}
+
+/// Replies with an error for a request that could not be parsed.
+fn legacy_error(error: RpcError) -> HttpReply {
+    let reply = reply(false, Value::Null, Err(error));
+    HttpReply::json(reply.status, &reply.response.expect("not a notification"))
+}
+
+fn rpc_error(code: StandardError, message: &str) -> RpcError {
+    RpcError { message: message.to_owned(), ..standard_error(code, None) }
+}
+
+/// Decodes the `%XX` escapes in a path segment.
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok());
+        match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+            Some(byte) if bytes[i] == b'%' => {
+                decoded.push(byte);
+                i += 3;
+            }
+            _ => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use super::*;
+
+    /// Sends `body` to `path` and returns the status code and the body of the reply.
+    fn post(server: &MockServer, path: &str, auth: Option<&str>, body: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(server.addr()).unwrap();
+        let auth = auth
+            .map(|a| format!("Authorization: Basic {}\r\n", BASE64.encode(a)))
+            .unwrap_or_default();
+        write!(
+            stream,
+            "POST {} HTTP/1.1\r\nConnection: close\r\n{}Content-Length: {}\r\n\r\n{}",
+            path,
+            auth,
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).unwrap();
+        let status = reply[9..12].parse().unwrap();
+        let body = reply.split_once("\r\n\r\n").unwrap().1.to_owned();
+        (status, body)
+    }
+
+    fn json(body: &str) -> Value { serde_json::from_str(body).unwrap() }
+
+    #[test]
+    fn requires_credentials() {
+        let server = MockServer::start().unwrap().with_auth("user", "pass");
+        server.set_response("getblockcount", &101);
+        let req = r#"{"method":"getblockcount","id":1}"#;
+
+        assert_eq!(post(&server, "/", None, req), (401, String::new()));
+        assert_eq!(post(&server, "/", Some("user:wrong"), req), (401, String::new()));
+        let (status, body) = post(&server, "/", Some("user:pass"), req);
+        assert_eq!(status, 200);
+        assert_eq!(json(&body), json!({ "result": 101, "error": null, "id": 1 }));
+    }
+
+    #[test]
+    fn routes_wallet_requests() {
+        let server = MockServer::start().unwrap();
+        server.set_handler("getwalletinfo", |req| Ok(json!({ "walletname": req.wallet })));
+        let req = r#"{"method":"getwalletinfo","params":[],"id":1}"#;
+
+        let (_, body) = post(&server, "/wallet/my%20wallet", None, req);
+        assert_eq!(json(&body)["result"], json!({ "walletname": "my wallet" }));
+        let (_, body) = post(&server, "/", None, req);
+        assert_eq!(json(&body)["result"], json!({ "walletname": null }));
+        assert_eq!(post(&server, "/foo", None, req).0, 404);
+
+        let wallets = server.requests().into_iter().map(|r| r.wallet).collect::<Vec<_>>();
+        assert_eq!(wallets, [Some("my wallet".to_owned()), None]);
+    }
+
+    #[test]
+    fn legacy_errors_use_http_status() {
+        let server = MockServer::start().unwrap();
+        server.set_error("getblock", -5, "Block not found");
+
+        let (status, body) = post(&server, "/", None, r#"{"method":"getblock","id":1}"#);
+        assert_eq!(status, 500);
+        assert_eq!(json(&body)["error"]["code"], -5);
+        assert_eq!(json(&body)["result"], Value::Null);
+
+        let (status, body) = post(&server, "/", None, r#"{"method":"nosuchmethod","id":1}"#);
+        assert_eq!(status, 404);
+        assert_eq!(json(&body)["error"]["code"], -32601);
+
+        assert_eq!(post(&server, "/", None, r#"{"params":[],"id":1}"#).0, 400);
+        let (status, body) = post(&server, "/", None, "{");
+        assert_eq!(status, 500);
+        assert_eq!(json(&body)["error"]["code"], -32700);
+    }
+
+    #[test]
+    fn json_rpc_2_errors_use_status_200() {
+        let server = MockServer::start().unwrap();
+        server.set_error("getblock", -5, "Block not found");
+        let req = r#"{"jsonrpc":"2.0","method":"getblock","id":1}"#;
+
+        let (status, body) = post(&server, "/", None, req);
+        assert_eq!(status, 200);
+        assert_eq!(
+            json(&body),
+            json!({ "jsonrpc": "2.0", "error": { "code": -5, "message": "Block not found" }, "id": 1 })
+        );
+
+        // Notifications get no response.
+        let (status, body) = post(&server, "/", None, r#"{"jsonrpc":"2.0","method":"getblock"}"#);
+        assert_eq!((status, body.as_str()), (204, ""));
+
+        let server = server.with_legacy_errors();
+        assert_eq!(post(&server, "/", None, req).0, 500);
+    }
+
+    #[test]
+    fn answers_batches() {
+        let server = MockServer::start().unwrap();
+        server.set_handler("echo", |req| Ok(req.params.clone()));
+        let batch = r#"[
+            {"method":"echo","params":[1],"id":1},
+            {"method":"nosuchmethod","id":2},
+            {"jsonrpc":"2.0","method":"echo","params":[3]}
+        ]"#;
+
+        let (status, body) = post(&server, "/", None, batch);
+        assert_eq!(status, 200);
+        let body = json(&body);
+        assert_eq!(body.as_array().unwrap().len(), 2);
+        assert_eq!(body[0]["result"], json!([1]));
+        assert_eq!(body[1]["error"]["code"], -32601);
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[cfg(all(feature = "simple_http", not(feature = "proxy")))]
+    #[test]
+    fn serves_simple_http_client() {
+        use crate::http::simple_http::SimpleHttpTransport;
+
+        let server = MockServer::start().unwrap().with_auth("user", "pass");
+        server.set_response("getblockcount", &101);
+        let transport = SimpleHttpTransport::builder()
+            .url(&server.url())
+            .unwrap()
+            .auth("user", Some("pass"))
+            .build();
+        let client = crate::Client::with_transport(transport);
+
+        // The connection is kept alive between calls.
+        for _ in 0..2 {
+            let count: u64 = client.call("getblockcount", None).unwrap();
+            assert_eq!(count, 101);
+        }
+        let err = client.call::<u64>("getblockhash", None).unwrap_err();
+        assert!(matches!(err, crate::Error::Rpc(RpcError { code: -32601, .. })));
+    }
+}
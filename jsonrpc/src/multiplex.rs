@@ -0,0 +1,358 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Sending requests from several threads over one persistent connection.
+//!
+//! Requests are written to the connection as they come, a thread per connection reads the
+//! responses and hands each to the request with the same `id`. Responses may arrive in any order,
+//! back to back or separated by whitespace e.g., one per line.
+//!
+//! A response without an `id`, or with a `null` one e.g., a "Parse error", goes to the only call
+//! in flight. If several calls are in flight it can't be matched, so the connection is closed and
+//! they all fail. Some servers answer notifications too, with a `null` id since they have none: a
+//! response without an `id` read after a notification was sent is taken as its answer and dropped.
+//!
+//! The transports to the same address share a [`Multiplexer`], kept in a [`Registry`].
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::io::{self, BufReader, Read, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex, OnceLock};
+use std::time::Duration;
+use std::{fmt, thread};
+
+use serde_json::Value;
+
+use crate::{Notification, Request};
+
+/// A connection that can be read and written from different threads.
+pub(crate) trait Stream: Read + Write + Send + Sized + 'static {
+    /// Returns a handle to the same connection.
+    fn try_clone(&self) -> io::Result<Self>;
+
+    /// Closes both directions of the connection.
+    fn shutdown(&self);
+}
+
+#[cfg(feature = "simple_tcp")]
+impl Stream for std::net::TcpStream {
+    fn try_clone(&self) -> io::Result<Self> { std::net::TcpStream::try_clone(self) }
+
+    fn shutdown(&self) { let _ = std::net::TcpStream::shutdown(self, std::net::Shutdown::Both); }
+}
+
+#[cfg(all(feature = "simple_uds", not(windows)))]
+impl Stream for std::os::unix::net::UnixStream {
+    fn try_clone(&self) -> io::Result<Self> { std::os::unix::net::UnixStream::try_clone(self) }
+
+    fn shutdown(&self) {
+        let _ = std::os::unix::net::UnixStream::shutdown(self, std::net::Shutdown::Both);
+    }
+}
+
+/// An error sending a request over a [`Multiplexer`].
+#[derive(Debug)]
+pub(crate) enum Error {
+    /// Connecting, writing the request or reading the response failed.
+    Io(io::Error),
+    /// No response arrived before the timeout.
+    Timeout,
+    /// The request could not be serialized.
+    Json(serde_json::Error),
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self { Error::Io(e) }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self { Error::Json(e) }
+}
+
+/// Sends requests over a persistent connection, opening a new one if it is closed.
+pub(crate) struct Multiplexer<S: Stream> {
+    connection: Mutex<Option<Arc<Connection<S>>>>,
+    /// Numbers the calls, and the ids that replace those already in flight.
+    next: AtomicU64,
+}
+
+/// A connection and the requests waiting for a response on it.
+struct Connection<S> {
+    writer: Mutex<S>,
+    /// The requests waiting for a response by `id`, `None` once the connection is closed.
+    pending: Mutex<Option<HashMap<String, Waiter>>>,
+    /// Whether a notification was sent since the last response without an `id`.
+    notified: AtomicBool,
+}
+
+/// A call waiting for the response to one of its requests.
+struct Waiter {
+    call: u64,
+    sender: mpsc::Sender<Value>,
+}
+
+impl<S: Stream> Multiplexer<S> {
+    pub(crate) fn new() -> Self {
+        Multiplexer { connection: Mutex::new(None), next: AtomicU64::new(0) }
+    }
+
+    /// Sends `reqs`, as a batch if `batch` is set, and waits up to `timeout` for the response.
+    ///
+    /// The response holds the ids of `reqs`, even if they were replaced on the connection because
+    /// requests with the same ids were in flight.
+    pub(crate) fn request<C>(
+        &self,
+        connect: C,
+        timeout: Option<Duration>,
+        reqs: &[Request],
+        batch: bool,
+    ) -> Result<Value, Error>
+    where
+        C: Fn() -> io::Result<S>,
+    {
+        let call = self.next.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = mpsc::channel();
+        let mut retry = true;
+        let (connection, ids) = loop {
+            let (connection, reused) = self.connection(&connect)?;
+            let mut pending = connection.pending.lock().expect("poisoned mutex");
+            // The connection may have closed since it was handed out.
+            let waiters = match pending.as_mut() {
+                Some(waiters) => waiters,
+                None => continue,
+            };
+            let mut ids = Vec::with_capacity(reqs.len());
+            for req in reqs {
+                let mut id = req.id.clone();
+                while waiters.contains_key(&id.to_string()) {
+                    id = Value::from(format!("mux-{}", self.next.fetch_add(1, Ordering::Relaxed)));
+                }
+                waiters.insert(id.to_string(), Waiter { call, sender: sender.clone() });
+                ids.push(id);
+            }
+            drop(pending);
+
+            let wire = reqs
+                .iter()
+                .zip(&ids)
+                .map(|(req, id)| Request { id: id.clone(), ..req.clone() })
+                .collect::<Vec<_>>();
+            let written = if batch { connection.write(&wire) } else { connection.write(&wire[0]) };
+            match written {
+                Ok(()) => break (connection, ids),
+                Err(e) => {
+                    connection.unregister(call, &ids);
+                    connection.close();
+                    // The server may have closed an idle connection before we noticed.
+                    if !(reused && retry) {
+                        return Err(e);
+                    }
+                    retry = false;
+                }
+            }
+        };
+        // Only the waiters hold a sender, so that the call fails once the connection closes.
+        drop(sender);
+
+        let response = match timeout {
+            Some(timeout) => receiver.recv_timeout(timeout).map_err(|e| match e {
+                mpsc::RecvTimeoutError::Timeout => Error::Timeout,
+                mpsc::RecvTimeoutError::Disconnected => closed(),
+            }),
+            None => receiver.recv().map_err(|_| closed()),
+        };
+        // Requests of a batch missing from the response are still registered.
+        connection.unregister(call, &ids);
+        let mut response = response?;
+
+        // Put back the ids that were replaced.
+        let restore = |response: &mut Value| {
+            if let Some(id) = response.get_mut("id") {
+                if let Some(i) = ids.iter().position(|wire| wire == id) {
+                    *id = reqs[i].id.clone();
+                }
+            }
+        };
+        match response {
+            Value::Array(ref mut responses) => responses.iter_mut().for_each(restore),
+            ref mut response => restore(response),
+        }
+        Ok(response)
+    }
+
+    /// Sends `notification` without waiting for anything.
+    pub(crate) fn notify<C>(&self, connect: C, notification: &Notification) -> Result<(), Error>
+    where
+        C: Fn() -> io::Result<S>,
+    {
+        let (connection, reused) = self.connection(&connect)?;
+        match connection.notify(notification) {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                connection.close();
+                if !reused {
+                    return Err(e);
+                }
+                let (connection, _) = self.connection(&connect)?;
+                connection.notify(notification).map_err(|e| {
+                    connection.close();
+                    e
+                })
+            }
+        }
+    }
+
+    /// Returns the open connection, connecting if there is none, and whether it was open.
+    fn connection<C>(&self, connect: &C) -> Result<(Arc<Connection<S>>, bool), Error>
+    where
+        C: Fn() -> io::Result<S>,
+    {
+        let mut connection = self.connection.lock().expect("poisoned mutex");
+        if let Some(ref open) = *connection {
+            if open.pending.lock().expect("poisoned mutex").is_some() {
+                return Ok((Arc::clone(open), true));
+            }
+        }
+
+        let stream = connect()?;
+        let reader = stream.try_clone()?;
+        let open = Arc::new(Connection {
+            writer: Mutex::new(stream),
+            pending: Mutex::new(Some(HashMap::new())),
+            notified: AtomicBool::new(false),
+        });
+        let read = Arc::clone(&open);
+        thread::spawn(move || read.read_responses(reader));
+        *connection = Some(Arc::clone(&open));
+        Ok((open, false))
+    }
+}
+
+impl<S: Stream> Drop for Multiplexer<S> {
+    fn drop(&mut self) {
+        // Ends the thread reading the responses, it holds the other reference to the connection.
+        if let Ok(Some(connection)) = self.connection.get_mut().map(Option::take) {
+            connection.close();
+        }
+    }
+}
+
+impl<S: Stream> fmt::Debug for Multiplexer<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let connected = self.connection.lock().map(|c| c.is_some()).unwrap_or(false);
+        f.debug_struct("Multiplexer").field("connected", &connected).finish_non_exhaustive()
+    }
+}
+
+impl<S: Stream> Connection<S> {
+    /// Writes `message` followed by a newline.
+    fn write<T: serde::Serialize>(&self, message: &T) -> Result<(), Error> {
+        let mut bytes = serde_json::to_vec(message)?;
+        bytes.push(b'\n');
+        let mut writer = self.writer.lock().expect("poisoned mutex");
+        writer.write_all(&bytes)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Writes `notification`, the server may answer it with a response without an `id`.
+    fn notify(&self, notification: &Notification) -> Result<(), Error> {
+        // Set first, the answer may be read before the write returns.
+        self.notified.store(true, Ordering::SeqCst);
+        self.write(notification)
+    }
+
+    /// Stops waiting for responses to the requests of `call` with `ids`.
+    fn unregister(&self, call: u64, ids: &[Value]) {
+        if let Some(ref mut waiters) = *self.pending.lock().expect("poisoned mutex") {
+            for id in ids {
+                let id = id.to_string();
+                // The id may have been taken by another call once the response arrived.
+                if waiters.get(&id).is_some_and(|w| w.call == call) {
+                    waiters.remove(&id);
+                }
+            }
+        }
+    }
+
+    /// Closes the connection, the requests waiting for a response fail.
+    fn close(&self) {
+        *self.pending.lock().expect("poisoned mutex") = None;
+        self.writer.lock().expect("poisoned mutex").shutdown();
+    }
+
+    /// Hands the responses read from `reader` to the waiting requests until the connection closes.
+    ///
+    /// Responses that no request waits for are dropped, e.g. those of calls that timed out.
+    fn read_responses(&self, reader: S) {
+        let responses = serde_json::Deserializer::from_reader(BufReader::new(reader));
+        for response in responses.into_iter::<Value>() {
+            let response = match response {
+                Ok(response) => response,
+                Err(_) => break,
+            };
+            // A batch response goes to the call that sent its first request.
+            let id = match response {
+                Value::Array(ref responses) => responses.first().and_then(|r| r.get("id")),
+                ref response => response.get("id"),
+            };
+            let mut pending = self.pending.lock().expect("poisoned mutex");
+            let waiters = match pending.as_mut() {
+                Some(waiters) => waiters,
+                None => return,
+            };
+            match id {
+                Some(id) if !id.is_null() =>
+                    if let Some(waiter) = waiters.remove(&id.to_string()) {
+                        let _ = waiter.sender.send(response);
+                    },
+                // It may answer a notification sent before or after the request of the call.
+                _ if self.notified.swap(false, Ordering::SeqCst) => {}
+                // Only the call in flight can have sent the request being answered.
+                _ if is_single_call(waiters) =>
+                    if let Some((_, waiter)) = waiters.drain().next() {
+                        let _ = waiter.sender.send(response);
+                    },
+                _ if waiters.is_empty() => {}
+                _ => {
+                    drop(pending);
+                    break;
+                }
+            }
+        }
+        self.close();
+    }
+}
+
+/// The [`Multiplexer`]s of the transports by address, created on first use.
+///
+/// They are kept for the lifetime of the process, their connection is opened again once the server
+/// closes it.
+pub(crate) struct Registry<K, S: Stream> {
+    multiplexers: OnceLock<Mutex<HashMap<K, Arc<Multiplexer<S>>>>>,
+}
+
+impl<K: Clone + Eq + Hash, S: Stream> Registry<K, S> {
+    pub(crate) const fn new() -> Self { Registry { multiplexers: OnceLock::new() } }
+
+    /// Returns the [`Multiplexer`] of the transports to `addr`.
+    pub(crate) fn get(&self, addr: &K) -> Arc<Multiplexer<S>> {
+        let mut multiplexers =
+            self.multiplexers.get_or_init(Default::default).lock().expect("poisoned mutex");
+        Arc::clone(multiplexers.entry(addr.clone()).or_insert_with(|| Arc::new(Multiplexer::new())))
+    }
+}
+
+/// Returns `true` if the requests waiting for a response were all sent by one call.
+fn is_single_call(waiters: &HashMap<String, Waiter>) -> bool {
+    let mut calls = waiters.values().map(|waiter| waiter.call);
+    match calls.next() {
+        Some(call) => calls.all(|other| other == call),
+        None => false,
+    }
+}
+
+/// The error of requests whose connection closed before the response arrived.
+fn closed() -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::ConnectionAborted, "connection closed"))
+}
@@ -0,0 +1,263 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Serving JSON-RPC over HTTP, for clients using the `simple_http` or `bitreq_http` transports.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde_json::Value;
+
+use crate::server::Server;
+
+/// Maximum size of a request body.
+const MAX_BODY_SIZE: usize = 32 * 1024 * 1024;
+
+/// Serves a [`Server`] over HTTP.
+///
+/// Requests are POSTed to any path, connections are kept alive unless the client asks otherwise.
+/// Requests holding only notifications are answered with `204 No Content`.
+#[derive(Clone, Debug)]
+pub struct HttpServer {
+    server: Arc<Server>,
+    /// The expected value of the `Authorization` header, `None` to accept any request.
+    authorization: Option<String>,
+}
+
+impl HttpServer {
+    /// Creates an HTTP front end for `server` that accepts requests without credentials.
+    pub fn new<S: Into<Arc<Server>>>(server: S) -> Self {
+        HttpServer { server: server.into(), authorization: None }
+    }
+
+    /// Rejects requests that don't authenticate with `user` and `pass` using basic auth.
+    pub fn with_auth(mut self, user: &str, pass: &str) -> Self {
+        self.authorization = Some(format!("Basic {}", BASE64.encode(format!("{}:{}", user, pass))));
+        self
+    }
+
+    /// Serves the connections accepted on `listener`, each on its own thread.
+    ///
+    /// Only returns if accepting a connection fails.
+    pub fn serve(&self, listener: TcpListener) -> io::Result<()> {
+        loop {
+            let (stream, _) = listener.accept()?;
+            let http = self.clone();
+            thread::spawn(move || {
+                let _ = http.serve_connection(stream);
+            });
+        }
+    }
+
+    /// Serves the requests sent on `stream` until the client closes it.
+    pub fn serve_connection(&self, stream: TcpStream) -> io::Result<()> {
+        serve_connection(stream, |request| self.reply(request))
+    }
+
+    fn reply(&self, request: &HttpRequest) -> HttpReply {
+        if self.authorization.is_some() && request.authorization != self.authorization {
+            return HttpReply::unauthorized();
+        }
+        if request.method != "POST" {
+            return HttpReply::new(405, b"JSON-RPC server handles only POST requests".to_vec());
+        }
+        match self.server.handle(&request.body) {
+            Some(body) => HttpReply::new(200, body).with_header("Content-Type", "application/json"),
+            None => HttpReply::new(204, vec![]),
+        }
+    }
+}
+
+/// An HTTP request as far as the servers care about it.
+pub(crate) struct HttpRequest {
+    pub(crate) method: String,
+    #[cfg_attr(not(feature = "mock"), allow(dead_code))]
+    pub(crate) path: String,
+    pub(crate) authorization: Option<String>,
+    pub(crate) body: Vec<u8>,
+    /// Whether to close the connection after replying.
+    close: bool,
+}
+
+/// An HTTP reply.
+pub(crate) struct HttpReply {
+    status: u16,
+    /// Extra headers, `Content-Length` is always sent.
+    headers: Vec<(&'static str, &'static str)>,
+    body: Vec<u8>,
+}
+
+impl HttpReply {
+    pub(crate) fn new(status: u16, body: Vec<u8>) -> Self {
+        HttpReply { status, headers: vec![], body }
+    }
+
+    #[cfg_attr(not(feature = "mock"), allow(dead_code))]
+    pub(crate) fn json(status: u16, body: &Value) -> Self {
+        Self::new(status, body.to_string().into_bytes())
+            .with_header("Content-Type", "application/json")
+    }
+
+    /// A `401 Unauthorized` reply asking for basic auth.
+    pub(crate) fn unauthorized() -> Self {
+        Self::new(401, vec![]).with_header("WWW-Authenticate", "Basic realm=\"jsonrpc\"")
+    }
+
+    pub(crate) fn with_header(mut self, name: &'static str, value: &'static str) -> Self {
+        self.headers.push((name, value));
+        self
+    }
+}
+
+/// Answers the requests sent on `stream` with `reply` until the client closes it or asks to.
+pub(crate) fn serve_connection<F>(stream: TcpStream, reply: F) -> io::Result<()>
+where
+    F: Fn(&HttpRequest) -> HttpReply,
+{
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    while let Some(request) = read_request(&mut reader)? {
+        write_reply(&mut writer, &reply(&request))?;
+        if request.close {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Reads an HTTP request, returns `None` if the client closed the connection.
+fn read_request<R: BufRead>(reader: &mut R) -> io::Result<Option<HttpRequest>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    let mut parts = line.split_whitespace();
+    let (method, path, version) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(path), Some(version)) => (method, path, version),
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "bad request line")),
+    };
+    let mut request = HttpRequest {
+        method: method.to_owned(),
+        path: path.to_owned(),
+        authorization: None,
+        body: vec![],
+        close: version == "HTTP/1.0",
+    };
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let (name, value) = match header.split_once(':') {
+            Some((name, value)) => (name.trim().to_ascii_lowercase(), value.trim()),
+            None => continue,
+        };
+        match name.as_str() {
+            "authorization" => request.authorization = Some(value.to_owned()),
+            "connection" if value.eq_ignore_ascii_case("close") => request.close = true,
+            "connection" if value.eq_ignore_ascii_case("keep-alive") => request.close = false,
+            "content-length" =>
+                content_length = value
+                    .parse()
+                    .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "bad content length"))?,
+            _ => {}
+        }
+    }
+    if content_length > MAX_BODY_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "request body too large"));
+    }
+
+    request.body = vec![0; content_length];
+    reader.read_exact(&mut request.body)?;
+    Ok(Some(request))
+}
+
+fn write_reply<W: Write>(writer: &mut W, reply: &HttpReply) -> io::Result<()> {
+    let reason = match reply.status {
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Internal Server Error",
+    };
+    let mut head = format!("HTTP/1.1 {} {}\r\n", reply.status, reason);
+    for (name, value) in &reply.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str(&format!("Content-Length: {}\r\n\r\n", reply.body.len()));
+    writer.write_all(head.as_bytes())?;
+    writer.write_all(&reply.body)?;
+    writer.flush()
+}
+
+#[cfg(all(
+    test,
+    any(all(feature = "simple_http", not(feature = "proxy")), feature = "bitreq_http")
+))]
+mod tests {
+    use super::*;
+    use crate::Client;
+
+    fn start(http: HttpServer) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        thread::spawn(move || http.serve(listener));
+        url
+    }
+
+    fn server() -> Server { Server::new().with_method("add", |(a, b): (u64, u64)| Ok(a + b)) }
+
+    #[cfg(all(feature = "simple_http", not(feature = "proxy")))]
+    #[test]
+    fn serves_simple_http() {
+        use crate::simple_http::{self, SimpleHttpTransport};
+
+        let url = start(HttpServer::new(server()).with_auth("user", "pass"));
+        let client = |pass| {
+            let transport =
+                SimpleHttpTransport::builder().url(&url).unwrap().auth("user", Some(pass)).build();
+            Client::with_transport(transport)
+        };
+
+        let client_ok = client("pass");
+        for i in 0..3 {
+            let args = [crate::arg(i), crate::arg(1)];
+            let sum: u64 = client_ok.call("add", Some(&*crate::arg(args))).unwrap();
+            assert_eq!(sum, i + 1);
+        }
+        let err = client("wrong").call::<u64>("add", None).unwrap_err();
+        match err {
+            crate::Error::Transport(e) => assert!(matches!(
+                e.downcast_ref::<simple_http::Error>(),
+                Some(simple_http::Error::HttpErrorCode(401))
+            )),
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[cfg(feature = "bitreq_http")]
+    #[test]
+    fn serves_bitreq_http() {
+        use crate::bitreq_http::BitreqHttpTransport;
+
+        let url = start(HttpServer::new(server()));
+        let transport = BitreqHttpTransport::builder().url(&url).unwrap().build();
+        let client = Client::with_transport(transport);
+
+        let args = crate::arg([2, 3]);
+        assert_eq!(client.call::<u64>("add", Some(&*args)).unwrap(), 5);
+        let err = client.call::<u64>("add", None).unwrap_err();
+        assert!(matches!(err, crate::Error::Rpc(ref e) if e.code == -32602));
+    }
+}
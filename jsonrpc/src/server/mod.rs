@@ -0,0 +1,379 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # JSON-RPC server
+//!
+//! A [`Server`] holds the methods it serves and answers single and batch requests, the front
+//! ends serve it over the same protocols the client transports speak:
+//!
+//! - [`http::HttpServer`] for `simple_http` and `bitreq_http`.
+//! - [`tcp::TcpServer`] for `simple_tcp`.
+//! - [`uds::UdsServer`] for `simple_uds`.
+//!
+//! ```no_run
+//! use jsonrpc::server::http::HttpServer;
+//! use jsonrpc::server::Server;
+//!
+//! let server = Server::new()
+//!     .with_method("add", |(a, b): (u64, u64)| Ok(a + b))
+//!     .with_method("ping", |()| Ok("pong"));
+//! let listener = std::net::TcpListener::bind("127.0.0.1:8332").unwrap();
+//! HttpServer::new(server).with_auth("user", "pass").serve(listener).unwrap();
+//! ```
+
+pub mod http;
+pub mod tcp;
+#[cfg(not(windows))]
+pub mod uds;
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+use std::{fmt, thread};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::value::RawValue;
+use serde_json::{json, Value};
+
+use crate::error::{standard_error, RpcError, StandardError};
+
+/// A method, taking the parameters of a call.
+type Method = dyn Fn(Value) -> Result<Value, RpcError> + Send + Sync;
+
+/// The handler of calls to methods that are not registered.
+type Fallback = dyn Fn(&str, Value) -> Result<Value, RpcError> + Send + Sync;
+
+/// A registry of methods that answers JSON-RPC 2.0 requests.
+#[derive(Default)]
+pub struct Server {
+    methods: HashMap<String, Box<Method>>,
+    fallback: Option<Box<Fallback>>,
+}
+
+impl Server {
+    /// Creates a server without any methods.
+    pub fn new() -> Self { Server::default() }
+
+    /// Serves `method` with `handler`, replacing any previous handler.
+    ///
+    /// The parameters of a call are deserialized into `P`, a tuple for positional parameters or
+    /// a struct for named ones. Use `()` for methods without parameters. Calls whose parameters
+    /// don't deserialize are answered with an "Invalid params" error.
+    pub fn with_method<P, R, F>(mut self, method: &str, handler: F) -> Self
+    where
+        P: DeserializeOwned,
+        R: Serialize,
+        F: Fn(P) -> Result<R, RpcError> + Send + Sync + 'static,
+    {
+        let method_fn = move |params: Value| {
+            let result = handler(parse_params(params)?)?;
+            serde_json::to_value(result)
+                .map_err(|e| internal_error(&format!("failed to serialize result: {}", e)))
+        };
+        self.methods.insert(method.to_owned(), Box::new(method_fn));
+        self
+    }
+
+    /// Answers calls to methods that are not registered with `handler` e.g., to forward them.
+    ///
+    /// Without a fallback such calls are answered with a "Method not found" error.
+    pub fn with_fallback<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(&str, Value) -> Result<Value, RpcError> + Send + Sync + 'static,
+    {
+        self.fallback = Some(Box::new(handler));
+        self
+    }
+
+    /// Returns `true` if `method` is registered.
+    pub fn has_method(&self, method: &str) -> bool { self.methods.contains_key(method) }
+
+    /// Answers a serialized request, single or batch.
+    ///
+    /// Returns `None` if there is nothing to send back because the request only holds
+    /// notifications.
+    pub fn handle(&self, request: &[u8]) -> Option<Vec<u8>> {
+        let response = match serde_json::from_slice(request) {
+            Ok(request) => self.handle_value(request)?,
+            Err(_) => error_response(Value::Null, standard_error(StandardError::ParseError, None)),
+        };
+        Some(response.to_string().into_bytes())
+    }
+
+    /// Answers a parsed request, single or batch.
+    ///
+    /// Returns `None` if there is nothing to send back because the request only holds
+    /// notifications.
+    pub fn handle_value(&self, request: Value) -> Option<Value> {
+        match request {
+            Value::Array(calls) if calls.is_empty() => Some(error_response(
+                Value::Null,
+                standard_error(StandardError::InvalidRequest, None),
+            )),
+            Value::Array(calls) => {
+                let responses =
+                    calls.into_iter().filter_map(|call| self.call(call)).collect::<Vec<_>>();
+                if responses.is_empty() {
+                    None
+                } else {
+                    Some(Value::Array(responses))
+                }
+            }
+            call => self.call(call),
+        }
+    }
+
+    /// Answers a single call, `None` for notifications.
+    fn call(&self, call: Value) -> Option<Value> {
+        let mut call = match call {
+            Value::Object(call) => call,
+            _ =>
+                return Some(error_response(
+                    Value::Null,
+                    standard_error(StandardError::InvalidRequest, None),
+                )),
+        };
+        let id = call.remove("id");
+        let method = match call.remove("method") {
+            Some(Value::String(method)) => method,
+            _ =>
+                return Some(error_response(
+                    id.unwrap_or(Value::Null),
+                    standard_error(StandardError::InvalidRequest, None),
+                )),
+        };
+        // `Request` sends `"params": null` rather than leaving it out.
+        let params = match call.remove("params") {
+            None | Some(Value::Null) => Value::Null,
+            Some(params @ (Value::Array(_) | Value::Object(_))) => params,
+            Some(_) =>
+                return Some(error_response(
+                    id.unwrap_or(Value::Null),
+                    standard_error(StandardError::InvalidRequest, None),
+                )),
+        };
+
+        let result = match (self.methods.get(&method), &self.fallback) {
+            (Some(method), _) => method(params),
+            (None, Some(fallback)) => fallback(&method, params),
+            (None, None) => Err(standard_error(StandardError::MethodNotFound, None)),
+        };
+        let id = id?;
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "result": result, "id": id }),
+            Err(error) => error_response(id, error),
+        })
+    }
+}
+
+impl fmt::Debug for Server {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut methods = self.methods.keys().collect::<Vec<_>>();
+        methods.sort();
+        f.debug_struct("Server")
+            .field("methods", &methods)
+            .field("fallback", &self.fallback.is_some())
+            .finish()
+    }
+}
+
+/// Deserializes the parameters of a call.
+///
+/// Missing parameters and an empty array are interchangeable so that `()` accepts both.
+fn parse_params<P: DeserializeOwned>(params: Value) -> Result<P, RpcError> {
+    let empty = match params {
+        Value::Null => Some(Value::Array(vec![])),
+        Value::Array(ref a) if a.is_empty() => Some(Value::Null),
+        _ => None,
+    };
+    serde_json::from_value(params).or_else(|e| match empty {
+        Some(empty) => serde_json::from_value(empty).map_err(|_| invalid_params(&e)),
+        None => Err(invalid_params(&e)),
+    })
+}
+
+fn invalid_params(e: &serde_json::Error) -> RpcError {
+    let data = RawValue::from_string(Value::String(e.to_string()).to_string()).ok();
+    standard_error(StandardError::InvalidParams, data)
+}
+
+fn internal_error(message: &str) -> RpcError {
+    let data = RawValue::from_string(Value::String(message.to_owned()).to_string()).ok();
+    standard_error(StandardError::InternalError, data)
+}
+
+fn error_response(id: Value, error: RpcError) -> Value {
+    json!({ "jsonrpc": "2.0", "error": error_object(error), "id": id })
+}
+
+/// Serializes `error`, leaving out `data` rather than sending `null`.
+pub(crate) fn error_object(error: RpcError) -> Value {
+    let mut object = json!({ "code": error.code, "message": error.message });
+    if let Some(data) = error.data {
+        object["data"] = serde_json::from_str(data.get()).unwrap_or_default();
+    }
+    object
+}
+
+/// Answers the requests read from `reader` until it is closed, as `simple_tcp` and `simple_uds`
+/// expect.
+///
+/// Requests are JSON values sent back to back, without any framing. A request that can't be
+/// parsed is answered with a "Parse error" and ends the connection.
+fn serve_stream<R: Read, W: Write>(server: &Server, reader: R, mut writer: W) -> io::Result<()> {
+    for request in serde_json::Deserializer::from_reader(reader).into_iter::<Value>() {
+        let response = match request {
+            Ok(request) => server.handle_value(request),
+            Err(e) if e.is_io() => return Err(e.into()),
+            Err(_) => {
+                let error = standard_error(StandardError::ParseError, None);
+                serde_json::to_writer(&mut writer, &error_response(Value::Null, error))?;
+                return writer.flush();
+            }
+        };
+        if let Some(response) = response {
+            serde_json::to_writer(&mut writer, &response)?;
+            writer.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// A listener of the stream front ends, [`tcp::TcpServer`] and [`uds::UdsServer`].
+trait Listener {
+    type Stream: Stream;
+
+    /// Accepts a new connection.
+    fn accept_stream(&self) -> io::Result<Self::Stream>;
+}
+
+/// A connection of the stream front ends.
+trait Stream: Read + Write + Send + Sized + 'static {
+    /// Returns a handle to the same connection, to read from while writing to the other one.
+    fn try_clone_stream(&self) -> io::Result<Self>;
+}
+
+/// Serves the connections accepted on `listener`, each on its own thread.
+///
+/// Only returns if accepting a connection fails.
+fn serve_listener<L: Listener>(server: &Arc<Server>, listener: L) -> io::Result<()> {
+    loop {
+        let stream = listener.accept_stream()?;
+        let server = server.clone();
+        thread::spawn(move || {
+            let _ = serve_connection(&server, stream);
+        });
+    }
+}
+
+/// Serves the requests sent on `stream` until the client closes it.
+fn serve_connection<S: Stream>(server: &Server, stream: S) -> io::Result<()> {
+    serve_stream(server, stream.try_clone_stream()?, stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    fn server() -> Server {
+        #[derive(Deserialize)]
+        struct Named {
+            a: u64,
+            b: Option<u64>,
+        }
+
+        Server::new()
+            .with_method("add", |(a, b): (u64, u64)| Ok(a + b))
+            .with_method("named", |p: Named| Ok(p.a + p.b.unwrap_or(0)))
+            .with_method("ping", |()| Ok("pong"))
+            .with_method("fail", |()| -> Result<(), _> {
+                Err(RpcError { code: -5, message: "Not found".to_owned(), data: None })
+            })
+    }
+
+    fn handle(server: &Server, request: &str) -> Option<Value> {
+        server.handle(request.as_bytes()).map(|r| serde_json::from_slice(&r).unwrap())
+    }
+
+    #[test]
+    fn answers_calls() {
+        let server = server();
+        let response = handle(&server, r#"{"jsonrpc":"2.0","method":"add","params":[1,2],"id":7}"#);
+        assert_eq!(response, Some(json!({ "jsonrpc": "2.0", "result": 3, "id": 7 })));
+
+        let response = handle(&server, r#"{"method":"named","params":{"a":1},"id":"x"}"#);
+        assert_eq!(response.unwrap()["result"], 1);
+
+        for params in ["", r#","params":[]"#] {
+            let request = format!(r#"{{"method":"ping"{},"id":1}}"#, params);
+            assert_eq!(handle(&server, &request).unwrap()["result"], "pong");
+        }
+    }
+
+    #[test]
+    fn answers_errors() {
+        let server = server();
+        let code = |request: &str| handle(&server, request).unwrap()["error"]["code"].clone();
+
+        let response = handle(&server, r#"{"method":"fail","id":1}"#).unwrap();
+        assert_eq!(
+            response,
+            json!({ "jsonrpc": "2.0", "error": { "code": -5, "message": "Not found" }, "id": 1 })
+        );
+        assert_eq!(code(r#"{"method":"add","params":["a"],"id":1}"#), -32602);
+        assert_eq!(code(r#"{"method":"nosuchmethod","id":1}"#), -32601);
+        assert_eq!(code(r#"{"method":1,"id":1}"#), -32600);
+        assert_eq!(code(r#"{"method":"ping","params":1,"id":1}"#), -32600);
+        assert_eq!(code("[]"), -32600);
+        assert_eq!(code("{"), -32700);
+    }
+
+    #[test]
+    fn answers_batches_and_notifications() {
+        let server = server();
+        assert_eq!(handle(&server, r#"{"method":"ping"}"#), None);
+        assert_eq!(handle(&server, r#"[{"method":"ping"},{"method":"fail"}]"#), None);
+
+        let response = handle(
+            &server,
+            r#"[{"method":"ping","id":1},{"method":"ping"},1,{"method":"add","params":[2,2],"id":2}]"#,
+        )
+        .unwrap();
+        let response = response.as_array().unwrap();
+        assert_eq!(response.len(), 3);
+        assert_eq!(response[0]["result"], "pong");
+        assert_eq!(response[1]["error"]["code"], -32600);
+        assert_eq!(response[2]["result"], 4);
+    }
+
+    #[test]
+    fn falls_back_for_unknown_methods() {
+        let server = server().with_fallback(|method, params| Ok(json!([method, params])));
+        let response = handle(&server, r#"{"method":"other","params":[1],"id":1}"#).unwrap();
+        assert_eq!(response["result"], json!(["other", [1]]));
+        assert_eq!(handle(&server, r#"{"method":"ping","id":1}"#).unwrap()["result"], "pong");
+    }
+
+    #[test]
+    fn serves_streams() {
+        let server = server();
+        let requests = br#"{"method":"ping","id":1}{"method":"ping"} [{"method":"add","params":[1,1],"id":2}]"#;
+        let mut out = vec![];
+        serve_stream(&server, &requests[..], &mut out).unwrap();
+        let responses = serde_json::Deserializer::from_slice(&out)
+            .into_iter::<Value>()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0]["result"], "pong");
+        assert_eq!(responses[1][0]["result"], 2);
+
+        let mut out = vec![];
+        serve_stream(&server, &b"{\"method\" 1}{\"method\":\"ping\",\"id\":1}"[..], &mut out)
+            .unwrap();
+        let response: Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(response["error"]["code"], -32700);
+    }
+}
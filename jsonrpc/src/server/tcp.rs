@@ -0,0 +1,71 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Serving JSON-RPC over raw TCP, for clients using the `simple_tcp` transport.
+
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+
+use crate::server::{serve_connection, serve_listener, Listener, Server, Stream};
+
+/// Serves a [`Server`] over raw TCP.
+///
+/// Requests and responses are JSON values sent back to back on the connection.
+#[derive(Clone, Debug)]
+pub struct TcpServer {
+    server: Arc<Server>,
+}
+
+impl TcpServer {
+    /// Creates a TCP front end for `server`.
+    pub fn new<S: Into<Arc<Server>>>(server: S) -> Self { TcpServer { server: server.into() } }
+
+    /// Serves the connections accepted on `listener`, each on its own thread.
+    ///
+    /// Only returns if accepting a connection fails.
+    pub fn serve(&self, listener: TcpListener) -> io::Result<()> {
+        serve_listener(&self.server, listener)
+    }
+
+    /// Serves the requests sent on `stream` until the client closes it.
+    pub fn serve_connection(&self, stream: TcpStream) -> io::Result<()> {
+        serve_connection(&self.server, stream)
+    }
+}
+
+impl Listener for TcpListener {
+    type Stream = TcpStream;
+
+    fn accept_stream(&self) -> io::Result<TcpStream> { self.accept().map(|(stream, _)| stream) }
+}
+
+impl Stream for TcpStream {
+    fn try_clone_stream(&self) -> io::Result<TcpStream> { self.try_clone() }
+}
+
+#[cfg(all(test, feature = "simple_tcp"))]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::simple_tcp::TcpTransport;
+    use crate::Client;
+
+    #[test]
+    fn serves_simple_tcp() {
+        let server = Server::new().with_method("add", |(a, b): (u64, u64)| Ok(a + b));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || TcpServer::new(server).serve(listener));
+
+        let client = Client::with_transport(TcpTransport::new(addr));
+        let args = crate::arg([2, 3]);
+        assert_eq!(client.call::<u64>("add", Some(&*args)).unwrap(), 5);
+
+        let args = crate::arg([1, 1]);
+        let batch = [client.build_request("add", Some(&*args)), client.build_request("sub", None)];
+        let responses = client.send_batch(&batch).unwrap();
+        assert_eq!(responses[0].as_ref().unwrap().result::<u64>().unwrap(), 2);
+        assert!(responses[1].as_ref().unwrap().result::<u64>().is_err());
+    }
+}
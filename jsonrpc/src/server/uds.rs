@@ -0,0 +1,70 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Serving JSON-RPC over a Unix Domain Socket, for clients using the `simple_uds` transport.
+
+use std::io;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::Arc;
+
+use crate::server::{serve_connection, serve_listener, Listener, Server, Stream};
+
+/// Serves a [`Server`] over a Unix Domain Socket.
+///
+/// Requests and responses are JSON values sent back to back on the connection.
+#[derive(Clone, Debug)]
+pub struct UdsServer {
+    server: Arc<Server>,
+}
+
+impl UdsServer {
+    /// Creates a UDS front end for `server`.
+    pub fn new<S: Into<Arc<Server>>>(server: S) -> Self { UdsServer { server: server.into() } }
+
+    /// Serves the connections accepted on `listener`, each on its own thread.
+    ///
+    /// Only returns if accepting a connection fails.
+    pub fn serve(&self, listener: UnixListener) -> io::Result<()> {
+        serve_listener(&self.server, listener)
+    }
+
+    /// Serves the requests sent on `stream` until the client closes it.
+    pub fn serve_connection(&self, stream: UnixStream) -> io::Result<()> {
+        serve_connection(&self.server, stream)
+    }
+}
+
+impl Listener for UnixListener {
+    type Stream = UnixStream;
+
+    fn accept_stream(&self) -> io::Result<UnixStream> { self.accept().map(|(stream, _)| stream) }
+}
+
+impl Stream for UnixStream {
+    fn try_clone_stream(&self) -> io::Result<UnixStream> { self.try_clone() }
+}
+
+#[cfg(all(test, feature = "simple_uds"))]
+mod tests {
+    use std::{env, fs, process, thread};
+
+    use super::*;
+    use crate::simple_uds::UdsTransport;
+    use crate::Client;
+
+    #[test]
+    fn serves_simple_uds() {
+        let socket_path =
+            env::temp_dir().join(format!("jsonrpc-uds-server-{}.socket", process::id()));
+        fs::remove_file(&socket_path).unwrap_or(());
+
+        let server = Server::new().with_method("add", |(a, b): (u64, u64)| Ok(a + b));
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        thread::spawn(move || UdsServer::new(server).serve(listener));
+
+        let client = Client::with_transport(UdsTransport::new(&socket_path));
+        let args = crate::arg([2, 3]);
+        assert_eq!(client.call::<u64>("add", Some(&*args)).unwrap(), 5);
+
+        fs::remove_file(&socket_path).unwrap();
+    }
+}
@@ -6,10 +6,17 @@
 use std::{error, fmt, io, net, time};
 
 use crate::client::Transport;
-use crate::{Request, Response};
+use crate::multiplex::{self, Registry};
+use crate::{Notification, Request, Response};
+
+/// The connections of the transports by address.
+static CONNECTIONS: Registry<net::SocketAddr, net::TcpStream> = Registry::new();
 
 #[derive(Debug, Clone)]
 /// Simple synchronous TCP transport.
+///
+/// Requests share one persistent connection with the other transports to the same address, and
+/// may be sent from several threads at once. Responses are matched to the requests by `id`.
 pub struct TcpTransport {
     /// The internet socket address to connect to.
     pub addr: net::SocketAddr,
@@ -21,32 +28,38 @@ impl TcpTransport {
     /// Creates a new `TcpTransport` without timeouts.
     pub fn new(addr: net::SocketAddr) -> TcpTransport { TcpTransport { addr, timeout: None } }
 
-    fn request<R>(&self, req: impl serde::Serialize) -> Result<R, Error>
+    /// Opens a connection, the read timeout is left to the requests waiting for a response.
+    fn connect(&self) -> io::Result<net::TcpStream> {
+        let sock = net::TcpStream::connect(self.addr)?;
+        sock.set_write_timeout(self.timeout)?;
+        Ok(sock)
+    }
+
+    fn request<R>(&self, reqs: &[Request], batch: bool) -> Result<R, Error>
     where
         R: for<'a> serde::de::Deserialize<'a>,
     {
-        let mut sock = net::TcpStream::connect(self.addr)?;
-        sock.set_read_timeout(self.timeout)?;
-        sock.set_write_timeout(self.timeout)?;
-
-        serde_json::to_writer(&mut sock, &req)?;
+        let resp =
+            CONNECTIONS.get(&self.addr).request(|| self.connect(), self.timeout, reqs, batch)?;
+        Ok(serde_json::from_value(resp)?)
+    }
 
-        // NOTE: we don't check the id there, so it *must* be synchronous
-        let resp: R = serde_json::Deserializer::from_reader(&mut sock)
-            .into_iter()
-            .next()
-            .ok_or(Error::Timeout)??;
-        Ok(resp)
+    fn notify(&self, notification: Notification) -> Result<(), Error> {
+        Ok(CONNECTIONS.get(&self.addr).notify(|| self.connect(), &notification)?)
     }
 }
 
 impl Transport for TcpTransport {
     fn send_request(&self, req: Request) -> Result<Response, crate::Error> {
-        Ok(self.request(req)?)
+        Ok(self.request(&[req], false)?)
     }
 
     fn send_batch(&self, reqs: &[Request]) -> Result<Vec<Response>, crate::Error> {
-        Ok(self.request(reqs)?)
+        Ok(self.request(reqs, true)?)
+    }
+
+    fn send_notification(&self, notification: Notification) -> Result<(), crate::Error> {
+        Ok(self.notify(notification)?)
     }
 
     fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "{}", self.addr) }
@@ -95,6 +108,16 @@ impl From<serde_json::Error> for Error {
     fn from(e: serde_json::Error) -> Self { Error::Json(e) }
 }
 
+impl From<multiplex::Error> for Error {
+    fn from(e: multiplex::Error) -> Self {
+        match e {
+            multiplex::Error::Io(e) => Error::SocketError(e),
+            multiplex::Error::Timeout => Error::Timeout,
+            multiplex::Error::Json(e) => Error::Json(e),
+        }
+    }
+}
+
 impl From<Error> for crate::Error {
     fn from(e: Error) -> crate::Error {
         match e {
@@ -106,7 +129,7 @@ impl From<Error> for crate::Error {
 
 #[cfg(test)]
 mod tests {
-    use std::io::{Read, Write};
+    use std::io::{BufRead, BufReader, Read, Write};
     use std::thread;
 
     use super::*;
@@ -155,4 +178,126 @@ mod tests {
         let recv_resp = client_thread.join().unwrap();
         assert_eq!(serde_json::to_vec(&recv_resp).unwrap(), dummy_resp_ser);
     }
+
+    // Requests from several threads share a connection, answered out of order
+    #[test]
+    fn multiplexes_requests() {
+        let server = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let transport = TcpTransport {
+            addr: server.local_addr().unwrap(),
+            timeout: Some(time::Duration::from_secs(5)),
+        };
+
+        // Each client numbers its requests from 1, so the ids collide on the connection.
+        let clients = (0..3)
+            .map(|i| {
+                let client = Client::with_transport(transport.clone());
+                thread::spawn(move || {
+                    let method = format!("method{}", i);
+                    let result: String = client.call(&method, None).unwrap();
+                    assert_eq!(result, method);
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let (stream, _) = server.accept().unwrap();
+        let mut writer = stream.try_clone().unwrap();
+        let mut requests = BufReader::new(stream).lines();
+        let mut received = vec![];
+        for _ in 0..3 {
+            let request: serde_json::Value =
+                serde_json::from_str(&requests.next().unwrap().unwrap()).unwrap();
+            received.push(request);
+        }
+        for request in received.iter().rev() {
+            let response = serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": request["id"],
+                "result": request["method"],
+            });
+            writeln!(writer, "{}", response).unwrap();
+        }
+        for client in clients {
+            client.join().unwrap();
+        }
+
+        // The connection stays open for later requests.
+        let client = thread::spawn(move || {
+            Client::with_transport(transport).call::<u64>("getblockcount", None).unwrap()
+        });
+        let request: serde_json::Value =
+            serde_json::from_str(&requests.next().unwrap().unwrap()).unwrap();
+        let response = serde_json::json!({ "jsonrpc": "2.0", "id": request["id"], "result": 7 });
+        writer.write_all(response.to_string().as_bytes()).unwrap();
+        assert_eq!(client.join().unwrap(), 7);
+    }
+
+    // A response without an id goes to the only request in flight, or fails them all
+    #[test]
+    fn handles_null_id_responses() {
+        let server = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let transport = TcpTransport {
+            addr: server.local_addr().unwrap(),
+            timeout: Some(time::Duration::from_secs(5)),
+        };
+        let parse_error = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": null,
+            "error": { "code": -32700, "message": "Parse error" },
+        });
+
+        let client = Client::with_transport(transport.clone());
+        let request = thread::spawn(move || {
+            let request = client.build_request("getblockcount", None);
+            client.send_request(request).unwrap()
+        });
+        let (stream, _) = server.accept().unwrap();
+        let mut writer = stream.try_clone().unwrap();
+        let mut requests = BufReader::new(stream).lines();
+        requests.next().unwrap().unwrap();
+        writeln!(writer, "{}", parse_error).unwrap();
+        let response = request.join().unwrap();
+        assert_eq!(response.error.unwrap().code, -32700);
+
+        let calls = (0..2)
+            .map(|_| {
+                let client = Client::with_transport(transport.clone());
+                thread::spawn(move || client.call::<u64>("getblockcount", None))
+            })
+            .collect::<Vec<_>>();
+        for _ in 0..2 {
+            requests.next().unwrap().unwrap();
+        }
+        writeln!(writer, "{}", parse_error).unwrap();
+        for call in calls {
+            assert!(call.join().unwrap().is_err());
+        }
+    }
+
+    // A server answering a notification doesn't hand the answer to the call sent after it
+    #[test]
+    fn drops_answers_to_notifications() {
+        let server = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let transport = TcpTransport {
+            addr: server.local_addr().unwrap(),
+            timeout: Some(time::Duration::from_secs(5)),
+        };
+
+        let client = Client::with_transport(transport);
+        let call = thread::spawn(move || {
+            client.notify("ping", None).unwrap();
+            client.call::<u64>("getblockcount", None).unwrap()
+        });
+        let (stream, _) = server.accept().unwrap();
+        let mut writer = stream.try_clone().unwrap();
+        let mut requests = BufReader::new(stream).lines();
+        requests.next().unwrap().unwrap();
+        let request: serde_json::Value =
+            serde_json::from_str(&requests.next().unwrap().unwrap()).unwrap();
+        let answer = serde_json::json!({ "jsonrpc": "2.0", "id": null, "result": null });
+        writeln!(writer, "{}", answer).unwrap();
+        let response = serde_json::json!({ "jsonrpc": "2.0", "id": request["id"], "result": 7 });
+        writeln!(writer, "{}", response).unwrap();
+        assert_eq!(call.join().unwrap(), 7);
+    }
 }
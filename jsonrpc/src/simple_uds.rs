@@ -6,9 +6,16 @@ use std::os::unix::net::UnixStream;
 use std::{error, fmt, io, path, time};
 
 use crate::client::Transport;
-use crate::{Request, Response};
+use crate::multiplex::{self, Registry};
+use crate::{Notification, Request, Response};
+
+/// The connections of the transports by socket path.
+static CONNECTIONS: Registry<path::PathBuf, UnixStream> = Registry::new();
 
 /// Simple synchronous UDS transport.
+///
+/// Requests share one persistent connection with the other transports to the same socket, and
+/// may be sent from several threads at once. Responses are matched to the requests by `id`.
 #[derive(Debug, Clone)]
 pub struct UdsTransport {
     /// The path to the Unix Domain Socket.
@@ -23,32 +30,42 @@ impl UdsTransport {
         UdsTransport { sockpath: sockpath.as_ref().to_path_buf(), timeout: None }
     }
 
-    fn request<R>(&self, req: impl serde::Serialize) -> Result<R, Error>
+    /// Opens a connection, the read timeout is left to the requests waiting for a response.
+    fn connect(&self) -> io::Result<UnixStream> {
+        let sock = UnixStream::connect(&self.sockpath)?;
+        sock.set_write_timeout(self.timeout)?;
+        Ok(sock)
+    }
+
+    fn request<R>(&self, reqs: &[Request], batch: bool) -> Result<R, Error>
     where
         R: for<'a> serde::de::Deserialize<'a>,
     {
-        let mut sock = UnixStream::connect(&self.sockpath)?;
-        sock.set_read_timeout(self.timeout)?;
-        sock.set_write_timeout(self.timeout)?;
-
-        serde_json::to_writer(&mut sock, &req)?;
+        let resp = CONNECTIONS.get(&self.sockpath).request(
+            || self.connect(),
+            self.timeout,
+            reqs,
+            batch,
+        )?;
+        Ok(serde_json::from_value(resp)?)
+    }
 
-        // NOTE: we don't check the id there, so it *must* be synchronous
-        let resp: R = serde_json::Deserializer::from_reader(&mut sock)
-            .into_iter()
-            .next()
-            .ok_or(Error::Timeout)??;
-        Ok(resp)
+    fn notify(&self, notification: Notification) -> Result<(), Error> {
+        Ok(CONNECTIONS.get(&self.sockpath).notify(|| self.connect(), &notification)?)
     }
 }
 
 impl Transport for UdsTransport {
     fn send_request(&self, req: Request) -> Result<Response, crate::error::Error> {
-        Ok(self.request(req)?)
+        Ok(self.request(&[req], false)?)
     }
 
     fn send_batch(&self, reqs: &[Request]) -> Result<Vec<Response>, crate::error::Error> {
-        Ok(self.request(reqs)?)
+        Ok(self.request(reqs, true)?)
+    }
+
+    fn send_notification(&self, notification: Notification) -> Result<(), crate::error::Error> {
+        Ok(self.notify(notification)?)
     }
 
     fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -99,6 +116,16 @@ impl From<serde_json::Error> for Error {
     fn from(e: serde_json::Error) -> Self { Error::Json(e) }
 }
 
+impl From<multiplex::Error> for Error {
+    fn from(e: multiplex::Error) -> Self {
+        match e {
+            multiplex::Error::Io(e) => Error::SocketError(e),
+            multiplex::Error::Timeout => Error::Timeout,
+            multiplex::Error::Json(e) => Error::Json(e),
+        }
+    }
+}
+
 impl From<Error> for crate::error::Error {
     fn from(e: Error) -> crate::error::Error {
         match e {
@@ -110,7 +137,7 @@ impl From<Error> for crate::error::Error {
 
 #[cfg(test)]
 mod tests {
-    use std::io::{Read, Write};
+    use std::io::{BufRead, BufReader, Read, Write};
     use std::os::unix::net::UnixListener;
     use std::{fs, process, thread};
 
@@ -169,4 +196,51 @@ mod tests {
         drop(server);
         fs::remove_file(&socket_path).unwrap();
     }
+
+    // A batch is answered on the connection, a closed connection is opened again
+    #[test]
+    fn reconnects_after_close() {
+        let socket_path: path::PathBuf = format!("uds_reconnect_{}.socket", process::id()).into();
+        fs::remove_file(&socket_path).unwrap_or(());
+        let server = UnixListener::bind(&socket_path).unwrap();
+        let transport = UdsTransport {
+            sockpath: socket_path.clone(),
+            timeout: Some(time::Duration::from_secs(5)),
+        };
+
+        let serve_once = || {
+            let (stream, _) = server.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut line = String::new();
+            BufReader::new(stream).read_line(&mut line).unwrap();
+            let requests: serde_json::Value = serde_json::from_str(&line).unwrap();
+            let responses = requests
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|r| serde_json::json!({ "jsonrpc": "2.0", "id": r["id"], "result": r["method"] }))
+                .collect::<Vec<_>>();
+            writeln!(writer, "{}", serde_json::Value::from(responses)).unwrap();
+            // Dropping the stream closes the connection.
+        };
+
+        for _ in 0..2 {
+            let transport = transport.clone();
+            let client_thread = thread::spawn(move || {
+                let client = Client::with_transport(transport);
+                let requests = [client.build_request("a", None), client.build_request("b", None)];
+                client.send_batch(&requests).unwrap()
+            });
+            serve_once();
+            let responses = client_thread.join().unwrap();
+            let results = responses
+                .into_iter()
+                .map(|r| r.unwrap().result::<String>().unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(results, ["a", "b"]);
+        }
+
+        drop(server);
+        fs::remove_file(&socket_path).unwrap();
+    }
 }
@@ -0,0 +1,88 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Checks the HTTP transports against both the legacy and the JSON-RPC 2.0 behaviour of
+//! Bitcoin Core, as emulated by the mock server.
+
+#![cfg(all(
+    feature = "mock",
+    any(all(feature = "simple_http", not(feature = "proxy")), feature = "bitreq_http")
+))]
+
+use jsonrpc::mock::MockServer;
+use jsonrpc::{Client, Error, Protocol, Transport};
+
+/// Returns a server answering `getblockcount` and failing `getblock`.
+fn server() -> MockServer {
+    let server = MockServer::start().unwrap().with_auth("user", "pass");
+    server.set_response("getblockcount", &101);
+    server.set_error("getblock", -5, "Block not found");
+    server
+}
+
+/// Runs the checks common to all modes, with `client` pointed at `server`.
+fn check(server: &MockServer, client: Client) {
+    assert_eq!(client.call::<u64>("getblockcount", None).unwrap(), 101);
+
+    let err = client.call::<u64>("getblock", None).unwrap_err();
+    assert!(matches!(err, Error::Rpc(ref e) if e.code == -5), "{}", err);
+    let err = client.call::<u64>("nosuchmethod", None).unwrap_err();
+    assert!(matches!(err, Error::Rpc(ref e) if e.code == -32601), "{}", err);
+
+    // Notifications succeed whether the server answers them or not.
+    client.notify("getblockcount", None).unwrap();
+    // The connection is still usable afterwards.
+    assert_eq!(client.call::<u64>("getblockcount", None).unwrap(), 101);
+
+    let methods = server.requests().into_iter().map(|r| r.method).collect::<Vec<_>>();
+    assert_eq!(
+        methods,
+        ["getblockcount", "getblock", "nosuchmethod", "getblockcount", "getblockcount"]
+    );
+}
+
+/// Checks all combinations of client and server versions with the transport made by `transport`.
+fn check_modes<T: Transport, F: Fn(&str, &str) -> T>(transport: F) {
+    // Core v28+, errors with HTTP 200 and notifications with HTTP 204.
+    let v28 = server();
+    check(&v28, Client::with_transport(transport(&v28.url(), "pass")));
+    // Core v28+ answering legacy requests, errors with HTTP 500 and 404.
+    let v28 = server();
+    check(
+        &v28,
+        Client::with_transport(transport(&v28.url(), "pass")).with_protocol(Protocol::Legacy),
+    );
+    // Core before v28 always answers the legacy way.
+    for protocol in [Protocol::V2, Protocol::Legacy] {
+        let v27 = server().with_legacy_errors();
+        check(&v27, Client::with_transport(transport(&v27.url(), "pass")).with_protocol(protocol));
+    }
+
+    // Notifications still need credentials.
+    let server = server();
+    let client = Client::with_transport(transport(&server.url(), "wrong"));
+    assert!(client.notify("getblockcount", None).is_err());
+}
+
+#[cfg(all(feature = "simple_http", not(feature = "proxy")))]
+#[test]
+fn simple_http() {
+    use jsonrpc::simple_http::SimpleHttpTransport;
+
+    check_modes(|url, pass| {
+        SimpleHttpTransport::builder().url(url).unwrap().auth("user", Some(pass)).build()
+    });
+}
+
+#[cfg(feature = "bitreq_http")]
+#[test]
+fn bitreq_http() {
+    use jsonrpc::bitreq_http::BitreqHttpTransport;
+
+    check_modes(|url, pass| {
+        BitreqHttpTransport::builder()
+            .url(url)
+            .unwrap()
+            .basic_auth("user".to_owned(), Some(pass.to_owned()))
+            .build()
+    });
+}
@@ -8,7 +8,7 @@ mod download {
 #[cfg(feature = "download")]
 mod download {
     use std::fs::File;
-    use std::io::{self, BufRead, BufReader, Cursor, Read};
+    use std::io::{self, BufRead, BufReader, Read};
     use std::path::{Path, PathBuf};
     use std::str::FromStr;
 
@@ -99,7 +99,8 @@ mod download {
 
             println!("filename:{} version:{} hash:{}", download_filename, VERSION, expected_hash);
 
-            let (file_or_url, tarball_bytes) = match std::env::var("BITCOIND_TARBALL_FILE") {
+            // A downloaded tarball is verified while it is downloaded, and removed once unpacked.
+            let (tarball_path, downloaded) = match std::env::var("BITCOIND_TARBALL_FILE") {
                 Err(_) => {
                     let download_endpoint = std::env::var("BITCOIND_DOWNLOAD_ENDPOINT")
                         .unwrap_or("https://bitcoincore.org/bin".to_owned());
@@ -108,12 +109,13 @@ mod download {
                         "{}/bitcoin-core-{}/{}",
                         download_endpoint, VERSION, download_filename
                     );
-                    let resp = bitreq::get(&url)
+                    let tarball_path = bitcoin_exe_home.join(&download_filename);
+                    bitreq::Download::new(bitreq::get(&url), &tarball_path)
+                        .with_sha256(expected_hash.to_byte_array())
                         .send()
-                        .with_context(|| format!("cannot reach url {}", url))?;
-                    assert_eq!(resp.status_code, 200, "url {} didn't return 200", url);
+                        .with_context(|| format!("cannot download url {}", url))?;
 
-                    (url, resp.as_bytes().to_vec())
+                    (tarball_path, true)
                 }
                 Ok(path) => {
                     let f = File::open(&path).with_context(|| {
@@ -125,19 +127,22 @@ mod download {
                     let mut reader = BufReader::new(f);
                     let mut buffer = Vec::new();
                     reader.read_to_end(&mut buffer)?;
-                    (path, buffer)
+
+                    let tarball_hash = sha256::Hash::hash(&buffer);
+                    assert_eq!(
+                        expected_hash, tarball_hash,
+                        "expected hash of {} is not matching",
+                        path
+                    );
+
+                    (PathBuf::from(path), false)
                 }
             };
 
-            let tarball_hash = sha256::Hash::hash(&tarball_bytes);
-            assert_eq!(
-                expected_hash, tarball_hash,
-                "expected hash of {} is not matching",
-                file_or_url
-            );
-
+            let tarball = File::open(&tarball_path)
+                .with_context(|| format!("cannot open file {:?}", tarball_path))?;
             if download_filename.ends_with(".tar.gz") {
-                let d = GzDecoder::new(&tarball_bytes[..]);
+                let d = GzDecoder::new(BufReader::new(tarball));
 
                 let mut archive = Archive::new(d);
                 for mut entry in archive.entries().unwrap().flatten() {
@@ -148,8 +153,7 @@ mod download {
                     }
                 }
             } else if download_filename.ends_with(".zip") {
-                let cursor = Cursor::new(tarball_bytes);
-                let mut archive = zip::ZipArchive::new(cursor).unwrap();
+                let mut archive = zip::ZipArchive::new(BufReader::new(tarball)).unwrap();
                 for i in 0..zip::ZipArchive::len(&archive) {
                     let mut file = archive.by_index(i).unwrap();
                     let outpath = match file.enclosed_name() {
@@ -169,6 +173,10 @@ mod download {
                     }
                 }
             }
+            if downloaded {
+                std::fs::remove_file(&tarball_path)
+                    .with_context(|| format!("cannot remove file {:?}", tarball_path))?;
+            }
 
             // Code signing for arm64 macOS:
             #[cfg(all(target_os = "macos", target_arch = "aarch64"))]
@@ -10,6 +10,7 @@ pub extern crate corepc_client as client;
 
 #[rustfmt::skip]
 mod client_versions;
+mod rpcauth;
 mod versions;
 
 use std::ffi::OsStr;
@@ -31,6 +32,8 @@ pub use self::{
     client_versions::*,
     // Re-export the version string e.g., "28.0".
     versions::VERSION,
+    // Re-export the `-rpcauth` credentials helper.
+    rpcauth::RpcAuth,
     // Re-export the model types as `mtype` to differentiate it from `vtype`.
     client::types::model as mtype, // `types` is the `corepc-types` crate.
 };
@@ -42,6 +45,8 @@ pub struct Node {
     process: Child,
     /// Rpc client linked to this bitcoind process.
     pub client: Client,
+    /// Rpc client for the wallet loaded after initialization, see [`Conf::wallet`].
+    wallet: Option<WalletClient>,
     /// Work directory, where the node store blocks and other stuff.
     work_dir: DataDir,
 
@@ -259,6 +264,17 @@ pub struct Conf<'a> {
 
     /// Load `wallet` after initialization.
     pub wallet: Option<String>,
+
+    /// Additional RPC users, passed to bitcoind as `-rpcauth` arguments.
+    ///
+    /// The node itself keeps using the cookie file. See [`Conf::add_rpc_user`].
+    pub rpc_auth: Vec<RpcAuth>,
+
+    /// RPC methods each user is allowed to call, passed to bitcoind as `-rpcwhitelist` arguments.
+    ///
+    /// Users without a whitelist, including the cookie user, can call all methods. Requires
+    /// Bitcoin Core v0.20 or later. See [`Conf::whitelist_rpc_methods`].
+    pub rpc_whitelist: Vec<(String, Vec<String>)>,
 }
 
 impl Default for Conf<'_> {
@@ -273,10 +289,28 @@ impl Default for Conf<'_> {
             attempts: 5,
             enable_zmq: false,
             wallet: Some("default".to_string()),
+            rpc_auth: vec![],
+            rpc_whitelist: vec![],
         }
     }
 }
 
+impl Conf<'_> {
+    /// Adds an RPC user with a random password, returns the credentials to connect with.
+    pub fn add_rpc_user(&mut self, user: &str) -> Auth {
+        let rpc_auth = RpcAuth::new(user);
+        let auth = rpc_auth.auth();
+        self.rpc_auth.push(rpc_auth);
+        auth
+    }
+
+    /// Only allows `user` to call the RPC `methods`.
+    pub fn whitelist_rpc_methods(&mut self, user: &str, methods: &[&str]) {
+        let methods = methods.iter().map(|m| m.to_string()).collect();
+        self.rpc_whitelist.push((user.to_string(), methods));
+    }
+}
+
 impl Node {
     /// Launch the bitcoind process from the given `exe` executable with default args.
     ///
@@ -314,6 +348,7 @@ impl Node {
             let (p2p_args, p2p_socket) = Self::p2p_args(&conf.p2p)?;
             let (zmq_args, zmq_pub_raw_tx_socket, zmq_pub_raw_block_socket) =
                 Self::zmq_args(conf.enable_zmq)?;
+            let rpc_auth_args = Self::rpc_auth_args(conf);
 
             let stdout = if conf.view_stdout { Stdio::inherit() } else { Stdio::null() };
 
@@ -327,6 +362,7 @@ impl Node {
                 .args(&p2p_args)
                 .args(&conf_args)
                 .args(&zmq_args)
+                .args(&rpc_auth_args)
                 .stdout(stdout)
                 .spawn()
                 .with_context(|| format!("Error while executing {:?}", exe.as_ref()))?;
@@ -349,26 +385,28 @@ impl Node {
             }
             let auth = Auth::CookieFile(cookie_file.clone());
 
-            let client_base = Self::create_client_base(&rpc_url, &auth)?;
-            let client = match &conf.wallet {
-                Some(wallet) =>
-                    match Self::create_client_wallet(&client_base, &rpc_url, &auth, wallet) {
-                        Ok(client) => client,
-                        Err(e) =>
-                            if attempt == conf.attempts - 1 {
-                                return Err(e);
-                            } else {
-                                // If the wallet cannot be created or loaded, there might be an issue
-                                // with the work_dir or process. Kill the process and retry.
-                                let _ = process.kill();
-                                continue;
-                            },
-                    },
-                None => client_base,
+            let client = Self::create_client_base(&rpc_url, &auth)?;
+            let wallet = match &conf.wallet {
+                Some(wallet) => match Self::create_client_wallet(&client, wallet) {
+                    Ok(wallet) => Some(wallet),
+                    Err(e) =>
+                        if attempt == conf.attempts - 1 {
+                            return Err(e);
+                        } else {
+                            // If the wallet cannot be created or loaded, there might be an issue
+                            // with the work_dir or process. Kill the process and retry.
+                            let _ = process.kill();
+                            continue;
+                        },
+                },
+                None => None,
             };
-            if Self::wait_for_client(&client, Duration::from_secs(5)).is_err() {
-                // If the client times out there might be an issue with the work_dir or process. Kill
-                // the process and retry.
+            if let Err(e) = Self::wait_for_client(&client, Duration::from_secs(5)) {
+                if attempt == conf.attempts - 1 {
+                    return Err(e);
+                }
+                // If the client is not ready there might be an issue with the work_dir or process.
+                // Kill the process and retry.
                 let _ = process.kill();
                 continue;
             }
@@ -376,6 +414,7 @@ impl Node {
             return Ok(Node {
                 process,
                 client,
+                wallet,
                 work_dir,
                 params: ConnectParams {
                     cookie_file,
@@ -463,6 +502,19 @@ impl Node {
         }
     }
 
+    /// Returns the `-rpcauth` and `-rpcwhitelist` args for the users in `conf`.
+    fn rpc_auth_args(conf: &Conf) -> Vec<String> {
+        let mut args: Vec<String> = conf.rpc_auth.iter().map(RpcAuth::arg).collect();
+        if !conf.rpc_whitelist.is_empty() {
+            // Otherwise users without a whitelist, including the cookie user, can't call anything.
+            args.push("-rpcwhitelistdefault=0".to_string());
+        }
+        for (user, methods) in &conf.rpc_whitelist {
+            args.push(format!("-rpcwhitelist={}:{}", user, methods.join(",")));
+        }
+        args
+    }
+
     /// Returns `Ok` once the cookie file is accessible, or an error if it times out.
     fn wait_for_cookie_file(cookie_file: &Path, timeout: Duration) -> anyhow::Result<()> {
         let start = std::time::Instant::now();
@@ -475,17 +527,13 @@ impl Node {
         Err(anyhow::anyhow!("timeout waiting for cookie file: {}", cookie_file.display()))
     }
 
-    /// Returns `Ok` once the client can successfully call, or an error if it times out.
+    /// Returns `Ok` once the client can successfully call, otherwise the last error.
+    ///
+    /// Transient errors are retried until `timeout` elapses, see [`Client::wait_until_ready`].
     fn wait_for_client(client: &Client, timeout: Duration) -> anyhow::Result<()> {
-        let start = std::time::Instant::now();
-        while start.elapsed() < timeout {
-            // Test calling GetBlockchainInfo. Use serde value to be resilient to upstream changes.
-            if client.call::<serde_json::Value>("getblockchaininfo", &[]).is_ok() {
-                return Ok(());
-            }
-            thread::sleep(Duration::from_millis(200));
-        }
-        Err(anyhow::anyhow!("timeout waiting for client to be ready"))
+        client
+            .wait_until_ready(timeout)
+            .map_err(|e| Error::NoBitcoindInstance(e.to_string()).into())
     }
 
     /// Create a new RPC client connected to the given `rpc_url` with the provided `auth`.
@@ -502,25 +550,18 @@ impl Node {
             .map_err(|e| Error::NoBitcoindInstance(e.to_string()).into())
     }
 
-    /// Create a new RPC client connected to the given `wallet`.
+    /// Create a new RPC client for the given `wallet`.
     ///
     /// If the wallet with the given name does not exist, it will create it.
     /// If the wallet already exists, it will load it.
     ///
     /// The client or wallet may not be immediately available, so retry up to 10 times.
-    fn create_client_wallet(
-        client_base: &Client,
-        rpc_url: &str,
-        auth: &Auth,
-        wallet: &str,
-    ) -> anyhow::Result<Client> {
+    fn create_client_wallet(client_base: &Client, wallet: &str) -> anyhow::Result<WalletClient> {
         for _ in 0..10 {
             // Try to create the wallet, or if that fails it might already exist so try to load it.
             if client_base.create_wallet(wallet).is_ok() || client_base.load_wallet(wallet).is_ok()
             {
-                let url = format!("{}/wallet/{}", rpc_url, wallet);
-                return Client::new_with_auth(&url, auth.clone())
-                    .map_err(|e| Error::NoBitcoindInstance(e.to_string()).into());
+                return Ok(client_base.wallet(wallet));
             }
             thread::sleep(Duration::from_millis(200));
         }
@@ -530,10 +571,10 @@ impl Node {
     /// Returns the rpc URL including the schema eg. http://127.0.0.1:44842.
     pub fn rpc_url(&self) -> String { format!("http://{}", self.params.rpc_socket) }
 
-    /// Returns the rpc URL including the schema and the given `wallet_name`.
-    /// eg. http://127.0.0.1:44842/wallet/my_wallet.
+    /// Returns the rpc URL including the schema and the given `wallet_name`, percent-encoded.
+    /// eg. http://127.0.0.1:44842/wallet/my%20wallet.
     pub fn rpc_url_with_wallet<T: AsRef<str>>(&self, wallet_name: T) -> String {
-        format!("http://{}/wallet/{}", self.params.rpc_socket, wallet_name.as_ref())
+        client_sync::wallet_url(&self.rpc_url(), wallet_name.as_ref())
     }
 
     /// Return the current workdir path of the running node.
@@ -550,14 +591,20 @@ impl Node {
         Ok(self.process.wait()?)
     }
 
-    /// Create a new wallet in the running node, and return an RPC client connected to the just
-    /// created wallet.
-    pub fn create_wallet<T: AsRef<str>>(&self, wallet: T) -> anyhow::Result<Client> {
+    /// Returns the RPC client of the wallet loaded after initialization, see [`Conf::wallet`].
+    ///
+    /// # Panics
+    ///
+    /// If the node was started without a wallet.
+    pub fn wallet(&self) -> &WalletClient {
+        self.wallet.as_ref().expect("no wallet loaded, see `Conf::wallet`")
+    }
+
+    /// Create a new wallet in the running node, and return a [`WalletClient`] for it, which only
+    /// exposes the wallet RPC methods.
+    pub fn create_wallet<T: AsRef<str>>(&self, wallet: T) -> anyhow::Result<WalletClient> {
         let _ = self.client.create_wallet(wallet.as_ref())?;
-        Ok(Client::new_with_auth(
-            &self.rpc_url_with_wallet(wallet),
-            Auth::CookieFile(self.params.cookie_file.clone()),
-        )?)
+        Ok(self.client.wallet(wallet.as_ref()))
     }
 }
 
@@ -692,7 +739,7 @@ mod test {
         let info = node.client.get_blockchain_info().unwrap();
 
         assert_eq!(0, info.blocks);
-        let address = node.client.new_address().unwrap();
+        let address = node.wallet().new_address().unwrap();
         let _ = node.client.generate_to_address(1, &address).unwrap();
         let info = node.client.get_blockchain_info().unwrap();
         assert_eq!(1, info.blocks);
@@ -742,9 +789,9 @@ mod test {
         // Generate 101 blocks
         // Wallet balance should be 50
         let node = Node::with_conf(exe_path().unwrap(), &conf).unwrap();
-        let core_addrs = node.client.new_address().unwrap();
+        let core_addrs = node.wallet().new_address().unwrap();
         node.client.generate_to_address(101, &core_addrs).unwrap();
-        let wallet_balance_1 = node.client.get_balance().unwrap();
+        let wallet_balance_1 = node.wallet().get_balance().unwrap();
         let best_block_1 = node.client.get_best_block_hash().unwrap();
 
         drop(node);
@@ -752,7 +799,7 @@ mod test {
         // Start a new Node with the same datadir
         let node = Node::with_conf(exe_path().unwrap(), &conf).unwrap();
 
-        let wallet_balance_2 = node.client.get_balance().unwrap();
+        let wallet_balance_2 = node.wallet().get_balance().unwrap();
         let best_block_2 = node.client.get_best_block_hash().unwrap();
 
         // Check node chain data persists
@@ -873,26 +920,59 @@ mod test {
 
         let mut conf = Conf::default();
         // rpcauth generated with [rpcauth.py](https://github.com/bitcoin/bitcoin/blob/master/share/rpcauth/rpcauth.py)
-        // this could be also added to node, example: [RpcAuth](https://github.com/testcontainers/testcontainers-rs/blob/dev/testcontainers/src/images/coblox_bitcoincore.rs#L39-L91)
         conf.args.push("-rpcauth=bitcoind:cccd5d7fd36e55c1b8576b8077dc1b83$60b5676a09f8518dcb4574838fb86f37700cd690d99bd2fdc2ea2bf2ab80ead6");
 
         let node = Node::with_conf(exe, &conf).unwrap();
 
         let auth = Auth::UserPass("bitcoind".to_string(), "bitcoind".to_string());
-        let client = Client::new_with_auth(
-            format!("{}/wallet/default", node.rpc_url().as_str()).as_str(),
-            auth,
-        )
-        .unwrap();
+        let client = Client::new_with_auth(node.rpc_url().as_str(), auth).unwrap();
         let info = client.get_blockchain_info().unwrap();
         assert_eq!(0, info.blocks);
 
-        let address = client.new_address().unwrap();
+        let address = client.wallet("default").new_address().unwrap();
         let _ = client.generate_to_address(1, &address).unwrap();
         let info = node.client.get_blockchain_info().unwrap();
         assert_eq!(1, info.blocks);
     }
 
+    #[test]
+    fn test_node_rpc_users() {
+        let exe = init();
+
+        let mut conf = Conf::default();
+        let alice = conf.add_rpc_user("alice");
+        let bob = conf.add_rpc_user("bob");
+        let node = Node::with_conf(exe, &conf).unwrap();
+
+        for auth in [alice, bob] {
+            let client = Client::new_with_auth(&node.rpc_url(), auth).unwrap();
+            let info = client.get_blockchain_info().unwrap();
+            assert_eq!(0, info.blocks);
+        }
+
+        let wrong = Auth::UserPass("alice".to_string(), "wrong".to_string());
+        let client = Client::new_with_auth(&node.rpc_url(), wrong).unwrap();
+        assert!(client.get_blockchain_info().is_err());
+    }
+
+    #[cfg(feature = "0_20_2")]
+    #[test]
+    fn test_node_rpc_whitelist() {
+        let exe = init();
+
+        let mut conf = Conf::default();
+        let auth = conf.add_rpc_user("readonly");
+        conf.whitelist_rpc_methods("readonly", &["getblockchaininfo"]);
+        let node = Node::with_conf(exe, &conf).unwrap();
+
+        let client = Client::new_with_auth(&node.rpc_url(), auth).unwrap();
+        assert!(client.get_blockchain_info().is_ok());
+        assert!(client.get_best_block_hash().is_err());
+
+        // The node's own client is not restricted.
+        assert!(node.client.get_best_block_hash().is_ok());
+    }
+
     #[test]
     fn test_get_cookie_user_and_pass() {
         let exe = init();